@@ -25,5 +25,192 @@ fn decode(c: &mut criterion::Criterion) {
     }
 }
 
-criterion_group!(benches, decode);
+/// Decoding a file with 1000 zTXt chunks, with and without parsing them.
+fn decode_text(c: &mut criterion::Criterion) {
+    use png_pong::chunk::{Chunk, CompressedText};
+
+    let raster = pix::Raster::<pix::rgb::SRgb8>::with_clear(16, 16);
+    let mut data = Vec::new();
+    png_pong::Encoder::new(&mut data)
+        .into_step_enc()
+        .still(&raster)
+        .expect("Failed to encode");
+    let mut text = Vec::new();
+    let mut encoder = png_pong::Encoder::new(&mut text).into_chunk_enc();
+    for i in 0..1000 {
+        let mut chunk = Chunk::CompressedText(CompressedText {
+            key: "Comment".to_string(),
+            val: format!("Comment number {i}, ").repeat(20),
+        });
+        encoder.encode(&mut chunk).expect("Failed to encode");
+    }
+    // Insert after the signature and header chunk
+    let tail = data.split_off(33);
+    data.extend(text);
+    data.extend(tail);
+
+    let mut group = c.benchmark_group("decode_text");
+    for (name, parse_text) in [("parsed", true), ("raw", false)] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                png_pong::Decoder::new(data.as_slice())
+                    .expect("Not PNG")
+                    .parse_text(parse_text)
+                    .into_steps()
+                    .last()
+                    .expect("No frames in PNG")
+                    .expect("PNG parsing error")
+            })
+        });
+    }
+}
+
+/// Decoding 500 64×64 frames from one file, reusing the inflater.
+///
+/// Frames are stand-ins for APNG frames: groups of IDAT chunks separated by
+/// zTXt chunks, each group being its own zlib stream.
+fn decode_frames(c: &mut criterion::Criterion) {
+    use png_pong::chunk::{
+        Chunk, ColorType, CompressedText, ImageData, ImageEnd, ImageHeader,
+    };
+
+    let mut header = Chunk::ImageHeader(ImageHeader {
+        width: 64,
+        height: 64,
+        color_type: ColorType::Rgb,
+        bit_depth: 8,
+        interlace: false,
+    });
+    // Image data chunks are compressed when written, so use raw scanlines
+    let mut frame = Chunk::ImageData(ImageData::with_data(vec![0; 64 * 193]));
+
+    let mut data = png_pong::SIGNATURE.to_vec();
+    let mut encoder = png_pong::Encoder::new(&mut data).into_chunk_enc();
+    encoder.encode(&mut header).expect("Failed to encode");
+    for i in 0..500 {
+        encoder.encode(&mut frame).expect("Failed to encode");
+        let mut chunk = Chunk::CompressedText(CompressedText {
+            key: "Frame".to_string(),
+            val: i.to_string(),
+        });
+        encoder.encode(&mut chunk).expect("Failed to encode");
+    }
+    encoder
+        .encode(&mut Chunk::ImageEnd(ImageEnd))
+        .expect("Failed to encode");
+
+    let mut group = c.benchmark_group("decode_frames");
+    group.sample_size(10);
+    group.bench_function("500x64x64", |b| {
+        b.iter(|| {
+            let steps = png_pong::Decoder::new(data.as_slice())
+                .expect("Not PNG")
+                .into_steps();
+            let mut frames = 0;
+            for step in steps {
+                step.expect("PNG parsing error");
+                frames += 1;
+            }
+            assert_eq!(frames, 500);
+        })
+    });
+    group.bench_function("nth_499", |b| {
+        b.iter(|| {
+            png_pong::Decoder::new(data.as_slice())
+                .expect("Not PNG")
+                .into_steps()
+                .nth(499)
+                .expect("No frame")
+                .expect("PNG parsing error")
+        })
+    });
+}
+
+/// Decoding a 2048×2048 file with uncompressed image data, with and without
+/// checking CRCs.
+fn decode_crc(c: &mut criterion::Criterion) {
+    use png_pong::decode::CrcPolicy;
+
+    let pixels: Vec<_> = (0..2048 * 2048u32)
+        .map(|i| {
+            let [r, g, b, _] = i.wrapping_mul(2_654_435_761).to_le_bytes();
+            pix::rgb::SRgb8::new(r, g, b)
+        })
+        .collect();
+    let raster =
+        pix::Raster::<pix::rgb::SRgb8>::with_pixels(2048, 2048, pixels);
+    let mut data = Vec::new();
+    png_pong::Encoder::new(&mut data)
+        .compression_level(0)
+        .into_step_enc()
+        .still(&raster)
+        .expect("Failed to encode");
+
+    let mut group = c.benchmark_group("decode_crc");
+    group.sample_size(10);
+    for (name, policy) in [("all", CrcPolicy::All), ("none", CrcPolicy::None)] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                png_pong::Decoder::new(data.as_slice())
+                    .expect("Not PNG")
+                    .verify_crc(policy)
+                    .into_steps()
+                    .last()
+                    .expect("No frames in PNG")
+                    .expect("PNG parsing error")
+            })
+        });
+    }
+}
+
+/// Decoding a 1024×1024 RGBA file to sRGB rasters, and to linear
+/// premultiplied f32 rasters (which adds a single pass over the pixels).
+fn decode_linear(c: &mut criterion::Criterion) {
+    let pixels: Vec<_> = (0..1024 * 1024u32)
+        .map(|i| {
+            let [r, g, b, a] = i.wrapping_mul(2_654_435_761).to_le_bytes();
+            pix::rgb::SRgba8::new(r, g, b, a)
+        })
+        .collect();
+    let raster =
+        pix::Raster::<pix::rgb::SRgba8>::with_pixels(1024, 1024, pixels);
+    let mut data = Vec::new();
+    png_pong::Encoder::new(&mut data)
+        .into_step_enc()
+        .still(&raster)
+        .expect("Failed to encode");
+
+    let mut group = c.benchmark_group("decode_linear");
+    group.sample_size(10);
+    group.bench_function("srgb8", |b| {
+        b.iter(|| {
+            png_pong::Decoder::new(data.as_slice())
+                .expect("Not PNG")
+                .into_steps()
+                .last()
+                .expect("No frames in PNG")
+                .expect("PNG parsing error")
+        })
+    });
+    group.bench_function("linear_f32_premultiplied", |b| {
+        b.iter(|| {
+            png_pong::Decoder::new(data.as_slice())
+                .expect("Not PNG")
+                .into_steps()
+                .into_linear_f32_premultiplied()
+                .last()
+                .expect("No frames in PNG")
+                .expect("PNG parsing error")
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    decode,
+    decode_text,
+    decode_frames,
+    decode_crc,
+    decode_linear
+);
 criterion_main!(benches);
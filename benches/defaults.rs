@@ -0,0 +1,87 @@
+//! Compare filter strategies for each color type, to choose the table in
+//! `png_pong::encode::Defaults`.  Encoded sizes are printed to stderr, and
+//! encoding times are measured by criterion.
+
+include!("../list.rs");
+
+#[macro_use]
+extern crate criterion;
+
+use png_pong::{encode::FilterStrategy, PngRaster};
+
+const STRATEGIES: &[(&str, FilterStrategy)] = &[
+    ("zero", FilterStrategy::Zero),
+    ("up", FilterStrategy::Up),
+    ("minsum", FilterStrategy::MinSum),
+    ("entropy", FilterStrategy::Entropy),
+];
+
+/// Name of the color type of a raster.
+fn color_type(raster: &PngRaster) -> &'static str {
+    use PngRaster::*;
+    match raster {
+        Gray8(_) | Gray16(_) => "grey",
+        Graya8(_) | Graya16(_) => "grey_alpha",
+        Rgb8(_) | Rgb16(_) => "rgb",
+        Rgba8(_) | Rgba16(_) => "rgba",
+        Palette(..) => "palette",
+    }
+}
+
+fn encode(raster: &PngRaster, strategy: FilterStrategy) -> Vec<u8> {
+    let mut encoder = png_pong::Encoder::new(Vec::new())
+        .filter_strategy(strategy)
+        .small_image_threshold(0)
+        .into_step_enc();
+    encoder.still(raster).expect("Failed to encode");
+    encoder.into_inner()
+}
+
+fn defaults(c: &mut criterion::Criterion) {
+    let mut corpus = Vec::new();
+    let suite = std::fs::read_dir("./tests/pngsuite-basic")
+        .expect("Missing PngSuite")
+        .map(|entry| entry.expect("Failed to read directory").path());
+    let paths = FILE_PATHS.iter().map(std::path::PathBuf::from).chain(suite);
+    for path in paths {
+        let data = std::fs::read(&path).expect("Failed to open PNG");
+        let Some(Ok(step)) = png_pong::Decoder::new(data.as_slice())
+            .expect("Not PNG")
+            .into_steps()
+            .next()
+        else {
+            continue;
+        };
+        corpus.push(step.raster);
+    }
+
+    for kind in ["grey", "grey_alpha", "rgb", "rgba", "palette"] {
+        let rasters: Vec<_> =
+            corpus.iter().filter(|r| color_type(r) == kind).collect();
+        if rasters.is_empty() {
+            continue;
+        }
+
+        let mut group = c.benchmark_group(format!("defaults_{kind}"));
+        group.sample_size(10);
+        for (name, strategy) in STRATEGIES.iter().cloned() {
+            let size: usize = rasters
+                .iter()
+                .map(|r| encode(r, strategy.clone()).len())
+                .sum();
+            eprintln!("{kind} {name}: {size} bytes");
+
+            group.bench_function(name, |b| {
+                b.iter(|| {
+                    for raster in &rasters {
+                        encode(raster, strategy.clone());
+                    }
+                })
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, defaults);
+criterion_main!(benches);
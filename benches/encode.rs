@@ -28,5 +28,29 @@ fn encode(c: &mut criterion::Criterion) {
     }
 }
 
-criterion_group!(benches, encode);
+/// Encoding a 16x16 icon, with and without the small image fast path.
+fn encode_icon(c: &mut criterion::Criterion) {
+    let mut group = c.benchmark_group("encode_icon");
+    let pixels: Vec<_> = (0..16 * 16u32)
+        .map(|i| {
+            let [r, g, b, a] = i.wrapping_mul(2_654_435_761).to_le_bytes();
+            pix::rgb::SRgba8::new(r, g, b, a)
+        })
+        .collect();
+    let raster = pix::Raster::with_pixels(16, 16, &pixels[..]);
+
+    for (name, threshold) in [("fast_path", 1024), ("adaptive", 0)] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut encoder = png_pong::Encoder::new(Vec::new())
+                    .small_image_threshold(threshold)
+                    .into_step_enc();
+                encoder.still(&raster).expect("Failed to encode icon");
+                encoder.into_inner()
+            })
+        });
+    }
+}
+
+criterion_group!(benches, encode, encode_icon);
 criterion_main!(benches);
@@ -3,7 +3,7 @@ use std::{fs::File, io::BufWriter};
 use pix::{hwb::SHwb8, rgb::SRgb8, Raster};
 use png_pong::Encoder;
 
-fn main() {
+fn main() -> std::io::Result<()> {
     let mut r = Raster::with_clear(256, 256);
     for (y, row) in r.rows_mut(()).enumerate() {
         for (x, p) in row.iter_mut().enumerate() {
@@ -16,8 +16,9 @@ fn main() {
     // Convert to SRgb8 pixel format
     let raster = Raster::<SRgb8>::with_raster(&r);
 
-    // Save PNG File Out
-    let writer = BufWriter::new(File::create("out.png").unwrap());
+    // Save PNG File Out (encoder errors convert into `std::io::Error`)
+    let writer = BufWriter::new(File::create("out.png")?);
     let mut encoder = Encoder::new(writer).into_step_enc();
-    encoder.still(&raster).expect("Failed to write PNG");
+    encoder.still(&raster)?;
+    Ok(())
 }
@@ -1,5 +1,8 @@
-// FIXME: API to get Adam7 image before fully loaded (the reason it exists).
-// And refactor code to depend on that to get the final image.
+//! Adam7 interlacing pass geometry.
+//!
+//! An interlaced PNG stores its pixels in seven passes (numbered `0..7` here),
+//! each a reduced image made of every `dx`th pixel of every `dy`th row,
+//! starting at `(x0, y0)`.
 
 use crate::bitstream::BitstreamReader;
 
@@ -13,52 +16,138 @@ const DX: [u32; 7] = [8, 8, 4, 4, 2, 2, 1];
 /// y delta values
 const DY: [u32; 7] = [8, 8, 8, 4, 4, 2, 2];
 
+/// Number of Adam7 passes.
+pub const PASSES: u8 = 7;
+
+/// Get the origin and stride of an Adam7 pass as `(x0, y0, dx, dy)`.
+///
+/// # Panics
+/// If `pass` is not less than [`PASSES`].
+pub const fn pass_offsets(pass: u8) -> (u32, u32, u32, u32) {
+    let i = pass as usize;
+
+    (IX[i], IY[i], DX[i], DY[i])
+}
+
+/// Get the width and height in pixels of an Adam7 pass of a `width` by
+/// `height` image.  A pass without any pixels is `(0, 0)`.
+///
+/// # Panics
+/// If `pass` is not less than [`PASSES`].
+pub const fn pass_dimensions(pass: u8, width: u32, height: u32) -> (u32, u32) {
+    let (x0, y0, dx, dy) = pass_offsets(pass);
+
+    if width <= x0 || height <= y0 {
+        return (0, 0);
+    }
+
+    ((width - x0 - 1) / dx + 1, (height - y0 - 1) / dy + 1)
+}
+
+/// Iterate over the image coordinates of each pixel in an Adam7 pass, in the
+/// order they're stored within the pass (row by row).
+///
+/// # Panics
+/// If `pass` is not less than [`PASSES`].
+pub fn pass_pixels(pass: u8, width: u32, height: u32) -> PassPixels {
+    let (x0, y0, dx, dy) = pass_offsets(pass);
+    let (width, height) = pass_dimensions(pass, width, height);
+
+    PassPixels {
+        x0,
+        y0,
+        dx,
+        dy,
+        width,
+        height,
+        x: 0,
+        y: 0,
+    }
+}
+
+/// Iterator over image coordinates of an Adam7 pass, from [`pass_pixels()`].
+#[derive(Copy, Clone, Debug)]
+pub struct PassPixels {
+    x0: u32,
+    y0: u32,
+    dx: u32,
+    dy: u32,
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+}
+
+impl Iterator for PassPixels {
+    type Item = (u32, u32);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.y >= self.height {
+            return None;
+        }
+
+        let coord = (self.x0 + self.x * self.dx, self.y0 + self.y * self.dy);
+
+        self.x += 1;
+        if self.x >= self.width {
+            self.x = 0;
+            self.y += 1;
+        }
+
+        Some(coord)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = (self.height - self.y) as usize * self.width as usize
+            - self.x as usize;
+
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for PassPixels {}
+
 type PassW = [u32; 7];
 type PassH = [u32; 7];
-type FilterPassStart = [u32; 8];
-type PaddedPassStart = [u32; 8];
-type PassStart = [u32; 8];
+type FilterPassStart = [usize; 8];
+type PaddedPassStart = [usize; 8];
+type PassStart = [usize; 8];
 
 pub(crate) fn get_pass_values(
     w: u32,
     h: u32,
     bpp: u8,
 ) -> (PassW, PassH, FilterPassStart, PaddedPassStart, PassStart) {
-    let bpp = bpp as u32;
+    let bpp = bpp as usize;
     let mut passw: [u32; 7] = [0; 7];
     let mut passh: [u32; 7] = [0; 7];
-    let mut filter_passstart: [u32; 8] = [0; 8];
-    let mut padded_passstart: [u32; 8] = [0; 8];
-    let mut passstart: [u32; 8] = [0; 8];
+    let mut filter_passstart: [usize; 8] = [0; 8];
+    let mut padded_passstart: [usize; 8] = [0; 8];
+    let mut passstart: [usize; 8] = [0; 8];
 
     // The passstart values have 8 values: the 8th one indicates the byte after
     // the end of the 7th (= last) pass
-    for i in 0..7 {
-        // calculate width and height in pixels of each pass
-        passw[i] = (w + DX[i] - IX[i] - 1) / DX[i];
-        passh[i] = (h + DY[i] - IY[i] - 1) / DY[i];
-        // if passw[i] is 0, it's 0 bytes, not 1 (no filter_type-byte)
-        if passw[i] == 0 {
-            passh[i] = 0; // only padded at end of reduced image
-        }
-        // bits padded if needed to fill full byte at end of each scanline
-        if passh[i] == 0 {
-            passw[i] = 0;
-        };
+    // calculate width and height in pixels of each pass (an empty pass is
+    // 0 bytes, not 1 - no filter_type-byte)
+    for (pass, (pw, ph)) in passw.iter_mut().zip(passh.iter_mut()).enumerate() {
+        (*pw, *ph) = pass_dimensions(pass as u8, w, h);
     }
     filter_passstart[0] = 0;
     padded_passstart[0] = 0;
     passstart[0] = 0;
+    // Offsets are computed in `usize`, as they may not fit in a `u32`
     for i in 0..7 {
+        let (pw, ph) = (passw[i] as usize, passh[i] as usize);
+
         filter_passstart[i + 1] = filter_passstart[i]
-            + if passw[i] != 0 && passh[i] != 0 {
-                passh[i] * (1 + (passw[i] * bpp + 7) / 8)
+            + if pw != 0 && ph != 0 {
+                ph * (1 + (pw * bpp + 7) / 8)
             } else {
                 0
             };
         padded_passstart[i + 1] =
-            padded_passstart[i] + passh[i] * ((passw[i] * bpp + 7) / 8);
-        passstart[i + 1] = passstart[i] + (passh[i] * passw[i] * bpp + 7) / 8;
+            padded_passstart[i] + ph * ((pw * bpp + 7) / 8);
+        passstart[i + 1] = passstart[i] + (ph * pw * bpp + 7) / 8;
     }
     (passw, passh, filter_passstart, padded_passstart, passstart)
 }
@@ -74,19 +163,20 @@ pub(crate) fn get_pass_values(
 /// NOTE: comments about padding bits are only relevant if bpp < 8
 pub(crate) fn deinterlace(out: &mut [u8], inp: &[u8], w: u32, h: u32, bpp: u8) {
     let (passw, passh, _, _, passstart) = get_pass_values(w, h, bpp);
-    let bpp = bpp as u32;
+    let bpp = bpp as usize;
+    let w = w as usize;
     if bpp >= 8 {
         for i in 0..7 {
+            let (x0, y0, dx, dy) = pass_offsets(i as u8);
+            let (x0, y0, dx, dy) =
+                (x0 as usize, y0 as usize, dx as usize, dy as usize);
             let bytewidth = bpp / 8;
-            for y in 0..passh[i] {
-                for x in 0..passw[i] {
-                    let pixelinstart = (passstart[i]
-                        + (y * passw[i] + x) * bytewidth)
-                        as usize;
-                    let bytewidth = bytewidth as usize;
+            for y in 0..passh[i] as usize {
+                for x in 0..passw[i] as usize {
+                    let pixelinstart =
+                        passstart[i] + (y * passw[i] as usize + x) * bytewidth;
                     let pixeloutstart =
-                        ((IY[i] + y * DY[i]) * w + IX[i] + x * DX[i]) as usize
-                            * bytewidth;
+                        ((y0 + y * dy) * w + x0 + x * dx) * bytewidth;
 
                     out[pixeloutstart..(bytewidth + pixeloutstart)]
                         .clone_from_slice(
@@ -97,17 +187,18 @@ pub(crate) fn deinterlace(out: &mut [u8], inp: &[u8], w: u32, h: u32, bpp: u8) {
         }
     } else {
         for i in 0..7 {
-            let ilinebits = bpp * passw[i];
+            let (x0, y0, dx, dy) = pass_offsets(i as u8);
+            let (x0, y0, dx, dy) =
+                (x0 as usize, y0 as usize, dx as usize, dy as usize);
+            let ilinebits = bpp * passw[i] as usize;
             let olinebits = bpp * w;
-            for y in 0..passh[i] {
-                for x in 0..passw[i] {
-                    let mut obp = ((IY[i] + y * DY[i]) * olinebits
-                        + (IX[i] + x * DX[i]) * bpp)
-                        as usize;
+            for y in 0..passh[i] as usize {
+                for x in 0..passw[i] as usize {
+                    let mut obp =
+                        (y0 + y * dy) * olinebits + (x0 + x * dx) * bpp;
                     let mut in_stream = BitstreamReader::with_bitpointer(
                         std::io::Cursor::new(inp),
-                        ((8 * passstart[i]) + (y * ilinebits + x * bpp))
-                            as usize,
+                        (8 * passstart[i]) + (y * ilinebits + x * bpp),
                     )
                     .unwrap();
                     for _ in 0..bpp {
@@ -131,22 +222,33 @@ pub(crate) fn deinterlace(out: &mut [u8], inp: &[u8], w: u32, h: u32, bpp: u8) {
 /// between reduced images.  in has the following size in bits: w * h * bpp.
 /// out is possibly bigger due to padding bits between reduced images
 ///
+/// stride: if bpp >= 8, the number of bytes from the start of one row of in to
+/// the next, if not w * bpp / 8 (such as for a region of a bigger image).
+///
 /// NOTE: comments about padding bits are only relevant if bpp < 8
-pub(crate) fn interlace(out: &mut [u8], inp: &[u8], w: u32, h: u32, bpp: u8) {
+pub(crate) fn interlace(
+    out: &mut [u8],
+    inp: &[u8],
+    w: u32,
+    h: u32,
+    bpp: u8,
+    stride: Option<usize>,
+) {
     let (passw, passh, _, _, passstart) = get_pass_values(w, h, bpp);
     let bpp = bpp as usize;
     if bpp >= 8 {
+        let bytewidth = bpp / 8;
+        let stride = stride.unwrap_or(w as usize * bytewidth);
         for i in 0..7 {
-            let bytewidth = bpp / 8;
+            let (x0, y0, dx, dy) = pass_offsets(i as u8);
+            let (x0, y0, dx, dy) =
+                (x0 as usize, y0 as usize, dx as usize, dy as usize);
             for y in 0..passh[i] as usize {
                 for x in 0..passw[i] as usize {
-                    let pixelinstart = ((IY[i] as usize + y * DY[i] as usize)
-                        * w as usize
-                        + IX[i] as usize
-                        + x * DX[i] as usize)
-                        * bytewidth;
-                    let pixeloutstart = passstart[i] as usize
-                        + (y * passw[i] as usize + x) * bytewidth;
+                    let pixelinstart =
+                        (y0 + y * dy) * stride + (x0 + x * dx) * bytewidth;
+                    let pixeloutstart =
+                        passstart[i] + (y * passw[i] as usize + x) * bytewidth;
                     out[pixeloutstart..(bytewidth + pixeloutstart)]
                         .clone_from_slice(
                             &inp[pixelinstart..(bytewidth + pixelinstart)],
@@ -156,16 +258,18 @@ pub(crate) fn interlace(out: &mut [u8], inp: &[u8], w: u32, h: u32, bpp: u8) {
         }
     } else {
         for i in 0..7 {
+            let (x0, y0, dx, dy) = pass_offsets(i as u8);
+            let (x0, y0, dx, dy) =
+                (x0 as usize, y0 as usize, dx as usize, dy as usize);
             let ilinebits = bpp * passw[i] as usize;
             let olinebits = bpp * w as usize;
             for y in 0..passh[i] as usize {
                 for x in 0..passw[i] as usize {
                     let mut obp =
-                        (8 * passstart[i] as usize) + (y * ilinebits + x * bpp);
+                        (8 * passstart[i]) + (y * ilinebits + x * bpp);
                     let mut in_stream = BitstreamReader::with_bitpointer(
                         std::io::Cursor::new(inp),
-                        (IY[i] as usize + y * DY[i] as usize) * olinebits
-                            + (IX[i] as usize + x * DX[i] as usize) * bpp,
+                        (y0 + y * dy) * olinebits + (x0 + x * dx) * bpp,
                     )
                     .unwrap();
                     for _ in 0..bpp {
@@ -209,3 +313,90 @@ pub(crate) fn set_bit_of_reversed_stream(
 
     *bitpointer += 1;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Pass widths for image widths 1 through 9, by pass.
+    const WIDTHS: [[u32; 9]; 7] = [
+        [1, 1, 1, 1, 1, 1, 1, 1, 2],
+        [0, 0, 0, 0, 1, 1, 1, 1, 1],
+        [1, 1, 1, 1, 2, 2, 2, 2, 3],
+        [0, 0, 1, 1, 1, 1, 2, 2, 2],
+        [1, 1, 2, 2, 3, 3, 4, 4, 5],
+        [0, 1, 1, 2, 2, 3, 3, 4, 4],
+        [1, 2, 3, 4, 5, 6, 7, 8, 9],
+    ];
+    // Pass heights for image heights 1 through 9, by pass.
+    const HEIGHTS: [[u32; 9]; 7] = [
+        [1, 1, 1, 1, 1, 1, 1, 1, 2],
+        [1, 1, 1, 1, 1, 1, 1, 1, 2],
+        [0, 0, 0, 0, 1, 1, 1, 1, 1],
+        [1, 1, 1, 1, 2, 2, 2, 2, 3],
+        [0, 0, 1, 1, 1, 1, 2, 2, 2],
+        [1, 1, 2, 2, 3, 3, 4, 4, 5],
+        [0, 1, 1, 2, 2, 3, 3, 4, 4],
+    ];
+
+    #[test]
+    fn dimensions() {
+        for (pass, (widths, heights)) in
+            WIDTHS.iter().zip(HEIGHTS.iter()).enumerate()
+        {
+            for (w, &pw) in (1..).zip(widths) {
+                for (h, &ph) in (1..).zip(heights) {
+                    let expected =
+                        if pw == 0 || ph == 0 { (0, 0) } else { (pw, ph) };
+
+                    assert_eq!(
+                        pass_dimensions(pass as u8, w, h),
+                        expected,
+                        "pass {pass} of {w}x{h}",
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pixels_cover_image_once() {
+        for w in 1..=9 {
+            for h in 1..=9 {
+                let mut seen = vec![0u8; (w * h) as usize];
+
+                for pass in 0..PASSES {
+                    let (x0, y0, dx, dy) = pass_offsets(pass);
+                    let (pw, ph) = pass_dimensions(pass, w, h);
+                    let pixels = pass_pixels(pass, w, h);
+
+                    assert_eq!(pixels.len(), (pw * ph) as usize);
+                    for (x, y) in pixels {
+                        assert_eq!((x - x0) % dx, 0);
+                        assert_eq!((y - y0) % dy, 0);
+                        seen[(y * w + x) as usize] += 1;
+                    }
+                }
+                assert!(seen.iter().all(|&n| n == 1), "{w}x{h}");
+            }
+        }
+    }
+
+    #[test]
+    fn pass_values_agree() {
+        for w in 1..=9 {
+            for h in 1..=9 {
+                let (passw, passh, ..) = get_pass_values(w, h, 8);
+
+                for pass in 0..PASSES {
+                    let i = usize::from(pass);
+
+                    assert_eq!(
+                        pass_dimensions(pass, w, h),
+                        (passw[i], passh[i]),
+                    );
+                }
+            }
+        }
+    }
+}
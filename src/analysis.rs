@@ -0,0 +1,420 @@
+//! Image analysis on unfiltered scanlines.
+//!
+//! Rows are passed as raw scanlines in the PNG's own sample layout: packed
+//! samples for bit depths below 8 (most significant bits first), and
+//! big-endian samples for a bit depth of 16.
+
+use pix::rgb::Rgb;
+
+use crate::chunk::{ColorType, ImageHeader, Palette, Transparency};
+
+/// Statistics of one channel.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ChannelStats {
+    /// Smallest sample value
+    pub min: u16,
+    /// Largest sample value
+    pub max: u16,
+    /// Mean sample value
+    pub mean: f64,
+    /// Standard deviation of the sample values
+    pub std_dev: f64,
+}
+
+/// Per-channel pixel statistics, as returned by [`compute_stats()`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct PixelStats {
+    /// Statistics for each channel, in the order the channels are stored
+    /// (such as red, green, blue and alpha).  All values are 0 for an empty
+    /// image.
+    pub channels: Vec<ChannelStats>,
+}
+
+/// Compute the statistics of each channel of an image from its rows.
+///
+/// For palette images, this computes the statistics of the palette indices;
+/// use [`compute_palette_stats()`] for the statistics of the colors.
+///
+/// Returns `None` if the header's bit depth isn't valid for its color type.
+pub fn compute_stats(
+    rows: &[&[u8]],
+    header: &ImageHeader,
+) -> Option<PixelStats> {
+    header
+        .color_type
+        .check_png_color_validity(header.bit_depth)
+        .ok()?;
+    let channels = usize::from(header.color_type.sample_count());
+    let mut stats = vec![Accumulator::default(); channels];
+
+    for row in rows {
+        for (i, sample) in samples(row, header)?.enumerate() {
+            stats[i % channels].add(sample);
+        }
+    }
+
+    Some(PixelStats {
+        channels: stats.iter().map(Accumulator::finish).collect(),
+    })
+}
+
+/// Compute the statistics of the red, green, blue and alpha channels of a
+/// palette image from its rows, palette and palette transparency.
+///
+/// Indices outside of the palette count as opaque black.  Returns `None` if
+/// the header's bit depth isn't valid for its color type.
+pub fn compute_palette_stats(
+    rows: &[&[u8]],
+    header: &ImageHeader,
+    palette: &Palette,
+    transparency: Option<&Transparency>,
+) -> Option<PixelStats> {
+    header
+        .color_type
+        .check_png_color_validity(header.bit_depth)
+        .ok()?;
+    let alphas = match transparency {
+        Some(Transparency::Palette(alphas)) => alphas.as_slice(),
+        _ => &[],
+    };
+    let mut stats = [Accumulator::default(); 4];
+
+    for row in rows {
+        for index in samples(row, header)? {
+            let index = usize::from(index);
+            let rgb = palette.palette.get(index).map_or([0; 3], |color| {
+                [
+                    Rgb::red(*color).into(),
+                    Rgb::green(*color).into(),
+                    Rgb::blue(*color).into(),
+                ]
+            });
+            let alpha = alphas.get(index).copied().unwrap_or(255);
+            for (channel, value) in
+                stats.iter_mut().zip(rgb.into_iter().chain([alpha]))
+            {
+                channel.add(value.into());
+            }
+        }
+    }
+
+    Some(PixelStats {
+        channels: stats.iter().map(Accumulator::finish).collect(),
+    })
+}
+
+/// Suggest the most compact color type that keeps every pixel of an 8-bit
+/// RGBA image from its rows.
+///
+/// Opaque images can drop the alpha channel (`Rgb`), images where the red,
+/// green and blue of each pixel are equal can be greyscale (`Grey`, or
+/// `GreyAlpha` if they aren't opaque), and images with at most 256 colors
+/// can use a palette (`Palette`, with transparency in a "tRNS" chunk).  The
+/// types are ranked by their bytes per pixel, with `Grey` before `Palette`
+/// as it needs no palette.  `hint` is returned instead if it's lossless and
+/// as compact as the best type, and for an empty image.
+pub fn auto_detect_color_type(rows: &[&[u8]], hint: ColorType) -> ColorType {
+    let (mut opaque, mut grey) = (true, true);
+    let mut colors = std::collections::HashSet::new();
+    let mut empty = true;
+
+    for pixel in rows.iter().flat_map(|row| row.chunks_exact(4)) {
+        empty = false;
+        opaque &= pixel[3] == u8::MAX;
+        grey &= pixel[0] == pixel[1] && pixel[1] == pixel[2];
+        // Stop counting once there are too many for a palette
+        if colors.len() <= 256 {
+            colors.insert([pixel[0], pixel[1], pixel[2], pixel[3]]);
+        }
+    }
+    if empty {
+        return hint;
+    }
+
+    // Bytes per pixel of each lossless color type, most compact first
+    let lossless = [
+        (ColorType::Grey, 1, grey && opaque),
+        (ColorType::Palette, 1, colors.len() <= 256),
+        (ColorType::GreyAlpha, 2, grey),
+        (ColorType::Rgb, 3, opaque),
+        (ColorType::Rgba, 4, true),
+    ];
+    let mut lossless = lossless.iter().filter(|(_, _, lossless)| *lossless);
+    let &(best, bytes, _) = lossless.next().unwrap();
+    let hinted = lossless
+        .take_while(|(_, other, _)| *other == bytes)
+        .any(|(color_type, _, _)| *color_type == hint);
+
+    if hinted {
+        hint
+    } else {
+        best
+    }
+}
+
+/// Score how complex an image is from its rows, between 0.0 for a single
+/// color and 1.0 for pixels that are all equally common.
+///
+/// The score is the entropy of the distribution of pixel values, relative to
+/// the most entropy possible for the number of pixels and the bits per pixel.
+/// Photographs tend to score high and compress poorly, while graphics and
+/// other synthetic images score low.
+///
+/// Returns `None` if the header's bit depth isn't valid for its color type.
+pub fn image_complexity(rows: &[&[u8]], header: &ImageHeader) -> Option<f64> {
+    let bits = header.color_type.bits_per_pixel(header.bit_depth)?;
+    let channels = usize::from(header.color_type.sample_count());
+    let mut counts = std::collections::HashMap::<u64, u64>::new();
+    let mut pixels = 0;
+
+    for row in rows {
+        let samples: Vec<u16> = samples(row, header)?.collect();
+        for pixel in samples.chunks_exact(channels) {
+            let value = pixel
+                .iter()
+                .fold(0, |value, &sample| value << 16 | u64::from(sample));
+            *counts.entry(value).or_default() += 1;
+            pixels += 1;
+        }
+    }
+
+    let max_entropy = (pixels as f64).log2().min(bits.into());
+    if max_entropy <= 0.0 {
+        return Some(0.0);
+    }
+    let entropy: f64 = counts
+        .values()
+        .map(|&count| {
+            let p = count as f64 / pixels as f64;
+            -p * p.log2()
+        })
+        .sum();
+
+    Some((entropy / max_entropy).clamp(0.0, 1.0))
+}
+
+/// Iterate over the samples of a row, or `None` if the header's bit depth
+/// isn't valid for its color type.
+pub(crate) fn samples<'a>(
+    row: &'a [u8],
+    header: &ImageHeader,
+) -> Option<impl Iterator<Item = u16> + 'a> {
+    header
+        .color_type
+        .check_png_color_validity(header.bit_depth)
+        .ok()?;
+    let bit_depth = usize::from(header.bit_depth);
+    let count =
+        header.width as usize * usize::from(header.color_type.sample_count());
+    let count = count.min(row.len() * 8 / bit_depth);
+
+    Some((0..count).map(move |i| match bit_depth {
+        16 => u16::from_be_bytes([row[i * 2], row[i * 2 + 1]]),
+        8 => row[i].into(),
+        _ => {
+            let bit = i * bit_depth;
+            let shift = 8 - bit_depth - bit % 8;
+
+            u16::from(row[bit / 8] >> shift & ((1 << bit_depth) - 1))
+        }
+    }))
+}
+
+/// Running statistics of one channel.
+#[derive(Copy, Clone, Default)]
+struct Accumulator {
+    count: u64,
+    sum: f64,
+    sum_squares: f64,
+    min: u16,
+    max: u16,
+}
+
+impl Accumulator {
+    fn add(&mut self, sample: u16) {
+        if self.count == 0 || sample < self.min {
+            self.min = sample;
+        }
+        self.max = self.max.max(sample);
+        self.count += 1;
+        self.sum += f64::from(sample);
+        self.sum_squares += f64::from(sample) * f64::from(sample);
+    }
+
+    fn finish(&self) -> ChannelStats {
+        let (mean, std_dev) = if self.count == 0 {
+            (0.0, 0.0)
+        } else {
+            let count = self.count as f64;
+            let mean = self.sum / count;
+            let variance = (self.sum_squares / count - mean * mean).max(0.0);
+
+            (mean, variance.sqrt())
+        };
+
+        ChannelStats {
+            min: self.min,
+            max: self.max,
+            mean,
+            std_dev,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pix::rgb::SRgb8;
+
+    use super::*;
+
+    fn header(color_type: ColorType, bit_depth: u8, width: u32) -> ImageHeader {
+        ImageHeader {
+            width,
+            height: 2,
+            color_type,
+            bit_depth,
+            interlace: false,
+        }
+    }
+
+    #[test]
+    fn rgb8() {
+        let rows: &[&[u8]] =
+            &[&[0, 10, 255, 4, 10, 255], &[2, 10, 0, 6, 10, 0]];
+        let stats = compute_stats(rows, &header(ColorType::Rgb, 8, 2)).unwrap();
+
+        assert_eq!(stats.channels.len(), 3);
+        let red = stats.channels[0];
+        assert_eq!((red.min, red.max), (0, 6));
+        assert_eq!(red.mean, 3.0);
+        assert!((red.std_dev - 5.0f64.sqrt()).abs() < 1e-9);
+        let green = stats.channels[1];
+        assert_eq!((green.min, green.max, green.mean), (10, 10, 10.0));
+        assert_eq!(green.std_dev, 0.0);
+        let blue = stats.channels[2];
+        assert_eq!((blue.min, blue.max, blue.mean), (0, 255, 127.5));
+    }
+
+    #[test]
+    fn grey16_and_packed() {
+        let rows: &[&[u8]] = &[&[0x01, 0x00, 0xFF, 0xFF], &[0x00, 0x01, 0, 0]];
+        let stats =
+            compute_stats(rows, &header(ColorType::Grey, 16, 2)).unwrap();
+        assert_eq!((stats.channels[0].min, stats.channels[0].max), (0, 65535));
+
+        // 3 pixels of 2 bits, with padding bits at the end
+        let rows: &[&[u8]] = &[&[0b11_01_10_11], &[0b00_00_00_11]];
+        let stats =
+            compute_stats(rows, &header(ColorType::Grey, 2, 3)).unwrap();
+        let grey = stats.channels[0];
+        assert_eq!((grey.min, grey.max, grey.mean), (0, 3, 1.0));
+    }
+
+    #[test]
+    fn palette() {
+        let palette = Palette {
+            palette: vec![SRgb8::new(255, 0, 0), SRgb8::new(0, 0, 255)],
+        };
+        let transparency = Transparency::Palette(vec![0]);
+        let rows: &[&[u8]] = &[&[0, 1], &[1, 1]];
+        let header = header(ColorType::Palette, 8, 2);
+
+        let indices = compute_stats(rows, &header).unwrap();
+        assert_eq!(indices.channels[0].mean, 0.75);
+
+        let stats =
+            compute_palette_stats(rows, &header, &palette, Some(&transparency))
+                .unwrap();
+        assert_eq!(stats.channels.len(), 4);
+        assert_eq!(stats.channels[0].mean, 63.75);
+        assert_eq!(stats.channels[2].mean, 191.25);
+        assert_eq!((stats.channels[3].min, stats.channels[3].max), (0, 255));
+    }
+
+    #[test]
+    fn empty() {
+        let stats =
+            compute_stats(&[], &header(ColorType::GreyAlpha, 8, 2)).unwrap();
+
+        assert_eq!(stats.channels.len(), 2);
+        assert_eq!(stats.channels[0].mean, 0.0);
+    }
+
+    #[test]
+    fn invalid_bit_depth() {
+        let rows: &[&[u8]] = &[&[0xFF; 4], &[0; 4]];
+        let palette = Palette {
+            palette: vec![SRgb8::new(0, 0, 0)],
+        };
+
+        for bit_depth in [0, 3, 5, 6, 17] {
+            let grey = header(ColorType::Grey, bit_depth, 2);
+            assert_eq!(compute_stats(rows, &grey), None);
+            assert_eq!(compute_stats(&[], &grey), None);
+            let indexed = header(ColorType::Palette, bit_depth, 2);
+            assert_eq!(
+                compute_palette_stats(rows, &indexed, &palette, None),
+                None
+            );
+        }
+        let rgb = header(ColorType::Rgb, 4, 2);
+        assert_eq!(compute_stats(rows, &rgb), None);
+    }
+
+    #[test]
+    fn color_type() {
+        let detect = |pixels: &[[u8; 4]], hint| {
+            let row: Vec<u8> = pixels.iter().flatten().copied().collect();
+            auto_detect_color_type(&[&row], hint)
+        };
+
+        let opaque_grey = [[7, 7, 7, 255], [9, 9, 9, 255]];
+        assert_eq!(detect(&opaque_grey, ColorType::Rgba), ColorType::Grey);
+        assert_eq!(
+            detect(&opaque_grey, ColorType::Palette),
+            ColorType::Palette
+        );
+        let grey = [[7, 7, 7, 255], [9, 9, 9, 0]];
+        assert_eq!(detect(&grey, ColorType::Rgba), ColorType::Palette);
+        let opaque = [[1, 2, 3, 255], [4, 5, 6, 255]];
+        assert_eq!(detect(&opaque, ColorType::Rgba), ColorType::Palette);
+        assert_eq!(detect(&[], ColorType::Rgb), ColorType::Rgb);
+
+        // 257 colors
+        let many: Vec<[u8; 4]> = (0..=256u16)
+            .map(|i| [(i >> 8) as u8, i as u8, 0, 255])
+            .collect();
+        assert_eq!(detect(&many, ColorType::Rgba), ColorType::Rgb);
+        let many: Vec<[u8; 4]> =
+            many.iter().map(|&[r, g, b, _]| [r, g, b, 0]).collect();
+        assert_eq!(detect(&many, ColorType::Rgba), ColorType::Rgba);
+        let many: Vec<[u8; 4]> = (0..=256u16)
+            .map(|i| [i as u8, i as u8, i as u8, (i >> 8) as u8])
+            .collect();
+        assert_eq!(detect(&many, ColorType::Rgba), ColorType::GreyAlpha);
+    }
+
+    #[test]
+    fn complexity() {
+        let grey = header(ColorType::Grey, 8, 4);
+        let rows: [&[u8]; 2] = [&[9; 4], &[9; 4]];
+        assert_eq!(image_complexity(&rows, &grey), Some(0.0));
+        let rows: [&[u8]; 2] = [&[0, 1, 2, 3], &[4, 5, 6, 7]];
+        assert_eq!(image_complexity(&rows, &grey), Some(1.0));
+        let rows: [&[u8]; 2] = [&[0, 0, 0, 0], &[0, 0, 1, 1]];
+        let score = image_complexity(&rows, &grey).unwrap();
+        assert!(score > 0.0 && score < 1.0);
+        assert_eq!(image_complexity(&[], &grey), Some(0.0));
+
+        // 1-bit pixels can't have more than 1 bit of entropy
+        let rows: [&[u8]; 2] = [&[0b1010_0000], &[0b0101_0000]];
+        let score = image_complexity(&rows, &header(ColorType::Grey, 1, 4));
+        assert_eq!(score, Some(1.0));
+
+        // Invalid bit depths
+        for bit_depth in [0, 3, 17] {
+            let header = header(ColorType::Grey, bit_depth, 4);
+            assert_eq!(image_complexity(&[], &header), None);
+        }
+    }
+}
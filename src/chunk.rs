@@ -68,6 +68,7 @@
 use crate::{
     decode::{Error as DecoderError, Result as DecoderResult},
     encode::{Error as EncoderError, Result as EncoderResult},
+    Encoder,
 };
 
 mod bkgd;
@@ -97,7 +98,7 @@ pub use self::{
     // Optional
     phys::Physical,
     // Required
-    plte::Palette,
+    plte::{Palette, PaletteFullError},
     // Optional
     text::Text,
     // Optional
@@ -111,7 +112,7 @@ pub use self::{
 };
 
 /// A chunk within a PNG file.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum Chunk {
     /// Required: Image Header
     ImageHeader(ImageHeader),
@@ -141,7 +142,71 @@ pub enum Chunk {
     Unknown(Unknown),
 }
 
+/// Where a chunk is in a PNG file, relative to the image data.
+///
+/// Some tools care whether metadata comes before the image data (so it can
+/// be read while streaming) or after it (such as metadata appended later).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Position {
+    /// Before the first IDAT chunk
+    BeforeImage,
+    /// After the first IDAT chunk
+    AfterImage,
+}
+
 impl Chunk {
+    /// Get the keyword of a text chunk ("tEXt", "zTXt" or "iTXt"), or `None`
+    /// if this chunk doesn't carry text.
+    pub fn text_key(&self) -> Option<&str> {
+        match self {
+            Chunk::Text(text) => Some(&text.key),
+            Chunk::CompressedText(text) => Some(&text.key),
+            Chunk::InternationalText(text) => Some(&text.key),
+            _ => None,
+        }
+    }
+
+    /// Get the image header, if this is an IHDR chunk.
+    pub fn as_image_header(&self) -> Option<&ImageHeader> {
+        match self {
+            Chunk::ImageHeader(header) => Some(header),
+            _ => None,
+        }
+    }
+
+    /// Get the text, if this is a tEXt chunk.
+    pub fn as_text(&self) -> Option<&Text> {
+        match self {
+            Chunk::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Get the compressed text, if this is a zTXt chunk.
+    pub fn as_compressed_text(&self) -> Option<&CompressedText> {
+        match self {
+            Chunk::CompressedText(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Get the international text, if this is an iTXt chunk.
+    pub fn as_international_text(&self) -> Option<&InternationalText> {
+        match self {
+            Chunk::InternationalText(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Serialize the chunk as it's written in a PNG file: length, name, data
+    /// and CRC.
+    pub fn to_bytes(&self) -> EncoderResult<Vec<u8>> {
+        let mut encoder = Encoder::new(Vec::new()).into_chunk_enc();
+
+        encoder.write(self)?;
+        encoder.into_inner()
+    }
+
     pub(super) fn is_idat(&self) -> bool {
         matches!(self, Chunk::ImageData(_))
     }
@@ -150,3 +215,104 @@ impl Chunk {
         matches!(self, Chunk::ImageEnd(_))
     }
 }
+
+/// Read a null-terminated keyword of 1 to `max_len` bytes off the front of
+/// `buffer`, advancing it past the null terminator.
+///
+/// At most `max_len + 1` bytes are scanned for the null terminator, so a
+/// keyword that's too long is reported as a `KeySize` of `max_len + 1`, with
+/// the start of the keyword for context.
+pub(crate) fn read_keyword<'a>(
+    buffer: &mut &'a [u8],
+    max_len: usize,
+) -> DecoderResult<&'a [u8]> {
+    let field = &buffer[..buffer.len().min(max_len + 1)];
+    let key_size = |size: usize| {
+        let mut key = [0; 10];
+        let start = &field[..size.min(10)];
+        key[..start.len()].copy_from_slice(start);
+        DecoderError::KeySize { size, key }
+    };
+    let Some(len) = field.iter().position(|&byte| byte == 0) else {
+        return Err(if field.len() > max_len {
+            key_size(field.len())
+        } else {
+            DecoderError::NulTerm
+        });
+    };
+    if len == 0 {
+        return Err(key_size(0));
+    }
+
+    let (keyword, rest) = buffer.split_at(len);
+    *buffer = &rest[1..];
+    Ok(keyword)
+}
+
+/// Check a text chunk keyword: 1 to 79 printable Latin-1 characters, without
+/// leading, trailing or consecutive spaces.
+fn check_keyword(key: &str) -> EncoderResult<()> {
+    let len = key.chars().count();
+    if !(1..=79).contains(&len) {
+        return Err(EncoderError::KeySize(len));
+    }
+    if !printable_keyword(key) {
+        return Err(EncoderError::InvalidKeyword(key.to_string()));
+    }
+    Ok(())
+}
+
+/// Check that a text chunk keyword is printable Latin-1 characters, without
+/// leading, trailing or consecutive spaces.
+fn printable_keyword(key: &str) -> bool {
+    let printable = key
+        .chars()
+        .all(|c| matches!(c, ' '..='~' | '\u{A1}'..='\u{FF}'));
+
+    printable
+        && !key.starts_with(' ')
+        && !key.ends_with(' ')
+        && !key.contains("  ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keyword() {
+        let mut buffer = &b"Title\0value"[..];
+
+        assert_eq!(read_keyword(&mut buffer, 79).unwrap(), b"Title");
+        assert_eq!(buffer, b"value");
+    }
+
+    #[test]
+    fn keyword_unterminated() {
+        let mut buffer = &b"Title"[..];
+
+        assert!(matches!(
+            read_keyword(&mut buffer, 79),
+            Err(DecoderError::NulTerm)
+        ));
+    }
+
+    #[test]
+    fn keyword_size() {
+        // Too long, with or without a null terminator after the limit
+        for buffer in [vec![b'k'; 100], [&[b'k'; 80][..], &[0]].concat()] {
+            assert!(matches!(
+                read_keyword(&mut &buffer[..], 79),
+                Err(DecoderError::KeySize { size: 80, key })
+                    if key == [b'k'; 10]
+            ));
+        }
+
+        let mut buffer = &b"\0value"[..];
+
+        assert!(matches!(
+            read_keyword(&mut buffer, 79),
+            Err(DecoderError::KeySize { size: 0, key }) if key == [0; 10]
+        ));
+    }
+}
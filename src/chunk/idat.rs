@@ -6,7 +6,7 @@ use crate::{
 };
 
 /// Image Data Chunk Data (IDAT)
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct ImageData {
     /// Part of a compressed ZLIB stream
     pub data: Vec<u8>,
@@ -23,15 +23,21 @@ impl ImageData {
     pub(crate) fn write<W: Write>(
         &self,
         enc: &mut Enc<W>,
+    ) -> Result<(), EncoderError> {
+        self.write_with_level(enc, enc.level())
+    }
+
+    /// Write, compressing at `level` rather than the encoder's level.
+    pub(crate) fn write_with_level<W: Write>(
+        &self,
+        enc: &mut Enc<W>,
+        level: u8,
     ) -> Result<(), EncoderError> {
         // FIXME: Should already be compressed.
         let mut zlib = Vec::new();
-        zlib::compress(&mut zlib, self.data.as_slice(), enc.level());
+        zlib::compress(&mut zlib, self.data.as_slice(), level);
 
-        //
-        enc.prepare(zlib.len(), consts::IMAGE_DATA)?;
-        enc.raw(&zlib)?;
-        enc.write_crc()
+        enc.write_chunk(consts::IMAGE_DATA, &zlib)
     }
 
     /// Construct from raw uncompressed image data.
@@ -1,22 +1,34 @@
-use std::io::Write;
+use std::io::{Read, Write};
 
-use super::{Chunk, EncoderError};
-use crate::{consts, encoder::Enc};
+use super::{Chunk, DecoderResult, EncoderError};
+use crate::{consts, decode::ChunkWarning, decoder::Parser, encoder::Enc};
 
 /// Image End Chunk Data (IEND)
 #[derive(Copy, Clone, Debug)]
 pub struct ImageEnd;
 
 impl ImageEnd {
-    pub(crate) fn parse() -> Chunk {
-        Chunk::ImageEnd(ImageEnd)
+    pub(crate) fn parse<R: Read>(
+        parse: &mut Parser<R>,
+    ) -> DecoderResult<Chunk> {
+        // Some broken encoders write a non-zero length; discard the extra
+        // bytes rather than failing
+        if parse.len() != 0 {
+            let length = parse.len() as u32;
+            parse.warn(ChunkWarning::ImageEndLength(length));
+            parse.skip()?;
+            parse.check_crc(&consts::IMAGE_END)?;
+            return Ok(Chunk::ImageEnd(ImageEnd));
+        }
+        parse.bytes::<0>()?;
+
+        Ok(Chunk::ImageEnd(ImageEnd))
     }
 
     pub(crate) fn write<W: Write>(
         &self,
         enc: &mut Enc<W>,
     ) -> Result<(), EncoderError> {
-        enc.prepare(0, consts::IMAGE_END)?;
-        enc.write_crc()
+        enc.write_chunk(consts::IMAGE_END, &[])
     }
 }
@@ -6,8 +6,8 @@ use std::{
 use parsenic::{be::Read as _, Read as _, Reader};
 
 use crate::{
-    chunk::Chunk, consts, decode::Error as DecoderError, decoder::Parser,
-    encode::Error as EncoderError, encoder::Enc,
+    adam7, chunk::Chunk, consts, decode::Error as DecoderError,
+    decoder::Parser, encode::Error as EncoderError, encoder::Enc,
 };
 
 /// Standard PNG color types.
@@ -27,8 +27,9 @@ pub enum ColorType {
 }
 
 impl ColorType {
-    /// channels * bytes per channel = bytes per pixel
-    pub(crate) fn channels(self) -> u8 {
+    /// Get the number of samples (channels) in each pixel.  Palette images
+    /// have one sample, the palette index.
+    pub fn sample_count(self) -> u8 {
         match self {
             ColorType::Grey | ColorType::Palette => 1,
             ColorType::GreyAlpha => 2,
@@ -37,21 +38,29 @@ impl ColorType {
         }
     }
 
+    /// Check if the color type has an alpha channel.
+    pub fn has_alpha(self) -> bool {
+        matches!(self, ColorType::GreyAlpha | ColorType::Rgba)
+    }
+
+    /// Check if the color type stores palette indices.
+    pub fn is_indexed(self) -> bool {
+        matches!(self, ColorType::Palette)
+    }
+
+    /// Get the total amount of bits per pixel at a bit depth, or `None` if
+    /// the bit depth isn't allowed for this color type.
+    pub fn bits_per_pixel(self, bit_depth: u8) -> Option<u8> {
+        self.check_png_color_validity(bit_depth).ok()?;
+        /* bits per pixel is amount of channels * bits per channel */
+        Some(self.sample_count() * bit_depth)
+    }
+
     /// get the total amount of bits per pixel, based on colortype and bitdepth
     /// in the struct
     pub(crate) fn bpp(self, bit_depth: u8) -> u8 {
-        assert!((1..=16).contains(&bit_depth));
-        /* bits per pixel is amount of channels * bits per channel */
-        let ch = self.channels();
-        ch * if ch > 1 {
-            if bit_depth == 8 {
-                8
-            } else {
-                16
-            }
-        } else {
-            bit_depth
-        }
+        self.bits_per_pixel(bit_depth)
+            .expect("invalid color type / bit depth combination")
     }
 
     /// Error if invalid color type / bit depth combination for PNG.
@@ -81,7 +90,7 @@ impl ColorType {
 }
 
 /// Image Header Chunk Data (IHDR)
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct ImageHeader {
     /// Width of the image
     pub width: u32,
@@ -122,27 +131,9 @@ impl ImageHeader {
         let height = NonZeroU32::new(reader.u32()?)
             .ok_or(DecoderError::ImageDimensions)?
             .get();
-        let bit_depth = {
-            let bit_depth = reader.u8()?;
-
-            (1..=16)
-                .contains(&bit_depth)
-                .then_some(bit_depth)
-                .ok_or(DecoderError::BitDepth(bit_depth))?
-        };
-        let color_type = {
-            let color_type = match reader.u8()? {
-                0 => ColorType::Grey,
-                2 => ColorType::Rgb,
-                3 => ColorType::Palette,
-                4 => ColorType::GreyAlpha,
-                6 => ColorType::Rgba,
-                c => return Err(DecoderError::ColorType(c)),
-            };
-
-            color_type.check_png_color_validity(bit_depth)?;
-            color_type
-        };
+        let bit_depth = reader.u8()?;
+        let (color_type, bit_depth) =
+            Self::for_png_color_type(reader.u8()?, bit_depth)?;
         let _compression_method = {
             let compression_method = reader.u8()?;
 
@@ -175,18 +166,416 @@ impl ImageHeader {
         }))
     }
 
+    /// Convert the color type and bit depth bytes of an IHDR chunk, checking
+    /// that they're a valid combination.
+    ///
+    /// Returns `BitDepth` for a bit depth outside of 1 to 16, `ColorType` for
+    /// an unknown color type, and `ColorMode` if the bit depth isn't allowed
+    /// for the color type (checked in that order, as when decoding).
+    pub fn for_png_color_type(
+        color_type: u8,
+        bit_depth: u8,
+    ) -> Result<(ColorType, u8), DecoderError> {
+        if !(1..=16).contains(&bit_depth) {
+            return Err(DecoderError::BitDepth(bit_depth));
+        }
+        let color_type = match color_type {
+            0 => ColorType::Grey,
+            2 => ColorType::Rgb,
+            3 => ColorType::Palette,
+            4 => ColorType::GreyAlpha,
+            6 => ColorType::Rgba,
+            c => return Err(DecoderError::ColorType(c)),
+        };
+
+        color_type.check_png_color_validity(bit_depth)?;
+        Ok((color_type, bit_depth))
+    }
+
     /// get the total amount of bits per pixel, based on colortype and bitdepth
     /// in the struct
     pub(crate) fn bpp(&self) -> u8 {
         self.color_type.bpp(self.bit_depth) /* 4 or 6 */
     }
 
-    /// Returns the byte size of a raw image buffer with given width, height and
-    /// color mode
-    pub(crate) fn raw_size(&self) -> usize {
-        /* will not overflow for any color type if roughly w * h < 268435455 */
-        let bpp = self.bpp() as usize;
-        let n = self.width as usize * self.height as usize;
-        ((n / 8) * bpp) + ((n & 7) * bpp + 7) / 8
+    /// Returns `true` if the color type has no alpha channel (`Grey`, `Rgb`
+    /// and `Palette`).  A palette image may still be made transparent by a
+    /// "tRNS" chunk, see [`is_certainly_opaque()`](Self::is_certainly_opaque).
+    pub fn is_opaque(&self) -> bool {
+        matches!(
+            self.color_type,
+            ColorType::Grey | ColorType::Rgb | ColorType::Palette
+        )
+    }
+
+    /// Conservative version of [`is_opaque()`](Self::is_opaque), which also
+    /// returns `false` for `Palette` since it may have a "tRNS" chunk.
+    pub fn is_certainly_opaque(&self) -> bool {
+        matches!(self.color_type, ColorType::Grey | ColorType::Rgb)
+    }
+
+    /// Returns the number of bytes in one scanline (row) of the image, with
+    /// pixels smaller than a byte packed and the last byte padded, or `None`
+    /// if it doesn't fit in a `usize` or the bit depth isn't allowed for the
+    /// color type.
+    pub fn scanline_bytes(&self) -> Option<usize> {
+        let bpp = self.color_type.bits_per_pixel(self.bit_depth)?;
+        let bits = (self.width as usize).checked_mul(bpp.into())?;
+
+        Some(bits / 8 + usize::from(bits % 8 != 0))
+    }
+
+    /// Returns the byte size of the raw (decoded) image with pixels smaller
+    /// than a byte packed, without any padding between rows, or `None` if it
+    /// doesn't fit in a `usize` or the bit depth isn't allowed for the color
+    /// type.
+    pub fn raw_size(&self) -> Option<usize> {
+        let bpp = self.color_type.bits_per_pixel(self.bit_depth)? as usize;
+        let n = (self.width as usize).checked_mul(self.height as usize)?;
+
+        (n / 8)
+            .checked_mul(bpp)?
+            .checked_add(((n & 7) * bpp + 7) / 8)
+    }
+
+    /// Returns the byte size of the decompressed image data in the IDAT
+    /// chunks: each (padded) scanline plus one filter type byte per row, of
+    /// each pass if interlaced.  Returns `None` if it doesn't fit in a
+    /// `usize` or the bit depth isn't allowed for the color type.
+    pub fn idat_raw_size(&self) -> Option<usize> {
+        self.color_type.bits_per_pixel(self.bit_depth)?;
+        if !self.interlace {
+            return self
+                .scanline_bytes()?
+                .checked_add(1)?
+                .checked_mul(self.height as usize);
+        }
+
+        (0..adam7::PASSES)
+            .filter_map(|pass| self.pass_header(pass))
+            .try_fold(0usize, |size, pass| {
+                size.checked_add(pass.idat_raw_size()?)
+            })
+    }
+
+    /// Returns the header of the reduced image for one of the Adam7 passes
+    /// (`0..7`), or `None` if the pass is out of range or has no pixels.  The
+    /// returned header isn't interlaced.
+    pub fn pass_header(&self, pass: u8) -> Option<ImageHeader> {
+        if pass >= adam7::PASSES {
+            return None;
+        }
+
+        let (width, height) =
+            adam7::pass_dimensions(pass, self.width, self.height);
+
+        (width != 0).then_some(ImageHeader {
+            width,
+            height,
+            interlace: false,
+            ..*self
+        })
+    }
+}
+
+/// Non-interlaced header from the width, height and color type, with a bit
+/// depth of 8 (valid for every color type).
+///
+/// ```rust
+/// use png_pong::chunk::{ColorType, ImageHeader};
+///
+/// let header = ImageHeader::from((640, 480, ColorType::Rgba));
+/// assert_eq!(header.bit_depth, 8);
+/// assert!(!header.interlace);
+/// ```
+impl From<(u32, u32, ColorType)> for ImageHeader {
+    fn from((width, height, color_type): (u32, u32, ColorType)) -> Self {
+        Self {
+            width,
+            height,
+            color_type,
+            bit_depth: 8,
+            interlace: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every valid color type / bit depth combination with its bits per pixel,
+    // as defined in table 11.1 of the PNG specification.
+    const VALID: [(ColorType, u8, u8); 15] = [
+        (ColorType::Grey, 1, 1),
+        (ColorType::Grey, 2, 2),
+        (ColorType::Grey, 4, 4),
+        (ColorType::Grey, 8, 8),
+        (ColorType::Grey, 16, 16),
+        (ColorType::Rgb, 8, 24),
+        (ColorType::Rgb, 16, 48),
+        (ColorType::Palette, 1, 1),
+        (ColorType::Palette, 2, 2),
+        (ColorType::Palette, 4, 4),
+        (ColorType::Palette, 8, 8),
+        (ColorType::GreyAlpha, 8, 16),
+        (ColorType::GreyAlpha, 16, 32),
+        (ColorType::Rgba, 8, 32),
+        (ColorType::Rgba, 16, 64),
+    ];
+
+    #[test]
+    fn bits_per_pixel() {
+        let color_types = [
+            ColorType::Grey,
+            ColorType::Rgb,
+            ColorType::Palette,
+            ColorType::GreyAlpha,
+            ColorType::Rgba,
+        ];
+
+        for color_type in color_types {
+            for bit_depth in 0..=17 {
+                let expected = VALID
+                    .iter()
+                    .find(|valid| valid.0 == color_type && valid.1 == bit_depth)
+                    .map(|valid| valid.2);
+
+                assert_eq!(
+                    color_type.bits_per_pixel(bit_depth),
+                    expected,
+                    "{color_type:?} at bit depth {bit_depth}",
+                );
+                assert_eq!(
+                    color_type.check_png_color_validity(bit_depth).is_ok(),
+                    expected.is_some(),
+                );
+                assert_eq!(
+                    ImageHeader::for_png_color_type(
+                        color_type as u8,
+                        bit_depth
+                    )
+                    .ok(),
+                    expected.map(|_| (color_type, bit_depth)),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn png_color_type_errors() {
+        assert!(matches!(
+            ImageHeader::for_png_color_type(1, 8),
+            Err(DecoderError::ColorType(1))
+        ));
+        assert!(matches!(
+            ImageHeader::for_png_color_type(1, 0),
+            Err(DecoderError::BitDepth(0))
+        ));
+        assert!(matches!(
+            ImageHeader::for_png_color_type(2, 4),
+            Err(DecoderError::ColorMode(ColorType::Rgb, 4))
+        ));
+    }
+
+    #[test]
+    fn color_type_helpers() {
+        use ColorType::*;
+
+        let expected = [
+            (Grey, 1, false, false),
+            (Rgb, 3, false, false),
+            (Palette, 1, false, true),
+            (GreyAlpha, 2, true, false),
+            (Rgba, 4, true, false),
+        ];
+
+        for (color_type, samples, alpha, indexed) in expected {
+            assert_eq!(color_type.sample_count(), samples);
+            assert_eq!(color_type.has_alpha(), alpha);
+            assert_eq!(color_type.is_indexed(), indexed);
+        }
+    }
+
+    #[test]
+    fn bpp() {
+        for (color_type, bit_depth, bpp) in VALID {
+            assert!(color_type.check_png_color_validity(bit_depth).is_ok());
+            assert_eq!(
+                color_type.bpp(bit_depth),
+                bpp,
+                "{color_type:?} at bit depth {bit_depth}",
+            );
+        }
+    }
+
+    fn header(
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+        bit_depth: u8,
+    ) -> ImageHeader {
+        ImageHeader {
+            width,
+            height,
+            color_type,
+            bit_depth,
+            interlace: false,
+        }
+    }
+
+    #[test]
+    fn raw_size_single_pixel() {
+        for (color_type, bit_depth, bpp) in VALID {
+            let size = usize::from(bpp + 7) / 8;
+
+            assert_eq!(
+                header(1, 1, color_type, bit_depth).raw_size(),
+                Some(size),
+                "{color_type:?} at bit depth {bit_depth}",
+            );
+        }
+    }
+
+    #[test]
+    fn raw_size_packed_bits() {
+        // 8 pixels fit exactly in one byte
+        assert_eq!(header(8, 1, ColorType::Grey, 1).raw_size(), Some(1));
+        // The 9th pixel takes one bit of an extra byte
+        assert_eq!(header(9, 1, ColorType::Grey, 1).raw_size(), Some(2));
+        // Rows aren't padded to a byte boundary
+        assert_eq!(header(9, 8, ColorType::Grey, 1).raw_size(), Some(9));
+    }
+
+    #[test]
+    fn raw_size_limit() {
+        // 16383 × 16384 = 268 419 072, just under the 268 435 455 limit
+        let (width, height) = (16383, 16384);
+        let pixels = 268_419_072;
+
+        assert_eq!(
+            header(width, height, ColorType::Rgba, 16).raw_size(),
+            Some(pixels * 8),
+        );
+        assert_eq!(
+            header(width, height, ColorType::Rgb, 8).raw_size(),
+            Some(pixels * 3),
+        );
+        assert_eq!(
+            header(width, height, ColorType::Grey, 1).raw_size(),
+            Some(pixels / 8),
+        );
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn size_over_4_gib() {
+        let mut header = header(40_000, 40_000, ColorType::Rgba, 16);
+        let pixels = 40_000 * 40_000;
+
+        assert_eq!(header.scanline_bytes(), Some(320_000));
+        assert_eq!(header.raw_size(), Some(pixels * 8));
+        assert_eq!(header.idat_raw_size(), Some(40_000 * 320_001));
+
+        // One filter type byte for each row of each pass
+        let rows = 5_000 + 5_000 + 5_000 + 10_000 + 10_000 + 20_000 + 20_000;
+        header.interlace = true;
+        assert_eq!(header.idat_raw_size(), Some(pixels * 8 + rows));
+
+        let (_, _, filter_passstart, padded_passstart, passstart) =
+            adam7::get_pass_values(40_000, 40_000, 64);
+        assert_eq!(filter_passstart[7], pixels * 8 + rows);
+        assert_eq!(padded_passstart[7], pixels * 8);
+        assert_eq!(passstart[7], pixels * 8);
+    }
+
+    #[test]
+    fn opaque() {
+        for (color_type, bit_depth, _) in VALID {
+            let header = header(1, 1, color_type, bit_depth);
+            let (opaque, certainly) = match color_type {
+                ColorType::Grey | ColorType::Rgb => (true, true),
+                ColorType::Palette => (true, false),
+                ColorType::GreyAlpha | ColorType::Rgba => (false, false),
+            };
+
+            assert_eq!(header.is_opaque(), opaque);
+            assert_eq!(header.is_certainly_opaque(), certainly);
+        }
+    }
+
+    #[test]
+    fn invalid_bit_depth_sizes() {
+        for (color_type, bit_depth) in [
+            (ColorType::Rgb, 4),
+            (ColorType::Palette, 16),
+            (ColorType::Grey, 0),
+        ] {
+            for interlace in [false, true] {
+                let header = ImageHeader {
+                    interlace,
+                    ..header(3, 3, color_type, bit_depth)
+                };
+
+                assert_eq!(header.scanline_bytes(), None);
+                assert_eq!(header.raw_size(), None);
+                assert_eq!(header.idat_raw_size(), None);
+            }
+        }
+    }
+
+    #[test]
+    fn scanline_geometry() {
+        for (color_type, bit_depth, bpp) in VALID {
+            let bpp = usize::from(bpp);
+
+            for width in 1..=9 {
+                for height in 1..=9 {
+                    let mut header =
+                        header(width, height, color_type, bit_depth);
+                    let (w, h) = (width as usize, height as usize);
+                    let scanline = (w * bpp + 7) / 8;
+
+                    assert_eq!(header.scanline_bytes(), Some(scanline));
+                    assert_eq!(header.raw_size(), Some((w * h * bpp + 7) / 8));
+                    assert_eq!(
+                        header.idat_raw_size(),
+                        Some(h * (scanline + 1))
+                    );
+
+                    // Cross-check passes against the deinterlacer
+                    header.interlace = true;
+                    let (passw, passh, filter_passstart, _, _) =
+                        adam7::get_pass_values(width, height, bpp as u8);
+                    for pass in 0..adam7::PASSES {
+                        let i = usize::from(pass);
+                        let dims = header
+                            .pass_header(pass)
+                            .map(|pass| (pass.width, pass.height))
+                            .unwrap_or((0, 0));
+
+                        assert_eq!(dims, (passw[i], passh[i]));
+                    }
+                    assert!(header.pass_header(adam7::PASSES).is_none());
+                    assert_eq!(
+                        header.idat_raw_size(),
+                        Some(filter_passstart[7]),
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn from_tuple() {
+        for (color_type, _, _) in VALID {
+            let header = ImageHeader::from((3, 2, color_type));
+            assert_eq!((header.width, header.height), (3, 2));
+            assert_eq!(header.color_type, color_type);
+            assert!(color_type
+                .check_png_color_validity(header.bit_depth)
+                .is_ok());
+            assert!(!header.interlace);
+        }
     }
 }
@@ -9,7 +9,7 @@ use crate::{
 };
 
 /// International Text Chunk Data (iTXt)
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct InternationalText {
     /// A keyword that gives a short description of what the text in `val`
     /// represents, e.g. Title, Author, Description, or anything else.  Minimum
@@ -31,17 +31,10 @@ impl InternationalText {
     pub(crate) fn parse<R: Read>(
         parse: &mut Parser<R>,
     ) -> Result<Chunk, DecoderError> {
-        let buffer = parse.raw()?;
-        let mut reader = Reader::new(&buffer);
-        let key = {
-            let key = reader.strz()?;
-            let key_len = key.len();
-
-            (1..=79)
-                .contains(&key_len)
-                .then_some(key)
-                .ok_or(DecoderError::KeySize(key_len))?
-        };
+        let mut buffer = parse.payload()?;
+        let key = super::read_keyword(&mut buffer, 79)?;
+        let key = String::from_utf8_lossy(key).into_owned();
+        let mut reader = Reader::new(buffer);
         let compressed = match reader.u8()? {
             0 => false,
             1 => true,
@@ -58,14 +51,11 @@ impl InternationalText {
         let langtag = reader.strz()?;
         let transkey = reader.strz()?;
         let data = reader
-            .slice(
-                parse.len() - (key.len() + langtag.len() + transkey.len() + 5),
-            )?
-            .to_vec();
+            .slice(buffer.len() - (langtag.len() + transkey.len() + 4))?;
         let val = if compressed {
-            String::from_utf8_lossy(&zlib::decompress(&data)?).to_string()
+            String::from_utf8_lossy(&zlib::decompress(data)?).into_owned()
         } else {
-            String::from_utf8_lossy(&data).to_string()
+            String::from_utf8_lossy(data).into_owned()
         };
 
         reader.end().unwrap();
@@ -6,7 +6,7 @@ use super::{Chunk, DecoderError, EncoderError};
 use crate::{consts, decoder::Parser, encoder::Enc};
 
 /// Physical dimensions chunk (pHYs)
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Physical {
     /// Pixels per unit: X dimension
     pub ppu_x: u32,
@@ -17,6 +17,14 @@ pub struct Physical {
 }
 
 impl Physical {
+    /// Get the horizontal and vertical resolution in dots per inch, or `None`
+    /// if the unit is unknown (only the aspect ratio of pixels is given).
+    pub fn dpi(&self) -> Option<(f64, f64)> {
+        let dpi = |ppu: u32| f64::from(ppu) * 0.0254;
+
+        self.is_meter.then(|| (dpi(self.ppu_x), dpi(self.ppu_y)))
+    }
+
     pub(crate) fn write<W: Write>(
         &self,
         enc: &mut Enc<W>,
@@ -49,3 +57,22 @@ impl Physical {
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dpi() {
+        let mut physical = Physical {
+            ppu_x: 3780,
+            ppu_y: 7559,
+            is_meter: true,
+        };
+        let (x, y) = physical.dpi().unwrap();
+        assert_eq!((x.round(), y.round()), (96.0, 192.0));
+
+        physical.is_meter = false;
+        assert_eq!(physical.dpi(), None);
+    }
+}
@@ -18,11 +18,11 @@ impl Palette {
     pub(crate) fn parse<R: Read>(
         parse: &mut Parser<R>,
     ) -> Result<Chunk, DecoderError> {
-        parse.set_palette();
+        parse.set_palette(parse.len() / 3);
 
-        let buffer = parse.raw()?;
-        let mut reader = Reader::new(&buffer);
-        let palette = (0..(parse.len() / 3))
+        let buffer = parse.payload()?;
+        let mut reader = Reader::new(buffer);
+        let palette = (0..(buffer.len() / 3))
             .map(|_| -> Result<_, DecoderError> {
                 let [r, g, b] = [reader.u8()?, reader.u8()?, reader.u8()?];
 
@@ -34,6 +34,67 @@ impl Palette {
         Ok(Chunk::Palette(Palette { palette }))
     }
 
+    /// Get the palette entries sorted by perceived brightness (darkest
+    /// first), as `(original_index, (r, g, b))`.
+    ///
+    /// Brightness is `0.2126 R + 0.7152 G + 0.0722 B`; entries of equal
+    /// brightness keep their palette order.
+    pub fn brightness_sorted(&self) -> Vec<(u8, (u8, u8, u8))> {
+        let brightness = |(r, g, b): (u8, u8, u8)| {
+            0.2126 * f32::from(r)
+                + 0.7152 * f32::from(g)
+                + 0.0722 * f32::from(b)
+        };
+        let mut entries: Vec<(u8, (u8, u8, u8))> = self
+            .palette
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                let rgb = (
+                    Rgb::red(*p).into(),
+                    Rgb::green(*p).into(),
+                    Rgb::blue(*p).into(),
+                );
+                // A palette has at most 256 entries
+                (i as u8, rgb)
+            })
+            .collect();
+        entries.sort_by(|(_, a), (_, b)| {
+            brightness(*a).total_cmp(&brightness(*b))
+        });
+        entries
+    }
+
+    /// Add an `(r, g, b)` color to the end of the palette, returning its
+    /// index for encoding pixels of that color.
+    ///
+    /// # Errors
+    /// [`PaletteFullError`] if the palette already has 256 entries.
+    pub fn add_entry(
+        &mut self,
+        r: u8,
+        g: u8,
+        b: u8,
+    ) -> Result<u8, PaletteFullError> {
+        let index =
+            u8::try_from(self.palette.len()).map_err(|_| PaletteFullError)?;
+
+        self.palette.push(SRgb8::new(r, g, b));
+        Ok(index)
+    }
+
+    /// Remove the entries at index `n` and above, such as to fit a palette
+    /// from a quantizer to the image's bit depth (at most 2 to the power of
+    /// the bit depth entries).  Does nothing if there are `n` entries or
+    /// fewer.
+    ///
+    /// # Panics
+    /// If `n` is more than 256.
+    pub fn truncate(&mut self, n: usize) {
+        assert!(n <= 256);
+        self.palette.truncate(n);
+    }
+
     pub(crate) fn write<W: Write>(
         &self,
         enc: &mut Enc<W>,
@@ -47,3 +108,107 @@ impl Palette {
         enc.write_crc()
     }
 }
+
+/// Append `(r, g, b)` colors to the palette.
+///
+/// The number of entries isn't checked; a palette of more than 256 colors
+/// can't be used by a valid PNG file.
+impl Extend<(u8, u8, u8)> for Palette {
+    fn extend<T: IntoIterator<Item = (u8, u8, u8)>>(&mut self, iter: T) {
+        self.palette
+            .extend(iter.into_iter().map(|(r, g, b)| SRgb8::new(r, g, b)));
+    }
+}
+
+/// Error from [`Palette::add_entry()`] when the palette already has 256
+/// entries.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PaletteFullError;
+
+impl std::fmt::Display for PaletteFullError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Palette already has 256 entries")
+    }
+}
+
+impl std::error::Error for PaletteFullError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn brightness_sorted() {
+        let palette = Palette {
+            palette: vec![
+                SRgb8::new(255, 255, 255),
+                SRgb8::new(0, 0, 255),
+                SRgb8::new(0, 255, 0),
+                SRgb8::new(255, 0, 0),
+                SRgb8::new(0, 0, 0),
+            ],
+        };
+
+        assert_eq!(
+            palette.brightness_sorted(),
+            [
+                (4, (0, 0, 0)),
+                (1, (0, 0, 255)),
+                (3, (255, 0, 0)),
+                (2, (0, 255, 0)),
+                (0, (255, 255, 255)),
+            ],
+        );
+    }
+
+    #[test]
+    fn extend() {
+        let mut palette = Palette {
+            palette: vec![SRgb8::new(1, 2, 3)],
+        };
+        palette.extend([(4, 5, 6), (7, 8, 9)]);
+
+        assert_eq!(
+            palette.palette,
+            [
+                SRgb8::new(1, 2, 3),
+                SRgb8::new(4, 5, 6),
+                SRgb8::new(7, 8, 9),
+            ],
+        );
+    }
+
+    #[test]
+    fn add_entry() {
+        let mut palette = Palette {
+            palette: vec![SRgb8::new(1, 2, 3)],
+        };
+        assert_eq!(palette.add_entry(4, 5, 6), Ok(1));
+        assert_eq!(palette.palette[1], SRgb8::new(4, 5, 6));
+
+        palette.extend((2..=255).map(|i| (i, i, i)));
+        assert_eq!(palette.palette.len(), 256);
+        assert_eq!(palette.add_entry(0, 0, 0), Err(PaletteFullError));
+        assert_eq!(palette.palette.len(), 256);
+    }
+
+    #[test]
+    fn truncate() {
+        let mut palette = Palette {
+            palette: (0..8).map(|i| SRgb8::new(i, i, i)).collect(),
+        };
+        palette.truncate(16);
+        assert_eq!(palette.palette.len(), 8);
+        palette.truncate(2);
+        assert_eq!(palette.palette, [SRgb8::new(0, 0, 0), SRgb8::new(1, 1, 1)]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn truncate_over_256() {
+        Palette {
+            palette: Vec::new(),
+        }
+        .truncate(257);
+    }
+}
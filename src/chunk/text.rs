@@ -1,44 +1,93 @@
 use std::io::{Read, Write};
 
-use parsenic::{Read as _, Reader};
+use super::{Chunk, DecoderError, EncoderError, EncoderResult};
+use crate::{consts, decoder::Parser, encoder::Enc};
 
-use super::{Chunk, DecoderError, EncoderError};
-use crate::{consts, decoder::Parser, encoder::Enc, parsing::Read as _};
-
-/// Non-International Text Chunk Data (tEXt and zTXt)
-#[derive(Clone, Debug)]
+/// Non-International Text Chunk Data (tEXt)
+///
+/// Both the keyword and the text are Latin-1 (ISO 8859-1) in the PNG file.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Text {
     /// A keyword that gives a short description of what the text in `val`
     /// represents, e.g. Title, Author, Description, or anything else.  Minimum
     /// of 1 character, and maximum 79 characters long.
-    pub key: String,
+    pub(crate) key: String,
     /// The actual message.  It's discouraged to use a single line length
     /// longer than 79 characters
-    pub val: String,
+    pub(crate) val: String,
 }
 
 impl Text {
+    /// Create a new text chunk, checking that:
+    ///
+    /// - `key` is 1 to 79 printable Latin-1 characters, without leading,
+    ///   trailing or consecutive spaces
+    /// - `val` is Latin-1, without null characters
+    pub fn new(
+        key: impl Into<String>,
+        val: impl Into<String>,
+    ) -> EncoderResult<Self> {
+        let text = Self::new_unchecked(key, val);
+
+        text.check()?;
+        Ok(text)
+    }
+
+    /// Create a new text chunk without checking the keyword and text.  Invalid
+    /// text chunks fail to encode.
+    pub fn new_unchecked(
+        key: impl Into<String>,
+        val: impl Into<String>,
+    ) -> Self {
+        Self {
+            key: key.into(),
+            val: val.into(),
+        }
+    }
+
+    /// Get the keyword.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Get the text.
+    pub fn val(&self) -> &str {
+        &self.val
+    }
+
+    /// Get the value as a byte slice, without copying it.
+    ///
+    /// The bytes are UTF-8, not the Latin-1 written to the chunk, so they
+    /// differ for characters above U+007F (for example, "é" is 2 bytes here
+    /// but 1 byte in the file).
+    pub fn val_bytes(&self) -> &[u8] {
+        self.val.as_bytes()
+    }
+
+    fn check(&self) -> EncoderResult<()> {
+        super::check_keyword(&self.key)?;
+        if !self.val.chars().all(|c| c != '\0' && c <= '\u{FF}') {
+            return Err(EncoderError::InvalidText);
+        }
+        Ok(())
+    }
+
     pub(crate) fn parse<R: Read>(
         parse: &mut Parser<R>,
     ) -> Result<Chunk, DecoderError> {
-        let buffer = parse.raw()?;
-        let mut reader = Reader::new(&buffer);
-        let key = {
-            let key = reader.strz()?;
-            let key_len = key.len();
-
-            (1..=79)
-                .contains(&key_len)
-                .then_some(key)
-                .ok_or(DecoderError::KeySize(key_len))?
-        };
-        let val = String::from_utf8_lossy(
-            reader.slice(parse.len() - (key.len() + 1))?,
-        )
-        .into_owned();
-
-        reader.end().unwrap();
-        Ok(Chunk::Text(Text { key, val }))
+        Self::parse_raw(parse.payload()?).map(Chunk::Text)
+    }
+
+    /// Parse the raw data of a "tEXt" chunk, such as one read as an unknown
+    /// chunk with [`Decoder::parse_text(false)`](crate::Decoder::parse_text).
+    pub fn parse_raw(mut data: &[u8]) -> Result<Self, DecoderError> {
+        let key = from_latin1(super::read_keyword(&mut data, 79)?);
+        if !super::printable_keyword(&key) {
+            return Err(DecoderError::InvalidKeyword(key));
+        }
+        let val = from_latin1(data);
+
+        Ok(Text { key, val })
     }
 
     pub(crate) fn write<W: Write>(
@@ -46,14 +95,26 @@ impl Text {
         enc: &mut Enc<W>,
     ) -> Result<(), EncoderError> {
         // Checks
-        if self.key.as_bytes().is_empty() {
-            return Err(EncoderError::KeySize(0));
-        }
+        self.check()?;
+
+        let key = to_latin1(&self.key);
+        let val = to_latin1(&self.val);
 
         // 1 Null-terminated string, 1 string
-        enc.prepare(self.key.len() + self.val.len() + 1, consts::TEXT)?;
-        enc.str(&self.key)?;
-        enc.string(&self.val)?;
+        enc.prepare(key.len() + val.len() + 1, consts::TEXT)?;
+        enc.raw(&key)?;
+        enc.u8(0)?;
+        enc.raw(&val)?;
         enc.write_crc()
     }
 }
+
+/// Decode Latin-1 bytes.
+fn from_latin1(bytes: &[u8]) -> String {
+    bytes.iter().cloned().map(char::from).collect()
+}
+
+/// Encode Latin-1 bytes (characters must already be checked to fit).
+fn to_latin1(text: &str) -> Vec<u8> {
+    text.chars().map(|c| c as u8).collect()
+}
@@ -17,9 +17,7 @@ impl Unknown {
         &self,
         enc: &mut Enc<W>,
     ) -> EncoderResult<()> {
-        enc.prepare(self.data.len(), self.name)?;
-        enc.raw(&self.data)?;
-        enc.write_crc()
+        enc.write_chunk(self.name, &self.data)
     }
 
     pub(crate) fn parse<R: Read>(
@@ -3,7 +3,7 @@ use std::io::{Read, Write};
 use parsenic::{Read as _, Reader};
 
 use super::{Chunk, DecoderError, DecoderResult, EncoderError, EncoderResult};
-use crate::{consts, decoder::Parser, encoder::Enc, zlib, parsing::Read as _};
+use crate::{consts, decoder::Parser, encoder::Enc, zlib};
 
 /// Compressed Text Chunk Data (zTXt)
 #[derive(Clone, Debug)]
@@ -42,17 +42,10 @@ impl CompressedText {
     pub(crate) fn parse<R: Read>(
         parse: &mut Parser<R>,
     ) -> DecoderResult<Chunk> {
-        let buffer = parse.raw()?;
-        let mut reader = Reader::new(&buffer);
-        let key = {
-            let key = reader.strz()?;
-            let key_len = key.len();
-
-            (1..=79)
-                .contains(&key_len)
-                .then_some(key)
-                .ok_or(DecoderError::KeySize(key_len))?
-        };
+        let mut buffer = parse.payload()?;
+        let key = super::read_keyword(&mut buffer, 79)?;
+        let key = String::from_utf8_lossy(key).into_owned();
+        let mut reader = Reader::new(buffer);
         let _compression_method = {
             let compression_method = reader.u8()?;
 
@@ -60,7 +53,7 @@ impl CompressedText {
                 .then_some(compression_method)
                 .ok_or(DecoderError::CompressionMethod)?
         };
-        let ztxt = reader.slice(parse.len() - (key.len() + 2))?;
+        let ztxt = reader.slice(buffer.len() - 1)?;
         let decoded = zlib::decompress(ztxt)?;
         let val = String::from_utf8_lossy(&decoded).into_owned();
 
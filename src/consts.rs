@@ -13,6 +13,13 @@ pub(super) const PHYSICAL: [u8; 4] = *b"pHYs";
 pub(super) const TIME: [u8; 4] = *b"tIME";
 pub(super) const ZTEXT: [u8; 4] = *b"zTXt";
 pub(super) const TEXT: [u8; 4] = *b"tEXt";
+pub(super) const ANIMATION_CONTROL: [u8; 4] = *b"acTL";
+pub(super) const FRAME_CONTROL: [u8; 4] = *b"fcTL";
+pub(super) const FRAME_DATA: [u8; 4] = *b"fdAT";
+pub(super) const GAMMA: [u8; 4] = *b"gAMA";
+pub(super) const SRGB: [u8; 4] = *b"sRGB";
+pub(super) const HISTOGRAM: [u8; 4] = *b"hIST";
+pub(super) const SUGGESTED_PALETTE: [u8; 4] = *b"sPLT";
 
 pub(super) const MAX_CHUNK_SIZE: usize = 1 << 31; // 2³¹
 
@@ -0,0 +1,79 @@
+//! CRC-32 checksums of PNG chunks
+//!
+//! PNG chunks end with the CRC-32 (as used by zlib and gzip) of the chunk name
+//! and data, which is what the functions here calculate, so they can be used
+//! to write or check chunks outside of the encoder and decoder.
+//!
+//! ```rust
+//! use png_pong::crc32::{crc32, Crc32};
+//!
+//! assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+//!
+//! let mut crc = Crc32::new();
+//! crc.update(b"IE");
+//! crc.update(b"ND");
+//! assert_eq!(crc.finish(), 0xAE42_6082);
+//! ```
+
+use crate::consts;
+
+/// Streaming CRC-32 calculation, for data that's not all in one slice.
+#[derive(Copy, Clone, Debug)]
+pub struct Crc32 {
+    crc: u32,
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Crc32 {
+    /// Start a new CRC-32 calculation.
+    pub fn new() -> Self {
+        Self {
+            crc: consts::CRC32_INIT,
+        }
+    }
+
+    /// Add more bytes to the calculation.
+    pub fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes.iter().cloned() {
+            let index: usize = (self.crc as u8 ^ byte).into();
+
+            self.crc = consts::CRC32_LOOKUP[index] ^ (self.crc >> 8);
+        }
+    }
+
+    /// Get the CRC-32 of the bytes so far.
+    pub fn finish(&self) -> u32 {
+        self.crc ^ consts::CRC32_INIT
+    }
+}
+
+/// Calculate the CRC-32 of bytes (for a chunk, of its name and data).
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+
+    crc.update(bytes);
+    crc.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_values() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(crc32(b""), 0);
+
+        let mut crc = Crc32::default();
+        for byte in b"123456789" {
+            crc.update(&[*byte]);
+        }
+        assert_eq!(crc.finish(), 0xCBF4_3926);
+    }
+}
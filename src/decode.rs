@@ -1,9 +1,37 @@
 //! PNG file decoding
 
 mod chunks;
+mod crc_policy;
 mod error;
+mod header;
+mod limits;
+mod planes;
+mod ratio;
+mod read;
+mod rows;
+pub(crate) mod scan;
+mod single;
+mod stats;
 mod steps;
+mod summary;
+mod validate;
+mod warning;
 
 pub use chunks::Chunks;
+pub use crc_policy::CrcPolicy;
 pub use error::{Error, Result};
-pub use steps::Steps;
+pub use header::{png_color_type, png_dimensions, png_header};
+pub use limits::Limits;
+pub use planes::{decode_planes_into, PlaneChannel, PlaneLayout};
+pub use ratio::measure_compression_ratio;
+pub use read::read_png;
+pub use rows::{Row, Rows};
+pub use scan::{png_chunk_names, png_chunk_sizes};
+pub use single::decode_single_chunk;
+pub use stats::DecodeStats;
+pub use steps::{decode_steps, LinearSteps, Steps};
+pub use summary::{scan, Summary};
+pub use validate::{
+    all_chunks_valid, validate, Severity, ValidationReport, Violation,
+};
+pub use warning::ChunkWarning;
@@ -3,11 +3,11 @@ use std::io::Read;
 use crate::{
     chunk::{
         Background, Chunk, CompressedText, ImageData, ImageEnd, ImageHeader,
-        InternationalText, Palette, Physical, Text, Time, Transparency,
-        Unknown,
+        InternationalText, Palette, Physical, Position, Text, Time,
+        Transparency, Unknown,
     },
     consts,
-    decode::Result,
+    decode::{ChunkWarning, Error, Result},
     decoder::Parser,
 };
 
@@ -16,28 +16,197 @@ use crate::{
 pub struct Chunks<R: Read> {
     /// Decoder
     dec: Parser<R>,
+    /// Position of the last chunk read
+    position: Position,
+    /// Has an IDAT chunk been read (or skipped)?
+    seen_image: bool,
 }
 
 impl<R: Read> Chunks<R> {
     /// Create a new encoder.  Will return an error if it's not a PNG file.
     pub(crate) fn new(dec: Parser<R>) -> Self {
-        Chunks { dec }
+        Chunks {
+            dec,
+            position: Position::BeforeImage,
+            seen_image: false,
+        }
+    }
+
+    /// Get the position of the last chunk read (by `next()` or one of the
+    /// skipping methods) relative to the image data, so that re-encoding can
+    /// keep ancillary chunks where they were.  The first IDAT chunk is
+    /// [`Position::BeforeImage`], and any chunks after it are
+    /// [`Position::AfterImage`].
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    /// Get the name and byte offset of the last chunk whose header was read.
+    pub(crate) fn last_chunk(&self) -> ([u8; 4], u64) {
+        self.dec.last_chunk()
+    }
+
+    /// Record that the chunk named `name` has been read.
+    fn read(&mut self, name: [u8; 4]) {
+        self.position = if self.seen_image {
+            Position::AfterImage
+        } else {
+            Position::BeforeImage
+        };
+        self.seen_image |= name == consts::IMAGE_DATA;
+    }
+
+    /// Get the name and length of the next chunk without consuming it.  The
+    /// following call to `next()` parses that chunk (unless it's skipped for
+    /// going over the decoder's [`Limits`](struct.Limits.html)).  Returns
+    /// `Ok(None)` at the end of the file.
+    pub fn peek_name(&mut self) -> Result<Option<([u8; 4], u32)>> {
+        self.dec.peek()
+    }
+
+    /// Read all of the remaining chunks, stopping at the first error.  This is
+    /// the same as `.collect::<Result<Vec<_>>>()`.
+    pub fn into_vec(self) -> Result<Vec<Chunk>> {
+        self.collect()
+    }
+
+    /// Count the remaining chunks, such as for showing progress through a file
+    /// before decoding it again.  The chunks are read and discarded without
+    /// being parsed (but their CRCs are still checked), so this doesn't
+    /// allocate for their data.
+    pub fn into_count(mut self) -> Result<usize> {
+        let mut count = 0;
+        while let Some(name) = self.dec.prepare()? {
+            self.dec.count_chunk()?;
+            self.dec.skip()?;
+            self.dec.check_crc(&name)?;
+            self.read(name);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Read the next chunk, which must be image data, appending its payload
+    /// to `out` without making a chunk of it.
+    pub(crate) fn read_image_data(&mut self, out: &mut Vec<u8>) -> Result<()> {
+        if let Some(name) = self.dec.prepare()? {
+            debug_assert_eq!(name, consts::IMAGE_DATA);
+            // Critical chunks are never skipped for limits
+            self.dec.within_limits(name)?;
+            self.read(name);
+            self.dec.extend_payload(out)?;
+        }
+        Ok(())
+    }
+
+    /// Get the problems the decoder has worked around so far, such as an IEND
+    /// chunk with a non-zero length.
+    pub fn warnings(&self) -> &[ChunkWarning] {
+        self.dec.warnings()
     }
 
     /// Get the next chunk in the PNG file.
     fn get_next(&mut self) -> Result<Option<Chunk>> {
         // Always start reading at the beginning of the next chunk:
-        let name = if let Some(name) = self.dec.prepare()? {
-            name
-        } else {
-            return Ok(None);
+        let name = loop {
+            let name = if let Some(name) = self.dec.prepare()? {
+                name
+            } else {
+                return Ok(None);
+            };
+            if self.dec.within_limits(name)? {
+                break name;
+            }
+            // Skip chunks over the limits
+            self.dec.skip()?;
+            self.dec.check_crc(&name)?;
+            self.read(name);
         };
+
+        self.parse_chunk(name).map(Some)
+    }
+
+    /// Skip chunks until one named `name` is found, and return it.  Skipped
+    /// chunks are read and discarded without being parsed (but their CRCs are
+    /// still checked).  Returns `Ok(None)` if the end of the file is reached
+    /// first.
+    pub fn step_through_until(
+        &mut self,
+        name: [u8; 4],
+    ) -> Result<Option<Chunk>> {
+        while let Some(found) = self.dec.prepare()? {
+            if found == name {
+                if self.dec.within_limits(found)? {
+                    return self.parse_chunk(found).map(Some);
+                }
+            } else {
+                self.dec.count_chunk()?;
+            }
+            self.dec.skip()?;
+            self.dec.check_crc(&found)?;
+            self.read(found);
+        }
+        Ok(None)
+    }
+
+    /// Skip chunks until the PLTE chunk is found, and return it.  Skipped
+    /// chunks are read and discarded without being parsed (but their CRCs are
+    /// still checked).  Returns `Ok(None)` if an IDAT chunk (which is left
+    /// unread) or the end of the file is reached first, meaning the image has
+    /// no palette.
+    pub fn skip_to_palette(&mut self) -> Result<Option<Palette>> {
+        while let Some((found, _)) = self.dec.peek()? {
+            match found {
+                consts::IMAGE_DATA => return Ok(None),
+                consts::PALETTE => {
+                    // Critical chunks are never skipped for limits
+                    return Ok(match self.get_next()? {
+                        Some(Chunk::Palette(palette)) => Some(palette),
+                        _ => None,
+                    });
+                }
+                _ => {
+                    let _ = self.dec.prepare()?;
+                    self.dec.count_chunk()?;
+                    self.dec.skip()?;
+                    self.dec.check_crc(&found)?;
+                    self.read(found);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Check the length of the prepared hIST or sPLT chunk named `name`: a
+    /// histogram has an entry for each palette entry (at most 256, if there's
+    /// no palette), and a suggested palette has whole entries of the sample
+    /// depth it declares.  For sPLT, the start of the chunk is read to find
+    /// the sample depth.
+    fn valid_length(&mut self, name: [u8; 4]) -> Result<bool> {
+        let len = self.dec.len();
+        if name == consts::HISTOGRAM {
+            return Ok(match self.dec.palette_len() {
+                Some(entries) => len == 2 * entries,
+                None => len <= 2 * 256 && len % 2 == 0,
+            });
+        }
+        // Up to 79 bytes of name, the null separator and the sample depth
+        Ok(whole_entries(self.dec.prefix(81)?, len))
+    }
+
+    /// Parse the prepared chunk named `name`.  The CRC checksum is checked
+    /// once the payload is read, before parsing it.
+    fn parse_chunk(&mut self, name: [u8; 4]) -> Result<Chunk> {
+        self.read(name);
         // Choose correct parser for the chunk based on it's name.
         use consts::*;
-        let chunk = match name {
+        if !self.dec.parse_text() && matches!(name, TEXT | ZTEXT | ITEXT) {
+            return Unknown::parse(&mut self.dec, name);
+        }
+        match name {
             IMAGE_HEADER => ImageHeader::parse(&mut self.dec),
             IMAGE_DATA => ImageData::parse(&mut self.dec),
-            IMAGE_END => Ok(ImageEnd::parse()),
+            IMAGE_END => ImageEnd::parse(&mut self.dec),
             PALETTE => Palette::parse(&mut self.dec),
             BACKGROUND => Background::parse(&mut self.dec),
             ITEXT => InternationalText::parse(&mut self.dec),
@@ -46,15 +215,35 @@ impl<R: Read> Chunks<R> {
             TIME => Time::parse(&mut self.dec),
             TRANSPARENCY => Transparency::parse(&mut self.dec),
             ZTEXT => CompressedText::parse(&mut self.dec),
+            // Kept as unknown chunks, but checked that their length is valid
+            // before reading them
+            HISTOGRAM | SUGGESTED_PALETTE if !self.valid_length(name)? => {
+                self.dec.skip()?;
+                self.dec.check_crc(&name)?;
+                Err(Error::ChunkLength(name))
+            }
             id => Unknown::parse(&mut self.dec, id),
-        }?;
-        // Check the CRC Checksum at the end of the chunk.
-        self.dec.check_crc(&name)?;
-        // Return the Chunk
-        Ok(Some(chunk))
+        }
     }
 }
 
+/// Check that the length of an sPLT chunk fits its `prefix` (the start of its
+/// data, up to the sample depth): a palette name (1 to 79 bytes, and a null
+/// separator), a sample depth of 8 or 16, and whole palette entries (6 or 10
+/// bytes each).
+fn whole_entries(prefix: &[u8], len: usize) -> bool {
+    let Some(name) = prefix.iter().position(|&byte| byte == 0) else {
+        return false;
+    };
+    let entry = match prefix.get(name + 1) {
+        Some(8) => 6,
+        Some(16) => 10,
+        _ => return false,
+    };
+
+    (1..=79).contains(&name) && (len - name - 2) % entry == 0
+}
+
 impl<R: Read> Iterator for Chunks<R> {
     type Item = Result<Chunk>;
 
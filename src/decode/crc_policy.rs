@@ -0,0 +1,29 @@
+/// Which chunks the decoder checks the CRCs of, set with
+/// [`Decoder::verify_crc()`](crate::Decoder::verify_crc).
+///
+/// Skipping a chunk's CRC skips computing it, not just comparing it, which
+/// saves time on large image data.  The image data is still covered by the
+/// zlib stream's Adler-32 checksum, but other corruption goes unnoticed, so
+/// only skip CRCs of files from a trusted encoder.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum CrcPolicy {
+    /// Check every chunk's CRC
+    #[default]
+    All,
+    /// Only check the CRCs of ancillary chunks (ones with a lowercase first
+    /// letter, such as "tEXt"), which aren't covered by another checksum
+    AncillaryOnly,
+    /// Don't check any CRCs
+    None,
+}
+
+impl CrcPolicy {
+    /// Should the CRC of the chunk named `name` be checked?
+    pub(crate) fn verifies(self, name: [u8; 4]) -> bool {
+        match self {
+            CrcPolicy::All => true,
+            CrcPolicy::AncillaryOnly => name[0].is_ascii_lowercase(),
+            CrcPolicy::None => false,
+        }
+    }
+}
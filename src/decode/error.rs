@@ -1,4 +1,4 @@
-use crate::chunk::ColorType;
+use crate::{chunk::ColorType, consts};
 
 /// PNG Pong Decoder Result Type
 pub type Result<T = (), E = Error> = std::result::Result<T, E>;
@@ -9,6 +9,23 @@ impl From<std::io::Error> for Error {
     }
 }
 
+/// Unwraps `Error::Io` back into the original I/O error when possible,
+/// otherwise wraps the decoder error (as `ErrorKind::InvalidData`) so it can be
+/// recovered with `get_ref()` and `downcast_ref()`.
+impl From<Error> for std::io::Error {
+    fn from(err: Error) -> std::io::Error {
+        use std::io::{Error as IoError, ErrorKind};
+
+        match err {
+            Error::Io(io) => match std::sync::Arc::try_unwrap(io) {
+                Ok(io) => io,
+                Err(io) => IoError::new(io.kind(), Error::Io(io)),
+            },
+            err => IoError::new(ErrorKind::InvalidData, err),
+        }
+    }
+}
+
 impl From<parsenic::error::LenError> for Error {
     fn from(_err: parsenic::error::LenError) -> Error {
         Self::Eof
@@ -18,6 +35,7 @@ impl From<parsenic::error::LenError> for Error {
 /// Decoding Errors.
 #[derive(Clone, Debug)]
 #[allow(variant_size_differences)]
+#[non_exhaustive]
 pub enum Error {
     /// A wrapped I/O error.
     Io(std::sync::Arc<std::io::Error>),
@@ -32,7 +50,12 @@ pub enum Error {
     /// The first 8 bytes are not the correct PNG signature
     InvalidSignature,
     /// Adler checksum not correct, data must be corrupted
-    AdlerChecksum,
+    AdlerChecksum {
+        /// Adler-32 computed from the decompressed data
+        expected: u32,
+        /// Adler-32 stored in the zlib stream
+        found: u32,
+    },
     /// Inflate algorithm failure
     Inflate(miniz_oxide::inflate::TINFLStatus),
     /// ZLib compression includes preset dictionary, which is not allowed
@@ -53,7 +76,14 @@ pub enum Error {
     /// File doesn't contain any chunks.
     Empty,
     /// Key is not between 1-79 characters
-    KeySize(usize),
+    KeySize {
+        /// Key size (one over the maximum if it's too long, as the rest isn't
+        /// read)
+        size: usize,
+        /// The first 10 bytes of the key (or fewer, if the key is shorter),
+        /// padded with zeros
+        key: [u8; 10],
+    },
     /// The length of the END symbol 256 in the Huffman tree is 0
     HuffmanEnd,
     /// Unrecognized filter type
@@ -85,7 +115,196 @@ pub enum Error {
     /// Multiple of a chunk were found when only one of this type is allowed.
     Multiple([u8; 4]),
     /// CRC32 Checksum failed for a chunk
-    Crc32([u8; 4]),
+    Crc32 {
+        /// Name of the chunk
+        chunk: [u8; 4],
+        /// CRC32 computed from the chunk's name and data
+        expected: u32,
+        /// CRC32 stored in the file
+        found: u32,
+        /// Byte offset of the chunk from the start of the file
+        offset: u64,
+    },
+    /// Decompressed image data is smaller than the image requires
+    InflatedSize {
+        /// Number of bytes the image requires
+        expected: usize,
+        /// Number of bytes decompressed
+        found: usize,
+    },
+    /// A decoder [`Limits`](struct.Limits.html) was exceeded
+    LimitExceeded,
+    /// Chunk is larger than the maximum chunk size from the decoder's
+    /// [`Limits`](struct.Limits.html)
+    ChunkTooLarge([u8; 4]),
+    /// Image data is JPEG compressed, rather than zlib (not a valid PNG file,
+    /// but made by some broken encoders)
+    JpegInIdat,
+    /// APNG fcTL or fdAT chunk with a sequence number out of order
+    FrameSequence {
+        /// Name of the chunk
+        chunk: [u8; 4],
+        /// Next sequence number
+        expected: u32,
+        /// Sequence number stored in the chunk
+        found: u32,
+    },
+    /// Text chunk keyword isn't printable Latin-1, or has leading, trailing or
+    /// consecutive spaces
+    InvalidKeyword(String),
+}
+
+impl Error {
+    /// Returns `true` if decoding may continue past this error (problems with
+    /// ancillary chunks, checksum mismatches, out-of-range metadata), or
+    /// `false` if the image can't be decoded (bad signature or header, broken
+    /// image data, I/O errors other than an unexpected end of file).
+    pub fn is_recoverable(&self) -> bool {
+        use Error::*;
+        match self {
+            Io(io) => io.kind() == std::io::ErrorKind::UnexpectedEof,
+            ColorType(_) => false,
+            BitDepth(_) => false,
+            ColorMode(_, _) => false,
+            BackgroundSize(_) => true,
+            InvalidSignature => false,
+            AdlerChecksum { .. } => true,
+            Inflate(_) => false,
+            PresetDict => false,
+            CompressionMethod => false,
+            ZlibHeader => false,
+            ZlibTooSmall => false,
+            InterlaceMethod => false,
+            FilterMethod => false,
+            ImageDimensions => false,
+            Empty => false,
+            KeySize { .. } => true,
+            HuffmanEnd => false,
+            IllegalFilterType => false,
+            AlphaPaletteLen => true,
+            ChunkSize => true,
+            AlphaPaletteWithAlphaMode => true,
+            NoEnd => true,
+            PhysUnits => true,
+            NulTerm => true,
+            ChunkLength(name) => is_ancillary(name),
+            UnknownChunkType(_) => true,
+            Eof => true,
+            ChunkOrder => false,
+            NoImageData => false,
+            TrailingChunk => true,
+            Multiple(name) => is_ancillary(name),
+            Crc32 { .. } => true,
+            InflatedSize { .. } => false,
+            LimitExceeded => false,
+            ChunkTooLarge(name) => is_ancillary(name),
+            JpegInIdat => false,
+            FrameSequence { .. } => true,
+            InvalidKeyword(_) => true,
+        }
+    }
+
+    /// Get the name of the chunk associated with this error, if known.
+    pub fn chunk(&self) -> Option<[u8; 4]> {
+        use Error::*;
+        match self {
+            Io(_) => None,
+            ColorType(_) => Some(consts::IMAGE_HEADER),
+            BitDepth(_) => Some(consts::IMAGE_HEADER),
+            ColorMode(_, _) => Some(consts::IMAGE_HEADER),
+            BackgroundSize(_) => Some(consts::BACKGROUND),
+            InvalidSignature => None,
+            AdlerChecksum { .. } => None,
+            Inflate(_) => None,
+            PresetDict => None,
+            CompressionMethod => None,
+            ZlibHeader => None,
+            ZlibTooSmall => None,
+            InterlaceMethod => Some(consts::IMAGE_HEADER),
+            FilterMethod => Some(consts::IMAGE_HEADER),
+            ImageDimensions => Some(consts::IMAGE_HEADER),
+            Empty => None,
+            KeySize { .. } => None,
+            HuffmanEnd => None,
+            IllegalFilterType => Some(consts::IMAGE_DATA),
+            AlphaPaletteLen => Some(consts::TRANSPARENCY),
+            ChunkSize => None,
+            AlphaPaletteWithAlphaMode => Some(consts::TRANSPARENCY),
+            NoEnd => None,
+            PhysUnits => Some(consts::PHYSICAL),
+            NulTerm => None,
+            ChunkLength(name) => Some(*name),
+            UnknownChunkType(name) => Some(*name),
+            Eof => None,
+            ChunkOrder => None,
+            NoImageData => Some(consts::IMAGE_DATA),
+            TrailingChunk => Some(consts::IMAGE_END),
+            Multiple(name) => Some(*name),
+            Crc32 { chunk, .. } => Some(*chunk),
+            InflatedSize { .. } => Some(consts::IMAGE_DATA),
+            LimitExceeded => None,
+            ChunkTooLarge(name) => Some(*name),
+            JpegInIdat => Some(consts::IMAGE_DATA),
+            FrameSequence { chunk, .. } => Some(*chunk),
+            InvalidKeyword(_) => None,
+        }
+    }
+
+    /// Get a short, machine readable name for the kind of error (such as
+    /// `"crc_mismatch"`), to tell errors apart without matching on their
+    /// [`Display`](std::fmt::Display) messages.
+    ///
+    /// Codes are stable: a variant's code won't change in minor or patch
+    /// releases, and each variant has a code of its own.
+    pub fn code(&self) -> &'static str {
+        use Error::*;
+        match self {
+            Io(_) => "io",
+            ColorType(_) => "bad_color_type",
+            BitDepth(_) => "bad_bit_depth",
+            ColorMode(_, _) => "bad_color_mode",
+            BackgroundSize(_) => "background_size",
+            InvalidSignature => "invalid_signature",
+            AdlerChecksum { .. } => "adler_mismatch",
+            Inflate(_) => "inflate",
+            PresetDict => "preset_dictionary",
+            CompressionMethod => "compression_method",
+            ZlibHeader => "zlib_header",
+            ZlibTooSmall => "zlib_too_small",
+            InterlaceMethod => "interlace_method",
+            FilterMethod => "filter_method",
+            ImageDimensions => "image_dimensions",
+            Empty => "no_header",
+            KeySize { .. } => "key_size",
+            HuffmanEnd => "huffman_end",
+            IllegalFilterType => "filter_type",
+            AlphaPaletteLen => "alpha_palette_length",
+            ChunkSize => "chunk_size",
+            AlphaPaletteWithAlphaMode => "alpha_palette_with_alpha",
+            NoEnd => "chunk_too_long",
+            PhysUnits => "physical_units",
+            NulTerm => "missing_nul",
+            ChunkLength(_) => "chunk_length",
+            UnknownChunkType(_) => "unknown_chunk",
+            Eof => "unexpected_eof",
+            ChunkOrder => "chunk_order",
+            NoImageData => "no_image_data",
+            TrailingChunk => "trailing_chunk",
+            Multiple(_) => "duplicate_chunk",
+            Crc32 { .. } => "crc_mismatch",
+            InflatedSize { .. } => "inflated_size",
+            LimitExceeded => "limit_exceeded",
+            ChunkTooLarge(_) => "chunk_too_large",
+            JpegInIdat => "jpeg_in_idat",
+            FrameSequence { .. } => "frame_sequence",
+            InvalidKeyword(_) => "invalid_keyword",
+        }
+    }
+}
+
+/// Ancillary chunks have a lowercase first letter.
+fn is_ancillary(name: &[u8; 4]) -> bool {
+    name[0].is_ascii_lowercase()
 }
 
 impl std::fmt::Display for Error {
@@ -98,7 +317,7 @@ impl std::fmt::Display for Error {
             ColorMode(_ct, _bd) => write!(f, "Invalid color type / bit depth combination"),
             BackgroundSize(_) => write!(f, "Background color type mismatch with image color type"),
             InvalidSignature => write!(f, "Not a PNG file"),
-            AdlerChecksum => write!(f, "Adler checksum not correct, data must be corrupted"),
+            AdlerChecksum { expected, found } => write!(f, "Adler checksum not correct, data must be corrupted (expected {expected:#010x}, found {found:#010x})"),
             Inflate(e) => write!(f, "Inflate: {:?}", e),
             PresetDict => write!(f, "ZLib compression using preset dictionary, PNG doesn't allow"),
             CompressionMethod => write!(f, "Invalid compression method in zlib header"),
@@ -108,7 +327,7 @@ impl std::fmt::Display for Error {
             FilterMethod => write!(f, "Invalid filter method"),
             ImageDimensions => write!(f, "Invalid image dimensions, must be greater than 0"),
             Empty => write!(f, "File doesn't contain any chunks."), // FIXME: NoImageData
-            KeySize(size) => write!(f, "Text size ({}) doesn't fit inequality 1 ≤ x ≤ 79", size),
+            KeySize { size, key } => write!(f, "Text size ({}) doesn't fit inequality 1 ≤ x ≤ 79 (key starts with {:?})", size, String::from_utf8_lossy(&key[..(*size).min(10)])),
             HuffmanEnd => write!(f, "The length of the END symbol 256 in the Huffman tree is 0"),
             IllegalFilterType => write!(f, "Unrecognized filter type"),
             AlphaPaletteLen => write!(f, "Alpha palette is larger than the palette."),
@@ -124,9 +343,196 @@ impl std::fmt::Display for Error {
             NoImageData => write!(f, "No IDAT chunk exists, invalid PNG file"),
             TrailingChunk => write!(f, "Trailing chunks were found after IEND, which is invalid"),
             Multiple(bytes) => write!(f, "Only one {} chunk allowed, but found multiple", String::from_utf8_lossy(bytes)),
-            Crc32(bytes) => write!(f, "CRC32 Checksum failed for {} chunk", String::from_utf8_lossy(bytes)),
+            Crc32 { chunk, expected, found, offset } => write!(f, "CRC32 Checksum failed for {} chunk at offset {offset:#x} (expected {expected:#010x}, found {found:#010x})", String::from_utf8_lossy(chunk)),
+            InflatedSize { expected, found } => write!(f, "Inflated {found} bytes of image data, expected {expected}"),
+            LimitExceeded => write!(f, "Decoder resource limit exceeded"),
+            ChunkTooLarge(bytes) => write!(f, "{} chunk is too large to load", String::from_utf8_lossy(bytes)),
+            JpegInIdat => write!(f, "Image data is JPEG, not zlib compressed (broken encoder wrote a JPEG with a PNG signature)"),
+            FrameSequence { chunk, expected, found } => write!(f, "{} chunk has sequence number {found}, expected {expected}", String::from_utf8_lossy(chunk)),
+            InvalidKeyword(key) => write!(f, "Text keyword {key:?} isn't printable Latin-1 without extra spaces"),
         }
     }
 }
 
 impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, sync::Arc};
+
+    use miniz_oxide::inflate::TINFLStatus;
+
+    use super::*;
+
+    /// Every variant, with whether it's recoverable and its chunk.
+    fn all() -> Vec<(Error, bool, Option<[u8; 4]>)> {
+        use Error::*;
+        let io = |kind| Io(Arc::new(io::Error::from(kind)));
+
+        vec![
+            (io(io::ErrorKind::UnexpectedEof), true, None),
+            (io(io::ErrorKind::Other), false, None),
+            (ColorType(1), false, Some(*b"IHDR")),
+            (BitDepth(0), false, Some(*b"IHDR")),
+            (
+                ColorMode(crate::chunk::ColorType::Rgb, 4),
+                false,
+                Some(*b"IHDR"),
+            ),
+            (
+                BackgroundSize(crate::chunk::ColorType::Rgb),
+                true,
+                Some(*b"bKGD"),
+            ),
+            (InvalidSignature, false, None),
+            (
+                AdlerChecksum {
+                    expected: 1,
+                    found: 2,
+                },
+                true,
+                None,
+            ),
+            (Inflate(TINFLStatus::Failed), false, None),
+            (PresetDict, false, None),
+            (CompressionMethod, false, None),
+            (ZlibHeader, false, None),
+            (ZlibTooSmall, false, None),
+            (InterlaceMethod, false, Some(*b"IHDR")),
+            (FilterMethod, false, Some(*b"IHDR")),
+            (ImageDimensions, false, Some(*b"IHDR")),
+            (Empty, false, None),
+            (
+                KeySize {
+                    size: 80,
+                    key: *b"kkkkkkkkkk",
+                },
+                true,
+                None,
+            ),
+            (HuffmanEnd, false, None),
+            (IllegalFilterType, false, Some(*b"IDAT")),
+            (AlphaPaletteLen, true, Some(*b"tRNS")),
+            (ChunkSize, true, None),
+            (AlphaPaletteWithAlphaMode, true, Some(*b"tRNS")),
+            (NoEnd, true, None),
+            (PhysUnits, true, Some(*b"pHYs")),
+            (NulTerm, true, None),
+            (ChunkLength(*b"tEXt"), true, Some(*b"tEXt")),
+            (ChunkLength(*b"IDAT"), false, Some(*b"IDAT")),
+            (UnknownChunkType(*b"ruSt"), true, Some(*b"ruSt")),
+            (Eof, true, None),
+            (ChunkOrder, false, None),
+            (NoImageData, false, Some(*b"IDAT")),
+            (TrailingChunk, true, Some(*b"IEND")),
+            (Multiple(*b"tIME"), true, Some(*b"tIME")),
+            (Multiple(*b"PLTE"), false, Some(*b"PLTE")),
+            (
+                Crc32 {
+                    chunk: *b"IDAT",
+                    expected: 1,
+                    found: 2,
+                    offset: 33,
+                },
+                true,
+                Some(*b"IDAT"),
+            ),
+            (
+                InflatedSize {
+                    expected: 2,
+                    found: 1,
+                },
+                false,
+                Some(*b"IDAT"),
+            ),
+            (LimitExceeded, false, None),
+            (ChunkTooLarge(*b"zTXt"), true, Some(*b"zTXt")),
+            (ChunkTooLarge(*b"IDAT"), false, Some(*b"IDAT")),
+            (JpegInIdat, false, Some(*b"IDAT")),
+            (
+                FrameSequence {
+                    chunk: *b"fdAT",
+                    expected: 2,
+                    found: 3,
+                },
+                true,
+                Some(*b"fdAT"),
+            ),
+            (InvalidKeyword(" Title".to_string()), true, None),
+        ]
+    }
+
+    /// Index of each variant in declaration order.  Adding a variant won't
+    /// compile until it's given an index here (and an entry in `all()`).
+    fn index(error: &Error) -> usize {
+        use Error::*;
+        match error {
+            Io(_) => 0,
+            ColorType(_) => 1,
+            BitDepth(_) => 2,
+            ColorMode(_, _) => 3,
+            BackgroundSize(_) => 4,
+            InvalidSignature => 5,
+            AdlerChecksum { .. } => 6,
+            Inflate(_) => 7,
+            PresetDict => 8,
+            CompressionMethod => 9,
+            ZlibHeader => 10,
+            ZlibTooSmall => 11,
+            InterlaceMethod => 12,
+            FilterMethod => 13,
+            ImageDimensions => 14,
+            Empty => 15,
+            KeySize { .. } => 16,
+            HuffmanEnd => 17,
+            IllegalFilterType => 18,
+            AlphaPaletteLen => 19,
+            ChunkSize => 20,
+            AlphaPaletteWithAlphaMode => 21,
+            NoEnd => 22,
+            PhysUnits => 23,
+            NulTerm => 24,
+            ChunkLength(_) => 25,
+            UnknownChunkType(_) => 26,
+            Eof => 27,
+            ChunkOrder => 28,
+            NoImageData => 29,
+            TrailingChunk => 30,
+            Multiple(_) => 31,
+            Crc32 { .. } => 32,
+            InflatedSize { .. } => 33,
+            LimitExceeded => 34,
+            ChunkTooLarge(_) => 35,
+            JpegInIdat => 36,
+            FrameSequence { .. } => 37,
+            InvalidKeyword(_) => 38,
+        }
+    }
+
+    #[test]
+    fn classification() {
+        let mut seen = [false; 39];
+
+        for (error, recoverable, chunk) in all() {
+            seen[index(&error)] = true;
+            assert_eq!(error.is_recoverable(), recoverable, "{error:?}");
+            assert_eq!(error.chunk(), chunk, "{error:?}");
+        }
+        assert!(seen.iter().all(|&seen| seen), "Variant missing from all()");
+    }
+
+    #[test]
+    fn codes() {
+        let mut codes = [None; 39];
+
+        for (error, _, _) in all() {
+            let code = codes[index(&error)].get_or_insert(error.code());
+            assert_eq!(*code, error.code(), "{error:?}");
+        }
+        let mut codes: Vec<&str> = codes.iter().flatten().copied().collect();
+        assert_eq!(codes.len(), 39, "Variant missing from all()");
+        codes.sort_unstable();
+        codes.dedup();
+        assert_eq!(codes.len(), 39, "Variants share a code");
+    }
+}
@@ -0,0 +1,53 @@
+use std::io::Read;
+
+use crate::{
+    chunk::{Chunk, ColorType, ImageHeader},
+    consts,
+    decode::{Error, Result},
+    decoder::Parser,
+    Decoder,
+};
+
+/// Get the width and height of a PNG image.
+///
+/// Only the signature and header chunk (the first 33 bytes of the file) are
+/// read.
+pub fn png_dimensions<R: Read>(reader: R) -> Result<(u32, u32)> {
+    let header = png_header(reader)?;
+
+    Ok((header.width, header.height))
+}
+
+/// Get the color type and bit depth of a PNG image.
+///
+/// Only the signature and header chunk (the first 33 bytes of the file) are
+/// read.
+pub fn png_color_type<R: Read>(reader: R) -> Result<(ColorType, u8)> {
+    let header = png_header(reader)?;
+
+    Ok((header.color_type, header.bit_depth))
+}
+
+/// Get the header of a PNG image.
+///
+/// Only the signature and header chunk (the first 33 bytes of the file) are
+/// read, and the header's CRC is checked.
+pub fn png_header<R: Read>(reader: R) -> Result<ImageHeader> {
+    read_header(&mut Decoder::new(reader)?.parser())
+}
+
+/// Read the header chunk, which must be the first chunk.
+pub(super) fn read_header<R: Read>(
+    parser: &mut Parser<R>,
+) -> Result<ImageHeader> {
+    match parser.prepare()? {
+        Some(consts::IMAGE_HEADER) => {}
+        Some(_) => return Err(Error::ChunkOrder),
+        None => return Err(Error::Empty),
+    }
+    let Chunk::ImageHeader(header) = ImageHeader::parse(parser)? else {
+        unreachable!()
+    };
+
+    Ok(header)
+}
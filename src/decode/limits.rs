@@ -0,0 +1,42 @@
+/// Resource limits enforced while decoding, to protect against files that
+/// are cheap to produce but expensive to read (such as millions of tiny
+/// ancillary chunks).
+///
+/// Limits are checked by the [`Chunks`](struct.Chunks.html) iterator, so they
+/// apply to the [`Steps`](struct.Steps.html) iterator as well.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Limits {
+    /// Maximum number of chunks in the file, exceeding it is always an error
+    /// (default: 100 000).
+    pub max_chunks: usize,
+    /// Maximum number of text chunks ("tEXt", "zTXt" and "iTXt") to keep
+    /// (default: 1024).
+    pub max_text_chunks: usize,
+    /// Maximum total payload bytes of ancillary chunks to keep, other than
+    /// APNG frame data (default: 64 MiB).
+    pub max_ancillary_bytes: usize,
+    /// Maximum payload bytes of a single chunk to load into memory, exceeding
+    /// it is always an error (default: 256 MiB).  Ancillary chunks over
+    /// `max_ancillary_bytes` are checked against that limit first, so are
+    /// skipped instead unless `strict`.  Image data chunks aren't limited,
+    /// since all of the image data is needed to decode the image, and encoders
+    /// (including this crate's) may write it as a single chunk; they're read
+    /// a piece at a time instead, so a chunk can't claim more memory than the
+    /// bytes actually in the file.
+    pub max_chunk_bytes: usize,
+    /// If `true`, exceeding `max_text_chunks` or `max_ancillary_bytes` is an
+    /// error, otherwise the excess chunks are skipped (default: `false`).
+    pub strict: bool,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_chunks: 100_000,
+            max_text_chunks: 1024,
+            max_ancillary_bytes: 1 << 26,
+            max_chunk_bytes: 1 << 28,
+            strict: false,
+        }
+    }
+}
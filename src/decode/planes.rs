@@ -0,0 +1,131 @@
+use std::io::Read;
+
+use super::steps;
+use crate::{
+    chunk::{ColorType, ImageHeader},
+    decode::Result,
+    Decoder,
+};
+
+/// Channel stored in a plane by [`decode_planes_into()`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PlaneChannel {
+    /// Grey level
+    Grey,
+    /// Red
+    Red,
+    /// Green
+    Green,
+    /// Blue
+    Blue,
+    /// Alpha
+    Alpha,
+    /// Palette index
+    Index,
+}
+
+/// Layout of the planes written by [`decode_planes_into()`].
+///
+/// Each plane holds one channel of every pixel, row by row.  Samples are 1
+/// byte (for bit depths of 8 or less, with lower bit depths scaled up to 8
+/// bits, except for palette indices) or 2 bytes (for a bit depth of 16, in
+/// big-endian byte order, as in the PNG file).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlaneLayout {
+    /// Channel of each plane, in order
+    pub channels: Vec<PlaneChannel>,
+    /// Bytes per sample: 1 or 2
+    pub sample_bytes: usize,
+    /// Bytes from the start of one row of a plane to the start of the next
+    pub stride: usize,
+    /// Number of rows
+    pub height: u32,
+}
+
+impl PlaneLayout {
+    /// Get the layout of the planes of an image.
+    pub fn new(header: &ImageHeader) -> Self {
+        use PlaneChannel::*;
+        let channels = match header.color_type {
+            ColorType::Grey => vec![Grey],
+            ColorType::GreyAlpha => vec![Grey, Alpha],
+            ColorType::Rgb => vec![Red, Green, Blue],
+            ColorType::Rgba => vec![Red, Green, Blue, Alpha],
+            ColorType::Palette => vec![Index],
+        };
+        let sample_bytes = if header.bit_depth == 16 { 2 } else { 1 };
+        let stride = header.width as usize * sample_bytes;
+
+        Self {
+            channels,
+            sample_bytes,
+            stride,
+            height: header.height,
+        }
+    }
+
+    /// Get the size of each plane in bytes.
+    pub fn plane_size(&self) -> usize {
+        self.stride * self.height as usize
+    }
+}
+
+/// Decode the image of a PNG file into separate channel planes (such as all
+/// of the red samples, then all of the green samples, then all of the blue
+/// samples), rather than interleaved pixels.
+///
+/// `planes` must have a slice for each channel, of at least
+/// [`PlaneLayout::plane_size()`] bytes; use [`png_header()`] and
+/// [`PlaneLayout::new()`] to get the layout before decoding.  The image is
+/// read a row at a time (see [`Rows`]), and each row's samples are
+/// de-interleaved into the planes as it's unfiltered, without building a
+/// raster first.  Interlaced images are deinterlaced into a buffer of the
+/// whole image first, so they take as much memory as decoding them into
+/// steps.  Palette images are written as a plane of indices.  Chunks other
+/// than the header and image data are skipped (but their CRCs are still
+/// checked).
+///
+/// # Panics
+/// If there are fewer planes than channels, or a plane is too small.
+///
+/// [`png_header()`]: crate::decode::png_header
+/// [`Rows`]: crate::decode::Rows
+pub fn decode_planes_into<R: Read>(
+    reader: R,
+    planes: &mut [&mut [u8]],
+) -> Result<PlaneLayout> {
+    let rows = Decoder::new(reader)?.into_rows()?;
+    let header = *rows.header();
+    let layout = PlaneLayout::new(&header);
+    let (count, size) = (layout.channels.len(), layout.sample_bytes);
+    assert!(planes.len() >= count, "not enough planes");
+    assert!(
+        planes[..count]
+            .iter()
+            .all(|plane| plane.len() >= layout.plane_size()),
+        "plane too small",
+    );
+
+    for row in rows {
+        let row = row?;
+        let at = row.y as usize * layout.stride;
+        // Only greyscale and palette images have depths below 8 bits
+        if header.bit_depth < 8 {
+            let width = header.width as usize;
+            let scale = header.color_type == ColorType::Grey;
+            let samples =
+                steps::unpack(&row.data, header.bit_depth, width, scale);
+            planes[0][at..][..width].copy_from_slice(&samples);
+            continue;
+        }
+        for (x, pixel) in row.data.chunks_exact(count * size).enumerate() {
+            let at = at + x * size;
+            for (plane, sample) in
+                planes.iter_mut().zip(pixel.chunks_exact(size))
+            {
+                plane[at..][..size].copy_from_slice(sample);
+            }
+        }
+    }
+    Ok(layout)
+}
@@ -0,0 +1,34 @@
+use std::io::Cursor;
+
+use super::{header::png_header, scan::png_chunk_sizes};
+use crate::{
+    consts,
+    decode::{Error, Result},
+};
+
+/// Measure how well a PNG file compresses: the size of the raw pixel data
+/// (see [`ImageHeader::raw_size()`]) divided by the total size of the
+/// compressed IDAT chunk data.
+///
+/// A ratio near 1.0 means poor compression (typical for noisy photographs),
+/// while screenshots and graphics are typically 3 to 10.  Image data isn't
+/// decompressed, and only the header's CRC is checked.
+///
+/// [`ImageHeader::raw_size()`]: crate::chunk::ImageHeader::raw_size
+pub fn measure_compression_ratio(input_png: &[u8]) -> Result<f64> {
+    let header = png_header(input_png)?;
+    let compressed: u64 = png_chunk_sizes(Cursor::new(input_png))?
+        .into_iter()
+        .filter(|(name, _, _)| *name == consts::IMAGE_DATA)
+        .map(|(_, length, _)| u64::from(length))
+        .sum();
+    if compressed == 0 {
+        return Err(Error::NoImageData);
+    }
+    let raw = f64::from(header.width)
+        * f64::from(header.height)
+        * f64::from(header.bpp())
+        / 8.0;
+
+    Ok(raw / compressed as f64)
+}
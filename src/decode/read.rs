@@ -0,0 +1,90 @@
+use std::io::Read;
+
+use super::{header::read_header, steps};
+use crate::{
+    chunk::ImageHeader,
+    consts,
+    decode::{Error, Result},
+    decoder::Parser,
+    zlib, Decoder,
+};
+
+/// Decode a PNG file into its header and the bytes of each row.
+///
+/// Each row is a scanline in PNG's sample layout, as taken by
+/// [`write_png()`](crate::write_png): samples in the order of the color
+/// type, 16-bit samples big endian, and samples smaller than a byte packed
+/// with the leftmost pixel in the high bits and the last byte padded.
+/// Interlaced images are deinterlaced (the header still says they were
+/// interlaced), and palette images are rows of indices.  Chunks other than
+/// the header and image data are skipped (but their CRCs are still checked).
+///
+/// ```rust
+/// # let header = png_pong::chunk::ImageHeader {
+/// #     width: 2,
+/// #     height: 1,
+/// #     color_type: png_pong::chunk::ColorType::Grey,
+/// #     bit_depth: 8,
+/// #     interlace: false,
+/// # };
+/// # let mut png = Vec::new();
+/// # png_pong::write_png(&mut png, header, &[&[0, 255]]).unwrap();
+/// let (header, rows) = png_pong::read_png(png.as_slice())
+///     .expect("Failed to decode");
+/// assert_eq!((header.width, header.height), (2, 1));
+/// assert_eq!(rows, [[0, 255]]);
+/// ```
+pub fn read_png<R: Read>(reader: R) -> Result<(ImageHeader, Vec<Vec<u8>>)> {
+    let mut parser = Decoder::new(reader)?.parser();
+    let header = read_header(&mut parser)?;
+    let rows = read_rows(&mut parser, &header)?;
+
+    Ok((header, rows))
+}
+
+/// Read the rest of a PNG file after the header chunk, decoding the image into
+/// rows all at once.
+pub(super) fn read_rows<R: Read>(
+    parser: &mut Parser<R>,
+    header: &ImageHeader,
+) -> Result<Vec<Vec<u8>>> {
+    let mut idat = Vec::new();
+    while let Some(name) = parser.prepare()? {
+        parser.count_chunk()?;
+        if name == consts::IMAGE_DATA {
+            parser.extend_payload(&mut idat)?;
+        } else {
+            parser.skip()?;
+            parser.check_crc(&name)?;
+        }
+    }
+    if idat.is_empty() {
+        return Err(Error::NoImageData);
+    }
+
+    let pixels = steps::pixels(
+        &mut zlib::Inflater::default(),
+        &mut Vec::new(),
+        &mut Vec::new(),
+        &idat,
+        header,
+    )?;
+    let scanline = header.scanline_bytes().ok_or(Error::LimitExceeded)?;
+    let bits = header.width as usize * usize::from(header.bpp());
+    if bits % 8 == 0 {
+        return Ok(pixels.chunks(scanline).map(<[u8]>::to_vec).collect());
+    }
+
+    // Pad each row of pixels smaller than a byte
+    Ok((0..header.height as usize)
+        .map(|y| {
+            let mut row = vec![0; scanline];
+            for i in 0..bits {
+                let o = y * bits + i;
+                let bit = (pixels[o / 8] >> (7 - o % 8)) & 1;
+                row[i / 8] |= bit << (7 - i % 8);
+            }
+            row
+        })
+        .collect())
+}
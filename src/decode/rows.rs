@@ -0,0 +1,295 @@
+use std::io::Read;
+
+use miniz_oxide::inflate::TINFLStatus;
+
+use super::{header::read_header, read::read_rows, steps::unfilter};
+use crate::{
+    chunk::ImageHeader,
+    consts,
+    decode::{Error, Result},
+    decoder::Parser,
+    zlib,
+};
+
+/// Compressed image data is read up to this many bytes at a time.
+const INPUT_SIZE: usize = 1 << 15;
+
+/// One row of an image, from [`Rows`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Row {
+    /// Header of the image the row is from
+    pub header: ImageHeader,
+    /// Row number, from 0 at the top of the image
+    pub y: u32,
+    /// Scanline in PNG's sample layout, the same as the rows from
+    /// [`read_png()`](crate::read_png) (with padding bits set to zero)
+    pub data: Vec<u8>,
+}
+
+/// Iterator over the rows of a PNG image, from
+/// [`Decoder::into_rows()`](crate::Decoder::into_rows).
+///
+/// Images that aren't interlaced are streamed: the image data is decompressed
+/// and unfiltered a row at a time, so only a piece of the compressed data, the
+/// inflate window and two rows are kept in memory however big the image is.
+/// Interlaced images can't be streamed (each row has pixels from every pass),
+/// so they're decoded all at once when the first row is read; see
+/// [`is_streaming()`](Self::is_streaming).
+///
+/// Chunks other than the header and image data are skipped (but their CRCs
+/// are still checked), and palette images are rows of indices.  The rest of
+/// the file is read after the last row, so an error may follow it.
+#[derive(Debug)]
+pub struct Rows<R: Read> {
+    parser: Parser<R>,
+    header: ImageHeader,
+    /// Has the file been read up to the image data?
+    started: bool,
+    /// Rows of an interlaced image, decoded all at once
+    buffered: Option<std::vec::IntoIter<Vec<u8>>>,
+    inflater: zlib::StreamInflater,
+    /// Compressed image data, and how many bytes of it have been inflated
+    input: Vec<u8>,
+    consumed: usize,
+    /// Bytes left to read of the current IDAT chunk, `None` once the last
+    /// IDAT chunk has been read
+    left: Option<usize>,
+    /// Filter type byte and filtered scanline of the current row
+    scanline: Vec<u8>,
+    /// Previous unfiltered scanline
+    previous: Vec<u8>,
+    /// Number of rows read so far
+    y: u32,
+    /// Has the end of the file or an error been reached?
+    done: bool,
+}
+
+impl<R: Read> Rows<R> {
+    /// Read the header chunk, which must be the first chunk.
+    pub(crate) fn new(mut parser: Parser<R>) -> Result<Self> {
+        let header = read_header(&mut parser)?;
+        let scanline = header.scanline_bytes().ok_or(Error::LimitExceeded)?;
+
+        Ok(Self {
+            parser,
+            header,
+            started: false,
+            buffered: None,
+            inflater: zlib::StreamInflater::default(),
+            input: Vec::new(),
+            consumed: 0,
+            left: None,
+            scanline: vec![0; scanline + 1],
+            previous: Vec::new(),
+            y: 0,
+            done: false,
+        })
+    }
+
+    /// Get the image header.
+    pub fn header(&self) -> &ImageHeader {
+        &self.header
+    }
+
+    /// Returns `true` if rows are decoded as they're read, or `false` if the
+    /// image is interlaced, in which case the whole image is decoded (and
+    /// kept in memory) when the first row is read.
+    pub fn is_streaming(&self) -> bool {
+        !self.header.interlace
+    }
+
+    /// Decode the next row, `None` after the last one.
+    fn row(&mut self) -> Result<Option<Vec<u8>>> {
+        if !self.started {
+            self.start()?;
+        }
+        if let Some(rows) = &mut self.buffered {
+            return Ok(rows.next());
+        }
+        if self.y == self.header.height {
+            self.finish()?;
+            return Ok(None);
+        }
+
+        self.inflate_scanline()?;
+        let bytewidth = (usize::from(self.header.bpp()) + 7) / 8;
+        let mut row = vec![0; self.scanline.len() - 1];
+        let length = row.len();
+        unfilter::unfilter_scanline(
+            &mut row,
+            &self.scanline[1..],
+            (self.y > 0).then_some(self.previous.as_slice()),
+            bytewidth,
+            self.scanline[0],
+            length,
+        )?;
+        self.previous.clone_from(&row);
+
+        // Padding bits are zero
+        let bits = self.header.width as usize * usize::from(self.header.bpp());
+        if bits % 8 != 0 {
+            row[length - 1] &= 0xFF << (8 - bits % 8);
+        }
+        Ok(Some(row))
+    }
+
+    /// Read up to the start of the image data (or all of it, if interlaced).
+    fn start(&mut self) -> Result<()> {
+        self.started = true;
+        if self.header.interlace {
+            let rows = read_rows(&mut self.parser, &self.header)?;
+            self.buffered = Some(rows.into_iter());
+            return Ok(());
+        }
+
+        // Skip chunks before the first IDAT chunk
+        loop {
+            match self.parser.prepare()? {
+                Some(consts::IMAGE_DATA) => break,
+                Some(name) => {
+                    self.parser.count_chunk()?;
+                    self.parser.skip()?;
+                    self.parser.check_crc(&name)?;
+                }
+                None => return Err(Error::NoImageData),
+            }
+        }
+        self.parser.count_chunk()?;
+        self.left = Some(self.parser.len());
+        self.fill()?;
+
+        // Some broken encoders write JPEG data into the IDAT chunks
+        if self.input.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            return Err(Error::JpegInIdat);
+        }
+        Ok(())
+    }
+
+    /// Decompress the next filter type byte and scanline.
+    fn inflate_scanline(&mut self) -> Result<()> {
+        let mut filled = 0;
+        while filled < self.scanline.len() {
+            let more = self.left.is_some();
+            let (consumed, written) = self.inflater.read(
+                &self.input[self.consumed..],
+                more,
+                &mut self.scanline[filled..],
+            )?;
+            self.consumed += consumed;
+            filled += written;
+            if written != 0 {
+                continue;
+            }
+            if self.inflater.is_done() {
+                let stride = self.scanline.len();
+                return Err(Error::InflatedSize {
+                    expected: stride
+                        .saturating_mul(self.header.height as usize),
+                    found: stride * self.y as usize + filled,
+                });
+            }
+            if !self.fill()? && !more {
+                return Err(Error::Inflate(
+                    TINFLStatus::FailedCannotMakeProgress,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Read more compressed image data, keeping what hasn't been inflated yet.
+    /// Returns `false` if there isn't any more.
+    fn fill(&mut self) -> Result<bool> {
+        self.input.drain(..self.consumed);
+        self.consumed = 0;
+
+        while let Some(left) = self.left {
+            if left == 0 {
+                self.parser.check_crc(&consts::IMAGE_DATA)?;
+                // The image data continues in consecutive IDAT chunks
+                self.left = match self.parser.peek()? {
+                    Some((consts::IMAGE_DATA, length)) => {
+                        self.parser.prepare()?;
+                        self.parser.count_chunk()?;
+                        Some(length as usize)
+                    }
+                    _ => None,
+                };
+                continue;
+            }
+
+            let start = self.input.len();
+            let length = left.min(INPUT_SIZE);
+            self.input.resize(start + length, 0);
+            self.parser.read_part(&mut self.input[start..])?;
+            self.left = Some(left - length);
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Finish the compressed stream (checking its checksum), and read the
+    /// rest of the file.
+    fn finish(&mut self) -> Result<()> {
+        let mut discard = [0; 1024];
+        while !self.inflater.is_done() {
+            let more = self.left.is_some();
+            let (consumed, written) = self.inflater.read(
+                &self.input[self.consumed..],
+                more,
+                &mut discard,
+            )?;
+            self.consumed += consumed;
+            if written == 0
+                && !self.inflater.is_done()
+                && !self.fill()?
+                && !more
+            {
+                return Err(Error::Inflate(
+                    TINFLStatus::FailedCannotMakeProgress,
+                ));
+            }
+        }
+
+        // Skip anything left in the IDAT chunks, and the chunks after them
+        while self.left.is_some() {
+            self.consumed = self.input.len();
+            self.fill()?;
+        }
+        while let Some(name) = self.parser.prepare()? {
+            self.parser.count_chunk()?;
+            self.parser.skip()?;
+            self.parser.check_crc(&name)?;
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for Rows<R> {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.row() {
+            Ok(Some(data)) => {
+                let y = self.y;
+                self.y += 1;
+                Some(Ok(Row {
+                    header: self.header,
+                    y,
+                    data,
+                }))
+            }
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
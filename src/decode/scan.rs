@@ -0,0 +1,89 @@
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom};
+
+use crate::{
+    consts,
+    decode::{Error, Result},
+};
+
+/// Get the names of all chunks in a PNG file, in order.
+///
+/// Only the length and name of each chunk are interpreted; chunk data and
+/// CRCs are read and discarded without being parsed or verified.
+pub fn png_chunk_names<R: Read>(mut reader: R) -> Result<Vec<[u8; 4]>> {
+    signature(&mut reader)?;
+
+    let mut names = Vec::new();
+    while let Some((name, length)) = chunk_header(&mut reader)? {
+        skip(&mut reader, u64::from(length) + 4)?;
+        names.push(name);
+    }
+    Ok(names)
+}
+
+/// Get the name, data length and byte offset (of the length field, from the
+/// start of the stream) of each chunk in a PNG file, in order.
+///
+/// Chunk data is seeked over without being read, so a truncated last chunk
+/// isn't detected.
+pub fn png_chunk_sizes<R: Read + Seek>(
+    mut reader: R,
+) -> Result<Vec<([u8; 4], u32, u64)>> {
+    signature(&mut reader)?;
+
+    let mut chunks = Vec::new();
+    loop {
+        let offset = reader.stream_position()?;
+        let Some((name, length)) = chunk_header(&mut reader)? else {
+            break;
+        };
+        reader.seek(SeekFrom::Current(i64::from(length) + 4))?;
+        chunks.push((name, length, offset));
+    }
+    Ok(chunks)
+}
+
+/// Read and check the PNG signature.
+pub(crate) fn signature<R: Read>(reader: &mut R) -> Result<()> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    if buf != consts::PNG_SIGNATURE {
+        return Err(Error::InvalidSignature);
+    }
+    Ok(())
+}
+
+/// Read the name and length of the next chunk, or `None` at the end of the
+/// file.
+pub(crate) fn chunk_header<R: Read>(
+    reader: &mut R,
+) -> Result<Option<([u8; 4], u32)>> {
+    let mut header = [0u8; 8];
+    match reader.read_exact(&mut header[..1]) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    reader.read_exact(&mut header[1..]).map_err(|e| {
+        if e.kind() == ErrorKind::UnexpectedEof {
+            Error::Eof
+        } else {
+            e.into()
+        }
+    })?;
+
+    let [l0, l1, l2, l3, n0, n1, n2, n3] = header;
+    let (length, name) =
+        (u32::from_be_bytes([l0, l1, l2, l3]), [n0, n1, n2, n3]);
+    if length > consts::MAX_CHUNK_SIZE as u32 {
+        return Err(Error::ChunkLength(name));
+    }
+    Ok(Some((name, length)))
+}
+
+/// Read and discard `count` bytes.
+pub(crate) fn skip<R: Read>(reader: &mut R, count: u64) -> Result<()> {
+    if io::copy(&mut reader.take(count), &mut io::sink())? != count {
+        return Err(Error::Eof);
+    }
+    Ok(())
+}
@@ -0,0 +1,25 @@
+use std::io::Read;
+
+use crate::{decode::Result, Decoder};
+
+/// Get the raw payload bytes of the first chunk named `name` in a PNG file
+/// (for example, the ICC profile from "iCCP"), or `None` if there isn't one.
+///
+/// Chunks before it are skipped without being parsed, but their CRC32
+/// checksums are still verified.
+pub fn decode_single_chunk<R: Read>(
+    reader: R,
+    name: [u8; 4],
+) -> Result<Option<Vec<u8>>> {
+    let mut parser = Decoder::new(reader)?.parser();
+
+    while let Some(found) = parser.prepare()? {
+        if found == name {
+            return parser.raw().map(Some);
+        }
+        parser.skip()?;
+        parser.check_crc(&found)?;
+    }
+
+    Ok(None)
+}
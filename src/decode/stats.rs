@@ -0,0 +1,10 @@
+/// Statistics about the last step decoded by [`Steps`](struct.Steps.html).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DecodeStats {
+    /// Filter type of each scanline (0 to 4 for None, Sub, Up, Average and
+    /// Paeth), in the order they're stored: pass by pass for interlaced
+    /// images.  Encoding with
+    /// [`FilterStrategy::Explicit`](crate::encode::FilterStrategy::Explicit)
+    /// and these filters reproduces the same filtered scanlines.
+    pub filters: Vec<u8>,
+}
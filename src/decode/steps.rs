@@ -1,18 +1,22 @@
-use std::{collections::HashMap, io::Read, iter::Peekable};
+use std::{collections::HashMap, io::Read};
 
 use pix::{Palette, Raster};
 
 use crate::{
+    adam7,
     chunk::{
         Background, Chunk, ColorType, ImageHeader, Palette as PaletteChunk,
         Physical, Time, Transparency,
     },
     consts,
-    decode::{Chunks, Error as DecoderError},
-    zlib, PngRaster, Step,
+    decode::{Chunks, DecodeStats, Error as DecoderError},
+    zlib, Decoder, PngRaster, Step,
 };
 
-mod unfilter;
+mod linear;
+pub(super) mod unfilter;
+
+pub use linear::LinearSteps;
 
 #[derive(Debug)]
 struct TextEntry {
@@ -24,10 +28,28 @@ struct TextEntry {
     transkey: Option<String>,
 }
 
+/// Kind of the next chunk, for finding where the image data of a step starts
+/// and ends.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Next {
+    /// Image data, left unread to be appended to the IDAT buffer
+    ImageData,
+    /// The image end chunk
+    ImageEnd,
+    /// Any other chunk
+    Other,
+}
+
 /// Iterator over `Step`s for PNG files.
+///
+/// Every step is an image: the header chunk is read along with the first
+/// step, and is available from [`header()`](Self::header) rather than as a
+/// step of its own.
 #[derive(Debug)]
 pub struct Steps<R: Read> {
-    decoder: Peekable<Chunks<R>>,
+    decoder: Chunks<R>,
+    // Chunk read ahead by `peek()`, if any.
+    peeked: Option<Option<Result<Chunk, DecoderError>>>,
     // FIXME: This is a workaround for not supporting APNG yet.
     #[allow(dead_code)]
     has_decoded: bool,
@@ -51,17 +73,28 @@ pub struct Steps<R: Read> {
     physical: Option<Physical>,
     //
     time: Option<Time>,
+    // Gamma times 100 000 (gAMA)
+    gamma: Option<u32>,
+    // Is the image in the sRGB color space (sRGB)?
+    srgb: bool,
     // True if after palette chunk found
     reject_pal: bool,
+    // Inflate state, reset for each frame
+    inflater: zlib::Inflater,
+    // Compressed image data of the current frame
+    idat: Vec<u8>,
+    // Inflated (filtered) scanlines of the current frame
+    scanlines: Vec<u8>,
+    // None if haven't decoded a frame yet.
+    stats: Option<DecodeStats>,
 }
 
 impl<R: Read> Steps<R> {
     /// Create a new decoder.
-    pub(crate) fn new(chunks: Chunks<R>) -> Self {
-        let decoder = chunks.peekable();
-
+    pub(crate) fn new(decoder: Chunks<R>) -> Self {
         Self {
             decoder,
+            peeked: None,
             has_decoded: false,
             header: None,
             idat_anim: false,
@@ -72,22 +105,98 @@ impl<R: Read> Steps<R> {
             physical: None,
             text: HashMap::new(),
             time: None,
+            gamma: None,
+            srgb: false,
             reject_pal: false,
+            inflater: zlib::Inflater::default(),
+            idat: Vec::new(),
+            scanlines: Vec::new(),
+            stats: None,
         }
     }
-}
 
-impl<R> Iterator for Steps<R>
-where
-    R: Read,
-{
-    type Item = Result<Step, DecoderError>;
+    /// Get the image header, available once the first chunk has been read.
+    pub fn header(&self) -> Option<&ImageHeader> {
+        self.header.as_ref()
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
+    /// Get the palette, available once the first step has been read.
+    pub fn palette(&self) -> Option<&PaletteChunk> {
+        self.palette.as_ref()
+    }
+
+    /// Get the transparency, available once the first step has been read.
+    pub fn transparency(&self) -> Option<&Transparency> {
+        self.transparency.as_ref()
+    }
+
+    /// Get statistics about the last decoded step, `None` if nothing has been
+    /// decoded yet.
+    pub fn stats(&self) -> Option<&DecodeStats> {
+        self.stats.as_ref()
+    }
+
+    /// Convert into an iterator over rasters ready for GPU upload: linear
+    /// light, premultiplied alpha, 32-bit floating point RGBA.
+    ///
+    /// The file's transfer function is undone (the sRGB curve if there's an
+    /// sRGB chunk, otherwise the gAMA chunk's gamma, otherwise the sRGB
+    /// curve), greyscale and palette images are expanded to RGBA, and colors
+    /// are premultiplied by alpha, in a single pass over each raster.
+    pub fn into_linear_f32_premultiplied(self) -> LinearSteps<R> {
+        LinearSteps::new(self)
+    }
+
+    /// Get the kind of the next chunk.  Chunks other than image data are read
+    /// ahead, and returned by the next call to `next_chunk()`.
+    fn peek(&mut self) -> Option<Result<Next, DecoderError>> {
+        if self.peeked.is_none() {
+            match self.decoder.peek_name() {
+                Ok(Some((consts::IMAGE_DATA, _))) => {
+                    return Some(Ok(Next::ImageData))
+                }
+                Ok(_) => self.peeked = Some(self.decoder.next()),
+                Err(e) => self.peeked = Some(Some(Err(e))),
+            }
+        }
+        Some(match self.peeked.as_ref().and_then(Option::as_ref)? {
+            // Read ahead when an ancillary chunk over the limits was skipped
+            Ok(chunk) if chunk.is_idat() => Ok(Next::ImageData),
+            Ok(chunk) if chunk.is_iend() => Ok(Next::ImageEnd),
+            Ok(_) => Ok(Next::Other),
+            Err(e) => Err(e.clone()),
+        })
+    }
+
+    /// Get the next chunk.
+    fn next_chunk(&mut self) -> Option<Result<Chunk, DecoderError>> {
+        self.peeked.take().unwrap_or_else(|| self.decoder.next())
+    }
+
+    /// Append the next chunk, which is image data, to the IDAT buffer.  Unless
+    /// it's already been read ahead, its payload is appended straight from
+    /// the parser, without a copy of its own.
+    fn read_image_data(&mut self) -> Result<(), DecoderError> {
+        match self.peeked.take() {
+            Some(Some(Ok(Chunk::ImageData(data)))) => {
+                self.idat.extend_from_slice(&data.data);
+                Ok(())
+            }
+            Some(_) => unreachable!(),
+            None => self.decoder.read_image_data(&mut self.idat),
+        }
+    }
+
+    /// Read the next step, only decoding it if `decode` is `true` (otherwise
+    /// returning `Ok(None)`).
+    fn step(
+        &mut self,
+        decode: bool,
+    ) -> Option<Result<Option<Step>, DecoderError>> {
         // First frame
         if self.header.is_none() {
             // First chunk must be IHDR
-            self.header = match self.decoder.next().ok_or(DecoderError::Empty) {
+            self.header = match self.next_chunk().ok_or(DecoderError::Empty) {
                 Ok(Ok(Chunk::ImageHeader(header))) => Some(header),
                 Ok(Ok(_chunk)) => return Some(Err(DecoderError::ChunkOrder)),
                 Ok(Err(e)) => return Some(Err(e)),
@@ -96,16 +205,16 @@ where
 
             // Go through chunks before IDAT
             while {
-                match self.decoder.peek() {
-                    Some(Ok(chunk)) => !chunk.is_idat(),
+                match self.peek() {
+                    Some(Ok(next)) => next != Next::ImageData,
                     Some(Err(DecoderError::UnknownChunkType(_))) => true,
-                    Some(Err(e)) => return Some(Err(e.clone())),
+                    Some(Err(e)) => return Some(Err(e)),
                     None => return Some(Err(DecoderError::NoImageData)),
                 }
             } {
                 use Chunk::*;
                 // Won't panic
-                let chunk = if let Ok(chunk) = self.decoder.next().unwrap() {
+                let chunk = if let Ok(chunk) = self.next_chunk().unwrap() {
                     chunk
                 } else {
                     continue; // Skip unknown chunks
@@ -191,65 +300,80 @@ where
                     }
                     ImageEnd(_) => return Some(Err(DecoderError::NoImageData)),
                     ImageData(_) => unreachable!(),
-                    Unknown(_) => continue, // Skip unknown chunks
+                    Unknown(chunk) => match chunk.name {
+                        consts::GAMMA => {
+                            self.gamma = chunk
+                                .data
+                                .as_slice()
+                                .try_into()
+                                .ok()
+                                .map(u32::from_be_bytes);
+                        }
+                        consts::SRGB => self.srgb = true,
+                        _ => {} // Skip unknown chunks
+                    },
                 }
             }
         }
 
         // Check for ImageEnd
-        if let Some(Ok(chunk)) = self.decoder.peek() {
-            if chunk.is_iend() {
-                if let Err(e) = self.decoder.next().unwrap() {
-                    return Some(Err(e));
-                }
-                if self.decoder.next().is_some() {
-                    return Some(Err(DecoderError::TrailingChunk));
-                }
-                return None;
+        if let Some(Ok(Next::ImageEnd)) = self.peek() {
+            if let Err(e) = self.next_chunk().unwrap() {
+                return Some(Err(e));
             }
+            if self.next_chunk().is_some() {
+                return Some(Err(DecoderError::TrailingChunk));
+            }
+            return None;
         }
 
         // Image data for consecutive IDAT chunks.
-        let mut idat = Vec::new();
+        self.idat.clear();
 
         // Go through until the last IDAT or fdAT chunk.
         while {
-            let chunk = match self.decoder.peek() {
-                Some(Ok(chunk)) => chunk,
-                Some(Err(e)) => return Some(Err(e.clone())),
+            match self.peek() {
+                Some(Ok(next)) => next == Next::ImageData,
+                Some(Err(e)) => return Some(Err(e)),
                 None => return Some(Err(DecoderError::NoImageData)),
-            };
-            chunk.is_idat()
+            }
         } {
-            match self.decoder.next().unwrap() {
-                Ok(Chunk::ImageData(data)) => idat.extend(data.data),
-                Ok(_) => unreachable!(),
-                Err(e) => return Some(Err(e)),
+            if let Err(e) = self.read_image_data() {
+                return Some(Err(e));
             }
         }
 
-        let raster = match decode(
-            idat.as_slice(),
-            self.header.as_ref().unwrap(),
-            self.palette.as_ref(),
-            self.transparency.as_ref(),
-        ) {
-            Ok(raster) => raster,
-            Err(e) => return Some(Err(e)),
+        let raster = if decode {
+            let mut filters = Vec::new();
+            let raster = match decode_with(
+                &mut self.inflater,
+                &mut self.scanlines,
+                &mut filters,
+                self.idat.as_slice(),
+                self.header.as_ref().unwrap(),
+                self.palette.as_ref(),
+                self.transparency.as_ref(),
+            ) {
+                Ok(raster) => raster,
+                Err(e) => return Some(Err(e)),
+            };
+            self.stats = Some(DecodeStats { filters });
+            Some(raster)
+        } else {
+            None
         };
 
         // Check for non-required chunks up until the next IDAT or fdAT chunk or
         // end
         while {
-            let chunk = match self.decoder.peek() {
-                Some(Ok(chunk)) => chunk,
-                Some(Err(e)) => return Some(Err(e.clone())),
+            match self.peek() {
+                Some(Ok(next)) => next == Next::Other,
+                Some(Err(e)) => return Some(Err(e)),
                 None => return Some(Err(DecoderError::NoImageData)),
-            };
-            !chunk.is_idat() && !chunk.is_iend()
+            }
         } {
             use Chunk::*;
-            match self.decoder.next().unwrap().unwrap() {
+            match self.next_chunk().unwrap().unwrap() {
                 // won't panic
                 InternationalText(chunk) => {
                     self.text.insert(
@@ -294,18 +418,51 @@ where
                 Transparency(_) => return Some(Err(DecoderError::ChunkOrder)),
                 ImageData(_) => unreachable!(),
                 ImageEnd(_) => unreachable!(),
-                Unknown(unknown) => {
-                    return Some(Err(DecoderError::UnknownChunkType(
-                        unknown.name,
-                    )))
-                }
+                Unknown(unknown) => match unknown.name {
+                    // Text chunks left unparsed by `Decoder::parse_text()`
+                    consts::TEXT | consts::ZTEXT | consts::ITEXT => {}
+                    name => {
+                        return Some(Err(DecoderError::UnknownChunkType(name)))
+                    }
+                },
             }
         }
 
-        Some(Ok(Step { raster, delay: 0 }))
+        Some(Ok(raster.map(|raster| Step { raster, delay: 0 })))
     }
 }
 
+impl<R> Iterator for Steps<R>
+where
+    R: Read,
+{
+    type Item = Result<Step, DecoderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Decoded steps are always `Some`
+        self.step(true).map(|step| step.map(Option::unwrap))
+    }
+
+    /// Skipped steps are read (and their CRCs checked), but not decompressed
+    /// or converted to rasters.
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        for _ in 0..n {
+            if let Err(e) = self.step(false)? {
+                return Some(Err(e));
+            }
+        }
+        self.next()
+    }
+}
+
+/// Decode every step of a PNG file, returning the first error if any.
+///
+/// This is the eager version of [`Decoder::into_steps()`], for images that
+/// fit in memory.
+pub fn decode_steps<R: Read>(reader: R) -> Result<Vec<Step>, DecoderError> {
+    Decoder::new(reader)?.into_steps().collect()
+}
+
 /// Decode one `Step` from header and compressed pixel data.
 pub(crate) fn decode(
     buffer: &[u8],
@@ -313,21 +470,33 @@ pub(crate) fn decode(
     palette: Option<&PaletteChunk>,
     transparency: Option<&Transparency>,
 ) -> Result<PngRaster, DecoderError> {
-    // Decompress and unfilter pixel data.
-    let mut scanlines = zlib::decompress(buffer)?;
-    let mut buf = vec![0; header.raw_size()];
-    unfilter::postprocess_scanlines(
-        &mut buf,
-        &mut scanlines,
-        header.width,
-        header.height,
+    decode_with(
+        &mut zlib::Inflater::default(),
+        &mut Vec::new(),
+        &mut Vec::new(),
+        buffer,
         header,
-    )?;
+        palette,
+        transparency,
+    )
+}
 
+/// Decode one `Step`, reusing the inflate state and scanline buffer of
+/// previous frames, and recording the filter type of each scanline.
+fn decode_with(
+    inflater: &mut zlib::Inflater,
+    scanlines: &mut Vec<u8>,
+    filters: &mut Vec<u8>,
+    buffer: &[u8],
+    header: &ImageHeader,
+    palette: Option<&PaletteChunk>,
+    transparency: Option<&Transparency>,
+) -> Result<PngRaster, DecoderError> {
+    let buf = samples(inflater, scanlines, filters, buffer, header)?;
     let width = header.width;
     let height = header.height;
     let color_type = header.color_type;
-    let bit_depth = header.bit_depth;
+    let bit_depth = header.bit_depth.max(8);
 
     Ok(match (color_type, bit_depth) {
         (ColorType::Grey, 8) => {
@@ -392,3 +561,117 @@ pub(crate) fn decode(
         (ct, bd) => return Err(DecoderError::ColorMode(ct, bd)),
     })
 }
+
+/// Decompress and unfilter pixel data, returning the interleaved samples of
+/// each row, without padding (samples below 8 bits are unpacked to a byte
+/// each, and 16-bit samples are big-endian).  The filter type of each
+/// scanline is written to `filters`.
+pub(crate) fn samples(
+    inflater: &mut zlib::Inflater,
+    scanlines: &mut Vec<u8>,
+    filters: &mut Vec<u8>,
+    buffer: &[u8],
+    header: &ImageHeader,
+) -> Result<Vec<u8>, DecoderError> {
+    let buf = pixels(inflater, scanlines, filters, buffer, header)?;
+
+    // Only greyscale and palette images have depths below 8 bits
+    if header.bit_depth < 8 {
+        let count = header.width as usize * header.height as usize;
+        let scale = header.color_type == ColorType::Grey;
+        return Ok(unpack(&buf, header.bit_depth, count, scale));
+    }
+    Ok(buf)
+}
+
+/// Decompress and unfilter pixel data like [`samples()`], but with samples
+/// below 8 bits left packed (without padding between rows).
+pub(crate) fn pixels(
+    inflater: &mut zlib::Inflater,
+    scanlines: &mut Vec<u8>,
+    filters: &mut Vec<u8>,
+    buffer: &[u8],
+    header: &ImageHeader,
+) -> Result<Vec<u8>, DecoderError> {
+    let idat_raw_size =
+        header.idat_raw_size().ok_or(DecoderError::LimitExceeded)?;
+    let raw_size = header.raw_size().ok_or(DecoderError::LimitExceeded)?;
+    // Some broken encoders write JPEG data into the IDAT chunks
+    if buffer.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Err(DecoderError::JpegInIdat);
+    }
+    inflater.decompress_into(buffer, scanlines)?;
+    if scanlines.len() < idat_raw_size {
+        return Err(DecoderError::InflatedSize {
+            expected: idat_raw_size,
+            found: scanlines.len(),
+        });
+    }
+    // Unfiltering overwrites the filter types
+    filter_types(scanlines, header, filters);
+    let mut buf = vec![0; raw_size];
+    unfilter::postprocess_scanlines(
+        &mut buf,
+        scanlines,
+        header.width,
+        header.height,
+        header,
+    )?;
+    Ok(buf)
+}
+
+/// Get the filter type of each scanline of inflated image data.
+fn filter_types(scanlines: &[u8], header: &ImageHeader, filters: &mut Vec<u8>) {
+    let bpp = header.bpp();
+    let line_bytes = |width: u32| (width as usize * usize::from(bpp) + 7) / 8;
+
+    filters.clear();
+    if !header.interlace {
+        let stride = line_bytes(header.width) + 1;
+        filters.extend(
+            scanlines
+                .iter()
+                .step_by(stride)
+                .take(header.height as usize),
+        );
+        return;
+    }
+    let (passw, passh, filter_passstart, _, _) =
+        adam7::get_pass_values(header.width, header.height, bpp);
+    for pass in 0..usize::from(adam7::PASSES) {
+        if passw[pass] == 0 || passh[pass] == 0 {
+            continue;
+        }
+        let stride = line_bytes(passw[pass]) + 1;
+        filters.extend(
+            scanlines[filter_passstart[pass]..]
+                .iter()
+                .step_by(stride)
+                .take(passh[pass] as usize),
+        );
+    }
+}
+
+/// Unpack `count` samples of `bit_depth` bits (packed without padding) into a
+/// byte each, scaling them up to 8 bits by bit replication if `scale`.
+pub(super) fn unpack(
+    packed: &[u8],
+    bit_depth: u8,
+    count: usize,
+    scale: bool,
+) -> Vec<u8> {
+    let mask = (1u8 << bit_depth) - 1;
+    let factor = if scale { u8::MAX / mask } else { 1 };
+    let per_byte = usize::from(8 / bit_depth);
+
+    packed
+        .iter()
+        .flat_map(|&byte| {
+            (1..=per_byte).map(move |i| {
+                let shift = 8 - i * usize::from(bit_depth);
+                ((byte >> shift) & mask) * factor
+            })
+        })
+        .take(count)
+        .collect()
+}
@@ -0,0 +1,228 @@
+use std::io::Read;
+
+use pix::{el::Pixel, rgb::Rgba32p, Raster};
+
+use super::Steps;
+use crate::{decode::Error as DecoderError, PngRaster};
+
+/// Iterator over linear, premultiplied, 32-bit floating point RGBA rasters,
+/// as returned by [`Steps::into_linear_f32_premultiplied()`].
+#[derive(Debug)]
+pub struct LinearSteps<R: Read> {
+    steps: Steps<R>,
+    // Linear value of each 8-bit sample, empty until the first step is read
+    lut: Vec<f32>,
+}
+
+impl<R: Read> LinearSteps<R> {
+    pub(super) fn new(steps: Steps<R>) -> Self {
+        Self {
+            steps,
+            lut: Vec::new(),
+        }
+    }
+
+    /// Get the transfer function of the file (available once the first step
+    /// has been read).
+    fn transfer(&self) -> Transfer {
+        match self.steps.gamma {
+            Some(gamma) if !self.steps.srgb && gamma != 0 => {
+                Transfer::Gamma(100_000.0 / gamma as f32)
+            }
+            _ => Transfer::Srgb,
+        }
+    }
+}
+
+impl<R: Read> Iterator for LinearSteps<R> {
+    type Item = Result<Raster<Rgba32p>, DecoderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let step = match self.steps.next()? {
+            Ok(step) => step,
+            Err(e) => return Some(Err(e)),
+        };
+        let transfer = self.transfer();
+        if self.lut.is_empty() {
+            self.lut = (0..=u8::MAX)
+                .map(|v| transfer.linear(f32::from(v) / 255.0))
+                .collect();
+        }
+
+        Some(Ok(convert(&step.raster, &self.lut, transfer)))
+    }
+}
+
+/// Transfer function from samples to linear light.
+#[derive(Copy, Clone, Debug)]
+enum Transfer {
+    /// The sRGB curve
+    Srgb,
+    /// A power curve with this exponent
+    Gamma(f32),
+}
+
+impl Transfer {
+    /// Get the linear value of a sample between 0 and 1.
+    fn linear(self, value: f32) -> f32 {
+        match self {
+            Transfer::Srgb if value <= 0.04045 => value / 12.92,
+            Transfer::Srgb => ((value + 0.055) / 1.055).powf(2.4),
+            Transfer::Gamma(exponent) => value.powf(exponent),
+        }
+    }
+}
+
+/// Get the 16-bit samples of a raster, which are stored big-endian (in the
+/// byte order of the PNG file).
+fn samples16(bytes: &[u8]) -> impl Iterator<Item = u16> + '_ {
+    bytes
+        .chunks_exact(2)
+        .map(|word| u16::from_be_bytes([word[0], word[1]]))
+}
+
+/// Convert `raster` to linear, premultiplied RGBA, using `lut` for the linear
+/// values of 8-bit samples.
+fn convert(
+    raster: &PngRaster,
+    lut: &[f32],
+    transfer: Transfer,
+) -> Raster<Rgba32p> {
+    use PngRaster::*;
+
+    let c8 = |v: u8| lut[usize::from(v)];
+    let c16 = |v: u16| transfer.linear(f32::from(v) / 65535.0);
+    let a8 = |v: u8| f32::from(v) / 255.0;
+    let a16 = |v: u16| f32::from(v) / 65535.0;
+    let pixel =
+        |r: f32, g: f32, b: f32, a: f32| Rgba32p::new(r * a, g * a, b * a, a);
+
+    let (width, height, pixels): (u32, u32, Vec<Rgba32p>) = match raster {
+        Gray8(r) => (
+            r.width(),
+            r.height(),
+            r.pixels()
+                .iter()
+                .map(|p| {
+                    let v = c8(p.one().into());
+                    pixel(v, v, v, 1.0)
+                })
+                .collect(),
+        ),
+        Gray16(r) => (
+            r.width(),
+            r.height(),
+            samples16(r.as_u8_slice())
+                .map(|v| {
+                    let v = c16(v);
+                    pixel(v, v, v, 1.0)
+                })
+                .collect(),
+        ),
+        Rgb8(r) => (
+            r.width(),
+            r.height(),
+            r.pixels()
+                .iter()
+                .map(|p| {
+                    let [red, green, blue] = [p.one(), p.two(), p.three()];
+                    pixel(
+                        c8(red.into()),
+                        c8(green.into()),
+                        c8(blue.into()),
+                        1.0,
+                    )
+                })
+                .collect(),
+        ),
+        Rgb16(r) => (
+            r.width(),
+            r.height(),
+            samples16(r.as_u8_slice())
+                .collect::<Vec<_>>()
+                .chunks_exact(3)
+                .map(|p| pixel(c16(p[0]), c16(p[1]), c16(p[2]), 1.0))
+                .collect(),
+        ),
+        Palette(r, pal, pa) => {
+            // Convert each palette entry once
+            let colors: Vec<Rgba32p> = pal
+                .colors()
+                .iter()
+                .enumerate()
+                .map(|(i, p)| {
+                    let [red, green, blue] = [p.one(), p.two(), p.three()];
+                    // The tRNS chunk can be shorter than the palette
+                    let a = pa.get(i).copied().unwrap_or(u8::MAX);
+                    pixel(
+                        c8(red.into()),
+                        c8(green.into()),
+                        c8(blue.into()),
+                        a8(a),
+                    )
+                })
+                .collect();
+            (
+                r.width(),
+                r.height(),
+                r.pixels()
+                    .iter()
+                    .map(|p| {
+                        let i: u8 = p.one().into();
+                        // Indices outside of the palette are opaque black
+                        colors
+                            .get(usize::from(i))
+                            .copied()
+                            .unwrap_or_else(|| pixel(0.0, 0.0, 0.0, 1.0))
+                    })
+                    .collect(),
+            )
+        }
+        Graya8(r) => (
+            r.width(),
+            r.height(),
+            r.pixels()
+                .iter()
+                .map(|p| {
+                    let v = c8(p.one().into());
+                    pixel(v, v, v, a8(p.alpha().into()))
+                })
+                .collect(),
+        ),
+        Graya16(r) => (
+            r.width(),
+            r.height(),
+            samples16(r.as_u8_slice())
+                .collect::<Vec<_>>()
+                .chunks_exact(2)
+                .map(|p| {
+                    let v = c16(p[0]);
+                    pixel(v, v, v, a16(p[1]))
+                })
+                .collect(),
+        ),
+        Rgba8(r) => (
+            r.width(),
+            r.height(),
+            r.pixels()
+                .iter()
+                .map(|p| {
+                    let [red, green, blue] = [p.one(), p.two(), p.three()];
+                    let a = a8(p.alpha().into());
+                    pixel(c8(red.into()), c8(green.into()), c8(blue.into()), a)
+                })
+                .collect(),
+        ),
+        Rgba16(r) => (
+            r.width(),
+            r.height(),
+            samples16(r.as_u8_slice())
+                .collect::<Vec<_>>()
+                .chunks_exact(4)
+                .map(|p| pixel(c16(p[0]), c16(p[1]), c16(p[2]), a16(p[3])))
+                .collect(),
+        ),
+    };
+
+    Raster::with_pixels(width, height, pixels)
+}
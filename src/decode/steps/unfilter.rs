@@ -1,9 +1,5 @@
 use crate::{
-    adam7,
-    bitstream::{BitstreamReader, BitstreamWriter},
-    chunk::ImageHeader,
-    decode::Error as DecoderError,
-    encode::filter,
+    adam7, chunk::ImageHeader, decode::Error as DecoderError, encode::filter,
 };
 
 /*out must be buffer big enough to contain full image, and in must contain the full decompressed data from
@@ -47,8 +43,8 @@ pub(super) fn postprocess_scanlines(
         for i in 0..7 {
             unfilter_aliased(
                 inp,
-                padded_passstart[i] as usize,
-                filter_passstart[i] as usize,
+                padded_passstart[i],
+                filter_passstart[i],
                 passw[i] as usize,
                 passh[i] as usize,
                 bpp as usize,
@@ -58,8 +54,8 @@ pub(super) fn postprocess_scanlines(
                 bits between the different reduced images: each reduced image still starts nicely at a byte*/
                 remove_padding_bits_aliased(
                     inp,
-                    passstart[i] as usize,
-                    padded_passstart[i] as usize,
+                    passstart[i],
+                    padded_passstart[i],
                     passw[i] as usize * bpp as usize,
                     ((passw[i] as usize * bpp as usize + 7) / 8) * 8,
                     passh[i] as usize,
@@ -80,26 +76,16 @@ fn remove_padding_bits_aliased(
     h: usize,
 ) {
     let diff = ilinebits - olinebits; /* input and output bit pointers */
-    let mut ibp = 0;
-    let mut out = Vec::with_capacity(olinebits);
-    let mut out_stream = BitstreamWriter::new(&mut out);
+    let mut ibp = 8 * in_off;
+    let mut obp = 8 * out_off;
     for _ in 0..h {
         for _ in 0..olinebits {
-            let bit = {
-                let mut in_stream = BitstreamReader::with_bitpointer(
-                    std::io::Cursor::new(&inout[in_off..]),
-                    ibp,
-                )
-                .unwrap();
-                in_stream.read().unwrap().unwrap()
-            };
-            out_stream.write(bit).unwrap();
+            // The output is never after the input, so no unread bits are
+            // overwritten
+            let bit = read_bit_from_reversed_stream(&mut ibp, inout);
+            adam7::set_bit_of_reversed_stream(&mut obp, inout, bit);
         }
-        ibp += olinebits + diff;
-    }
-    // Copy output buffer into array.
-    for (i, byte) in out.iter().cloned().enumerate() {
-        inout[out_off + i] = byte;
+        ibp += diff;
     }
 }
 
@@ -152,28 +138,27 @@ fn remove_padding_bits(
 ) {
     let diff = ilinebits - olinebits; /* input and output bit pointers */
     let mut ibp = 0;
-    let mut out_buf = Vec::new();
-    let mut out_stream = BitstreamWriter::new(&mut out_buf);
+    let mut obp = 0;
     for _ in 0..h {
         for _ in 0..olinebits {
-            let bit = {
-                let mut in_stream = BitstreamReader::with_bitpointer(
-                    std::io::Cursor::new(inp),
-                    ibp,
-                )
-                .unwrap();
-                in_stream.read().unwrap().unwrap()
-            };
-            out_stream.write(bit).unwrap();
+            let bit = read_bit_from_reversed_stream(&mut ibp, inp);
+            adam7::set_bit_of_reversed_stream(&mut obp, out, bit);
         }
-        ibp += olinebits + diff;
-    }
-    // Copy output buffer into array.
-    for (i, byte) in out_buf.iter().cloned().enumerate() {
-        out[i] = byte;
+        ibp += diff;
     }
 }
 
+/// Read the bit at `bitpointer` (most significant bit first), and advance it.
+#[inline(always)]
+fn read_bit_from_reversed_stream(
+    bitpointer: &mut usize,
+    bitstream: &[u8],
+) -> bool {
+    let bit = (bitstream[*bitpointer >> 3] >> (7 - (*bitpointer & 7))) & 1;
+    *bitpointer += 1;
+    bit != 0
+}
+
 /*
 For PNG filter method 0
 this function unfilters a single image (e.g. without interlacing this is called once, with Adam7 seven times)
@@ -309,7 +294,7 @@ fn unfilter_scanline_aliased(
 /// result, scanline the current one the incoming scanlines do NOT include the
 /// filter_type byte, that one is given in the parameter filter_type instead
 /// recon and scanline MAY be the same memory address! precon must be disjoint.
-pub(super) fn unfilter_scanline(
+pub(crate) fn unfilter_scanline(
     recon: &mut [u8],
     scanline: &[u8],
     precon: Option<&[u8]>,
@@ -375,3 +360,44 @@ pub(super) fn unfilter_scanline(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::ColorType;
+
+    /// 3×4 1-bit greyscale image, with rows of 1 0 1, 0 1 1, 1 1 0 and 0 0 1
+    const PIXELS: [u8; 2] = [0b1010_1111, 0b0001_0000];
+
+    fn header(interlace: bool) -> ImageHeader {
+        ImageHeader {
+            width: 3,
+            height: 4,
+            color_type: ColorType::Grey,
+            bit_depth: 1,
+            interlace,
+        }
+    }
+
+    #[test]
+    fn remove_padding_bits() {
+        // Filter type byte and padded scanline for each row
+        let mut scanlines = [0, 0xA0, 0, 0x60, 0, 0xC0, 0, 0x20];
+        let mut out = [0; 2];
+        postprocess_scanlines(&mut out, &mut scanlines, 3, 4, &header(false))
+            .unwrap();
+        assert_eq!(out, PIXELS);
+    }
+
+    #[test]
+    fn remove_padding_bits_interlaced() {
+        // Scanlines of passes 1, 4, 5, 6 and 7 (the others are empty)
+        let mut scanlines = [
+            0, 0x80, 0, 0x80, 0, 0x80, 0, 0x00, 0, 0x80, 0, 0x60, 0, 0x20,
+        ];
+        let mut out = [0; 2];
+        postprocess_scanlines(&mut out, &mut scanlines, 3, 4, &header(true))
+            .unwrap();
+        assert_eq!(out, PIXELS);
+    }
+}
@@ -0,0 +1,123 @@
+use std::io::Read;
+
+use super::header::read_header;
+use crate::{
+    chunk::{self, Chunk, ColorType, Physical, Time},
+    consts,
+    decode::{Error, Result},
+    Decoder,
+};
+
+/// Metadata of a PNG file, as returned by [`scan()`].
+#[derive(Clone, Debug)]
+pub struct Summary {
+    /// Width of the image in pixels
+    pub width: u32,
+    /// Height of the image in pixels
+    pub height: u32,
+    /// Color type of the image
+    pub color_type: ColorType,
+    /// Bit depth of each sample
+    pub bit_depth: u8,
+    /// Whether the image is interlaced
+    pub interlace: bool,
+    /// Number of frames, if the file is an APNG animation (acTL)
+    pub frames: Option<u32>,
+    /// Physical pixel dimensions (pHYs)
+    pub physical: Option<Physical>,
+    /// Gamma times 100 000 (gAMA)
+    pub gamma: Option<u32>,
+    /// Rendering intent, if the image is in the sRGB color space (sRGB)
+    pub srgb_intent: Option<u8>,
+    /// Time of the last modification (tIME)
+    pub time: Option<Time>,
+    /// Keywords of the text chunks (tEXt, zTXt and iTXt), in order
+    pub keywords: Vec<String>,
+}
+
+impl Summary {
+    /// Is the file an APNG animation?
+    pub fn is_animation(&self) -> bool {
+        self.frames.is_some()
+    }
+
+    /// Get the horizontal and vertical resolution in dots per inch, if the
+    /// physical pixel dimensions are given in meters.
+    pub fn dpi(&self) -> Option<(f32, f32)> {
+        let (x, y) = self.physical?.dpi()?;
+
+        Some((x as f32, y as f32))
+    }
+}
+
+/// Get the metadata of a PNG file (dimensions, color type, animation, pixel
+/// density, color space, time and text keywords) in one pass.
+///
+/// No image data is decompressed: image data and unknown chunks are read and
+/// discarded, and text chunks are only read up to their keyword.  CRCs are
+/// still checked.  Reading stops at the IEND chunk.
+pub fn scan<R: Read>(reader: R) -> Result<Summary> {
+    let mut parser = Decoder::new(reader)?.parser();
+    let header = read_header(&mut parser)?;
+    let mut summary = Summary {
+        width: header.width,
+        height: header.height,
+        color_type: header.color_type,
+        bit_depth: header.bit_depth,
+        interlace: header.interlace,
+        frames: None,
+        physical: None,
+        gamma: None,
+        srgb_intent: None,
+        time: None,
+        keywords: Vec::new(),
+    };
+
+    while let Some(name) = parser.prepare()? {
+        parser.count_chunk()?;
+        match name {
+            consts::ANIMATION_CONTROL => {
+                let [f0, f1, f2, f3, ..] = parser.bytes::<8>()?;
+                summary.frames = Some(u32::from_be_bytes([f0, f1, f2, f3]));
+            }
+            consts::PHYSICAL => {
+                if let Chunk::Physical(physical) = Physical::parse(&mut parser)?
+                {
+                    summary.physical = Some(physical);
+                }
+            }
+            consts::GAMMA => {
+                summary.gamma = Some(u32::from_be_bytes(parser.bytes()?));
+            }
+            consts::SRGB => {
+                let [intent] = parser.bytes()?;
+                summary.srgb_intent = Some(intent);
+            }
+            consts::TIME => {
+                if let Chunk::Time(time) = Time::parse(&mut parser)? {
+                    summary.time = Some(time);
+                }
+            }
+            consts::TEXT | consts::ZTEXT | consts::ITEXT => {
+                // Keywords are at most 79 bytes, and null-terminated
+                let mut prefix = parser.prefix(80)?;
+                let key = chunk::read_keyword(&mut prefix, 79)?;
+                // Keywords are Latin-1
+                let key = key.iter().copied().map(char::from).collect();
+                parser.skip()?;
+                parser.check_crc(&name)?;
+                summary.keywords.push(key);
+            }
+            consts::IMAGE_HEADER => return Err(Error::Multiple(name)),
+            _ => {
+                parser.skip()?;
+                parser.check_crc(&name)?;
+                if name == consts::IMAGE_END {
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(summary)
+}
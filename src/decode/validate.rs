@@ -0,0 +1,391 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+use super::steps;
+use crate::{
+    chunk::{Chunk, ImageHeader, Palette, Transparency},
+    consts,
+    decode::{ChunkWarning, Error},
+    encode::normalize::{AFTER_PALETTE, BEFORE_PALETTE},
+    Decoder,
+};
+
+/// How serious a [`Violation`] is.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// The file breaks a rule of the PNG specification, and decoders may
+    /// reject it or lose some of it
+    Error,
+    /// The file breaks a rule that decoders can safely ignore, such as an
+    /// IEND chunk with data or chunks after the IEND chunk
+    Warning,
+}
+
+/// A problem found by [`validate()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Violation {
+    /// Name of the chunk with the problem, if it's about one chunk
+    pub chunk: Option<[u8; 4]>,
+    /// Byte offset of that chunk from the start of the file, if known
+    pub offset: Option<u64>,
+    /// How serious the problem is
+    pub severity: Severity,
+    /// Machine readable name of the problem (such as `"crc_mismatch"`), which
+    /// doesn't change between versions (see [`Error::code()`])
+    pub code: &'static str,
+    /// Human readable description of the problem
+    pub message: String,
+}
+
+/// Every problem found in a PNG file by [`validate()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// Problems found, in file order (problems with the whole file, such as
+    /// missing chunks, come last)
+    pub violations: Vec<Violation>,
+    /// Could the image data be decoded?
+    pub decodable: bool,
+}
+
+impl ValidationReport {
+    /// Returns `true` if there are no violations with [`Severity::Error`].
+    pub fn is_valid(&self) -> bool {
+        self.violations
+            .iter()
+            .all(|violation| violation.severity == Severity::Warning)
+    }
+
+    /// Get the code of each violation, in order.
+    pub fn codes(&self) -> Vec<&'static str> {
+        self.violations
+            .iter()
+            .map(|violation| violation.code)
+            .collect()
+    }
+}
+
+/// Check every chunk of the PNG file read from `reader`, reporting every
+/// problem found instead of stopping at the first one.
+///
+/// This finds the same problems as [`all_chunks_valid()`], as well as the
+/// ones the decoder works around (see [`ChunkWarning`]) and APNG sequence
+/// numbers that are out of order.  Checking stops early only at problems
+/// that make the rest of the file unreadable (such as I/O errors or a broken
+/// chunk header).
+///
+/// ```rust
+/// let file = std::fs::File::open("tests/png/bad/badcrc.png").unwrap();
+/// let report = png_pong::validate(file);
+/// assert!(!report.is_valid());
+/// assert_eq!(report.codes(), ["crc_mismatch"]);
+/// ```
+pub fn validate<R: Read>(reader: R) -> ValidationReport {
+    let validator = check(reader);
+    let mut violations: Vec<Violation> = validator
+        .errors
+        .iter()
+        .map(|(error, chunk, offset)| Violation {
+            chunk: chunk.or_else(|| error.chunk()),
+            offset: *offset,
+            severity: match error {
+                Error::TrailingChunk => Severity::Warning,
+                _ => Severity::Error,
+            },
+            code: error.code(),
+            message: error.to_string(),
+        })
+        .collect();
+    // Each warning goes after the errors found before it
+    for (i, (warning, offset, errors)) in
+        validator.warnings.into_iter().enumerate()
+    {
+        let violation = Violation {
+            chunk: Some(match warning {
+                ChunkWarning::ImageEndLength(_) => consts::IMAGE_END,
+                ChunkWarning::OverLimit(name) => name,
+            }),
+            offset: Some(offset),
+            severity: Severity::Warning,
+            code: match warning {
+                ChunkWarning::ImageEndLength(_) => "image_end_length",
+                ChunkWarning::OverLimit(_) => "over_limit",
+            },
+            message: warning.to_string(),
+        };
+        violations.insert(errors + i, violation);
+    }
+
+    ValidationReport {
+        violations,
+        decodable: validator.decodable,
+    }
+}
+
+/// Check every chunk of the PNG file at `path`, collecting all of the errors
+/// found instead of stopping at the first one.
+///
+/// Checks CRCs, chunk order, duplicate chunks, chunk contents and the image
+/// data.  Checking stops early only at errors that make the rest of the file
+/// unreadable (such as I/O errors or a broken chunk header).
+pub fn all_chunks_valid(path: impl AsRef<Path>) -> Result<(), Vec<Error>> {
+    let file = File::open(path).map_err(|e| vec![Error::from(e)])?;
+    let errors: Vec<Error> = check(BufReader::new(file))
+        .errors
+        .into_iter()
+        .map(|(error, _, _)| error)
+        .collect();
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Check every chunk read from `reader`.
+fn check<R: Read>(reader: R) -> Validator {
+    let mut validator = Validator::default();
+    let mut chunks = match Decoder::new(reader) {
+        Ok(decoder) => decoder.into_chunks(),
+        Err(error) => {
+            validator.errors.push((error, None, Some(0)));
+            return validator;
+        }
+    };
+
+    while let Some(chunk) = chunks.next() {
+        let (name, offset) = chunks.last_chunk();
+        validator.current = (name, offset);
+        for &warning in &chunks.warnings()[validator.warnings.len()..] {
+            let index = validator.errors.len();
+            validator.warnings.push((warning, offset, index));
+        }
+        match chunk {
+            Ok(chunk) => validator.chunk(chunk),
+            Err(error) => {
+                // Chunk wasn't read to the end
+                let stop = !error.is_recoverable()
+                    || matches!(
+                        error,
+                        Error::Io(_) | Error::Eof | Error::ChunkTooLarge(_)
+                    );
+                validator.errors.push((error, Some(name), Some(offset)));
+                if stop {
+                    return validator;
+                }
+                validator.skipped(name);
+            }
+        }
+    }
+    validator.end();
+    validator
+}
+
+/// Chunk sequence state.
+#[derive(Default)]
+struct Validator {
+    /// Errors found, with the name and offset of the chunk they were found in
+    errors: Vec<(Error, Option<[u8; 4]>, Option<u64>)>,
+    /// Problems worked around by the decoder, with the offset of the chunk
+    /// and the number of errors found before them
+    warnings: Vec<(ChunkWarning, u64, usize)>,
+    /// Name and offset of the chunk being checked
+    current: ([u8; 4], u64),
+    /// Offset of the first IDAT chunk
+    idat_offset: Option<u64>,
+    /// Next APNG sequence number
+    sequence: u32,
+    /// Could the image data be decoded?
+    decodable: bool,
+    /// Number of chunks read so far
+    chunks: usize,
+    header: Option<ImageHeader>,
+    palette: Option<Palette>,
+    transparency: Option<Transparency>,
+    /// Names of the chunks found so far that are only allowed once
+    found: Vec<[u8; 4]>,
+    /// Compressed image data
+    idat: Option<Vec<u8>>,
+    /// Was the previous chunk an IDAT chunk?
+    in_idat: bool,
+    /// Was the IEND chunk found?
+    end: bool,
+    /// Names of the chunks that couldn't be read (with errors already
+    /// reported, so problems caused by their absence aren't)
+    skipped: Vec<[u8; 4]>,
+}
+
+impl Validator {
+    fn chunk(&mut self, chunk: Chunk) {
+        let first = self.chunks == 0;
+        let was_idat = std::mem::replace(&mut self.in_idat, chunk.is_idat());
+        self.chunks += 1;
+
+        if self.end {
+            self.error(Error::TrailingChunk);
+            return;
+        }
+        if first != matches!(chunk, Chunk::ImageHeader(_)) {
+            self.error(Error::ChunkOrder);
+        }
+
+        // Chunks that are only allowed once
+        let unique = match &chunk {
+            Chunk::ImageHeader(_) => Some(consts::IMAGE_HEADER),
+            Chunk::Palette(_) => Some(consts::PALETTE),
+            Chunk::Background(_) => Some(consts::BACKGROUND),
+            Chunk::Transparency(_) => Some(consts::TRANSPARENCY),
+            Chunk::Physical(_) => Some(consts::PHYSICAL),
+            _ => None,
+        };
+        if let Some(name) = unique {
+            if self.found.contains(&name) {
+                self.error(Error::Multiple(name));
+            }
+            self.found.push(name);
+        }
+
+        // Chunks that must come before the image data, some of them before
+        // the PLTE chunk too
+        let name = self.current.0;
+        let before_palette = BEFORE_PALETTE.contains(&name);
+        let before_data = before_palette
+            || name == consts::PALETTE
+            || AFTER_PALETTE.contains(&name);
+        if (before_data && self.after_image_data())
+            || (before_palette && self.found.contains(&consts::PALETTE))
+        {
+            self.error(Error::ChunkOrder);
+        }
+
+        match chunk {
+            Chunk::ImageHeader(header) => {
+                self.header.get_or_insert(header);
+            }
+            Chunk::Palette(palette) => {
+                if self.found.contains(&consts::BACKGROUND)
+                    || self.found.contains(&consts::TRANSPARENCY)
+                {
+                    self.error(Error::ChunkOrder);
+                }
+                self.palette.get_or_insert(palette);
+            }
+            Chunk::Transparency(transparency) => {
+                if let Some(header) = &self.header {
+                    if header.color_type.has_alpha() {
+                        self.error(Error::AlphaPaletteWithAlphaMode);
+                    }
+                }
+                if let (Some(palette), Transparency::Palette(alphas)) =
+                    (&self.palette, &transparency)
+                {
+                    if alphas.len() > palette.palette.len() {
+                        self.error(Error::AlphaPaletteLen);
+                    }
+                }
+                self.transparency.get_or_insert(transparency);
+            }
+            Chunk::Time(_) => {
+                if self.found.contains(&consts::TIME) {
+                    self.error(Error::Multiple(consts::TIME));
+                }
+                self.found.push(consts::TIME);
+            }
+            Chunk::ImageData(data) => {
+                if !was_idat && self.after_image_data() {
+                    // IDAT chunks must be consecutive
+                    self.error(Error::ChunkOrder);
+                }
+                self.idat.get_or_insert_with(Vec::new).extend(data.data);
+                self.idat_offset.get_or_insert(self.current.1);
+            }
+            Chunk::ImageEnd(_) => self.end = true,
+            Chunk::Unknown(unknown)
+                if matches!(
+                    unknown.name,
+                    consts::FRAME_CONTROL | consts::FRAME_DATA
+                ) =>
+            {
+                // fcTL and fdAT chunks share one sequence, starting at 0
+                if let Some(found) = unknown.data.get(..4) {
+                    let found = u32::from_be_bytes(found.try_into().unwrap());
+                    if found != self.sequence {
+                        self.error(Error::FrameSequence {
+                            chunk: unknown.name,
+                            expected: self.sequence,
+                            found,
+                        });
+                    }
+                    self.sequence = found.wrapping_add(1);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Record a chunk that couldn't be read, after its error.
+    fn skipped(&mut self, name: [u8; 4]) {
+        self.chunks += 1;
+        self.in_idat = name == consts::IMAGE_DATA;
+        self.end |= name == consts::IMAGE_END;
+        self.skipped.push(name);
+    }
+
+    /// Was any image data found (even if it couldn't be read)?
+    fn after_image_data(&self) -> bool {
+        self.idat.is_some() || self.skipped.contains(&consts::IMAGE_DATA)
+    }
+
+    /// Record an error in the chunk being checked.
+    fn error(&mut self, error: Error) {
+        let (name, offset) = self.current;
+        self.errors.push((error, Some(name), Some(offset)));
+    }
+
+    /// Check the image data once all chunks are read.
+    fn end(&mut self) {
+        let (header, idat) = match (&self.header, &self.idat) {
+            (Some(header), Some(idat)) => (header, idat),
+            (None, _) => {
+                if !self.skipped.contains(&consts::IMAGE_HEADER) {
+                    self.errors.push((Error::Empty, None, None));
+                }
+                return self.end_found();
+            }
+            (Some(_), None) => {
+                if !self.skipped.contains(&consts::IMAGE_DATA) {
+                    self.errors.push((Error::NoImageData, None, None));
+                }
+                return self.end_found();
+            }
+        };
+        match steps::decode(
+            idat,
+            header,
+            self.palette.as_ref(),
+            self.transparency.as_ref(),
+        ) {
+            Ok(_) => self.decodable = true,
+            // Missing image data or palette is already reported
+            Err(_)
+                if self.skipped.contains(&consts::IMAGE_DATA)
+                    || self.skipped.contains(&consts::PALETTE) => {}
+            Err(error) => {
+                // Found in the image data, which starts at the first IDAT
+                let chunk = Some(consts::IMAGE_DATA);
+                self.errors.push((error, chunk, self.idat_offset));
+            }
+        }
+        self.end_found();
+    }
+
+    /// Check that the IEND chunk was found.
+    fn end_found(&mut self) {
+        if !self.end {
+            self.errors.push((Error::Eof, None, None));
+        }
+    }
+}
@@ -0,0 +1,29 @@
+/// A problem in a PNG file that the decoder worked around instead of
+/// returning an [`Error`](crate::decode::Error).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ChunkWarning {
+    /// The IEND chunk has a non-zero length (which the spec doesn't allow).
+    /// The extra bytes were read and discarded.
+    ImageEndLength(u32),
+    /// An ancillary chunk was skipped because keeping it would go over the
+    /// decoder's [`Limits`](crate::decode::Limits) on text chunks or
+    /// ancillary bytes (which are errors in strict mode).
+    OverLimit([u8; 4]),
+}
+
+impl std::fmt::Display for ChunkWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use ChunkWarning::*;
+        match self {
+            ImageEndLength(length) => {
+                write!(f, "IEND chunk has length {length} (should be 0)")
+            }
+            OverLimit(name) => write!(
+                f,
+                "{} chunk skipped, over the decoder's limits",
+                String::from_utf8_lossy(name)
+            ),
+        }
+    }
+}
@@ -1,27 +1,78 @@
-use std::io::{ErrorKind, Read};
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
 
 use crate::{
     consts,
-    decode::{Chunks, Error, Result, Steps},
+    crc32::Crc32,
+    decode::{
+        ChunkWarning, Chunks, CrcPolicy, Error, Limits, Result, Rows, Steps,
+    },
     Step,
 };
 
+/// Size of the pieces image data is read in.
+const PIECE_SIZE: usize = 1 << 16;
+
 /// Chunk parser.
 #[derive(Debug)]
 pub(crate) struct Parser<R: Read> {
+    /// Chunk name
+    name: [u8; 4],
     /// Chunk length
     length: u32,
+    /// Has the chunk header been peeked, but not yet prepared?
+    pending: bool,
+    /// Byte offset of the chunk from the start of the file
+    offset: u64,
+    /// Number of bytes read from the start of the file
+    position: u64,
     /// CRC32
-    chksum: u32,
+    chksum: Crc32,
+    /// Is the CRC of the chunk being read checked?
+    verify: bool,
     /// Decoder
     decode: Decoder<R>,
-    /// Palette chunk found?
-    palette: bool,
+    /// Number of entries in the palette chunk, once found
+    palette: Option<usize>,
+    /// Number of chunks read so far
+    chunks: usize,
+    /// Number of text chunks kept so far
+    text_chunks: usize,
+    /// Total payload bytes of ancillary chunks kept so far
+    ancillary_bytes: usize,
+    /// Grow-only buffer chunk payloads are read into
+    scratch: Vec<u8>,
+    /// Number of bytes at the start of the chunk's data already read into the
+    /// scratch buffer by `prefix()`
+    read: usize,
+    /// Problems worked around so far
+    warnings: Vec<ChunkWarning>,
 }
 
 impl<R: Read> Parser<R> {
     /// Prepare a chunk for reading, returning it's name.
     pub(crate) fn prepare(&mut self) -> Result<Option<[u8; 4]>> {
+        if self.pending {
+            self.pending = false;
+            return Ok(Some(self.name));
+        }
+        self.header()
+    }
+
+    /// Read the next chunk's header without consuming it, returning its name
+    /// and length.
+    pub(crate) fn peek(&mut self) -> Result<Option<([u8; 4], u32)>> {
+        if !self.pending {
+            if self.header()?.is_none() {
+                return Ok(None);
+            }
+            self.pending = true;
+        }
+        Ok(Some((self.name, self.length)))
+    }
+
+    /// Read a chunk header, returning its name.
+    fn header(&mut self) -> Result<Option<[u8; 4]>> {
+        self.offset = self.position;
         let first = match self.u8() {
             Ok(first) => first,
             Err(Error::Io(e)) if e.kind() == ErrorKind::UnexpectedEof => {
@@ -32,92 +83,280 @@ impl<R: Read> Parser<R> {
         self.length =
             u32::from_be_bytes([first, self.u8()?, self.u8()?, self.u8()?]);
         // Start checksum over
-        self.chksum = consts::CRC32_INIT;
+        self.chksum = Crc32::new();
+        self.read = 0;
+        self.verify = true;
         // Return chunk name
         let name = [self.u8()?, self.u8()?, self.u8()?, self.u8()?];
+        self.name = name;
+        self.verify = self.decode.crc_policy.verifies(name);
         if self.length > consts::MAX_CHUNK_SIZE as u32 {
             return Err(Error::ChunkLength(name));
         }
         Ok(Some(name))
     }
 
-    /// Call this when palette chunk is found, whether or not it shows up
-    /// influences how other chunks are parsed.
-    pub(crate) fn set_palette(&mut self) {
-        self.palette = true;
+    /// Should text chunks be parsed (rather than read as unknown chunks)?
+    pub(crate) fn parse_text(&self) -> bool {
+        self.decode.parse_text
+    }
+
+    /// Record a problem that was worked around.
+    pub(crate) fn warn(&mut self, warning: ChunkWarning) {
+        self.warnings.push(warning);
+    }
+
+    /// Get the problems worked around so far.
+    pub(crate) fn warnings(&self) -> &[ChunkWarning] {
+        &self.warnings
+    }
+
+    /// Call this when palette chunk is found, with its number of entries,
+    /// whether or not it shows up influences how other chunks are parsed.
+    pub(crate) fn set_palette(&mut self, entries: usize) {
+        self.palette = Some(entries);
     }
 
     /// Has palette been parsed yet?
     pub(crate) fn has_palette(&self) -> bool {
+        self.palette.is_some()
+    }
+
+    /// Get the number of entries in the palette, if it's been parsed yet.
+    pub(crate) fn palette_len(&self) -> Option<usize> {
         self.palette
     }
 
+    /// Count the chunk against the decoder's limits, returning `false` if it
+    /// should be skipped.
+    pub(crate) fn within_limits(&mut self, name: [u8; 4]) -> Result<bool> {
+        let limits = self.decode.limits;
+
+        self.count_chunk()?;
+        // Critical chunks are always kept, as is animation frame data, which
+        // is image data even though the chunk is ancillary
+        if name[0].is_ascii_uppercase() || name == consts::FRAME_DATA {
+            return Ok(true);
+        }
+
+        let is_text =
+            matches!(name, consts::TEXT | consts::ZTEXT | consts::ITEXT);
+        let text_chunks = self.text_chunks + usize::from(is_text);
+        let ancillary_bytes = self.ancillary_bytes.saturating_add(self.len());
+
+        if text_chunks > limits.max_text_chunks
+            || ancillary_bytes > limits.max_ancillary_bytes
+        {
+            if limits.strict {
+                return Err(Error::LimitExceeded);
+            }
+            self.warn(ChunkWarning::OverLimit(name));
+            return Ok(false);
+        }
+        self.text_chunks = text_chunks;
+        self.ancillary_bytes = ancillary_bytes;
+
+        Ok(true)
+    }
+
+    /// Count a chunk against the decoder's maximum number of chunks.
+    pub(crate) fn count_chunk(&mut self) -> Result<()> {
+        self.chunks += 1;
+        if self.chunks > self.decode.limits.max_chunks {
+            return Err(Error::LimitExceeded);
+        }
+        Ok(())
+    }
+
+    /// Get the name and byte offset of the last chunk whose header was read.
+    pub(crate) fn last_chunk(&self) -> ([u8; 4], u64) {
+        (self.name, self.offset)
+    }
+
     /// Get the length of the chunk.
     pub(crate) fn len(&self) -> usize {
         self.length.try_into().unwrap()
     }
 
-    /// Read and ignore the entire chunk.
+    /// Read the entire chunk.
     pub(crate) fn unknown_chunk(&mut self) -> Result<Vec<u8>> {
-        self.vec(self.len())
+        self.raw()
+    }
+
+    /// Read and discard the entire chunk.
+    pub(crate) fn skip(&mut self) -> Result<()> {
+        let mut buffer = [0; 4096];
+        let mut left = self.len() - self.read;
+        while left > 0 {
+            let block = &mut buffer[..left.min(4096)];
+            self.decode.reader.read_exact(block)?;
+            self.position += block.len() as u64;
+            if self.verify {
+                self.chksum.update(block);
+            }
+            left -= block.len();
+        }
+        Ok(())
+    }
+
+    /// Read the next `buffer.len()` bytes of the chunk, for reading a chunk a
+    /// piece at a time.  The CRC must be checked after the last piece.
+    pub(crate) fn read_part(&mut self, buffer: &mut [u8]) -> Result<()> {
+        self.decode.reader.read_exact(buffer)?;
+        self.position += buffer.len() as u64;
+        if self.verify {
+            self.chksum.update(buffer);
+        }
+        Ok(())
     }
 
     /// Read entire chunk into a `Vec<u8>`.
     pub(crate) fn raw(&mut self) -> Result<Vec<u8>> {
-        self.vec(self.len())
+        if self.name == consts::IMAGE_DATA {
+            let mut data = Vec::new();
+            self.extend_payload(&mut data)?;
+            return Ok(data);
+        }
+        self.payload().map(<[u8]>::to_vec)
     }
 
-    /// Get an array of bytes out of the reader.
+    /// Read the first `len` bytes of the chunk (or all of it, if it's shorter)
+    /// into the scratch buffer, so that they can be checked before deciding
+    /// to read the rest.  The rest is then read by `payload()` (which returns
+    /// these bytes too) or `skip()`.
+    pub(crate) fn prefix(&mut self, len: usize) -> Result<&[u8]> {
+        let len = len.min(self.len());
+        if self.scratch.len() < len {
+            self.scratch.resize(len, 0);
+        }
+
+        let prefix = &mut self.scratch[..len];
+        self.decode.reader.read_exact(prefix)?;
+        self.position += len as u64;
+        if self.verify {
+            self.chksum.update(prefix);
+        }
+        self.read = len;
+
+        Ok(&self.scratch[..len])
+    }
+
+    /// Read entire chunk into the scratch buffer and check its CRC, returning
+    /// an error instead of reading more than the maximum chunk size from the
+    /// decoder's limits.  Image data should be read with `extend_payload()`
+    /// instead, which isn't limited.
+    ///
+    /// The scratch buffer is reused for every chunk, so this only allocates
+    /// when the chunk is bigger than any before it.
+    pub(crate) fn payload(&mut self) -> Result<&[u8]> {
+        let (name, len) = (self.name, self.len());
+        if len > self.decode.limits.max_chunk_bytes {
+            return Err(Error::ChunkTooLarge(name));
+        }
+        if self.scratch.len() < len {
+            self.scratch.resize(len, 0);
+        }
+
+        let payload = &mut self.scratch[self.read..len];
+        self.decode.reader.read_exact(payload)?;
+        self.position += payload.len() as u64;
+        if self.verify {
+            self.chksum.update(payload);
+        }
+        self.check_crc(&name)?;
+
+        Ok(&self.scratch[..len])
+    }
+
+    /// Read entire chunk onto the end of `out` and check its CRC, for image
+    /// data, which is all needed however it's split into chunks so isn't
+    /// limited by the maximum chunk size.
+    ///
+    /// The chunk is read a piece at a time, so that a chunk with a length
+    /// longer than the file only allocates for the bytes actually there.
+    pub(crate) fn extend_payload(&mut self, out: &mut Vec<u8>) -> Result<()> {
+        out.extend_from_slice(&self.scratch[..self.read]);
+
+        let mut left = self.len() - self.read;
+        while left > 0 {
+            let start = out.len();
+            let length = left.min(PIECE_SIZE);
+            out.resize(start + length, 0);
+            self.read_part(&mut out[start..])?;
+            left -= length;
+        }
+        let name = self.name;
+        self.check_crc(&name)
+    }
+
+    /// Read the entire chunk into an array and check its CRC, returning
+    /// `ChunkLength` if the chunk isn't exactly `N` bytes long.
     pub(crate) fn bytes<const N: usize>(&mut self) -> Result<[u8; N]> {
+        if self.len() != N {
+            return Err(Error::ChunkLength(self.name));
+        }
+
+        let mut array = [0; N];
+        array.copy_from_slice(self.payload()?);
+        Ok(array)
+    }
+
+    /// Get an array of bytes out of the reader.
+    fn read<const N: usize>(&mut self) -> Result<[u8; N]> {
         let mut array = [0; N];
 
         self.decode
             .reader
             .read_exact(&mut array)
             .map_err(Error::from)?;
-
-        for byte in array {
-            let index: usize = (self.chksum as u8 ^ byte).into();
-
-            self.chksum = consts::CRC32_LOOKUP[index] ^ (self.chksum >> 8);
+        self.position += N as u64;
+        if self.verify {
+            self.chksum.update(&array);
         }
 
         Ok(array)
     }
 
-    /// Check if the CRC matches calculated CRC.
+    /// Check if the CRC matches calculated CRC (unless the decoder's
+    /// [`CrcPolicy`] skips it).
     pub(crate) fn check_crc(&mut self, name: &[u8; 4]) -> Result<()> {
         let mut crc32 = [0; 4];
         self.decode.reader.read_exact(&mut crc32)?;
-        if u32::from_be_bytes(crc32) != (self.chksum ^ consts::CRC32_INIT) {
-            return Err(Error::Crc32(*name));
+        self.position += 4;
+        if !self.verify {
+            return Ok(());
+        }
+
+        let expected = self.chksum.finish();
+        let found = u32::from_be_bytes(crc32);
+        if found != expected {
+            return Err(Error::Crc32 {
+                chunk: *name,
+                expected,
+                found,
+                offset: self.offset,
+            });
         }
         Ok(())
     }
 
     /// Get a u8 out of the reader.
     fn u8(&mut self) -> Result<u8> {
-        self.bytes().map(|[byte]| byte)
-    }
-
-    /// Read into a `Vec<u8>`.
-    fn vec(&mut self, len: usize) -> Result<Vec<u8>> {
-        let mut out = Vec::with_capacity(len);
-        for _ in 0..len {
-            out.push(self.u8()?);
-        }
-        Ok(out)
+        self.read().map(|[byte]| byte)
     }
 }
 
 /// PNG file decoder
 ///
-/// Can be converted into one of two iterators:
+/// Can be converted into one of three iterators:
 /// - [into_iter] / [into_steps] for high-level [Step]s
+/// - [into_rows] for the rows of large images, without decoding all of them
+///   at once
 /// - [into_chunks] for low-level [Chunk]s
 ///
 /// [into_iter]: struct.Decoder.html#method.into_iter
 /// [into_steps]: struct.Decoder.html#method.into_steps
+/// [into_rows]: struct.Decoder.html#method.into_rows
 /// [into_chunks]: struct.Decoder.html#method.into_chunks
 /// [Step]: struct.Step.html
 /// [Chunk]: chunk/enum.Chunk.html
@@ -125,6 +364,12 @@ impl<R: Read> Parser<R> {
 pub struct Decoder<R: Read> {
     // The source of PNG input.
     reader: R,
+    // Resource limits.
+    limits: Limits,
+    // Parse text chunks?
+    parse_text: bool,
+    // Which CRCs to check.
+    crc_policy: CrcPolicy,
 }
 
 impl<R: Read> Decoder<R> {
@@ -137,7 +382,41 @@ impl<R: Read> Decoder<R> {
             return Err(Error::InvalidSignature);
         }
 
-        Ok(Decoder { reader })
+        Ok(Decoder {
+            reader,
+            limits: Limits::default(),
+            parse_text: true,
+            crc_policy: CrcPolicy::All,
+        })
+    }
+
+    /// Set the resource limits (default: `Limits::default()`).
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Set whether or not to parse text chunks (default: `true`).
+    ///
+    /// If `false`, "tEXt", "zTXt" and "iTXt" chunks are returned as
+    /// [`Chunk::Unknown`](crate::chunk::Chunk::Unknown) without being
+    /// decompressed or converted to strings, which saves work when only the
+    /// pixels are needed.  Use [`Text::parse_raw()`] to convert a "tEXt" chunk
+    /// later.
+    ///
+    /// [`Text::parse_raw()`]: crate::chunk::Text::parse_raw
+    pub fn parse_text(mut self, parse: bool) -> Self {
+        self.parse_text = parse;
+        self
+    }
+
+    /// Set which chunks' CRCs to check (default: [`CrcPolicy::All`]).
+    ///
+    /// Files with wrong CRCs in skipped chunks decode without an error, so
+    /// only skip CRCs of files from a trusted source.
+    pub fn verify_crc(mut self, policy: CrcPolicy) -> Self {
+        self.crc_policy = policy;
+        self
     }
 
     /// Convert into a `Chunk` iterator.
@@ -150,17 +429,88 @@ impl<R: Read> Decoder<R> {
         Steps::new(self.into_chunks())
     }
 
+    /// Convert into an iterator over the rows of the image, for images too big
+    /// to decode all at once.  The header chunk is read first, returning an
+    /// error if it's missing or invalid.
+    ///
+    /// Rows are decoded as they're read unless the image is interlaced; see
+    /// [`Rows`] for details.
+    pub fn into_rows(self) -> Result<Rows<R>> {
+        Rows::new(self.parser())
+    }
+
+    /// Get the still-compressed image data of all IDAT chunks, concatenated
+    /// without decompressing it.  Other chunks are skipped (but their CRCs are
+    /// still checked).
+    pub fn into_raw_idat_bytes(self) -> Result<Vec<u8>> {
+        let mut parser = self.parser();
+        let mut idat = Vec::new();
+
+        while let Some(name) = parser.prepare()? {
+            parser.count_chunk()?;
+            if name == consts::IMAGE_DATA {
+                parser.extend_payload(&mut idat)?;
+            } else {
+                parser.skip()?;
+                parser.check_crc(&name)?;
+            }
+        }
+
+        Ok(idat)
+    }
+
     /// Convert into a `Parser`.
-    fn parser(self) -> Parser<R> {
+    pub(crate) fn parser(self) -> Parser<R> {
         Parser {
             decode: self,
+            name: [0; 4],
             length: 0,
-            chksum: 0,
-            palette: false,
+            pending: false,
+            offset: 0,
+            position: consts::PNG_SIGNATURE.len() as u64,
+            chksum: Crc32::new(),
+            verify: true,
+            palette: None,
+            chunks: 0,
+            text_chunks: 0,
+            ancillary_bytes: 0,
+            scratch: Vec::new(),
+            read: 0,
+            warnings: Vec::new(),
         }
     }
 }
 
+impl<R: Read + Seek> Decoder<R> {
+    /// Create a new PNG decoder for a PNG file that starts `offset` bytes into
+    /// `reader`, such as one stored in an archive.  Returns `Err` if it's not
+    /// a PNG file.
+    ///
+    /// The reader is seeked to `offset` before reading the signature, and is
+    /// neither copied nor limited to the PNG file, so it's read until the
+    /// IEND chunk; [`Steps`] reports data following it as an error once the
+    /// last step has been read.  Chunk offsets are from the start of the PNG
+    /// file, not the reader.
+    pub fn new_at_offset(mut reader: R, offset: u64) -> Result<Self> {
+        reader.seek(SeekFrom::Start(offset))?;
+        Self::new(reader)
+    }
+}
+
+impl Decoder<Box<dyn Read>> {
+    /// Create a new PNG decoder over a type-erased reader.  Returns `Err` if
+    /// it's not a PNG file.
+    ///
+    /// Decoding from several reader types (files, sockets, in-memory buffers,
+    /// decompressors) with [`Decoder::new()`] compiles the whole decoder once
+    /// per type; going through `new_erased()` instead shares one copy.  Wrap
+    /// unbuffered readers in a [`BufReader`](std::io::BufReader) first, as
+    /// every read goes through dynamic dispatch.
+    pub fn new_erased(reader: impl Read + 'static) -> Result<Self> {
+        Self::new(Box::new(reader))
+    }
+}
+
 impl<R: Read> IntoIterator for Decoder<R> {
     type IntoIter = Steps<R>;
     type Item = Result<Step>;
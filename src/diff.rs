@@ -0,0 +1,209 @@
+use std::{collections::HashMap, io::Read};
+
+use crate::{
+    decode::{
+        scan::{chunk_header, signature},
+        Error, Result,
+    },
+    Decoder,
+};
+
+/// A difference between the chunks of two PNG files, found by [`diff()`].
+///
+/// Offsets are byte offsets of a chunk's length field from the start of its
+/// file, and indices are positions in the file's list of chunks (starting at
+/// 0 for the first chunk after the signature).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChunkDiff {
+    /// The chunk is only in the first file
+    OnlyInFirst {
+        /// Chunk name
+        name: [u8; 4],
+        /// Offset in the first file
+        offset: u64,
+    },
+    /// The chunk is only in the second file
+    OnlyInSecond {
+        /// Chunk name
+        name: [u8; 4],
+        /// Offset in the second file
+        offset: u64,
+    },
+    /// The chunk is in both files, with different data
+    Payload {
+        /// Chunk name
+        name: [u8; 4],
+        /// Data length in the first file
+        first_len: u32,
+        /// Data length in the second file
+        second_len: u32,
+        /// Offset of the first differing byte within the chunk data
+        differs_at: u32,
+        /// Offset in the first file
+        first_offset: u64,
+        /// Offset in the second file
+        second_offset: u64,
+    },
+    /// The chunk is in both files, but moved relative to the other chunks
+    Moved {
+        /// Chunk name
+        name: [u8; 4],
+        /// Index in the first file
+        first_index: usize,
+        /// Index in the second file
+        second_index: usize,
+    },
+}
+
+/// A chunk read by `diff()`.
+struct Record {
+    name: [u8; 4],
+    offset: u64,
+    data: Vec<u8>,
+}
+
+/// Read the chunks of a PNG file, without parsing or checking CRCs.
+fn records<R: Read>(mut reader: R) -> Result<Vec<Record>> {
+    signature(&mut reader)?;
+
+    let mut records = Vec::new();
+    let mut offset = 8;
+    while let Some((name, length)) = chunk_header(&mut reader)? {
+        let mut data = Vec::new();
+        reader.by_ref().take(length.into()).read_to_end(&mut data)?;
+        if data.len() != length as usize {
+            return Err(Error::Eof);
+        }
+        // CRCs aren't compared
+        reader.read_exact(&mut [0; 4])?;
+        records.push(Record { name, offset, data });
+        offset += u64::from(length) + 12;
+    }
+    Ok(records)
+}
+
+/// Compare the chunks of two PNG files.
+///
+/// Chunks are matched up by name and by how many chunks with the same name
+/// come before them (so the 3rd IDAT of one file is compared with the 3rd IDAT
+/// of the other).  Matched chunks with different data are listed as
+/// [`ChunkDiff::Payload`], and ones that changed position relative to the
+/// other matched chunks as [`ChunkDiff::Moved`] (keeping the largest set of
+/// chunks in the same order unmoved).  The remaining chunks are listed as
+/// only in one file.  Differences are listed in the order of the first file,
+/// followed by the chunks only in the second file.
+///
+/// Chunk data is compared byte for byte without being parsed, so files that
+/// decode to the same pixels can still differ; use [`same_pixels()`] to
+/// compare the decoded images.  An empty list means the files only differ in
+/// their CRCs, if at all.
+pub fn diff<A: Read, B: Read>(a: A, b: B) -> Result<Vec<ChunkDiff>> {
+    let (a, b) = (records(a)?, records(b)?);
+
+    // Indices in `b` of the chunks with each name
+    let mut by_name: HashMap<[u8; 4], Vec<usize>> = HashMap::new();
+    for (j, chunk) in b.iter().enumerate() {
+        by_name.entry(chunk.name).or_default().push(j);
+    }
+    // Index in `b` matched with each chunk of `a`
+    let mut matched = vec![None; a.len()];
+    let mut used = vec![false; b.len()];
+    // Number of chunks in `a` with each name so far
+    let mut seen: HashMap<[u8; 4], usize> = HashMap::new();
+    for (i, chunk) in a.iter().enumerate() {
+        let nth = seen.entry(chunk.name).or_default();
+        let found = by_name
+            .get(&chunk.name)
+            .and_then(|indices| indices.get(*nth));
+        *nth += 1;
+        if let Some(&j) = found {
+            matched[i] = Some(j);
+            used[j] = true;
+        }
+    }
+    let unmoved = longest_increasing(&matched);
+
+    let mut diffs = Vec::new();
+    for (i, (chunk, j)) in a.iter().zip(&matched).enumerate() {
+        let Some(j) = *j else {
+            diffs.push(ChunkDiff::OnlyInFirst {
+                name: chunk.name,
+                offset: chunk.offset,
+            });
+            continue;
+        };
+        let other = &b[j];
+        if chunk.data != other.data {
+            let differs_at = chunk
+                .data
+                .iter()
+                .zip(&other.data)
+                .position(|(x, y)| x != y)
+                .unwrap_or(chunk.data.len().min(other.data.len()));
+            diffs.push(ChunkDiff::Payload {
+                name: chunk.name,
+                first_len: chunk.data.len() as u32,
+                second_len: other.data.len() as u32,
+                differs_at: differs_at as u32,
+                first_offset: chunk.offset,
+                second_offset: other.offset,
+            });
+        }
+        if !unmoved[i] {
+            diffs.push(ChunkDiff::Moved {
+                name: chunk.name,
+                first_index: i,
+                second_index: j,
+            });
+        }
+    }
+    for (chunk, _) in b.iter().zip(&used).filter(|(_, used)| !**used) {
+        diffs.push(ChunkDiff::OnlyInSecond {
+            name: chunk.name,
+            offset: chunk.offset,
+        });
+    }
+    Ok(diffs)
+}
+
+/// Mark the longest increasing subsequence of the matched indices.
+fn longest_increasing(matched: &[Option<usize>]) -> Vec<bool> {
+    // Position in `matched` of the smallest tail of an increasing
+    // subsequence of each length, and the previous element of each element
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous = vec![None; matched.len()];
+    for (i, j) in matched.iter().enumerate() {
+        let Some(j) = *j else { continue };
+        let length = tails.partition_point(|&t| matched[t] < Some(j));
+        if length > 0 {
+            previous[i] = Some(tails[length - 1]);
+        }
+        if length == tails.len() {
+            tails.push(i);
+        } else {
+            tails[length] = i;
+        }
+    }
+
+    let mut marks = vec![false; matched.len()];
+    let mut next = tails.last().copied();
+    while let Some(i) = next {
+        marks[i] = true;
+        next = previous[i];
+    }
+    marks
+}
+
+/// Check whether two PNG files decode to the same frames (with the same
+/// delays), regardless of how their chunks differ.
+pub fn same_pixels<A: Read, B: Read>(a: A, b: B) -> Result<bool> {
+    let mut a = Decoder::new(a)?.into_steps();
+    let mut b = Decoder::new(b)?.into_steps();
+    loop {
+        match (a.next().transpose()?, b.next().transpose()?) {
+            (None, None) => return Ok(true),
+            (Some(x), Some(y)) if x == y => {}
+            _ => return Ok(false),
+        }
+    }
+}
@@ -1,11 +1,35 @@
 //! PNG file encoding
 
+mod budget;
 mod chunk_enc;
+mod defaults;
 mod error;
 pub(super) mod filter;
+mod frame_options;
+pub(super) mod normalize;
+mod optimize;
+mod recompress;
+mod repair;
+mod rewrite;
+mod stats;
 mod step_enc; // Share with unfilter
+mod steps;
+mod test_image;
+mod write;
 
+pub use budget::Rung;
 pub use chunk_enc::ChunkEnc;
+pub use defaults::Defaults;
 pub use error::{Error, Result};
-pub use filter::FilterStrategy;
+pub use filter::{FilterChoice, FilterStrategy};
+pub use frame_options::FrameOptions;
+pub use normalize::normalize_png;
+pub use optimize::{optimize, Effort};
+pub use recompress::recompress_idat;
+pub use repair::{repair, RepairReport};
+pub use rewrite::{rewrite, ChunkSelector, Edit};
+pub use stats::EncodeStats;
 pub use step_enc::StepEnc;
+pub use steps::encode_steps;
+pub use test_image::generate_test_image;
+pub use write::write_png;
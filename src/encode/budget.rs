@@ -0,0 +1,96 @@
+use std::io::Write;
+
+use crate::{
+    encode::{optimize, EncodeStats, Error, FilterStrategy, Result},
+    encoder::Enc,
+    PngRaster,
+};
+
+/// One set of lossless settings an encoder with a
+/// [`max_output_size()`](crate::Encoder::max_output_size) retries with, when
+/// the previous settings gave a file over the budget.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rung {
+    /// Filter strategy
+    pub filter_strategy: FilterStrategy,
+    /// Compression level (0 to 10)
+    pub level: u8,
+    /// Whether or not to also try lossless color reductions of 8-bit rasters
+    /// (dropping an opaque alpha channel, storing grey pixels as greyscale,
+    /// and storing rasters with at most 256 colors as a palette), keeping the
+    /// smallest
+    pub reduce_colors: bool,
+}
+
+impl Rung {
+    /// Get the default ladder: higher compression levels first, then
+    /// adaptive and brute force filtering, then color reductions.
+    pub fn default_ladder() -> Vec<Self> {
+        use FilterStrategy::*;
+        let rung = |filter_strategy, level, reduce_colors| Rung {
+            filter_strategy,
+            level,
+            reduce_colors,
+        };
+
+        vec![
+            rung(MinSum, 9, false),
+            rung(Entropy, 10, false),
+            rung(BruteForce, 10, false),
+            rung(MinSum, 10, true),
+            rung(BruteForce, 10, true),
+        ]
+    }
+}
+
+/// Encode `raster` within `budget` bytes, first with the encoder's settings,
+/// then with each rung of the ladder in turn.
+pub(super) fn fit<W: Write>(
+    enc: &Enc<W>,
+    raster: &PngRaster,
+    budget: usize,
+) -> Result<(Vec<u8>, EncodeStats)> {
+    let mut encoder = enc.settings(Vec::new()).into_step_enc();
+    encoder.still(raster)?;
+    let stats = encoder.stats().cloned();
+    let mut best = (encoder.into_inner(), stats);
+
+    let default_ladder;
+    let ladder = match enc.ladder() {
+        Some(ladder) => ladder,
+        None => {
+            default_ladder = Rung::default_ladder();
+            &default_ladder[..]
+        }
+    };
+    for rung in ladder {
+        if best.0.len() <= budget {
+            break;
+        }
+        let mut rasters = vec![raster.clone()];
+        if rung.reduce_colors {
+            rasters.extend(optimize::reductions(raster));
+        }
+        for raster in &rasters {
+            let mut encoder = enc
+                .settings(Vec::new())
+                .filter_strategy(rung.filter_strategy.clone())
+                .compression_level(rung.level)
+                .into_step_enc();
+            encoder.still(raster)?;
+            let stats = encoder.stats().cloned();
+            let png = encoder.into_inner();
+            if png.len() < best.0.len() {
+                best = (png, stats);
+            }
+        }
+    }
+
+    match best {
+        (png, Some(stats)) if png.len() <= budget => Ok((png, stats)),
+        (png, _) => Err(Error::SizeBudget {
+            best: png.len(),
+            budget,
+        }),
+    }
+}
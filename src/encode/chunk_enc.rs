@@ -1,6 +1,11 @@
 use std::io::Write;
 
-use crate::{chunk::Chunk, encode::Error, encoder::Enc};
+use crate::{
+    chunk::{Chunk, Position},
+    consts,
+    encode::Error,
+    encoder::Enc,
+};
 
 /// Chunk Encoder for PNG files.
 ///
@@ -10,16 +15,95 @@ use crate::{chunk::Chunk, encode::Error, encoder::Enc};
 pub struct ChunkEnc<W: Write> {
     // FIXME: use .encode() instead of pub(crate).
     pub(crate) enc: Enc<W>,
+    /// Has an IDAT chunk been encoded?
+    seen_image: bool,
+    /// Chunks held back to normalize positions
+    held: Vec<u8>,
 }
 
 impl<W: Write> ChunkEnc<W> {
     /// Create a new encoder.
     pub(crate) fn new(enc: Enc<W>) -> Self {
-        Self { enc }
+        Self {
+            enc,
+            seen_image: false,
+            held: Vec::new(),
+        }
+    }
+
+    /// Unwrap the writer, for example to get back the `Vec<u8>` the PNG was
+    /// encoded into.  Chunks held back by
+    /// [`Encoder::normalize_positions()`](crate::Encoder::normalize_positions)
+    /// are written first, so this can fail.
+    pub fn into_inner(mut self) -> Result<W, Error> {
+        self.enc.bytes(&self.held)?;
+        Ok(self.enc.into_inner())
     }
 
     /// Encode one [`Chunk`](struct.Chunk.html)
     pub fn encode(&mut self, chunk: &mut Chunk) -> Result<(), Error> {
+        self.write(chunk)
+    }
+
+    /// Write a chunk named `name` containing `data`, computing the length and
+    /// CRC.  Returns [`Error::ChunkTooBig`] if `data` is too long for a PNG
+    /// chunk.
+    ///
+    /// The data is written as is, so this can write chunks that aren't
+    /// supported by [`Chunk`], such as private chunks.
+    pub fn write_chunk(
+        &mut self,
+        name: [u8; 4],
+        data: &[u8],
+    ) -> Result<(), Error> {
+        if self.hold(name)? {
+            let mut held = self.enc.settings(Vec::new()).into_chunk_enc();
+            held.write_chunk(name, data)?;
+            self.held.extend(held.into_inner()?);
+            return Ok(());
+        }
+        self.enc.write_chunk(name, data)
+    }
+
+    /// Encode one chunk.
+    pub(crate) fn write(&mut self, chunk: &Chunk) -> Result<(), Error> {
+        if self.hold(name(chunk))? {
+            let mut held = self.enc.settings(Vec::new()).into_chunk_enc();
+            held.write_now(chunk)?;
+            self.held.extend(held.into_inner()?);
+            return Ok(());
+        }
+        self.write_now(chunk)
+    }
+
+    /// Should the chunk named `name` be held back to normalize positions?
+    /// Otherwise, any held chunks that go before it are written.
+    fn hold(&mut self, name: [u8; 4]) -> Result<bool, Error> {
+        let Some(position) = self.enc.normalize_positions() else {
+            return Ok(false);
+        };
+        let image = name == consts::IMAGE_DATA;
+        let movable = matches!(
+            name,
+            consts::TEXT | consts::ZTEXT | consts::ITEXT | consts::TIME
+        );
+        let (hold, flush) = match position {
+            Position::BeforeImage => (image, !image && !movable),
+            Position::AfterImage => {
+                let before = !self.seen_image;
+                self.seen_image |= image;
+                (movable && before, !image && !before)
+            }
+        };
+        if flush {
+            self.enc.bytes(&self.held)?;
+            self.held.clear();
+        }
+        Ok(hold)
+    }
+
+    /// Encode one chunk, without normalizing its position.
+    fn write_now(&mut self, chunk: &Chunk) -> Result<(), Error> {
         use Chunk::*;
         match chunk {
             ImageHeader(image_header) => image_header.write(&mut self.enc),
@@ -37,3 +121,21 @@ impl<W: Write> ChunkEnc<W> {
         }
     }
 }
+
+/// Get the name of a chunk.
+fn name(chunk: &Chunk) -> [u8; 4] {
+    match chunk {
+        Chunk::ImageHeader(_) => consts::IMAGE_HEADER,
+        Chunk::ImageData(_) => consts::IMAGE_DATA,
+        Chunk::ImageEnd(_) => consts::IMAGE_END,
+        Chunk::Palette(_) => consts::PALETTE,
+        Chunk::Background(_) => consts::BACKGROUND,
+        Chunk::InternationalText(_) => consts::ITEXT,
+        Chunk::Physical(_) => consts::PHYSICAL,
+        Chunk::Text(_) => consts::TEXT,
+        Chunk::Time(_) => consts::TIME,
+        Chunk::Transparency(_) => consts::TRANSPARENCY,
+        Chunk::CompressedText(_) => consts::ZTEXT,
+        Chunk::Unknown(unknown) => unknown.name,
+    }
+}
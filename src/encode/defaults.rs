@@ -0,0 +1,48 @@
+use super::FilterStrategy;
+use crate::chunk::ColorType;
+
+/// Encoder settings used for each color type when they aren't set on the
+/// [`Encoder`](crate::Encoder).
+///
+/// | Color type                    | Filter strategy | Level |
+/// |-------------------------------|-----------------|-------|
+/// | Palette, or bit depth below 8 | `Zero`          | 6     |
+/// | Grey, GreyAlpha, Rgb, Rgba    | `MinSum`        | 6     |
+///
+/// This is the heuristic recommended by the PNG specification: palette
+/// indices and packed samples don't predict well from their neighbors, so
+/// filtering them only adds noise for the compressor, and other images
+/// compress best with adaptive filtering.  The `defaults` benchmark compares
+/// the strategies for each color type over the test image corpus, so that an
+/// entry can be changed once its results show a better choice:
+///
+/// ```sh
+/// cargo bench --bench defaults
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Defaults {
+    /// Filter strategy
+    pub filter_strategy: FilterStrategy,
+    /// Compression level (0 to 10)
+    pub level: u8,
+}
+
+impl Defaults {
+    /// Get the default settings for images with the given color type and bit
+    /// depth.
+    pub fn for_color_type(color_type: ColorType, bit_depth: u8) -> Self {
+        let filter_strategy = match color_type {
+            ColorType::Palette => FilterStrategy::Zero,
+            _ if bit_depth < 8 => FilterStrategy::Zero,
+            ColorType::Grey
+            | ColorType::GreyAlpha
+            | ColorType::Rgb
+            | ColorType::Rgba => FilterStrategy::MinSum,
+        };
+
+        Self {
+            filter_strategy,
+            level: 6,
+        }
+    }
+}
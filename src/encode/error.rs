@@ -1,3 +1,5 @@
+use crate::chunk::ColorType;
+
 /// PNG Pong Encoder Result Type
 pub type Result<T = (), E = Error> = std::result::Result<T, E>;
 
@@ -7,9 +9,27 @@ impl From<std::io::Error> for Error {
     }
 }
 
+/// Unwraps `Error::Io` back into the original I/O error when possible,
+/// otherwise wraps the encoder error (as `ErrorKind::InvalidData`) so it can be
+/// recovered with `get_ref()` and `downcast_ref()`.
+impl From<Error> for std::io::Error {
+    fn from(err: Error) -> std::io::Error {
+        use std::io::{Error as IoError, ErrorKind};
+
+        match err {
+            Error::Io(io) => match std::sync::Arc::try_unwrap(io) {
+                Ok(io) => io,
+                Err(io) => IoError::new(io.kind(), Error::Io(io)),
+            },
+            err => IoError::new(ErrorKind::InvalidData, err),
+        }
+    }
+}
+
 /// Encoding Errors.
 #[derive(Debug)]
 #[allow(variant_size_differences)]
+#[non_exhaustive]
 pub enum Error {
     /// A wrapped I/O error.
     Io(std::sync::Arc<std::io::Error>),
@@ -19,11 +39,85 @@ pub enum Error {
     ChunkTooBig,
     /// key is not between 1-79 characters
     KeySize(usize),
+    /// Keyword has characters other than printable Latin-1, or leading,
+    /// trailing or consecutive spaces
+    InvalidKeyword(String),
+    /// Text has characters that aren't Latin-1, or null characters
+    InvalidText,
     /// PLTE chunk with a palette that has less than 1 or more than 256 colors
     BadPalette,
     /// Chunks arranged in invalid sequence.  Provides PNG chunk identifier of
     /// the out-of-order chunk.
     ChunkOrder([u8; 4]),
+    /// A still image was already encoded, or an animation finished
+    AlreadyFinished,
+    /// Animation frame with a different size, color type or palette than the
+    /// first frame
+    FrameMismatch,
+    /// Palette image with a pixel that indexes past the end of the palette
+    IndexOutOfPalette {
+        /// First out of range index
+        index: u8,
+        /// Number of colors in the palette
+        palette_size: u8,
+    },
+    /// No encoding tried fit within the maximum output size
+    SizeBudget {
+        /// Size in bytes of the smallest encoding
+        best: usize,
+        /// Maximum output size in bytes
+        budget: usize,
+    },
+    /// Invalid color type / bit depth combination in the image header
+    ColorMode(ColorType, u8),
+    /// Region to encode that doesn't fit within the raster
+    RegionBounds,
+    /// Number of rows isn't the height of the image
+    RowCount {
+        /// Height of the image
+        expected: u32,
+        /// Number of rows
+        found: usize,
+    },
+    /// Row that isn't the length of a scanline of the image
+    RowSize {
+        /// Index of the row
+        row: usize,
+        /// Length in bytes of a scanline
+        expected: usize,
+        /// Length in bytes of the row
+        found: usize,
+    },
+}
+
+impl Error {
+    /// Get a short, machine readable name for the kind of error (such as
+    /// `"bad_palette"`), to tell errors apart without matching on their
+    /// [`Display`](std::fmt::Display) messages.
+    ///
+    /// Codes are stable: a variant's code won't change in minor or patch
+    /// releases, and each variant has a code of its own.
+    pub fn code(&self) -> &'static str {
+        use Error::*;
+        match self {
+            Io(_) => "io",
+            InvalidChunkSequence => "chunk_sequence",
+            ChunkTooBig => "chunk_too_big",
+            KeySize(_) => "key_size",
+            InvalidKeyword(_) => "invalid_keyword",
+            InvalidText => "invalid_text",
+            BadPalette => "bad_palette",
+            ChunkOrder(_) => "chunk_order",
+            AlreadyFinished => "already_finished",
+            FrameMismatch => "frame_mismatch",
+            IndexOutOfPalette { .. } => "index_out_of_palette",
+            SizeBudget { .. } => "size_budget",
+            RegionBounds => "region_bounds",
+            ColorMode(_, _) => "bad_color_mode",
+            RowCount { .. } => "row_count",
+            RowSize { .. } => "row_size",
+        }
+    }
 }
 
 impl std::fmt::Display for Error {
@@ -36,14 +130,136 @@ impl std::fmt::Display for Error {
             KeySize(size) => {
                 write!(f, "Key size {size} is not between 1 and 79 characters")
             }
+            InvalidKeyword(key) => write!(f, "Invalid keyword {key:?}"),
+            InvalidText => {
+                write!(f, "Text must be Latin-1 without null characters")
+            }
             BadPalette => write!(f, "Invalid palette"),
             ChunkOrder(bytes) => write!(
                 f,
                 "Chunk {} out of order",
                 String::from_utf8_lossy(bytes)
             ),
+            AlreadyFinished => write!(f, "Encoder already finished"),
+            FrameMismatch => {
+                write!(f, "Frame doesn't match the first frame's format")
+            }
+            IndexOutOfPalette {
+                index,
+                palette_size,
+            } => write!(
+                f,
+                "Index {index} is out of range for a palette of \
+                 {palette_size} colors"
+            ),
+            SizeBudget { best, budget } => write!(
+                f,
+                "Smallest encoding is {best} bytes, over the budget of \
+                 {budget} bytes"
+            ),
+            ColorMode(color_type, bit_depth) => write!(
+                f,
+                "Invalid color type {color_type:?} / bit depth {bit_depth} \
+                 combination"
+            ),
+            RegionBounds => write!(f, "Region doesn't fit within the raster"),
+            RowCount { expected, found } => {
+                write!(f, "Expected {expected} rows, found {found}")
+            }
+            RowSize {
+                row,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Row {row} is {found} bytes, expected {expected} bytes"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(io) => Some(&**io),
+            _ => None,
         }
     }
 }
 
-impl std::error::Error for Error {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One of each variant.
+    fn all() -> Vec<Error> {
+        let io = std::io::Error::from(std::io::ErrorKind::Other);
+        vec![
+            Error::from(io),
+            Error::InvalidChunkSequence,
+            Error::ChunkTooBig,
+            Error::KeySize(80),
+            Error::InvalidKeyword(" key".to_string()),
+            Error::InvalidText,
+            Error::BadPalette,
+            Error::ChunkOrder(*b"PLTE"),
+            Error::AlreadyFinished,
+            Error::FrameMismatch,
+            Error::IndexOutOfPalette {
+                index: 2,
+                palette_size: 2,
+            },
+            Error::SizeBudget { best: 2, budget: 1 },
+            Error::ColorMode(ColorType::Rgb, 4),
+            Error::RegionBounds,
+            Error::RowCount {
+                expected: 2,
+                found: 1,
+            },
+            Error::RowSize {
+                row: 0,
+                expected: 2,
+                found: 1,
+            },
+        ]
+    }
+
+    /// Index of each variant in declaration order.  Adding a variant won't
+    /// compile until it's given an index here (and an entry in `all()`).
+    fn index(error: &Error) -> usize {
+        use Error::*;
+        match error {
+            Io(_) => 0,
+            InvalidChunkSequence => 1,
+            ChunkTooBig => 2,
+            KeySize(_) => 3,
+            InvalidKeyword(_) => 4,
+            InvalidText => 5,
+            BadPalette => 6,
+            ChunkOrder(_) => 7,
+            AlreadyFinished => 8,
+            FrameMismatch => 9,
+            IndexOutOfPalette { .. } => 10,
+            SizeBudget { .. } => 11,
+            ColorMode(_, _) => 12,
+            RegionBounds => 13,
+            RowCount { .. } => 14,
+            RowSize { .. } => 15,
+        }
+    }
+
+    #[test]
+    fn codes() {
+        let mut codes = [None; 16];
+
+        for error in all() {
+            let code = codes[index(&error)].get_or_insert(error.code());
+            assert_eq!(*code, error.code(), "{error:?}");
+        }
+        let mut codes: Vec<&str> = codes.iter().flatten().copied().collect();
+        assert_eq!(codes.len(), 16, "Variant missing from all()");
+        codes.sort_unstable();
+        codes.dedup();
+        assert_eq!(codes.len(), 16, "Variants share a code");
+    }
+}
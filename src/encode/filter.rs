@@ -1,17 +1,20 @@
 //! Algorithms for png "filtering" - A compression algorithm applied before
 //! the deflate algorithm.
 
-use crate::{
-    chunk::{ColorType, ImageHeader},
-    zlib,
-};
+use std::{fmt, sync::Arc};
+
+use crate::{chunk::ImageHeader, zlib};
 
 // FIXME: Move to `encode` module
 /// Filter strategy for compression.
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum FilterStrategy {
     /// Every filter at zero
     Zero,
+    /// Every filter at Up (subtract the scanline above), which suits smooth
+    /// vertical gradients.
+    Up,
     /// Use filter that gives minumum sum, as described in the official PNG
     /// filter heuristic.  This is a good default (balance between time to
     /// compress and size).
@@ -23,6 +26,62 @@ pub enum FilterStrategy {
     /// scanline.  Very slow, and only rarely gives better compression than
     /// MINSUM.
     BruteForce,
+    /// Use the given filter type (0 to 4) for each scanline, in the order
+    /// they're stored (pass by pass for interlaced images), such as the
+    /// filters recorded in [`DecodeStats`].  Missing or invalid filter types
+    /// are replaced with 0 (None).
+    ///
+    /// [`DecodeStats`]: crate::decode::DecodeStats
+    Explicit(Vec<u8>),
+}
+
+/// Filter type chosen for a scanline by an
+/// [`Encoder::filter_fn()`](crate::Encoder::filter_fn) hook.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FilterChoice {
+    /// Filter type 0: no filtering
+    None,
+    /// Filter type 1: subtract the pixel to the left
+    Sub,
+    /// Filter type 2: subtract the pixel above
+    Up,
+    /// Filter type 3: subtract the average of the pixels to the left and
+    /// above
+    Average,
+    /// Filter type 4: subtract the Paeth predictor of the pixels to the left,
+    /// above and above left
+    Paeth,
+    /// Choose with the encoder's filter strategy
+    Auto,
+}
+
+impl FilterChoice {
+    /// Get the filter type, or `None` for `Auto`.
+    fn filter_type(self) -> Option<u8> {
+        match self {
+            FilterChoice::None => Some(0),
+            FilterChoice::Sub => Some(1),
+            FilterChoice::Up => Some(2),
+            FilterChoice::Average => Some(3),
+            FilterChoice::Paeth => Some(4),
+            FilterChoice::Auto => None,
+        }
+    }
+}
+
+/// Filter hook function, see [`FilterFn`].
+type FilterHook =
+    dyn Fn(u32, &[u8], &[u8], usize) -> FilterChoice + Send + Sync;
+
+/// Per-scanline filter hook, called with the scanline index, the scanline,
+/// the previous scanline and the bytes per pixel.
+#[derive(Clone)]
+pub(crate) struct FilterFn(pub(crate) Arc<FilterHook>);
+
+impl fmt::Debug for FilterFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("FilterFn")
+    }
 }
 
 // FIXME: Not Pub
@@ -109,15 +168,28 @@ fn filter_scanline(
 /// For PNG filter method 0 out must be a buffer with as size:
 /// h + (w * h * bpp + 7) / 8, because there are the scanlines with 1 extra byte
 /// per scanline
+///
+/// Rows of `inp` start `stride` bytes apart, which is more than the length of
+/// a scanline when filtering a region of a bigger image in place.
+///
+/// `first_row` is the index of the first scanline among those of all passes,
+/// for [`FilterStrategy::Explicit`] and the `hook`.  Filter types chosen by the
+/// hook replace the ones chosen by the strategy.
+///
+/// Returns how many scanlines use each filter type.
+#[allow(clippy::too_many_arguments)]
 pub(super) fn filter(
     out: &mut [u8],
     inp: &[u8],
+    stride: usize,
     w: usize,
     h: usize,
     header: &ImageHeader,
-    filter_strategy: Option<FilterStrategy>,
+    strategy: &FilterStrategy,
     level: u8,
-) {
+    first_row: usize,
+    hook: Option<&FilterFn>,
+) -> [usize; 5] {
     let color_type = header.color_type;
     let bit_depth = header.bit_depth;
 
@@ -129,44 +201,66 @@ pub(super) fn filter(
      * per pixel otherwise */
     let bytewidth = (bpp + 7) / 8;
     let mut prevline = None;
-    /*
-    There is a heuristic called the minimum sum of absolute differences heuristic, suggested by the PNG standard:
-     *  If the image type is Palette, or the bit depth is smaller than 8, then do not filter the image (i.e.
-        use fixed filtering, with the filter None).
-     * (The other case) If the image type is Grayscale or RGB (with or without Alpha), and the bit depth is
-       not smaller than 8, then use adaptive filtering heuristic as follows: independently for each row, apply
-       all five filters and select the filter that produces the smallest sum of absolute values per row.
-    This heuristic is used if filter strategy is FilterStrategy::MINSUM and filter_palette_zero is true.
-
-    If filter_palette_zero is true and filter_strategy is not FilterStrategy::MINSUM, the above heuristic is followed,
-    but for "the other case", whatever strategy filter_strategy is set to instead of the minimum sum
-    heuristic is used.
-    */
-    let strategy = if let Some(strategy) = filter_strategy {
-        strategy
-    } else if color_type == ColorType::Palette || bit_depth < 8 {
-        FilterStrategy::Zero
-    } else {
-        FilterStrategy::MinSum
-    };
+    let mut histogram = [0; 5];
 
     // Shouldn't happen
     assert_ne!(bpp, 0);
+
+    // Ask the hook before filtering, with the unfiltered scanlines
+    let row = |y: usize| &inp[(y * stride)..][..linebytes];
+    let choices: Vec<Option<u8>> = match hook {
+        Some(FilterFn(hook)) => (0..h)
+            .map(|y| {
+                let prev = if y == 0 { &[][..] } else { row(y - 1) };
+                hook((first_row + y) as u32, row(y), prev, bytewidth)
+                    .filter_type()
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+
     match strategy {
-        FilterStrategy::Zero => {
+        FilterStrategy::Zero | FilterStrategy::Up => {
+            let filter_type = if *strategy == FilterStrategy::Up {
+                2
+            } else {
+                0
+            };
+            for y in 0..h {
+                let outindex = (1 + linebytes) * y;
+                let inindex = stride * y;
+                out[outindex] = filter_type;
+                filter_scanline(
+                    &mut out[(outindex + 1)..],
+                    &inp[inindex..],
+                    prevline,
+                    linebytes,
+                    bytewidth,
+                    filter_type,
+                );
+                prevline = Some(&inp[inindex..]);
+            }
+            histogram[usize::from(filter_type)] += h;
+        }
+        FilterStrategy::Explicit(filters) => {
             for y in 0..h {
+                let filter_type = match filters.get(first_row + y) {
+                    Some(&filter_type) if filter_type <= 4 => filter_type,
+                    _ => 0,
+                };
                 let outindex = (1 + linebytes) * y;
-                let inindex = linebytes * y;
-                out[outindex] = 0u8;
+                let inindex = stride * y;
+                out[outindex] = filter_type;
                 filter_scanline(
                     &mut out[(outindex + 1)..],
                     &inp[inindex..],
                     prevline,
                     linebytes,
                     bytewidth,
-                    0u8,
+                    filter_type,
                 );
                 prevline = Some(&inp[inindex..]);
+                histogram[usize::from(filter_type)] += 1;
             }
         }
         FilterStrategy::MinSum => {
@@ -184,7 +278,7 @@ pub(super) fn filter(
                 for type_ in 0..5 {
                     filter_scanline(
                         &mut attempt[type_],
-                        &inp[(y * linebytes)..],
+                        &inp[(y * stride)..],
                         prevline,
                         linebytes,
                         bytewidth,
@@ -213,8 +307,9 @@ pub(super) fn filter(
                         smallest = sum[type_];
                     };
                 }
-                prevline = Some(&inp[(y * linebytes)..]);
+                prevline = Some(&inp[(y * stride)..]);
                 out[y * (linebytes + 1)] = best_type as u8;
+                histogram[best_type] += 1;
                 /* the first byte of a scanline will be the filter type */
                 for x in 0..linebytes {
                     out[y * (linebytes + 1) + 1 + x] = attempt[best_type][x];
@@ -236,7 +331,7 @@ pub(super) fn filter(
                 for type_ in 0..5 {
                     filter_scanline(
                         &mut attempt[type_],
-                        &inp[(y * linebytes)..],
+                        &inp[(y * stride)..],
                         prevline,
                         linebytes,
                         bytewidth,
@@ -262,8 +357,9 @@ pub(super) fn filter(
                                                 * type */
                     }; /* the extra filterbyte added to each row */
                 }
-                prevline = Some(&inp[(y * linebytes)..]);
+                prevline = Some(&inp[(y * stride)..]);
                 out[y * (linebytes + 1)] = best_type as u8;
+                histogram[best_type] += 1;
                 for x in 0..linebytes {
                     out[y * (linebytes + 1) + 1 + x] = attempt[best_type][x];
                 }
@@ -293,7 +389,7 @@ pub(super) fn filter(
                      * row */
                     filter_scanline(
                         &mut attempt[type_],
-                        &inp[(y * linebytes)..],
+                        &inp[(y * stride)..],
                         prevline,
                         linebytes,
                         bytewidth,
@@ -310,14 +406,33 @@ pub(super) fn filter(
                         smallest = size[type_]; /* unknown filter strategy */
                     }
                 }
-                prevline = Some(&inp[(y * linebytes)..]);
+                prevline = Some(&inp[(y * stride)..]);
                 out[y * (linebytes + 1)] = best_type as u8;
+                histogram[best_type] += 1;
                 for x in 0..linebytes {
                     out[y * (linebytes + 1) + 1 + x] = attempt[best_type][x];
                 }
             }
         }
     };
+
+    for (y, choice) in choices.into_iter().enumerate() {
+        let Some(filter_type) = choice else { continue };
+        let outindex = (1 + linebytes) * y;
+        histogram[usize::from(out[outindex])] -= 1;
+        histogram[usize::from(filter_type)] += 1;
+        out[outindex] = filter_type;
+        filter_scanline(
+            &mut out[(outindex + 1)..],
+            &inp[(y * stride)..],
+            y.checked_sub(1).map(|prev| &inp[(prev * stride)..]),
+            linebytes,
+            bytewidth,
+            filter_type,
+        );
+    }
+
+    histogram
 }
 
 #[cfg(test)]
@@ -0,0 +1,18 @@
+use crate::encode::FilterStrategy;
+
+/// Settings for one frame, passed to
+/// [`StepEnc::encode_frame_with()`](crate::encode::StepEnc::encode_frame_with),
+/// that override the [`Encoder`](crate::Encoder)'s settings.
+///
+/// Frames of an animation can differ in content, such as a photograph
+/// followed by flat overlays, which compress best with different settings.
+/// Settings left as `None` fall back to the encoder's.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FrameOptions {
+    /// Filter strategy (see
+    /// [`Encoder::filter_strategy()`](crate::Encoder::filter_strategy))
+    pub filter: Option<FilterStrategy>,
+    /// Compression level, between 0 and 10 (see
+    /// [`Encoder::compression_level()`](crate::Encoder::compression_level))
+    pub level: Option<u8>,
+}
@@ -0,0 +1,120 @@
+use super::recompress::write_chunk;
+use crate::{
+    consts,
+    decode::{Error, Result},
+    Decoder,
+};
+
+/// Chunks that go before PLTE, in output order.
+pub(crate) const BEFORE_PALETTE: &[[u8; 4]] = &[
+    *b"cHRM", *b"cICP", *b"gAMA", *b"iCCP", *b"mDCV", *b"cLLI", *b"sBIT",
+    *b"sRGB",
+];
+
+/// Chunks that go after PLTE and before IDAT, in output order.
+pub(crate) const AFTER_PALETTE: &[[u8; 4]] = &[
+    *b"tRNS", *b"bKGD", *b"hIST", *b"eXIf", *b"pHYs", *b"oFFs", *b"pCAL",
+    *b"sCAL", *b"sPLT", *b"acTL",
+];
+
+/// Chunks that may only appear once (other than IHDR, IDAT and IEND).
+const ONCE: &[[u8; 4]] = &[
+    *b"PLTE", *b"cHRM", *b"cICP", *b"gAMA", *b"iCCP", *b"mDCV", *b"cLLI",
+    *b"sBIT", *b"sRGB", *b"tRNS", *b"bKGD", *b"hIST", *b"eXIf", *b"pHYs",
+    *b"oFFs", *b"pCAL", *b"sCAL", *b"acTL", *b"tIME",
+];
+
+/// Sort key, name and data of a chunk that isn't IHDR, IDAT or IEND.
+type Kept = ((u8, usize), [u8; 4], Vec<u8>);
+
+/// Rewrite the PNG file `input` in a canonical form, for byte-level
+/// comparison of files that only differ in chunk layout.
+///
+/// The output has the header first, known ancillary chunks in the order
+/// recommended by the PNG specification (such as sRGB before PLTE, and pHYs
+/// before IDAT), the image data merged into a single IDAT chunk, and the IEND
+/// chunk last.  Text, time and unknown chunks keep their order (unknown
+/// chunks after the image data stay after it, so APNG frames stay in order).
+/// Duplicates of chunks that are only allowed once are dropped (keeping the
+/// first), as are exact duplicates of other chunks, and anything after the
+/// IEND chunk.  Chunk data isn't changed, so the output decodes to identical
+/// pixels.  Errors are from decoding `input`.
+pub fn normalize_png(input: &[u8]) -> Result<Vec<u8>> {
+    let mut parser = Decoder::new(input)?.parser();
+    let mut header = None;
+    let mut idat: Option<Vec<u8>> = None;
+    let mut chunks: Vec<Kept> = Vec::new();
+
+    while let Some(name) = parser.prepare()? {
+        if name == consts::IMAGE_DATA {
+            parser.extend_payload(idat.get_or_insert_with(Vec::new))?;
+            continue;
+        }
+
+        let data = parser.payload()?;
+        let key = match name {
+            consts::IMAGE_HEADER => {
+                header.get_or_insert_with(|| data.to_vec());
+                continue;
+            }
+            consts::IMAGE_END => break,
+            consts::PALETTE => (1, 0),
+            name => {
+                if let Some(i) = position(BEFORE_PALETTE, name) {
+                    (0, i)
+                } else if let Some(i) = position(AFTER_PALETTE, name) {
+                    (2, i)
+                } else if idat.is_some() && is_unknown(name) {
+                    (4, 0)
+                } else {
+                    (3, 0)
+                }
+            }
+        };
+        let duplicate = chunks
+            .iter()
+            .any(|(_, n, d)| *n == name && (ONCE.contains(&name) || d == data));
+        if !duplicate {
+            chunks.push((key, name, data.to_vec()));
+        }
+    }
+    // Stable, so chunks with the same key keep their order
+    chunks.sort_by_key(|(key, _, _)| *key);
+
+    let header = header.ok_or(Error::Empty)?;
+    let idat = idat.ok_or(Error::NoImageData)?;
+
+    let mut out = consts::PNG_SIGNATURE.to_vec();
+    write_chunk(&mut out, consts::IMAGE_HEADER, &header);
+    let (before, after) = chunks.split_at(
+        chunks
+            .iter()
+            .position(|(key, _, _)| key.0 == 4)
+            .unwrap_or(chunks.len()),
+    );
+    for (_, name, data) in before {
+        write_chunk(&mut out, *name, data);
+    }
+    for data in idat.chunks(consts::MAX_CHUNK_SIZE) {
+        write_chunk(&mut out, consts::IMAGE_DATA, data);
+    }
+    for (_, name, data) in after {
+        write_chunk(&mut out, *name, data);
+    }
+    write_chunk(&mut out, consts::IMAGE_END, &[]);
+
+    Ok(out)
+}
+
+/// Get the position of `name` in `list`.
+fn position(list: &[[u8; 4]], name: [u8; 4]) -> Option<usize> {
+    list.iter().position(|n| *n == name)
+}
+
+/// Is the chunk neither a text nor a time chunk?
+fn is_unknown(name: [u8; 4]) -> bool {
+    !matches!(
+        name,
+        consts::TEXT | consts::ZTEXT | consts::ITEXT | consts::TIME
+    )
+}
@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+
+use pix::{
+    el::Pixel,
+    gray::{Gray8, SGray8, SGraya8},
+    rgb::{SRgb8, SRgba16, SRgba8},
+    Palette, Raster,
+};
+
+use crate::{
+    encode::{FilterStrategy, Result},
+    Decoder, Encoder, PngRaster, Step,
+};
+
+/// How many encodings [`optimize()`] tries.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Effort {
+    /// Color reductions, with the Zero and MinSum filter strategies at level 6
+    Fast,
+    /// Color reductions, with every filter strategy except BruteForce at
+    /// levels 6 and 9
+    #[default]
+    Normal,
+    /// Color reductions, with every filter strategy at levels 6, 9 and 10
+    Max,
+}
+
+impl Effort {
+    /// Filter strategies to try.
+    fn strategies(self) -> &'static [FilterStrategy] {
+        use FilterStrategy::*;
+        match self {
+            Effort::Fast => &[Zero, MinSum],
+            Effort::Normal => &[Zero, Up, MinSum, Entropy],
+            Effort::Max => &[Zero, Up, MinSum, Entropy, BruteForce],
+        }
+    }
+
+    /// Compression levels to try.
+    fn levels(self) -> &'static [u8] {
+        match self {
+            Effort::Fast => &[6],
+            Effort::Normal => &[6, 9],
+            Effort::Max => &[6, 9, 10],
+        }
+    }
+}
+
+/// Encode `step` as a PNG file several ways, and return the smallest.
+///
+/// Besides the raster as is, this tries lossless color reductions of 8-bit
+/// rasters: dropping an alpha channel that's fully opaque, storing grey
+/// pixels as greyscale, and storing rasters with at most 256 colors as a
+/// palette.  Each raster is encoded non-interlaced with each filter strategy
+/// and compression level chosen by `effort`.  Candidates are decoded again,
+/// and only kept if they decode to exactly the same pixels, so the result is
+/// never lossy (or bigger than the default encoding).
+///
+/// With the `rayon` feature, candidates are encoded in parallel.
+pub fn optimize(step: &Step, effort: Effort) -> Result<Vec<u8>> {
+    let mut best = Vec::new();
+    Encoder::new(&mut best).into_step_enc().encode(step)?;
+
+    let expected = Raster::<SRgba16>::from(step.raster.clone());
+    let mut rasters = vec![step.raster.clone()];
+    rasters.extend(reductions(&step.raster));
+    let candidates: Vec<_> = rasters
+        .iter()
+        .flat_map(|raster| {
+            effort.strategies().iter().flat_map(move |strategy| {
+                effort
+                    .levels()
+                    .iter()
+                    .map(move |&level| (raster, strategy.clone(), level))
+            })
+        })
+        .collect();
+    let encode =
+        |(raster, strategy, level): &(&PngRaster, FilterStrategy, u8)| {
+            encode_verified(raster, strategy.clone(), *level, &expected)
+        };
+
+    #[cfg(feature = "rayon")]
+    let smallest = {
+        use rayon::prelude::*;
+
+        candidates
+            .par_iter()
+            .filter_map(encode)
+            .min_by_key(Vec::len)
+    };
+    #[cfg(not(feature = "rayon"))]
+    let smallest = candidates.iter().filter_map(encode).min_by_key(Vec::len);
+
+    if let Some(smallest) = smallest {
+        if smallest.len() < best.len() {
+            best = smallest;
+        }
+    }
+    Ok(best)
+}
+
+/// Encode `raster`, returning `None` if it doesn't decode back to `expected`.
+fn encode_verified(
+    raster: &PngRaster,
+    strategy: FilterStrategy,
+    level: u8,
+    expected: &Raster<SRgba16>,
+) -> Option<Vec<u8>> {
+    let mut png = Vec::new();
+    Encoder::new(&mut png)
+        .filter_strategy(strategy)
+        .compression_level(level)
+        .into_step_enc()
+        .still(raster)
+        .ok()?;
+
+    let step = Decoder::new(png.as_slice())
+        .ok()?
+        .into_steps()
+        .next()?
+        .ok()?;
+    let decoded = Raster::<SRgba16>::from(step.raster);
+    (decoded.pixels() == expected.pixels()).then_some(png)
+}
+
+/// Get lossless color reductions of an 8-bit raster.
+pub(super) fn reductions(raster: &PngRaster) -> Vec<PngRaster> {
+    if !matches!(
+        raster,
+        PngRaster::Gray8(_)
+            | PngRaster::Rgb8(_)
+            | PngRaster::Graya8(_)
+            | PngRaster::Rgba8(_)
+    ) {
+        return Vec::new();
+    }
+
+    let rgba = Raster::<SRgba8>::from(raster.clone());
+    let (width, height) = (rgba.width(), rgba.height());
+    let pixels = rgba.pixels();
+    let opaque = pixels.iter().all(|p| u8::from(p.four()) == u8::MAX);
+    let grey = pixels
+        .iter()
+        .all(|p| p.one() == p.two() && p.two() == p.three());
+
+    let mut reductions = Vec::new();
+    let reduced = match (grey, opaque) {
+        (false, false) => None,
+        (true, true) => Some(PngRaster::Gray8(Raster::with_pixels(
+            width,
+            height,
+            pixels
+                .iter()
+                .map(|p| SGray8::new(p.one()))
+                .collect::<Vec<_>>(),
+        ))),
+        (true, false) => Some(PngRaster::Graya8(Raster::with_pixels(
+            width,
+            height,
+            pixels
+                .iter()
+                .map(|p| SGraya8::new(p.one(), p.four()))
+                .collect::<Vec<_>>(),
+        ))),
+        (false, true) => Some(PngRaster::Rgb8(Raster::with_pixels(
+            width,
+            height,
+            pixels
+                .iter()
+                .map(|p| SRgb8::new(p.one(), p.two(), p.three()))
+                .collect::<Vec<_>>(),
+        ))),
+    };
+    let color_type = raster.header(false).color_type;
+    if let Some(reduced) = reduced {
+        if reduced.header(false).color_type != color_type {
+            reductions.push(reduced);
+        }
+    }
+    if let Some(palette) = palette(&rgba) {
+        reductions.push(palette);
+    }
+    reductions
+}
+
+/// Convert to a palette raster, if it has at most 256 colors (which don't
+/// differ only in alpha).
+fn palette(rgba: &Raster<SRgba8>) -> Option<PngRaster> {
+    let key = |p: &SRgba8| -> [u8; 4] {
+        [
+            p.one().into(),
+            p.two().into(),
+            p.three().into(),
+            p.four().into(),
+        ]
+    };
+    let mut colors = Vec::new();
+    let mut indices = HashMap::new();
+    for pixel in rgba.pixels() {
+        if indices.insert(key(pixel), 0).is_none() {
+            if colors.len() == 256 {
+                return None;
+            }
+            colors.push(key(pixel));
+        }
+    }
+    // Translucent colors first, so the tRNS chunk is as short as possible
+    colors.sort_by_key(|&[_, _, _, alpha]| alpha == u8::MAX);
+
+    let mut palette = Palette::new(colors.len());
+    let mut alphas = Vec::with_capacity(colors.len());
+    for (i, &color) in colors.iter().enumerate() {
+        let [red, green, blue, alpha] = color;
+        if palette.set_entry(SRgb8::new(red, green, blue))? != i {
+            return None;
+        }
+        alphas.push(alpha);
+        indices.insert(color, i as u8);
+    }
+    while alphas.last() == Some(&u8::MAX) {
+        alphas.pop();
+    }
+
+    let pixels: Vec<Gray8> = rgba
+        .pixels()
+        .iter()
+        .map(|pixel| Gray8::new(indices[&key(pixel)]))
+        .collect();
+    Some(PngRaster::Palette(
+        Raster::with_pixels(rgba.width(), rgba.height(), pixels),
+        Box::new(palette),
+        alphas,
+    ))
+}
@@ -0,0 +1,58 @@
+use crate::{consts, crc32, decode::Result, zlib, Decoder};
+
+/// Recompress the image data of the PNG file `input` at compression `level`
+/// (between 0 and 10), returning the new PNG file.
+///
+/// The IDAT chunks are merged and placed where the first one was.  Scanlines
+/// aren't re-filtered, so the output decodes to identical pixels.  All other
+/// chunks are copied unchanged.  Errors are from decoding `input`.
+///
+/// # Panics
+/// If `level` is more than 10.
+pub fn recompress_idat(input: &[u8], level: u8) -> Result<Vec<u8>> {
+    assert!(level <= 10);
+
+    let mut parser = Decoder::new(input)?.parser();
+    let mut out = consts::PNG_SIGNATURE.to_vec();
+    // Chunks after the image data
+    let mut after = Vec::new();
+    let mut idat: Option<Vec<u8>> = None;
+
+    while let Some(name) = parser.prepare()? {
+        if name == consts::IMAGE_DATA {
+            parser.extend_payload(idat.get_or_insert_with(Vec::new))?;
+            continue;
+        }
+
+        let data = parser.payload()?;
+        if idat.is_some() {
+            write_chunk(&mut after, name, data);
+        } else {
+            write_chunk(&mut out, name, data);
+        }
+    }
+
+    if let Some(idat) = idat {
+        let mut zlib = Vec::new();
+        zlib::compress(&mut zlib, &zlib::decompress(&idat)?, level);
+        for data in zlib.chunks(consts::MAX_CHUNK_SIZE) {
+            write_chunk(&mut out, consts::IMAGE_DATA, data);
+        }
+    }
+    out.extend(after);
+
+    Ok(out)
+}
+
+/// Append a chunk to `out`.
+pub(super) fn write_chunk(out: &mut Vec<u8>, name: [u8; 4], data: &[u8]) {
+    let len: u32 = data.len().try_into().unwrap();
+    out.extend(len.to_be_bytes());
+
+    let start = out.len();
+    out.extend(name);
+    out.extend_from_slice(data);
+
+    let crc = crc32::crc32(&out[start..]);
+    out.extend(crc.to_be_bytes());
+}
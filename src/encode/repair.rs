@@ -0,0 +1,221 @@
+use std::io::{Read, Write};
+
+use super::{normalize_png, recompress::write_chunk, Defaults};
+use crate::{
+    adam7,
+    chunk::ImageHeader,
+    consts, crc32,
+    decode::{png_chunk_names, png_header, Error, Result},
+    zlib, Decoder,
+};
+
+/// What [`repair()`] changed to recover a damaged PNG file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    /// Names of the chunks that were dropped, in file order: ancillary chunks
+    /// with a bad CRC, cut off or that don't parse, unknown critical chunks,
+    /// and duplicates of chunks that are only allowed once
+    pub dropped: Vec<[u8; 4]>,
+    /// Names of the critical chunks (IHDR, PLTE and IDAT) that were kept
+    /// despite a bad CRC
+    pub fixed: Vec<[u8; 4]>,
+    /// Names of the chunks cut off by the end of the file
+    pub truncated: Vec<[u8; 4]>,
+    /// Was the IEND chunk missing?
+    pub missing_end: bool,
+    /// Were chunks moved to put them in a valid order?
+    pub reordered: bool,
+    /// Number of bytes of image data (scanlines and filter types) that were
+    /// missing or invalid, and replaced with zeros
+    pub image_bytes_replaced: usize,
+}
+
+/// Recover what can be recovered from a damaged PNG file read from `reader`,
+/// writing a valid PNG file to `writer` and reporting what was changed.
+///
+/// CRCs are checked but mismatches are tolerated for critical chunks, a file
+/// cut off part way is accepted, ancillary chunks that are damaged are
+/// dropped, and chunks are put in a valid order (see [`normalize_png()`]).
+/// The image data is decompressed as far as possible, and any missing
+/// scanlines are filled with zeros (the first palette color, or black), so the
+/// output decodes.  Anything after the IEND chunk is ignored.
+///
+/// Missing image data is filled in, so the output is as large as the IHDR
+/// chunk says, which a damaged file can't be trusted for.  Images of more
+/// than `max_pixels` pixels are rejected with [`Error::LimitExceeded`].
+///
+/// Returns an error if the file doesn't start with the PNG signature or has
+/// no valid IHDR chunk, as there's nothing to recover then, or on I/O errors.
+pub fn repair<R: Read, W: Write>(
+    mut reader: R,
+    mut writer: W,
+    max_pixels: u64,
+) -> Result<RepairReport> {
+    let mut input = Vec::new();
+    reader.read_to_end(&mut input)?;
+    let Some(mut rest) = input.strip_prefix(&consts::PNG_SIGNATURE[..]) else {
+        return Err(Error::InvalidSignature);
+    };
+    let mut report = RepairReport {
+        missing_end: true,
+        ..RepairReport::default()
+    };
+
+    // Read every chunk that's there, with whether its CRC is correct (or
+    // `None` if it's cut off)
+    let mut chunks: Vec<([u8; 4], &[u8], Option<bool>)> = Vec::new();
+    while rest.len() >= 8 {
+        let length = u32::from_be_bytes(rest[..4].try_into().unwrap());
+        let name: [u8; 4] = rest[4..8].try_into().unwrap();
+        if !name.iter().all(u8::is_ascii_alphabetic) {
+            // The rest isn't chunks
+            break;
+        }
+        if name == consts::IMAGE_END {
+            report.missing_end = false;
+            break;
+        }
+        let end = usize::try_from(length)
+            .ok()
+            .and_then(|length| length.checked_add(8))
+            .filter(|&end| end + 4 <= rest.len());
+        let Some(end) = end else {
+            report.truncated.push(name);
+            chunks.push((name, &rest[8..], None));
+            break;
+        };
+        let crc = u32::from_be_bytes(rest[end..][..4].try_into().unwrap());
+        let valid = crc == crc32::crc32(&rest[4..end]);
+        chunks.push((name, &rest[8..end], Some(valid)));
+        rest = &rest[end + 4..];
+    }
+    let position =
+        |wanted| chunks.iter().position(|(name, _, _)| *name == wanted);
+
+    // The header, and the palette without any colors cut off part way
+    let header_position = position(consts::IMAGE_HEADER).ok_or(Error::Empty)?;
+    let (_, header_data, _) = chunks[header_position];
+    let header =
+        png_header(file(&[(consts::IMAGE_HEADER, header_data)]).as_slice())?;
+    let palette_position =
+        position(consts::PALETTE).filter(|&i| chunks[i].1.len() >= 3);
+    let palette =
+        palette_position.map(|i| &chunks[i].1[..chunks[i].1.len() / 3 * 3]);
+    if u64::from(header.width) * u64::from(header.height) > max_pixels {
+        return Err(Error::LimitExceeded);
+    }
+    let mut context = vec![(consts::IMAGE_HEADER, header_data)];
+    context.extend(palette.map(|palette| (consts::PALETTE, palette)));
+
+    // The image data, made the right size with valid filter types
+    let idat: Vec<u8> = chunks
+        .iter()
+        .filter(|(name, _, _)| *name == consts::IMAGE_DATA)
+        .flat_map(|(_, data, _)| data.iter().copied())
+        .collect();
+    let size = header.idat_raw_size().ok_or(Error::ImageDimensions)?;
+    // Anything past the size of the image is cut off anyway
+    let mut image = zlib::decompress_partial(&idat, size);
+    report.image_bytes_replaced = size.saturating_sub(image.len());
+    image.resize(size, 0);
+    let passes: Vec<ImageHeader> = if header.interlace {
+        (0..adam7::PASSES)
+            .filter_map(|pass| header.pass_header(pass))
+            .collect()
+    } else {
+        vec![header]
+    };
+    let mut start = 0;
+    for pass in passes {
+        let row = pass.scanline_bytes().ok_or(Error::ImageDimensions)? + 1;
+        let rows = pass.height as usize;
+        for scanline in image[start..].chunks_mut(row).take(rows) {
+            if scanline[0] > 4 {
+                scanline[0] = 0;
+                report.image_bytes_replaced += 1;
+            }
+        }
+        start += row * rows;
+    }
+    let level =
+        Defaults::for_color_type(header.color_type, header.bit_depth).level;
+    let mut zlib = Vec::new();
+    zlib::compress(&mut zlib, &image, level);
+
+    // Chunks to keep, with the image data at the first IDAT chunk
+    let mut kept = consts::PNG_SIGNATURE.to_vec();
+    let mut kept_names = Vec::new();
+    for (i, &(name, data, crc)) in chunks.iter().enumerate() {
+        let data = match name {
+            consts::IMAGE_HEADER if i == header_position => data,
+            consts::PALETTE if Some(i) == palette_position => palette.unwrap(),
+            consts::IMAGE_DATA if !kept_names.contains(&name) => {
+                zlib.as_slice()
+            }
+            consts::IMAGE_DATA => {
+                if crc == Some(false) {
+                    report.fixed.push(name);
+                }
+                continue;
+            }
+            _ if crc != Some(true) || name[0].is_ascii_uppercase() => {
+                report.dropped.push(name);
+                continue;
+            }
+            _ => {
+                let mut chunk = context.clone();
+                chunk.push((name, data));
+                let parses = Decoder::new(file(&chunk).as_slice()).is_ok_and(
+                    |decoder| decoder.into_chunks().all(|chunk| chunk.is_ok()),
+                );
+                if !parses {
+                    report.dropped.push(name);
+                    continue;
+                }
+                data
+            }
+        };
+        if crc == Some(false) && name[0].is_ascii_uppercase() {
+            report.fixed.push(name);
+        }
+        write_chunk(&mut kept, name, data);
+        kept_names.push(name);
+    }
+    if !kept_names.contains(&consts::IMAGE_DATA) {
+        write_chunk(&mut kept, consts::IMAGE_DATA, &zlib);
+        kept_names.push(consts::IMAGE_DATA);
+    }
+    write_chunk(&mut kept, consts::IMAGE_END, &[]);
+    kept_names.push(consts::IMAGE_END);
+
+    // Put the chunks in order, and drop duplicates
+    let output = normalize_png(&kept)?;
+    let mut names = png_chunk_names(output.as_slice())?;
+    names.dedup_by(|a, b| *a == consts::IMAGE_DATA && a == b);
+    let mut unmatched = kept_names.clone();
+    for name in &names {
+        if let Some(i) = unmatched.iter().position(|n| n == name) {
+            unmatched.remove(i);
+        }
+    }
+    report.dropped.extend(unmatched);
+    report.reordered = !is_subsequence(&names, &kept_names);
+
+    writer.write_all(&output)?;
+    Ok(report)
+}
+
+/// Build a PNG file from `chunks`.
+fn file(chunks: &[([u8; 4], &[u8])]) -> Vec<u8> {
+    let mut file = consts::PNG_SIGNATURE.to_vec();
+    for (name, data) in chunks {
+        write_chunk(&mut file, *name, data);
+    }
+    file
+}
+
+/// Are the items of `a` in `b`, in the same order?
+fn is_subsequence(a: &[[u8; 4]], b: &[[u8; 4]]) -> bool {
+    let mut b = b.iter();
+    a.iter().all(|item| b.any(|other| other == item))
+}
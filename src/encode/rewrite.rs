@@ -0,0 +1,145 @@
+use std::io::{self, Read, Write};
+
+use crate::{
+    chunk::Chunk,
+    consts,
+    decode::{
+        scan::{chunk_header, signature, skip},
+        Error,
+    },
+    encode::Result,
+};
+
+/// Selects chunks of a PNG file for an [`Edit`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChunkSelector {
+    /// Every chunk with this name
+    Name([u8; 4]),
+    /// The chunk with this name that comes `n`th (starting at 0) among the
+    /// chunks with this name
+    Nth([u8; 4], usize),
+    /// The chunk at this position in the file (starting at 0 for the first
+    /// chunk after the signature)
+    Index(usize),
+}
+
+impl ChunkSelector {
+    /// Does the chunk at `index`, which is the `nth` chunk named `name`,
+    /// match?
+    fn matches(self, index: usize, name: [u8; 4], nth: usize) -> bool {
+        match self {
+            ChunkSelector::Name(n) => n == name,
+            ChunkSelector::Nth(n, i) => n == name && i == nth,
+            ChunkSelector::Index(i) => i == index,
+        }
+    }
+}
+
+/// An edit to apply with [`rewrite()`].
+#[derive(Clone, Debug)]
+pub enum Edit {
+    /// Insert a chunk after each selected chunk
+    Insert(ChunkSelector, Chunk),
+    /// Remove the selected chunks
+    Remove(ChunkSelector),
+    /// Replace each selected chunk with a chunk
+    Replace(ChunkSelector, Chunk),
+}
+
+/// Copy the PNG file from `reader` to `writer`, applying `edits`.
+///
+/// The file is streamed: chunks are copied through a small buffer and never
+/// held in memory whole, so files of any size can be edited in constant
+/// memory.  Chunks that aren't selected by an edit are copied byte for byte in
+/// their original position, including unknown, duplicate and misordered chunks
+/// (and without checking their CRCs), so the output only differs from the
+/// input where the edits apply.  Chunks are selected by their position in the
+/// input.  If several `Replace` edits select the same chunk, the first one
+/// applies, and a chunk that's both removed and replaced is replaced.
+///
+/// Decoding errors are returned as `ErrorKind::InvalidData` I/O errors
+/// wrapping the [`decode::Error`](crate::decode::Error), and encoding errors
+/// from the new chunks as ones wrapping the [`Error`](crate::encode::Error).
+pub fn rewrite<R: Read, W: Write>(
+    mut reader: R,
+    mut writer: W,
+    edits: &[Edit],
+) -> io::Result<()> {
+    // Encode the new chunks up front
+    let chunks = edits
+        .iter()
+        .map(|edit| match edit {
+            Edit::Insert(_, chunk) | Edit::Replace(_, chunk) => {
+                chunk.to_bytes().map(Some)
+            }
+            Edit::Remove(_) => Ok(None),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    signature(&mut reader)?;
+    writer.write_all(&consts::PNG_SIGNATURE)?;
+
+    // Number of chunks read so far for each name
+    let mut counts: Vec<([u8; 4], usize)> = Vec::new();
+    let mut index = 0;
+    while let Some((name, length)) = chunk_header(&mut reader)? {
+        let nth = match counts.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, count)) => {
+                *count += 1;
+                *count - 1
+            }
+            None => {
+                counts.push((name, 1));
+                0
+            }
+        };
+
+        let mut keep = true;
+        let mut replacement = None;
+        for (edit, data) in edits.iter().zip(&chunks) {
+            match edit {
+                Edit::Remove(selector)
+                    if selector.matches(index, name, nth) =>
+                {
+                    keep = false
+                }
+                Edit::Replace(selector, _)
+                    if selector.matches(index, name, nth) =>
+                {
+                    replacement = replacement.or(data.as_ref());
+                }
+                _ => {}
+            }
+        }
+        // Data and CRC
+        let rest = u64::from(length) + 4;
+        match replacement {
+            Some(data) => {
+                skip(&mut reader, rest)?;
+                writer.write_all(data)?;
+            }
+            None if keep => {
+                writer.write_all(&length.to_be_bytes())?;
+                writer.write_all(&name)?;
+                // Streamed through a small buffer
+                if io::copy(&mut reader.by_ref().take(rest), &mut writer)?
+                    != rest
+                {
+                    return Err(Error::Eof.into());
+                }
+            }
+            None => skip(&mut reader, rest)?,
+        }
+
+        for (edit, data) in edits.iter().zip(&chunks) {
+            if let (Edit::Insert(selector, _), Some(data)) = (edit, data) {
+                if selector.matches(index, name, nth) {
+                    writer.write_all(data)?;
+                }
+            }
+        }
+        index += 1;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,14 @@
+use super::FilterStrategy;
+
+/// Statistics about the last image encoded by a
+/// [`StepEnc`](struct.StepEnc.html).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EncodeStats {
+    /// Filter strategy used
+    pub filter_strategy: FilterStrategy,
+    /// Compression level used
+    pub level: u8,
+    /// Number of scanlines using each filter type (None, Sub, Up, Average
+    /// and Paeth)
+    pub filters: [usize; 5],
+}
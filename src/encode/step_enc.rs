@@ -14,16 +14,28 @@ use crate::{
         ColorType, ImageData, ImageEnd, ImageHeader, Palette as PaletteChunk,
         Transparency,
     },
-    encode::{filter, ChunkEnc, Error as EncoderError, FilterStrategy, Result},
+    consts,
+    encode::{
+        budget,
+        filter::{self, FilterFn},
+        ChunkEnc, Defaults, EncodeStats, Error as EncoderError, FilterStrategy,
+        FrameOptions, Result,
+    },
     encoder::Enc,
-    PngRaster, Step,
+    zlib, PngRaster, Step,
 };
 
 pub trait AsRaster {
     fn get_header(&self, interlace: bool) -> ImageHeader;
     fn get_u8_slice(&self) -> &[u8];
+    /// Bytes from the start of one row to the next, if the rows aren't packed
+    /// one after the other.
+    fn get_stride(&self) -> Option<usize> {
+        None
+    }
     fn get_palette_colors(&self) -> &[SRgb8];
     fn get_palette_alphas(&self) -> &[u8];
+    fn to_png_raster(&self) -> Result<PngRaster>;
 }
 
 impl AsRaster for PngRaster {
@@ -61,6 +73,10 @@ impl AsRaster for PngRaster {
             _ => &[],
         }
     }
+
+    fn to_png_raster(&self) -> Result<PngRaster> {
+        Ok(self.clone())
+    }
 }
 
 impl<P: Pixel> AsRaster for Raster<P> {
@@ -105,88 +121,402 @@ impl<P: Pixel> AsRaster for Raster<P> {
     fn get_palette_alphas(&self) -> &[u8] {
         &[]
     }
+
+    fn to_png_raster(&self) -> Result<PngRaster> {
+        png_raster(&self.get_header(false), self.as_u8_slice())
+    }
+}
+
+/// A region of a raster, read in place.
+struct Region<'a> {
+    header: ImageHeader,
+    /// Bytes of the raster from the region's first pixel on
+    bytes: &'a [u8],
+    /// Bytes from the start of one row of the raster to the next
+    stride: usize,
+}
+
+impl AsRaster for Region<'_> {
+    fn get_header(&self, interlace: bool) -> ImageHeader {
+        ImageHeader {
+            interlace,
+            ..self.header
+        }
+    }
+
+    fn get_u8_slice(&self) -> &[u8] {
+        self.bytes
+    }
+
+    fn get_stride(&self) -> Option<usize> {
+        Some(self.stride)
+    }
+
+    fn get_palette_colors(&self) -> &[SRgb8] {
+        &[]
+    }
+
+    fn get_palette_alphas(&self) -> &[u8] {
+        &[]
+    }
+
+    fn to_png_raster(&self) -> Result<PngRaster> {
+        let bytes: Vec<u8> = rows(self.bytes, &self.header, self.get_stride())
+            .flatten()
+            .copied()
+            .collect();
+        png_raster(&self.header, &bytes)
+    }
+}
+
+/// Get the rows of an image of at least 8 bits per pixel, which start
+/// `stride` bytes apart (or are packed one after the other if `None`).
+fn rows<'a>(
+    image: &'a [u8],
+    header: &ImageHeader,
+    stride: Option<usize>,
+) -> impl Iterator<Item = &'a [u8]> {
+    let len = header.width as usize * usize::from(header.bpp() / 8);
+    let stride = stride.unwrap_or(len);
+
+    (0..header.height as usize).map(move |y| &image[y * stride..][..len])
+}
+
+/// Get a raster of one of the PNG types other than palette from `bytes`,
+/// which has 8 or 16-bit channels in the raster's native layout.
+fn png_raster(header: &ImageHeader, bytes: &[u8]) -> Result<PngRaster> {
+    let (width, height) = (header.width, header.height);
+    // 16-bit channels are stored in native byte order
+    let words = || -> Vec<u16> {
+        bytes
+            .chunks_exact(2)
+            .map(|word| u16::from_ne_bytes([word[0], word[1]]))
+            .collect()
+    };
+    Ok(match (header.color_type, header.bit_depth) {
+        (ColorType::Grey, 8) => {
+            PngRaster::Gray8(Raster::with_u8_buffer(width, height, bytes))
+        }
+        (ColorType::Grey, 16) => {
+            PngRaster::Gray16(Raster::with_u16_buffer(width, height, words()))
+        }
+        (ColorType::GreyAlpha, 8) => {
+            PngRaster::Graya8(Raster::with_u8_buffer(width, height, bytes))
+        }
+        (ColorType::GreyAlpha, 16) => {
+            PngRaster::Graya16(Raster::with_u16_buffer(width, height, words()))
+        }
+        (ColorType::Rgb, 8) => {
+            PngRaster::Rgb8(Raster::with_u8_buffer(width, height, bytes))
+        }
+        (ColorType::Rgb, 16) => {
+            PngRaster::Rgb16(Raster::with_u16_buffer(width, height, words()))
+        }
+        (ColorType::Rgba, 8) => {
+            PngRaster::Rgba8(Raster::with_u8_buffer(width, height, bytes))
+        }
+        (ColorType::Rgba, 16) => {
+            PngRaster::Rgba16(Raster::with_u16_buffer(width, height, words()))
+        }
+        (color_type, bit_depth) => {
+            return Err(EncoderError::ColorMode(color_type, bit_depth))
+        }
+    })
 }
 
 /// Frame Encoder for PNG files.
+///
+/// By default, this encodes a still image: the first call to
+/// [`still()`](Self::still) or [`encode()`](Self::encode) writes a whole PNG
+/// file, and later calls return [`EncoderError::AlreadyFinished`].  With
+/// [`Encoder::animation()`](crate::Encoder::animation), each call adds a frame
+/// to an APNG animation instead, which is written by
+/// [`finish()`](Self::finish).
 #[derive(Debug)]
 pub struct StepEnc<W: Write> {
     encoder: ChunkEnc<W>,
     // FIXME
     #[allow(dead_code)]
     coldepth: Option<(ColorType, u32)>,
-    #[allow(dead_code)]
+    /// Header of the first frame
     header: Option<ImageHeader>,
+    stats: Option<EncodeStats>,
+    /// Frames of an animation, until it's finished
+    frames: Option<Frames>,
+    finished: bool,
+}
+
+/// Frames of an animation, buffered until the frame count is known.
+#[derive(Debug, Default)]
+struct Frames {
+    palette: Vec<SRgb8>,
+    transparency: Vec<u8>,
+    /// Number of frames
+    count: u32,
+    /// Sequence number of the next fcTL or fdAT chunk
+    sequence: u32,
+    /// Chunks after the acTL chunk, up to the IEND chunk
+    chunks: Vec<([u8; 4], Vec<u8>)>,
 }
 
 impl<W: Write> StepEnc<W> {
     /// Create a new encoder.
     pub(crate) fn new(encoder: ChunkEnc<W>) -> Self {
+        let frames = encoder.enc.animation().then(Frames::default);
         Self {
             encoder,
             coldepth: None,
             header: None,
+            stats: None,
+            frames,
+            finished: false,
         }
     }
 
+    /// Unwrap the writer, for example to get back the `Vec<u8>` the PNG was
+    /// encoded into.  Frames of an animation aren't written until
+    /// [`finish()`](Self::finish) is called.
+    pub fn into_inner(self) -> W {
+        // Held chunks are always written before IEND, which ends every file
+        self.encoder.enc.into_inner()
+    }
+
+    /// Get statistics about the last encoded image, `None` if nothing has
+    /// been encoded yet.
+    pub fn stats(&self) -> Option<&EncodeStats> {
+        self.stats.as_ref()
+    }
+
     /// Encode a still (takes either a `png_pong::PngRaster` or `pix::Raster`).
+    ///
+    /// When encoding an animation, this adds a frame with no delay.
     pub fn still<R: AsRaster>(&mut self, raster: &R) -> Result<()> {
-        let image_header = raster.get_header(self.encoder.enc.interlace());
+        self.frame(raster, 0, &FrameOptions::default())
+    }
 
-        encode(
-            &mut self.encoder.enc,
-            raster.get_u8_slice(),
-            &image_header,
-            raster.get_palette_colors(),
-            raster.get_palette_alphas(),
-        )
+    /// Encode the `width` by `height` region of `raster` with its top left
+    /// corner at (`x`, `y`), like [`still()`](Self::still), without copying
+    /// it to a raster of its own first.  Returns [`EncoderError::RegionBounds`]
+    /// if the region doesn't fit within `raster`.
+    ///
+    /// This is useful for writing each image of a texture atlas.  The rows of
+    /// the region are read from `raster`, with its stride, as the scanlines
+    /// are filtered.  The region is only copied when reducing the bit depth
+    /// (see [`Encoder::reduce_depth()`](crate::Encoder::reduce_depth)) or
+    /// fitting a maximum output size, which work on a copy of any image.
+    pub fn encode_region<P: Pixel>(
+        &mut self,
+        raster: &Raster<P>,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        let fits = |start: u32, len: u32, size: u32| {
+            start.checked_add(len).is_some_and(|end| end <= size)
+        };
+        if !fits(x, width, raster.width()) || !fits(y, height, raster.height())
+        {
+            return Err(EncoderError::RegionBounds);
+        }
+
+        let header = raster.get_header(false);
+        let pixel = usize::from(header.bpp() / 8);
+        let stride = raster.width() as usize * pixel;
+        let start = y as usize * stride + x as usize * pixel;
+        let region = Region {
+            header: ImageHeader {
+                width,
+                height,
+                ..header
+            },
+            bytes: &raster.as_u8_slice()[start..],
+            stride,
+        };
+
+        self.frame(&region, 0, &FrameOptions::default())
     }
 
     /// Encode one [`Step`](struct.Step.html) of an animation.
+    ///
+    /// The delay is in milliseconds.  When encoding a still image, the delay
+    /// is ignored.
     pub fn encode(&mut self, frame: &Step) -> Result<()> {
-        self.still(&frame.raster)
+        self.frame(&frame.raster, frame.delay, &FrameOptions::default())
     }
-}
 
-pub(super) fn encode<W: Write>(
-    enc: &mut Enc<W>,
-    image: &[u8],
-    header: &ImageHeader,
-    palette: &[SRgb8],
-    transparency: &[u8],
-) -> Result<()> {
-    enc.raw(&crate::consts::PNG_SIGNATURE)?;
+    /// Encode one [`Step`](struct.Step.html) like [`encode()`](Self::encode),
+    /// with `options` overriding the encoder's filter strategy and
+    /// compression level for this frame only.
+    ///
+    /// The size, color type and palette can't vary between frames of an
+    /// animation, so a frame that doesn't match the first returns
+    /// [`EncoderError::FrameMismatch`].  The options are ignored for a still
+    /// image with a maximum output size, which chooses its own settings.
+    ///
+    /// # Panics
+    /// If the compression level is over 10.
+    pub fn encode_frame_with(
+        &mut self,
+        frame: &Step,
+        options: FrameOptions,
+    ) -> Result<()> {
+        assert!(options.level.map_or(true, |level| level <= 10));
+        self.frame(&frame.raster, frame.delay, &options)
+    }
 
-    let transparency = Transparency::Palette(transparency.to_vec());
+    /// Write the animation, once all of its frames are encoded.
+    ///
+    /// Every frame must have the same size, color type and palette as the
+    /// first.  The animation loops forever.  Does nothing for a still image,
+    /// or an animation without frames.
+    pub fn finish(&mut self) -> Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        let (Some(frames), Some(header)) = (&self.frames, &self.header) else {
+            return Ok(());
+        };
+        self.finished = true;
 
-    if header.color_type == ColorType::Palette
-        && (palette.is_empty() || palette.len() > 256)
-    {
-        return Err(EncoderError::BadPalette);
+        let enc = &mut self.encoder.enc;
+        enc.signature()?;
+        write_header(enc, header, &frames.palette, &frames.transparency)?;
+        let mut control = Vec::with_capacity(8);
+        control.extend(frames.count.to_be_bytes());
+        control.extend(0u32.to_be_bytes());
+        enc.write_chunk(consts::ANIMATION_CONTROL, &control)?;
+        for (name, data) in &frames.chunks {
+            enc.write_chunk(*name, data)?;
+        }
+        ImageEnd.write(enc)
     }
-    header
-        .color_type
-        .check_png_color_validity(header.bit_depth)
-        .unwrap();
 
-    let data = pre_process_scanlines(
-        image,
-        header,
-        enc.filter_strategy(),
-        enc.level(),
-    );
+    /// Encode a still image, or add a frame to an animation.
+    fn frame<R: AsRaster>(
+        &mut self,
+        raster: &R,
+        delay: u32,
+        options: &FrameOptions,
+    ) -> Result<()> {
+        if self.finished {
+            return Err(EncoderError::AlreadyFinished);
+        }
 
-    header.write(enc)?;
+        let mut image_header = raster.get_header(self.encoder.enc.interlace());
+        let mut image = raster.get_u8_slice();
+        let mut stride = raster.get_stride();
+        let reduced;
+        // Frames of an animation share one header, so aren't reduced
+        if self.encoder.enc.reduce_depth() && self.frames.is_none() {
+            if let Some((bit_depth, data)) =
+                reduce_depth(&image_header, image, stride)
+            {
+                image_header.bit_depth = bit_depth;
+                reduced = data;
+                image = &reduced;
+                stride = None;
+            }
+        }
 
-    if header.color_type == ColorType::Palette {
-        let palette = PaletteChunk {
-            palette: palette.to_vec(),
+        let Some(frames) = &mut self.frames else {
+            if let Some(budget) = self.encoder.enc.max_output_size() {
+                let raster = raster.to_png_raster()?;
+                let (png, stats) =
+                    budget::fit(&self.encoder.enc, &raster, budget)?;
+                self.encoder.enc.bytes(&png)?;
+                self.stats = Some(stats);
+                self.header = Some(image_header);
+                self.finished = true;
+                return Ok(());
+            }
+            self.stats = Some(encode(
+                &mut self.encoder.enc,
+                image,
+                stride,
+                &image_header,
+                raster.get_palette_colors(),
+                raster.get_palette_alphas(),
+                options,
+            )?);
+            self.header = Some(image_header);
+            self.finished = true;
+            return Ok(());
         };
 
-        palette.write(enc)?;
-    }
-    if header.color_type == ColorType::Palette && transparency.len() != 0 {
-        transparency.write(enc)?;
+        let (palette, transparency) =
+            (raster.get_palette_colors(), raster.get_palette_alphas());
+        match &self.header {
+            Some(header) => {
+                if *header != image_header
+                    || frames.palette != palette
+                    || frames.transparency != transparency
+                {
+                    return Err(EncoderError::FrameMismatch);
+                }
+            }
+            None => {
+                frames.palette = palette.to_vec();
+                frames.transparency = transparency.to_vec();
+            }
+        }
+        let (data, level, stats) = prepare(
+            &self.encoder.enc,
+            image,
+            stride,
+            &image_header,
+            palette,
+            options,
+        )?;
+        let mut zlib = Vec::new();
+        zlib::compress(&mut zlib, &data, level);
+
+        // Delay in milliseconds, as a fraction of a second
+        let delay = u16::try_from(delay).unwrap_or(u16::MAX);
+        let mut control = Vec::with_capacity(26);
+        control.extend(frames.sequence.to_be_bytes());
+        control.extend(image_header.width.to_be_bytes());
+        control.extend(image_header.height.to_be_bytes());
+        control.extend([0; 8]); // x and y offsets
+        control.extend(delay.to_be_bytes());
+        control.extend(1000u16.to_be_bytes());
+        control.extend([0, 0]); // No disposal, source blending
+        frames.chunks.push((consts::FRAME_CONTROL, control));
+        frames.sequence += 1;
+
+        // The first frame is the default image
+        if self.header.is_none() {
+            frames.chunks.push((consts::IMAGE_DATA, zlib));
+        } else {
+            let mut data = Vec::with_capacity(zlib.len() + 4);
+            data.extend(frames.sequence.to_be_bytes());
+            data.extend(zlib);
+            frames.chunks.push((consts::FRAME_DATA, data));
+            frames.sequence += 1;
+        }
+        frames.count += 1;
+        self.header = Some(image_header);
+        self.stats = Some(stats);
+        Ok(())
     }
+}
+
+/// Encode a still image.  Rows of `image` start `stride` bytes apart, or are
+/// packed one after the other if `None`.
+pub(super) fn encode<W: Write>(
+    enc: &mut Enc<W>,
+    image: &[u8],
+    stride: Option<usize>,
+    header: &ImageHeader,
+    palette: &[SRgb8],
+    transparency: &[u8],
+    options: &FrameOptions,
+) -> Result<EncodeStats> {
+    let (data, level, stats) =
+        prepare(enc, image, stride, header, palette, options)?;
+
+    enc.signature()?;
+    write_header(enc, header, palette, transparency)?;
     // FIXME: Transparency KEY
     /*if color_type == ColorType::Grey
         && discriminant(transparency)
@@ -210,7 +540,7 @@ pub(super) fn encode<W: Write>(
     /*if let Some(_chunks) = info.unknown_chunks_data(ChunkPosition::PLTE) {
         // add_unknown_chunks(&mut outv, _chunks);
     }*/
-    ImageData::with_data(data).write(enc)?;
+    ImageData::with_data(data).write_with_level(enc, level)?;
     /*if let Some(ref time) = info.time {
         time.write(&mut outv)?;
     }*/
@@ -248,7 +578,155 @@ pub(super) fn encode<W: Write>(
     /*if let Some(_chunks) = info.unknown_chunks_data(ChunkPosition::IDAT) {
         // add_unknown_chunks(&mut outv, _chunks);
     }*/
-    ImageEnd.write(enc)
+    ImageEnd.write(enc)?;
+
+    Ok(stats)
+}
+
+/// Check the palette, and filter the image, returning the filtered scanlines
+/// and the compression level to use for them.
+fn prepare<W: Write>(
+    enc: &Enc<W>,
+    image: &[u8],
+    stride: Option<usize>,
+    header: &ImageHeader,
+    palette: &[SRgb8],
+    options: &FrameOptions,
+) -> Result<(Vec<u8>, u8, EncodeStats)> {
+    if header.color_type == ColorType::Palette
+        && (palette.is_empty() || palette.len() > 256)
+    {
+        return Err(EncoderError::BadPalette);
+    }
+    if header.color_type == ColorType::Palette && header.bit_depth == 8 {
+        let size = palette.len();
+        if let Some(&index) = image.iter().find(|&&i| usize::from(i) >= size) {
+            // Only palettes with less than 256 colors can be indexed past
+            return Err(EncoderError::IndexOutOfPalette {
+                index,
+                palette_size: size as u8,
+            });
+        }
+    }
+    header
+        .color_type
+        .check_png_color_validity(header.bit_depth)
+        .unwrap();
+
+    // Fast path for small images
+    let defaults =
+        Defaults::for_color_type(header.color_type, header.bit_depth);
+    let level = options.level.or(enc.chosen_level());
+    let filter_strategy =
+        options.filter.clone().or_else(|| enc.filter_strategy());
+    let pixels = u64::from(header.width) * u64::from(header.height);
+    // Only the defaults change, a chosen filter strategy or level is kept
+    let (filter_strategy, level) =
+        if pixels < u64::from(enc.small_image_threshold()) {
+            (
+                filter_strategy.unwrap_or(FilterStrategy::Zero),
+                level.unwrap_or(defaults.level.min(1)),
+            )
+        } else {
+            (
+                filter_strategy.unwrap_or(defaults.filter_strategy),
+                level.unwrap_or(defaults.level),
+            )
+        };
+    let (data, filters) = pre_process_scanlines(
+        image,
+        stride,
+        header,
+        &filter_strategy,
+        level,
+        enc.filter_fn(),
+    );
+
+    let stats = EncodeStats {
+        filter_strategy,
+        level,
+        filters,
+    };
+    Ok((data, level, stats))
+}
+
+/// Write the header chunk, and the palette and transparency chunks of palette
+/// images.
+fn write_header<W: Write>(
+    enc: &mut Enc<W>,
+    header: &ImageHeader,
+    palette: &[SRgb8],
+    transparency: &[u8],
+) -> Result<()> {
+    let transparency = Transparency::Palette(transparency.to_vec());
+
+    header.write(enc)?;
+
+    if header.color_type == ColorType::Palette {
+        let palette = PaletteChunk {
+            palette: palette.to_vec(),
+        };
+
+        palette.write(enc)?;
+    }
+    if header.color_type == ColorType::Palette && transparency.len() != 0 {
+        transparency.write(enc)?;
+    }
+    Ok(())
+}
+
+/// Get the smallest bit depth a greyscale image can be stored at losslessly,
+/// and the samples packed at that depth, or `None` if it can't be reduced.
+fn reduce_depth(
+    header: &ImageHeader,
+    image: &[u8],
+    stride: Option<usize>,
+) -> Option<(u8, Vec<u8>)> {
+    if header.color_type != ColorType::Grey || header.bit_depth < 8 {
+        return None;
+    }
+
+    // Gathered from the rows, as the samples are copied anyway
+    let image: Vec<u8> =
+        rows(image, header, stride).flatten().copied().collect();
+    let samples: Vec<u8> = if header.bit_depth == 16 {
+        // Equal bytes, so byte order doesn't matter
+        if !image.chunks_exact(2).all(|pair| pair[0] == pair[1]) {
+            return None;
+        }
+        image.iter().step_by(2).copied().collect()
+    } else {
+        image
+    };
+    // Samples at a bit depth are scaled up to 8 bits by bit replication, which
+    // multiplies them by 255 / (2^depth - 1)
+    let bit_depth = [1, 2, 4]
+        .into_iter()
+        .find(|&depth| {
+            let factor = u8::MAX / ((1 << depth) - 1);
+            samples.iter().all(|sample| sample % factor == 0)
+        })
+        .unwrap_or(8);
+    if bit_depth == header.bit_depth {
+        return None;
+    }
+    if bit_depth == 8 {
+        return Some((8, samples));
+    }
+
+    // Samples are packed without padding between rows
+    let factor = u8::MAX / ((1 << bit_depth) - 1);
+    let per_byte = 8 / usize::from(bit_depth);
+    let packed = samples
+        .chunks(per_byte)
+        .map(|chunk| {
+            chunk.iter().enumerate().fold(0, |byte, (i, sample)| {
+                byte | (sample / factor)
+                    << (8 - (i + 1) * usize::from(bit_depth))
+            })
+        })
+        .collect();
+    Some((bit_depth, packed))
 }
 
 /// The opposite of the remove_padding_bits function
@@ -281,13 +759,16 @@ fn add_padding_bits(
 }
 
 // Out must be buffer big enough to contain uncompressed IDAT chunk data, and in
-// must contain the full image.
+// must contain the full image, with rows starting stride bytes apart if there
+// are at least 8 bits per pixel (or packed one after the other if None).
 fn pre_process_scanlines(
     inp: &[u8],
+    stride: Option<usize>,
     header: &ImageHeader,
-    filter_strategy: Option<FilterStrategy>,
+    filter_strategy: &FilterStrategy,
     level: u8,
-) -> Vec<u8> {
+    hook: Option<&FilterFn>,
+) -> (Vec<u8>, [usize; 5]) {
     let width = header.width;
     let height = header.height;
     let bit_depth = header.bit_depth;
@@ -295,6 +776,7 @@ fn pre_process_scanlines(
     let h = height as usize;
     let w = width as usize;
     let bpp = color_type.bpp(bit_depth);
+    let mut filters = [0; 5];
     /*
     This function converts the pure 2D image with the PNG's colortype, into filtered-padded-interlaced data. Steps:
     *) if no Adam7: 1) add padding bits (= posible extra bits per scanline if bpp < 8) 2) filter
@@ -316,63 +798,91 @@ fn pre_process_scanlines(
                 w * bpp,
                 h,
             );
-            filter::filter(
+            filters = filter::filter(
                 &mut out,
                 &padded,
+                (w * bpp + 7) / 8,
                 w,
                 h,
                 header,
                 filter_strategy,
                 level,
+                0,
+                hook,
             );
         } else {
-            filter::filter(&mut out, inp, w, h, header, filter_strategy, level);
+            filters = filter::filter(
+                &mut out,
+                inp,
+                stride.unwrap_or((w * bpp + 7) / 8),
+                w,
+                h,
+                header,
+                filter_strategy,
+                level,
+                0,
+                hook,
+            );
         }
-        out
+        (out, filters)
     } else {
         let (passw, passh, filter_passstart, padded_passstart, passstart) =
             adam7::get_pass_values(width, height, bpp);
         let outsize = filter_passstart[7];
         /* image size plus an extra byte per scanline + possible padding bits */
-        let mut out = vec![0u8; outsize as usize];
-        let mut adam7 = vec![0u8; passstart[7] as usize + 1];
-        adam7::interlace(&mut adam7, inp, width, height, bpp);
+        let mut out = vec![0u8; outsize];
+        let mut adam7 = vec![0u8; passstart[7] + 1];
+        adam7::interlace(&mut adam7, inp, width, height, bpp, stride);
         let bpp = bpp as usize;
+        let mut first_row = 0;
         for i in 0..7 {
             if bpp < 8 {
-                let mut padded = vec![
-                    0u8;
-                    (padded_passstart[i + 1] - padded_passstart[i])
-                        as usize
-                ];
+                let mut padded =
+                    vec![0u8; padded_passstart[i + 1] - padded_passstart[i]];
                 add_padding_bits(
                     &mut padded,
-                    &adam7[passstart[i] as usize..],
+                    &adam7[passstart[i]..],
                     ((passw[i] as usize * bpp + 7) / 8) * 8,
                     passw[i] as usize * bpp,
                     passh[i] as usize,
                 );
-                filter::filter(
-                    &mut out[filter_passstart[i] as usize..],
+                let pass = filter::filter(
+                    &mut out[filter_passstart[i]..],
                     &padded,
+                    (passw[i] as usize * bpp + 7) / 8,
                     passw[i] as usize,
                     passh[i] as usize,
                     header,
                     filter_strategy,
                     level,
+                    first_row,
+                    hook,
                 );
+                add_histogram(&mut filters, pass);
             } else {
-                filter::filter(
-                    &mut out[filter_passstart[i] as usize..],
-                    &adam7[padded_passstart[i] as usize..],
+                let pass = filter::filter(
+                    &mut out[filter_passstart[i]..],
+                    &adam7[padded_passstart[i]..],
+                    passw[i] as usize * bpp / 8,
                     passw[i] as usize,
                     passh[i] as usize,
                     header,
                     filter_strategy,
                     level,
+                    first_row,
+                    hook,
                 );
+                add_histogram(&mut filters, pass);
             }
+            first_row += passh[i] as usize;
         }
-        out
+        (out, filters)
+    }
+}
+
+/// Add the filter type counts of one pass to the total.
+fn add_histogram(total: &mut [usize; 5], pass: [usize; 5]) {
+    for (total, count) in total.iter_mut().zip(pass) {
+        *total += count;
     }
 }
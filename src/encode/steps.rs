@@ -0,0 +1,37 @@
+use std::io::Write;
+
+use crate::{encode::Result, Encoder, Step};
+
+/// Encode `steps` as a PNG file with the default settings.
+///
+/// A single step is written as a still image, and more than one as an APNG
+/// animation (see [`Encoder::animation()`]).  Nothing is written if there
+/// are no steps.  Use an [`Encoder`] to change the settings.
+///
+/// ```rust
+/// let raster = png_pong::PngRaster::Rgba8(pix::Raster::with_pixels(1, 1, &[
+///     pix::rgb::SRgba8::new(0, 0, 0, 0)][..]
+/// ));
+/// let mut png = Vec::new();
+/// png_pong::encode_steps(&mut png, [png_pong::Step { raster, delay: 0 }])
+///     .expect("Failed to encode");
+/// ```
+pub fn encode_steps<W: Write>(
+    writer: W,
+    steps: impl IntoIterator<Item = Step>,
+) -> Result<()> {
+    let mut steps = steps.into_iter().peekable();
+    let Some(first) = steps.next() else {
+        return Ok(());
+    };
+    if steps.peek().is_none() {
+        return Encoder::new(writer).into_step_enc().encode(&first);
+    }
+
+    let mut encoder = Encoder::new(writer).animation().into_step_enc();
+    encoder.encode(&first)?;
+    for step in steps {
+        encoder.encode(&step)?;
+    }
+    encoder.finish()
+}
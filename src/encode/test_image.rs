@@ -0,0 +1,100 @@
+use pix::rgb::SRgb8;
+
+use super::{step_enc, FrameOptions};
+use crate::{
+    chunk::{ColorType, ImageHeader},
+    Encoder,
+};
+
+/// Generate a PNG file with a synthetic test pattern, for tests and benchmarks
+/// that shouldn't depend on bundled image files.
+///
+/// The pattern (a diagonal XOR pattern, shifted for each channel) only
+/// depends on the arguments, so the output is always the same for the same
+/// arguments.  Palette images get a palette with an entry for each possible
+/// index.
+///
+/// # Panics
+/// If the bit depth isn't allowed for the color type.
+pub fn generate_test_image(
+    color_type: ColorType,
+    bit_depth: u8,
+    width: u32,
+    height: u32,
+) -> Vec<u8> {
+    assert!(
+        color_type.bits_per_pixel(bit_depth).is_some(),
+        "invalid color type / bit depth combination",
+    );
+    let header = ImageHeader {
+        width,
+        height,
+        color_type,
+        bit_depth,
+        interlace: false,
+    };
+
+    // Samples are packed without padding between rows
+    let mut image = Vec::new();
+    let mut bits = 0u32;
+    let mut nbits = 0;
+    for y in 0..height {
+        for x in 0..width {
+            for channel in 0..color_type.sample_count() {
+                let value = sample(x, y, width, height, channel);
+                let value = if bit_depth < 8 {
+                    u16::from(value >> (8 - bit_depth))
+                } else if bit_depth == 8 {
+                    value.into()
+                } else {
+                    u16::from(value) << 8 | u16::from(value ^ 0x5A)
+                };
+                bits = bits << bit_depth | u32::from(value);
+                nbits += bit_depth;
+                while nbits >= 8 {
+                    nbits -= 8;
+                    image.push((bits >> nbits) as u8);
+                }
+            }
+        }
+    }
+    if nbits > 0 {
+        image.push((bits << (8 - nbits)) as u8);
+    }
+
+    let palette: Vec<_> = if color_type == ColorType::Palette {
+        (0..1u16 << bit_depth)
+            .map(|i| {
+                let i = i as u8;
+                SRgb8::new(
+                    i.wrapping_mul(37),
+                    i.wrapping_mul(91),
+                    255 - i.wrapping_mul(53),
+                )
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let mut encoder = Encoder::new(Vec::new()).into_chunk_enc();
+    step_enc::encode(
+        &mut encoder.enc,
+        &image,
+        None,
+        &header,
+        &palette,
+        &[],
+        &FrameOptions::default(),
+    )
+    .expect("Failed to encode test image");
+    encoder.into_inner().expect("Failed to encode test image")
+}
+
+/// 8-bit sample of the test pattern.
+fn sample(x: u32, y: u32, width: u32, height: u32, channel: u8) -> u8 {
+    let u = u64::from(x) * 255 / u64::from(width.max(1));
+    let v = u64::from(y) * 255 / u64::from(height.max(1));
+
+    ((u ^ v) as u8).wrapping_add(channel.wrapping_mul(64))
+}
@@ -0,0 +1,98 @@
+use std::io::Write;
+
+use super::step_enc;
+use crate::{
+    chunk::{ColorType, ImageHeader},
+    encode::{Error, FrameOptions, Result},
+    Encoder,
+};
+
+/// Encode an image from `header` and its `rows` as a PNG file with the
+/// default settings.
+///
+/// Each row is a scanline in PNG's sample layout: samples in the order of the
+/// color type, 16-bit samples big endian, and samples smaller than a byte
+/// packed with the leftmost pixel in the high bits and the last byte padded.
+/// The rows are never interlaced, even if `header.interlace` is set (the
+/// encoder interlaces them).  Palette images need a palette, so use an
+/// [`Encoder`] for those.
+///
+/// ```rust
+/// use png_pong::chunk::{ColorType, ImageHeader};
+///
+/// let header = ImageHeader {
+///     width: 2,
+///     height: 2,
+///     color_type: ColorType::Rgb,
+///     bit_depth: 8,
+///     interlace: false,
+/// };
+/// let rows: [&[u8]; 2] = [&[255, 0, 0, 0, 255, 0], &[0, 0, 255, 0, 0, 0]];
+/// let mut png = Vec::new();
+/// png_pong::write_png(&mut png, header, &rows).expect("Failed to encode");
+/// ```
+///
+/// # Errors
+/// [`Error::ColorMode`] if the bit depth isn't allowed for the color type,
+/// [`Error::BadPalette`] for palette images, [`Error::RowCount`] and
+/// [`Error::RowSize`] if the rows don't match the header, and I/O errors.
+pub fn write_png<W: Write>(
+    writer: W,
+    header: ImageHeader,
+    rows: &[&[u8]],
+) -> Result<()> {
+    header
+        .color_type
+        .check_png_color_validity(header.bit_depth)
+        .map_err(|_| Error::ColorMode(header.color_type, header.bit_depth))?;
+    if header.color_type == ColorType::Palette {
+        return Err(Error::BadPalette);
+    }
+    if rows.len() != header.height as usize {
+        return Err(Error::RowCount {
+            expected: header.height,
+            found: rows.len(),
+        });
+    }
+    let scanline = header.scanline_bytes().ok_or(Error::ChunkTooBig)?;
+    if let Some((row, found)) = rows
+        .iter()
+        .map(|row| row.len())
+        .enumerate()
+        .find(|&(_, len)| len != scanline)
+    {
+        return Err(Error::RowSize {
+            row,
+            expected: scanline,
+            found,
+        });
+    }
+
+    // The encoder takes pixels smaller than a byte packed without padding
+    let bits = header.width as usize * usize::from(header.bpp());
+    let image = if bits % 8 == 0 {
+        rows.concat()
+    } else {
+        let mut image = vec![0; (bits * rows.len() + 7) / 8];
+        for (y, row) in rows.iter().enumerate() {
+            for i in 0..bits {
+                let bit = (row[i / 8] >> (7 - i % 8)) & 1;
+                let o = y * bits + i;
+                image[o / 8] |= bit << (7 - o % 8);
+            }
+        }
+        image
+    };
+
+    let mut encoder = Encoder::new(writer).into_chunk_enc();
+    step_enc::encode(
+        &mut encoder.enc,
+        &image,
+        None,
+        &header,
+        &[],
+        &[],
+        &FrameOptions::default(),
+    )?;
+    Ok(())
+}
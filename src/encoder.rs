@@ -1,8 +1,12 @@
-use std::io::Write;
+use std::{io::Write, sync::Arc};
 
 use crate::{
-    consts,
-    encode::{ChunkEnc, Error, FilterStrategy, Result, StepEnc},
+    chunk::Position,
+    consts, crc32,
+    encode::{
+        filter::FilterFn, ChunkEnc, Error, FilterChoice, FilterStrategy,
+        Result, Rung, StepEnc,
+    },
 };
 
 /// Chunk encoder.
@@ -10,61 +14,50 @@ use crate::{
 pub(crate) struct Enc<W: Write> {
     /// Encoder
     encode: Encoder<W>,
-    /// CRC32
-    chksum: u32,
+    /// Length, name and data of the chunk being written
+    chunk: Vec<u8>,
 }
 
 impl<W: Write> Enc<W> {
-    /// Prepare a chunk for writing (reset checksum).
+    /// Write the PNG signature.
+    pub(crate) fn signature(&mut self) -> Result<()> {
+        self.bytes(&consts::PNG_SIGNATURE)
+    }
+
+    /// Write bytes as is, such as a whole encoded file.
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        self.encode.writer.write_all(bytes).map_err(Error::from)
+    }
+
+    /// Prepare a chunk for writing (chunk is buffered until `write_crc()`).
     pub(crate) fn prepare(&mut self, len: usize, name: [u8; 4]) -> Result<()> {
         assert!(len <= consts::MAX_CHUNK_SIZE);
         let len: u32 = len.try_into().unwrap();
-        self.encode
-            .writer
-            .write_all(&len.to_be_bytes())
-            .map_err(Error::from)?;
-        self.chksum = consts::CRC32_INIT;
-        for c in name.iter().cloned() {
-            self.u8(c)?;
-        }
+        self.chunk.clear();
+        self.chunk.extend(len.to_be_bytes());
+        self.chunk.extend(name);
         Ok(())
     }
 
     /// Write a u8
     pub(crate) fn u8(&mut self, value: u8) -> Result<()> {
-        self.encode
-            .writer
-            .write_all(&[value])
-            .map_err(Error::from)?;
-        let index: usize = (self.chksum as u8 ^ value).into();
-        self.chksum = consts::CRC32_LOOKUP[index] ^ (self.chksum >> 8);
+        self.chunk.push(value);
         Ok(())
     }
 
     /// Write a u16
     pub(crate) fn u16(&mut self, value: u16) -> Result<()> {
-        let bytes = value.to_be_bytes();
-        for byte in bytes.iter().cloned() {
-            self.u8(byte)?;
-        }
-        Ok(())
+        self.raw(&value.to_be_bytes())
     }
 
     /// Write a u32
     pub(crate) fn u32(&mut self, value: u32) -> Result<()> {
-        let bytes = value.to_be_bytes();
-        for byte in bytes.iter().cloned() {
-            self.u8(byte)?;
-        }
-        Ok(())
+        self.raw(&value.to_be_bytes())
     }
 
     /// Write a string
     pub(crate) fn string(&mut self, value: &str) -> Result<()> {
-        for byte in value.bytes() {
-            self.u8(byte)?;
-        }
-        Ok(())
+        self.raw(value.as_bytes())
     }
 
     /// Write a null-terminated string
@@ -75,28 +68,67 @@ impl<W: Write> Enc<W> {
 
     /// Write raw data
     pub(crate) fn raw(&mut self, raw: &[u8]) -> Result<()> {
-        for byte in raw.iter().cloned() {
-            self.u8(byte)?;
-        }
+        self.chunk.extend_from_slice(raw);
         Ok(())
     }
 
-    /// Calculate and write Chunk CRC, ending the chunk.
+    /// Calculate Chunk CRC over the buffered chunk, and write the whole chunk,
+    /// ending it.
     pub(crate) fn write_crc(&mut self) -> Result<()> {
-        let crc = self.chksum ^ consts::CRC32_INIT;
+        let (len, data) = self.chunk.split_at(4);
+        debug_assert_eq!(
+            u32::from_be_bytes(len.try_into().unwrap()) as usize + 4,
+            data.len(),
+        );
+        let crc = crc32::crc32(data);
+        self.chunk.extend(crc.to_be_bytes());
         self.encode
             .writer
-            .write_all(&crc.to_be_bytes())
+            .write_all(&self.chunk)
+            .map_err(Error::from)
+    }
+
+    /// Write a whole chunk: length, name, `data` and CRC.  `data` is written
+    /// as is rather than copied into the chunk buffer, so that image data
+    /// isn't held in memory twice.
+    pub(crate) fn write_chunk(
+        &mut self,
+        name: [u8; 4],
+        data: &[u8],
+    ) -> Result<()> {
+        if data.len() > consts::MAX_CHUNK_SIZE {
+            return Err(Error::ChunkTooBig);
+        }
+        let len: u32 = data.len().try_into().unwrap();
+        let mut crc = crc32::Crc32::new();
+        crc.update(&name);
+        crc.update(data);
+        let writer = &mut self.encode.writer;
+        writer.write_all(&len.to_be_bytes())?;
+        writer.write_all(&name)?;
+        writer.write_all(data)?;
+        writer
+            .write_all(&crc.finish().to_be_bytes())
             .map_err(Error::from)
     }
 
     /// Get the chosen filter strategy    
     pub(crate) fn filter_strategy(&self) -> Option<FilterStrategy> {
-        self.encode.filter_strategy
+        self.encode.filter_strategy.clone()
+    }
+
+    /// Get the per-scanline filter hook, if any
+    pub(crate) fn filter_fn(&self) -> Option<&FilterFn> {
+        self.encode.filter_fn.as_ref()
     }
 
     /// Get the compression level.    
     pub(crate) fn level(&self) -> u8 {
+        self.encode.level.unwrap_or(6)
+    }
+
+    /// Get the compression level, if set.
+    pub(crate) fn chosen_level(&self) -> Option<u8> {
         self.encode.level
     }
 
@@ -104,6 +136,60 @@ impl<W: Write> Enc<W> {
     pub(crate) fn interlace(&self) -> bool {
         self.encode.interlace
     }
+
+    /// Whether or not to reduce the bit depth of greyscale images.
+    pub(crate) fn reduce_depth(&self) -> bool {
+        self.encode.reduce_depth
+    }
+
+    /// Get whether or not to encode an animation
+    pub(crate) fn animation(&self) -> bool {
+        self.encode.animation
+    }
+
+    /// Get the maximum output size of still images, if any.
+    pub(crate) fn max_output_size(&self) -> Option<usize> {
+        self.encode.max_output_size
+    }
+
+    /// Get the settings to retry with when over the maximum output size, if
+    /// not the default ones.
+    pub(crate) fn ladder(&self) -> Option<&[Rung]> {
+        self.encode.ladder.as_deref()
+    }
+
+    /// Get the position to move text and time chunks to, if any.
+    pub(crate) fn normalize_positions(&self) -> Option<Position> {
+        self.encode.positions
+    }
+
+    /// Get a still image encoder for `writer` with the same settings, except
+    /// for the maximum output size and chunk positions.
+    pub(crate) fn settings<V: Write>(&self, writer: V) -> Encoder<V> {
+        Encoder {
+            filter_strategy: self.encode.filter_strategy.clone(),
+            filter_fn: self.encode.filter_fn.clone(),
+            level: self.encode.level,
+            interlace: self.encode.interlace,
+            reduce_depth: self.encode.reduce_depth,
+            animation: false,
+            max_output_size: None,
+            ladder: None,
+            positions: None,
+            small_image_threshold: self.encode.small_image_threshold,
+            writer,
+        }
+    }
+
+    /// Get the pixel count below which images take the small image fast path.
+    pub(crate) fn small_image_threshold(&self) -> u32 {
+        self.encode.small_image_threshold
+    }
+
+    /// Get back the writer.
+    pub(crate) fn into_inner(self) -> W {
+        self.encode.writer
+    }
 }
 
 /// PNG file encoder
@@ -120,8 +206,15 @@ impl<W: Write> Enc<W> {
 #[derive(Debug)]
 pub struct Encoder<W: Write> {
     filter_strategy: Option<FilterStrategy>,
-    level: u8,
+    filter_fn: Option<FilterFn>,
+    level: Option<u8>,
     interlace: bool,
+    reduce_depth: bool,
+    animation: bool,
+    max_output_size: Option<usize>,
+    ladder: Option<Vec<Rung>>,
+    positions: Option<Position>,
+    small_image_threshold: u32,
     writer: W,
 }
 
@@ -131,23 +224,68 @@ impl<W: Write> Encoder<W> {
         Encoder {
             writer,
             filter_strategy: None,
-            level: 6,
+            filter_fn: None,
+            level: None,
             interlace: false,
+            reduce_depth: false,
+            animation: false,
+            max_output_size: None,
+            ladder: None,
+            positions: None,
+            small_image_threshold: 1024,
         }
     }
 
     /// Set a specific filter strategy.  If this is never called, than png_pong
     /// attempts to choose the best (compromise speed / compression) filter
-    /// strategy.
+    /// strategy for the color type (see [`Defaults`]).
+    ///
+    /// [`Defaults`]: crate::encode::Defaults
     pub fn filter_strategy(mut self, strategy: FilterStrategy) -> Self {
         self.filter_strategy = Some(strategy);
         self
     }
 
-    /// Set the compression level (default: 6).  Must be between 0 and 10.
+    /// Set a hook to choose the filter type of each scanline (default: none).
+    ///
+    /// The hook is called before filtering each scanline, with its index
+    /// (counting from the first scanline of the first pass, for interlaced
+    /// images), its unfiltered bytes (padded to a whole byte for bit depths
+    /// below 8), the unfiltered bytes of the previous scanline (empty for the
+    /// first scanline of a pass), and the bytes per pixel (at least 1).  The
+    /// encoder applies the chosen filter itself, and
+    /// [`FilterChoice::Auto`] leaves the choice to the filter strategy.
+    pub fn filter_fn(
+        mut self,
+        hook: impl Fn(u32, &[u8], &[u8], usize) -> FilterChoice
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.filter_fn = Some(FilterFn(Arc::new(hook)));
+        self
+    }
+
+    /// Set the compression level (default: depends on the color type, see
+    /// [`Defaults`], and 6 for text chunks).  Must be between 0 and 10.
+    ///
+    /// [`Defaults`]: crate::encode::Defaults
     pub fn compression_level(mut self, level: u8) -> Self {
         assert!(level <= 10);
-        self.level = level;
+        self.level = Some(level);
+        self
+    }
+
+    /// Set the number of pixels below which images are encoded with a fast
+    /// path (default: 1024, such as a 32x32 icon), or 0 to disable it.
+    ///
+    /// Small images skip adaptive filtering (using filter type None unless a
+    /// filter strategy is set), and are compressed at a level of at most 1
+    /// (unless a compression level is set).
+    /// For tiny images, the setup cost of the slower settings outweighs the
+    /// few bytes they save.
+    pub fn small_image_threshold(mut self, pixels: u32) -> Self {
+        self.small_image_threshold = pixels;
         self
     }
 
@@ -157,6 +295,65 @@ impl<W: Write> Encoder<W> {
         self
     }
 
+    /// Set whether or not to encode greyscale rasters at the smallest bit
+    /// depth that stores them losslessly (default: `false`).
+    ///
+    /// 8-bit samples that are all multiples of 17 (such as 0 and 255 only, for
+    /// a black and white image) are stored at 4, 2 or 1 bits, and 16-bit
+    /// samples with equal high and low bytes at 8 bits (or less).  Decoding
+    /// scales the samples back up to the same values, so only the raster type
+    /// changes: reduced 16-bit images decode to
+    /// [`PngRaster::Gray8`](crate::PngRaster::Gray8) instead of `Gray16`.
+    pub fn reduce_depth(mut self, reduce: bool) -> Self {
+        self.reduce_depth = reduce;
+        self
+    }
+
+    /// Encode an APNG animation with the step encoder (default: a still
+    /// image).
+    ///
+    /// Each call to [`StepEnc::encode()`] adds a frame, and
+    /// [`StepEnc::finish()`] writes the file.  Without this, the first call
+    /// writes a whole PNG file, and any more calls fail.
+    pub fn animation(mut self) -> Self {
+        self.animation = true;
+        self
+    }
+
+    /// Set the maximum size in bytes of a still image encoded with the step
+    /// encoder (default: no limit).
+    ///
+    /// The image is first encoded with the other settings.  If the file is
+    /// over `budget`, it's encoded again with each [`Rung`] of the ladder in
+    /// turn (see [`size_ladder()`](Self::size_ladder)), until one fits.  All
+    /// of the settings are lossless.  If none fit, encoding fails with
+    /// [`Error::SizeBudget`], and nothing is written.
+    pub fn max_output_size(mut self, budget: usize) -> Self {
+        self.max_output_size = Some(budget);
+        self
+    }
+
+    /// Set the settings to retry with when a still image is over the
+    /// [`max_output_size()`](Self::max_output_size) (default:
+    /// [`Rung::default_ladder()`]).
+    pub fn size_ladder(mut self, ladder: Vec<Rung>) -> Self {
+        self.ladder = Some(ladder);
+        self
+    }
+
+    /// Move text and time chunks (tEXt, zTXt, iTXt and tIME) encoded with the
+    /// chunk encoder to `position` relative to the image data (default: keep
+    /// them where they're encoded).
+    ///
+    /// To move them before the image data, IDAT chunks are held back until a
+    /// chunk that isn't text, time or image data (such as IEND) is encoded.
+    /// To move them after, text and time chunks are held back until a chunk
+    /// other than image data is encoded after the image data.
+    pub fn normalize_positions(mut self, position: Position) -> Self {
+        self.positions = Some(position);
+        self
+    }
+
     /// Convert into a chunk encoder.
     pub fn into_chunk_enc(self) -> ChunkEnc<W> {
         ChunkEnc::new(self.into_enc())
@@ -170,7 +367,7 @@ impl<W: Write> Encoder<W> {
     fn into_enc(self) -> Enc<W> {
         Enc {
             encode: self,
-            chksum: 0,
+            chunk: Vec::new(),
         }
     }
 }
@@ -5,10 +5,45 @@
 //!
 //! ```toml
 //! [dependencies.png_pong]
-//! version = "0.9"
+//! version = "0.10"
 //! ```
 //!
 //! ### Example
+//! The simplest way to save an image is to give [`write_png()`] a header and
+//! the bytes of each row.
+//!
+//! ```rust
+//! use png_pong::chunk::{ColorType, ImageHeader};
+//!
+//! let header = ImageHeader {
+//!     width: 2,
+//!     height: 1,
+//!     color_type: ColorType::Grey,
+//!     bit_depth: 8,
+//!     interlace: false,
+//! };
+//! let file = std::fs::File::create("gray.png").expect("Failed to create");
+//! png_pong::write_png(file, header, &[&[0, 255]]).expect("Failed to save");
+//! ```
+//!
+//! Likewise, [`read_png()`] gives back the header and the bytes of each row.
+//!
+//! ```rust
+//! # let header = png_pong::chunk::ImageHeader {
+//! #     width: 2,
+//! #     height: 1,
+//! #     color_type: png_pong::chunk::ColorType::Grey,
+//! #     bit_depth: 8,
+//! #     interlace: false,
+//! # };
+//! # let file = std::fs::File::create("gray.png").unwrap();
+//! # png_pong::write_png(file, header, &[&[0, 255]]).unwrap();
+//! let file = std::fs::File::open("gray.png").expect("Failed to open");
+//! let (header, rows) = png_pong::read_png(file).expect("Failed to load");
+//! ```
+//!
+//! Rasters can also be encoded and decoded as steps.
+//!
 //! ```rust
 //! // Saving raster as a PNG file
 //! let raster = png_pong::PngRaster::Rgba8(pix::Raster::with_pixels(1, 1, &[
@@ -52,22 +87,31 @@
     variant_size_differences
 )]
 
+pub mod adam7;
+pub mod analysis;
 pub mod chunk;
+pub mod crc32;
 pub mod decode;
 pub mod encode;
+pub mod netpbm;
 
 pub(crate) mod decoder;
 
-mod adam7;
 mod bitstream;
 mod consts;
+mod diff;
 mod encoder;
 mod parsing;
 mod raster;
+mod sniff;
 mod step;
 mod zlib;
 
+pub use decode::{decode_steps, read_png, scan, validate};
 pub use decoder::Decoder;
+pub use diff::{diff, same_pixels, ChunkDiff};
+pub use encode::{encode_steps, optimize, repair, write_png};
 pub use encoder::Encoder;
 pub use raster::PngRaster;
+pub use sniff::{is_png, sniff, SIGNATURE};
 pub use step::Step;
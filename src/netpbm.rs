@@ -0,0 +1,133 @@
+//! Conversion of rows to Netpbm images.
+//!
+//! Rows are passed as raw scanlines in the PNG's own sample layout, like the
+//! rows returned by [`read_png()`](crate::read_png).  The Netpbm formats are
+//! a short text header followed by the samples, which tools such as
+//! ImageMagick and FFmpeg can read from standard input.
+
+use crate::{
+    analysis,
+    chunk::{ColorType, ImageHeader},
+};
+
+/// Convert the rows of an RGB image to a binary PPM (Portable Pixmap) image.
+///
+/// ```rust
+/// use png_pong::chunk::{ColorType, ImageHeader};
+///
+/// let header = ImageHeader {
+///     width: 1,
+///     height: 1,
+///     color_type: ColorType::Rgb,
+///     bit_depth: 8,
+///     interlace: false,
+/// };
+/// let ppm = png_pong::netpbm::to_ppm(&[&[255, 0, 0]], &header);
+/// assert_eq!(ppm, b"P6\n1 1\n255\n\xFF\x00\x00");
+/// ```
+///
+/// # Panics
+/// If the image isn't RGB, the bit depth isn't valid, or the rows don't match
+/// the header.
+pub fn to_ppm(rows: &[&[u8]], header: &ImageHeader) -> Vec<u8> {
+    assert_eq!(header.color_type, ColorType::Rgb, "PPM images are RGB");
+    netpbm("P6", rows, header)
+}
+
+/// Convert the rows of a greyscale image to a binary PGM (Portable Graymap)
+/// image.  Samples below 8 bits are written a byte each, with a maximum
+/// value of 1, 3 or 15.
+///
+/// # Panics
+/// If the image isn't greyscale, the bit depth isn't valid, or the rows don't
+/// match the header.
+pub fn to_pgm(rows: &[&[u8]], header: &ImageHeader) -> Vec<u8> {
+    assert_eq!(
+        header.color_type,
+        ColorType::Grey,
+        "PGM images are greyscale"
+    );
+    netpbm("P5", rows, header)
+}
+
+/// Write the Netpbm header and samples.  16-bit samples are big endian in
+/// both PNG and Netpbm images, so are copied as they are.
+fn netpbm(magic: &str, rows: &[&[u8]], header: &ImageHeader) -> Vec<u8> {
+    assert!(
+        header.color_type.bits_per_pixel(header.bit_depth).is_some(),
+        "invalid bit depth"
+    );
+    assert_eq!(rows.len(), header.height as usize, "wrong number of rows");
+    let scanline = header.scanline_bytes().expect("image too large");
+    let max = (1u32 << header.bit_depth) - 1;
+    let (width, height) = (header.width, header.height);
+
+    let mut image = format!("{magic}\n{width} {height}\n{max}\n").into_bytes();
+    for row in rows {
+        assert_eq!(row.len(), scanline, "wrong row length");
+        if header.bit_depth >= 8 {
+            image.extend_from_slice(row);
+        } else {
+            let samples = analysis::samples(row, header).unwrap();
+            image.extend(samples.map(|s| s as u8));
+        }
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(color_type: ColorType, bit_depth: u8, width: u32) -> ImageHeader {
+        ImageHeader {
+            width,
+            height: 2,
+            color_type,
+            bit_depth,
+            interlace: false,
+        }
+    }
+
+    #[test]
+    fn ppm() {
+        let rows: &[&[u8]] = &[&[1, 2, 3, 4, 5, 6], &[7, 8, 9, 10, 11, 12]];
+        let ppm = to_ppm(rows, &header(ColorType::Rgb, 8, 2));
+        assert_eq!(
+            ppm,
+            b"P6\n2 2\n255\n\x01\x02\x03\x04\x05\x06\x07\x08\x09\x0A\x0B\x0C"
+        );
+
+        let rows: &[&[u8]] = &[&[0xFF, 0xFE, 0, 1, 0, 2], &[0, 3, 0, 4, 0, 5]];
+        let ppm = to_ppm(rows, &header(ColorType::Rgb, 16, 1));
+        let (head, samples) = ppm.split_at(13);
+        assert_eq!(head, b"P6\n1 2\n65535\n");
+        assert_eq!(samples, [0xFF, 0xFE, 0, 1, 0, 2, 0, 3, 0, 4, 0, 5]);
+    }
+
+    #[test]
+    fn pgm() {
+        // 3 pixels of 2 bits, with padding bits at the end
+        let rows: &[&[u8]] = &[&[0b11_01_10_11], &[0b00_00_00_11]];
+        let pgm = to_pgm(rows, &header(ColorType::Grey, 2, 3));
+        assert_eq!(pgm, b"P5\n3 2\n3\n\x03\x01\x02\x00\x00\x00");
+
+        let rows: &[&[u8]] = &[&[200], &[100]];
+        let pgm = to_pgm(rows, &header(ColorType::Grey, 8, 1));
+        assert_eq!(pgm, b"P5\n1 2\n255\n\xC8\x64");
+    }
+
+    #[test]
+    #[should_panic]
+    fn pgm_of_rgb() {
+        let rows: &[&[u8]] = &[&[0, 0, 0], &[0, 0, 0]];
+        to_pgm(rows, &header(ColorType::Rgb, 8, 1));
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid bit depth")]
+    fn pgm_of_invalid_bit_depth() {
+        let rows: &[&[u8]] = &[&[0], &[0]];
+        to_pgm(rows, &header(ColorType::Grey, 3, 1));
+    }
+}
@@ -10,7 +10,10 @@ use crate::chunk::{ColorType, ImageHeader};
 
 /// A Raster of one of the PNG types (all are sRGB gamma).
 /// PNGs with less than 8 bits per channel are scaled up to 8 bits per channel.
-#[allow(missing_debug_implementations)]
+///
+/// Rasters are equal if they have the same format, dimensions and pixel data
+/// (and palette for `Palette`).  `Debug` shows the format and dimensions
+/// rather than the pixel data.
 pub enum PngRaster {
     /// 1, 2, 4, 8-bit greyscale
     Gray8(Raster<SGray8>),
@@ -103,6 +106,96 @@ impl PngRaster {
     }
 }
 
+impl PngRaster {
+    /// Get the raster's pixel data as bytes.
+    fn u8_slice(&self) -> &[u8] {
+        use PngRaster::*;
+        match self {
+            Gray8(r) => r.as_u8_slice(),
+            Gray16(r) => r.as_u8_slice(),
+            Rgb8(r) => r.as_u8_slice(),
+            Rgb16(r) => r.as_u8_slice(),
+            Palette(r, _pal, _pa) => r.as_u8_slice(),
+            Graya8(r) => r.as_u8_slice(),
+            Graya16(r) => r.as_u8_slice(),
+            Rgba8(r) => r.as_u8_slice(),
+            Rgba16(r) => r.as_u8_slice(),
+        }
+    }
+}
+
+/// Deep copy of a raster.
+fn copy<P: Pixel>(raster: &Raster<P>) -> Raster<P> {
+    Raster::with_raster(raster)
+}
+
+impl Clone for PngRaster {
+    fn clone(&self) -> Self {
+        use PngRaster::*;
+        match self {
+            Gray8(r) => Gray8(copy(r)),
+            Gray16(r) => Gray16(copy(r)),
+            Rgb8(r) => Rgb8(copy(r)),
+            Rgb16(r) => Rgb16(copy(r)),
+            Palette(r, pal, pa) => Palette(copy(r), pal.clone(), pa.clone()),
+            Graya8(r) => Graya8(copy(r)),
+            Graya16(r) => Graya16(copy(r)),
+            Rgba8(r) => Rgba8(copy(r)),
+            Rgba16(r) => Rgba16(copy(r)),
+        }
+    }
+}
+
+impl PartialEq for PngRaster {
+    fn eq(&self, other: &Self) -> bool {
+        use PngRaster::*;
+        let same_palette = match (self, other) {
+            (Palette(_, pal, pa), Palette(_, other_pal, other_pa)) => {
+                pal.colors() == other_pal.colors() && pa == other_pa
+            }
+            _ => true,
+        };
+        let header = self.header(false);
+        let other_header = other.header(false);
+
+        header.color_type == other_header.color_type
+            && header.bit_depth == other_header.bit_depth
+            && header.width == other_header.width
+            && header.height == other_header.height
+            && same_palette
+            && self.u8_slice() == other.u8_slice()
+    }
+}
+
+impl std::fmt::Debug for PngRaster {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use PngRaster::*;
+        let format = match self {
+            Gray8(_) => "Gray8",
+            Gray16(_) => "Gray16",
+            Rgb8(_) => "Rgb8",
+            Rgb16(_) => "Rgb16",
+            Palette(..) => "Palette",
+            Graya8(_) => "Graya8",
+            Graya16(_) => "Graya16",
+            Rgba8(_) => "Rgba8",
+            Rgba16(_) => "Rgba16",
+        };
+        let header = self.header(false);
+        let mut debug = f.debug_struct(format);
+
+        debug
+            .field("width", &header.width)
+            .field("height", &header.height);
+        if let Palette(_, pal, pa) = self {
+            debug
+                .field("colors", &pal.colors().len())
+                .field("alphas", &pa.len());
+        }
+        debug.finish()
+    }
+}
+
 impl<P: Pixel> From<PngRaster> for Raster<P>
 where
     P::Chan: From<Ch8> + From<Ch16>,
@@ -120,11 +213,13 @@ where
                     let i: u8 = pixel.one().into();
                     let i = i as usize;
                     let px: SRgb8 = pal.entry(i).unwrap();
+                    // The tRNS chunk can be shorter than the palette
+                    let alpha = pa.get(i).copied().unwrap_or(u8::MAX);
                     let px = SRgba8::new(
                         px.one(),
                         px.two(),
                         px.three(),
-                        Ch8::new(pa[i]),
+                        Ch8::new(alpha),
                     );
                     pixels.push(px.convert());
                 }
@@ -0,0 +1,29 @@
+use std::io::{self, Read};
+
+use crate::consts;
+
+/// The 8 bytes every PNG (and APNG) file starts with.
+pub const SIGNATURE: [u8; 8] = consts::PNG_SIGNATURE;
+
+/// Check whether `bytes` starts with the PNG signature.
+///
+/// Only the first 8 bytes are looked at; slices shorter than that are never
+/// PNG files.
+pub fn is_png(bytes: &[u8]) -> bool {
+    bytes.starts_with(&SIGNATURE)
+}
+
+/// Read exactly 8 bytes from `reader`, and check whether they're the PNG
+/// signature.
+///
+/// The reader is left advanced past those 8 bytes (whether or not they
+/// matched), so seek back or chain the bytes before decoding.  Returns
+/// `Ok(false)` if the reader ends before 8 bytes.
+pub fn sniff<R: Read>(reader: &mut R) -> io::Result<bool> {
+    let mut buf = [0; 8];
+    match reader.read_exact(&mut buf) {
+        Ok(()) => Ok(buf == SIGNATURE),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
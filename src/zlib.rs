@@ -1,11 +1,231 @@
 //! Compression algorithms
 
-use miniz_oxide::{deflate::compress_to_vec, inflate::decompress_to_vec};
+use std::fmt;
+
+use miniz_oxide::{
+    deflate::compress_to_vec,
+    inflate::{
+        core::{
+            decompress as inflate,
+            inflate_flags::{
+                TINFL_FLAG_HAS_MORE_INPUT, TINFL_FLAG_IGNORE_ADLER32,
+                TINFL_FLAG_PARSE_ZLIB_HEADER,
+                TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF,
+            },
+            DecompressorOxide, TINFL_LZ_DICT_SIZE,
+        },
+        TINFLStatus,
+    },
+};
 
 use crate::decode::Error;
 
 // FIXME: Streaming API
 pub(crate) fn decompress(inp: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::new();
+    Inflater::default().decompress_into(inp, &mut out)?;
+    Ok(out)
+}
+
+/// Decompress as much of a damaged zlib stream as possible, up to `limit`
+/// bytes, stopping at the first error (such as the stream being cut off, or
+/// going over the limit) without reporting it.
+pub(crate) fn decompress_partial(inp: &[u8], limit: usize) -> Vec<u8> {
+    match miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(inp, limit) {
+        Ok(out) => out,
+        Err(error) => error.output,
+    }
+}
+
+/// Reusable zlib decompressor.
+///
+/// Keeps the inflate state (including its window) and is reset at the start
+/// of each stream, so decompressing many streams doesn't reallocate.
+#[derive(Default)]
+pub(crate) struct Inflater {
+    state: Box<DecompressorOxide>,
+}
+
+impl fmt::Debug for Inflater {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Inflater").finish_non_exhaustive()
+    }
+}
+
+impl Inflater {
+    /// Decompress one zlib stream, replacing the contents of `out`.  The
+    /// capacity of `out` is reused.
+    pub(crate) fn decompress_into(
+        &mut self,
+        inp: &[u8],
+        out: &mut Vec<u8>,
+    ) -> Result<(), Error> {
+        check_header(inp)?;
+        if inp.len() < 6 {
+            return Err(Error::Inflate(TINFLStatus::FailedCannotMakeProgress));
+        }
+
+        let data = &inp[2..(inp.len() - 4)];
+        self.state.init();
+        out.clear();
+        // Use all of the capacity left over from previous streams.
+        let len = out.capacity().max(data.len().saturating_mul(2)).max(64);
+        out.resize(len, 0);
+
+        let mut in_pos = 0;
+        let mut out_pos = 0;
+        loop {
+            let (status, in_consumed, out_consumed) = inflate(
+                &mut self.state,
+                &data[in_pos..],
+                out,
+                out_pos,
+                TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF,
+            );
+            in_pos += in_consumed;
+            out_pos += out_consumed;
+            match status {
+                TINFLStatus::Done => break,
+                TINFLStatus::HasMoreOutput => {
+                    let len = out.len().saturating_mul(2);
+                    out.resize(len, 0);
+                }
+                status => return Err(Error::Inflate(status)),
+            }
+        }
+        out.truncate(out_pos);
+
+        let adler32_val = u32::from_be_bytes([
+            inp[inp.len() - 4],
+            inp[inp.len() - 3],
+            inp[inp.len() - 2],
+            inp[inp.len() - 1],
+        ]);
+        let checksum = adler32(out);
+        if checksum != adler32_val {
+            return Err(Error::AdlerChecksum {
+                expected: checksum,
+                found: adler32_val,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Streaming zlib decompressor.
+///
+/// Inflates a stream given in pieces through a 32 KiB window, rather than into
+/// one buffer for the whole stream, so memory use doesn't depend on the size
+/// of the stream.
+pub(crate) struct StreamInflater {
+    state: Box<DecompressorOxide>,
+    /// Sliding window the stream is inflated into
+    window: Box<[u8]>,
+    /// Range of inflated bytes in the window that haven't been read yet
+    start: usize,
+    end: usize,
+    /// Has the zlib header been checked?
+    checked: bool,
+    /// Has the end of the stream been reached?
+    done: bool,
+    /// Adler-32 of the inflated bytes so far
+    adler: simd_adler32::Adler32,
+    /// Last 4 bytes of input consumed, the Adler-32 once the stream is done
+    tail: [u8; 4],
+}
+
+impl fmt::Debug for StreamInflater {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StreamInflater")
+            .field("done", &self.done)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for StreamInflater {
+    fn default() -> Self {
+        Self {
+            state: Box::default(),
+            window: vec![0; TINFL_LZ_DICT_SIZE].into_boxed_slice(),
+            start: 0,
+            end: 0,
+            checked: false,
+            done: false,
+            adler: simd_adler32::Adler32::new(),
+            tail: [0; 4],
+        }
+    }
+}
+
+impl StreamInflater {
+    /// Decompress the next piece of the stream from `inp` into `out`,
+    /// returning the number of bytes of `inp` consumed and written to `out`.
+    /// `more` is whether there is more input after `inp`.
+    ///
+    /// Writing nothing means that more input is needed (starting with the
+    /// bytes of `inp` that weren't consumed), or that the stream is done.
+    pub(crate) fn read(
+        &mut self,
+        inp: &[u8],
+        more: bool,
+        out: &mut [u8],
+    ) -> Result<(usize, usize), Error> {
+        let mut consumed = 0;
+        if self.start == self.end && !self.done {
+            if !self.checked {
+                if inp.len() < 2 {
+                    return if more {
+                        Ok((0, 0))
+                    } else {
+                        Err(Error::ZlibTooSmall)
+                    };
+                }
+                check_header(inp)?;
+                self.checked = true;
+            }
+
+            let flags = TINFL_FLAG_PARSE_ZLIB_HEADER
+                | TINFL_FLAG_IGNORE_ADLER32
+                | if more { TINFL_FLAG_HAS_MORE_INPUT } else { 0 };
+            // Wraps around to the start once the window is full
+            let pos = self.end % self.window.len();
+            let (status, in_consumed, out_consumed) =
+                inflate(&mut self.state, inp, &mut self.window, pos, flags);
+            consumed = in_consumed;
+            for &byte in &inp[..in_consumed] {
+                self.tail = [self.tail[1], self.tail[2], self.tail[3], byte];
+            }
+            (self.start, self.end) = (pos, pos + out_consumed);
+            self.adler.write(&self.window[self.start..self.end]);
+            match status {
+                TINFLStatus::Done => {
+                    self.done = true;
+                    let expected = self.adler.finish();
+                    let found = u32::from_be_bytes(self.tail);
+                    if expected != found {
+                        return Err(Error::AdlerChecksum { expected, found });
+                    }
+                }
+                TINFLStatus::HasMoreOutput | TINFLStatus::NeedsMoreInput => {}
+                status => return Err(Error::Inflate(status)),
+            }
+        }
+
+        let len = out.len().min(self.end - self.start);
+        out[..len].copy_from_slice(&self.window[self.start..][..len]);
+        self.start += len;
+        Ok((consumed, len))
+    }
+
+    /// Has the end of the stream been reached (and all of it read)?
+    pub(crate) fn is_done(&self) -> bool {
+        self.done && self.start == self.end
+    }
+}
+
+/// Check the 2 byte zlib header at the start of `inp`.
+fn check_header(inp: &[u8]) -> Result<(), Error> {
     if inp.len() < 2 {
         return Err(Error::ZlibTooSmall);
     }
@@ -19,8 +239,8 @@ pub(crate) fn decompress(inp: &[u8]) -> Result<Vec<u8>, Error> {
     let cinfo = ((inp[0] as u32) >> 4) & 15;
     let fdict = ((inp[1] as u32) >> 5) & 1;
     if cm != 8 || cinfo > 7 {
-        /* error: only compression method 8: inflate with sliding window of
-         * 32k is supported by the PNG spec */
+        /* error: only compression method 8: inflate with sliding window
+         * of 32k is supported by the PNG spec */
         return Err(Error::CompressionMethod);
     }
     if fdict != 0 {
@@ -28,26 +248,7 @@ pub(crate) fn decompress(inp: &[u8]) -> Result<Vec<u8>, Error> {
         "The additional flags shall not specify a preset dictionary."*/
         return Err(Error::PresetDict);
     }
-
-    let out = match decompress_to_vec(&inp[2..(inp.len() - 4)]) {
-        Ok(rtn) => rtn,
-        Err(e) => {
-            return Err(Error::Inflate(e.status));
-        }
-    };
-
-    let adler32_val = u32::from_be_bytes([
-        inp[inp.len() - 4],
-        inp[inp.len() - 3],
-        inp[inp.len() - 2],
-        inp[inp.len() - 1],
-    ]);
-    let checksum = adler32(&out);
-    if checksum != adler32_val {
-        return Err(Error::AdlerChecksum);
-    }
-
-    Ok(out)
+    Ok(())
 }
 
 // FIXME: Streaming API
@@ -79,3 +280,77 @@ fn adler32(data: &[u8]) -> u32 {
     adler.write(data);
     adler.finish()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inflater_reuse() {
+        let mut inflater = Inflater::default();
+        let mut out = Vec::new();
+        let mut streams = Vec::new();
+        for i in 0..16u8 {
+            let mut stream = Vec::new();
+            compress(&mut stream, &[i; 4096], 6);
+            streams.push(stream);
+        }
+
+        inflater.decompress_into(&streams[0], &mut out).unwrap();
+        let (ptr, capacity) = (out.as_ptr(), out.capacity());
+        for (i, stream) in streams.iter().enumerate() {
+            inflater.decompress_into(stream, &mut out).unwrap();
+            assert_eq!(out, [i as u8; 4096]);
+            // No reallocation once the buffer is big enough
+            assert_eq!((out.as_ptr(), out.capacity()), (ptr, capacity));
+        }
+    }
+
+    #[test]
+    fn stream_inflater() {
+        let data: Vec<u8> =
+            (0..100_000u64).map(|i| ((i * i) >> 7) as u8).collect();
+        let mut stream = Vec::new();
+        compress(&mut stream, &data, 6);
+
+        // Fed a byte or a few bytes at a time, and read into a small buffer
+        for size in [1, 7, 4096] {
+            let mut inflater = StreamInflater::default();
+            let mut inflated = Vec::new();
+            let mut input = Vec::new();
+            let mut rest = stream.as_slice();
+            let mut out = [0; 1000];
+            while !inflater.is_done() {
+                let more = !rest.is_empty();
+                let (consumed, written) =
+                    inflater.read(&input, more, &mut out).unwrap();
+                input.drain(..consumed);
+                inflated.extend_from_slice(&out[..written]);
+                if written == 0 && !inflater.is_done() {
+                    // Needs more input
+                    assert!(more);
+                    let piece = &rest[..rest.len().min(size)];
+                    input.extend_from_slice(piece);
+                    rest = &rest[piece.len()..];
+                }
+            }
+            assert_eq!(inflated, data, "{size} bytes at a time");
+        }
+
+        // Checksum mismatch
+        let last = stream.len() - 1;
+        stream[last] ^= 1;
+        let mut inflater = StreamInflater::default();
+        let mut out = vec![0; data.len()];
+        let mut result = Ok((0, 0));
+        let mut input = stream.as_slice();
+        while !inflater.is_done() {
+            result = inflater.read(input, false, &mut out);
+            match result {
+                Ok((consumed, _)) => input = &input[consumed..],
+                Err(_) => break,
+            }
+        }
+        assert!(matches!(result, Err(Error::AdlerChecksum { .. })));
+    }
+}
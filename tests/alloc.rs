@@ -0,0 +1,397 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    io::{self, Cursor, Read},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, MutexGuard, PoisonError,
+    },
+};
+
+use pix::{rgb::SRgb8, Raster};
+use png_pong::{
+    chunk::{
+        Chunk, ColorType, ImageData, ImageEnd, ImageHeader, Palette, Text, Time,
+    },
+    decode::{decode_single_chunk, Error as DecoderError},
+    diff,
+    encode::{rewrite, ChunkSelector, Edit},
+    read_png, repair, ChunkDiff, Decoder, Encoder,
+};
+
+/// Global allocator counting allocations and allocated bytes.
+struct Counter;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// Tests run in parallel, so each holds this while it runs, to keep
+/// allocations of the others out of the counts.
+static SERIAL: Mutex<()> = Mutex::new(());
+
+fn serial() -> MutexGuard<'static, ()> {
+    // A failed test shouldn't fail the others
+    SERIAL.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn allocated(size: usize) {
+    let now = ALLOCATED.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(now, Ordering::Relaxed);
+}
+
+unsafe impl GlobalAlloc for Counter {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        allocated(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(
+        &self,
+        ptr: *mut u8,
+        layout: Layout,
+        new_size: usize,
+    ) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        allocated(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counter = Counter;
+
+/// PNG file with 1001 chunks: IHDR, PLTE, 499 pairs of tIME and IDAT, IEND.
+fn png_with_many_chunks() -> Vec<u8> {
+    let signature = vec![137, 80, 78, 71, 13, 10, 26, 10];
+    let mut enc = Encoder::new(signature).into_chunk_enc();
+    let mut chunks = vec![
+        Chunk::ImageHeader(ImageHeader {
+            width: 1,
+            height: 1,
+            color_type: ColorType::Palette,
+            bit_depth: 8,
+            interlace: false,
+        }),
+        Chunk::Palette(Palette {
+            palette: vec![SRgb8::new(255, 0, 0)],
+        }),
+    ];
+    for i in 0..499 {
+        chunks.push(Chunk::Time(Time {
+            year: 2000,
+            month: 1,
+            day: 1,
+            hour: 0,
+            minute: 0,
+            second: (i % 60) as u8,
+        }));
+        chunks.push(Chunk::ImageData(ImageData::with_data(vec![i as u8; 8])));
+    }
+    chunks.push(Chunk::ImageEnd(ImageEnd));
+
+    for chunk in &mut chunks {
+        enc.encode(chunk).unwrap();
+    }
+    enc.into_inner().unwrap()
+}
+
+#[test]
+fn chunk_allocations() {
+    let _serial = serial();
+    let png = png_with_many_chunks();
+    let chunks = Decoder::new(Cursor::new(png)).unwrap().into_chunks();
+    let mut count = 0;
+
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for chunk in chunks {
+        // Dropping chunks deallocates, but never allocates
+        drop(chunk.unwrap());
+        count += 1;
+    }
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+
+    assert_eq!(count, 1001);
+    assert!(
+        allocations <= count,
+        "{allocations} allocations for {count} chunks",
+    );
+}
+
+/// PNG file with 1002 chunks: IHDR, the image data split into 1000 IDAT
+/// chunks, and IEND.
+fn png_with_many_idat_chunks() -> Vec<u8> {
+    // Noise, so that the image data doesn't compress much
+    let mut seed = 1u32;
+    let pixels: Vec<_> = (0..128 * 128)
+        .map(|_| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let [r, g, b, _] = seed.to_be_bytes();
+            SRgb8::new(r, g, b)
+        })
+        .collect();
+    let raster = Raster::<SRgb8>::with_pixels(128, 128, pixels);
+    let mut png = Vec::new();
+    Encoder::new(&mut png)
+        .into_step_enc()
+        .still(&raster)
+        .unwrap();
+    let idat = Decoder::new(Cursor::new(&png))
+        .unwrap()
+        .into_raw_idat_bytes()
+        .unwrap();
+
+    // Signature (8 bytes) and header chunk (25 bytes)
+    let mut enc = Encoder::new(png[..33].to_vec()).into_chunk_enc();
+    let len = idat.len();
+    for i in 0..1000 {
+        let piece = &idat[i * len / 1000..(i + 1) * len / 1000];
+        enc.write_chunk(*b"IDAT", piece).unwrap();
+    }
+    enc.encode(&mut Chunk::ImageEnd(ImageEnd)).unwrap();
+    enc.into_inner().unwrap()
+}
+
+#[test]
+fn image_data_allocations() {
+    let _serial = serial();
+    let png = png_with_many_idat_chunks();
+    let count = 1002;
+
+    // Image data is appended to one buffer, without a copy per chunk
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let mut steps = Decoder::new(Cursor::new(&png)).unwrap().into_steps();
+    let step = steps.next().unwrap().unwrap();
+    assert!(steps.next().is_none());
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    drop(step);
+
+    assert!(
+        allocations * 10 <= count,
+        "{allocations} allocations decoding {count} chunks",
+    );
+
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let idat = Decoder::new(Cursor::new(&png))
+        .unwrap()
+        .into_raw_idat_bytes()
+        .unwrap();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    drop(idat);
+
+    assert!(
+        allocations * 10 <= count,
+        "{allocations} allocations reading {count} chunks",
+    );
+}
+
+/// Reader repeating a slice.
+struct Repeat<'a> {
+    data: &'a [u8],
+    offset: usize,
+    left: usize,
+}
+
+impl Read for Repeat<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.left == 0 {
+            return Ok(0);
+        }
+        let n = buf.len().min(self.data.len() - self.offset);
+        buf[..n].copy_from_slice(&self.data[self.offset..][..n]);
+        self.offset += n;
+        if self.offset == self.data.len() {
+            self.offset = 0;
+            self.left -= 1;
+        }
+        Ok(n)
+    }
+}
+
+#[test]
+fn streaming_rewrite() {
+    let _serial = serial();
+    // 16 IDAT chunks of 1 MiB each, with different data
+    let signature = vec![137, 80, 78, 71, 13, 10, 26, 10];
+    let mut enc = Encoder::new(signature).into_chunk_enc();
+    enc.encode(&mut Chunk::ImageHeader(ImageHeader {
+        width: 1,
+        height: 1,
+        color_type: ColorType::Grey,
+        bit_depth: 8,
+        interlace: false,
+    }))
+    .unwrap();
+    let mut input = enc.into_inner().unwrap();
+    let ihdr_end = input.len();
+    let mut last_idat = 0;
+    for i in 0..16 {
+        last_idat = input.len();
+        let idat = ImageData::with_data(vec![i; 1 << 20]);
+        input.extend(Chunk::ImageData(idat).to_bytes().unwrap());
+    }
+    input.extend(Chunk::ImageEnd(ImageEnd).to_bytes().unwrap());
+    let text = Chunk::Text(Text::new("Comment", "Inserted").unwrap());
+    let inserted = text.to_bytes().unwrap();
+
+    let edits = [
+        Edit::Insert(ChunkSelector::Name(*b"IHDR"), text),
+        Edit::Remove(ChunkSelector::Nth(*b"IDAT", 15)),
+    ];
+    // Allocated up front, so writing doesn't count
+    let mut output = Vec::with_capacity(input.len() + inserted.len());
+
+    let start = ALLOCATED.load(Ordering::Relaxed);
+    PEAK.store(start, Ordering::Relaxed);
+    rewrite(input.as_slice(), &mut output, &edits).unwrap();
+    let peak = PEAK.load(Ordering::Relaxed).saturating_sub(start);
+
+    assert!(peak < 1 << 20, "{peak} bytes allocated while streaming");
+    assert_eq!(
+        diff(input.as_slice(), output.as_slice()).unwrap(),
+        [
+            ChunkDiff::OnlyInFirst {
+                name: *b"IDAT",
+                offset: last_idat as u64,
+            },
+            ChunkDiff::OnlyInSecond {
+                name: *b"tEXt",
+                offset: ihdr_end as u64,
+            },
+        ],
+    );
+}
+
+#[test]
+fn streaming_rows() {
+    let _serial = serial();
+    // 64 MiB black image, with a stored (uncompressed) deflate block per row
+    let (width, height) = (65534, 1024);
+    let signature = vec![137, 80, 78, 71, 13, 10, 26, 10];
+    let mut enc = Encoder::new(signature).into_chunk_enc();
+    enc.encode(&mut Chunk::ImageHeader(ImageHeader {
+        width,
+        height,
+        color_type: ColorType::Grey,
+        bit_depth: 8,
+        interlace: false,
+    }))
+    .unwrap();
+    // zlib header
+    enc.write_chunk(*b"IDAT", &[0x78, 0x01]).unwrap();
+    let head = enc.into_inner().unwrap();
+    // IDAT chunk holding `data` as is
+    let idat = |data: &[u8]| {
+        let mut enc = Encoder::new(Vec::new()).into_chunk_enc();
+        enc.write_chunk(*b"IDAT", data).unwrap();
+        enc.into_inner().unwrap()
+    };
+    let block = |last: bool| {
+        let mut block = vec![u8::from(last), 0xFF, 0xFF, 0x00, 0x00];
+        block.resize(block.len() + 65535, 0);
+        block
+    };
+    let middle = idat(&block(false));
+    // Adler-32 of zeros is the count (mod 65521) in the high half, and 1
+    let count = 65535 * u64::from(height);
+    let adler = ((count % 65521) << 16 | 1) as u32;
+    let mut last = block(true);
+    last.extend(adler.to_be_bytes());
+    let mut tail = idat(&last);
+    tail.extend(Chunk::ImageEnd(ImageEnd).to_bytes().unwrap());
+
+    let input = Cursor::new(&head)
+        .chain(Repeat {
+            data: &middle,
+            offset: 0,
+            left: height as usize - 1,
+        })
+        .chain(Cursor::new(&tail));
+
+    let start = ALLOCATED.load(Ordering::Relaxed);
+    PEAK.store(start, Ordering::Relaxed);
+    let rows = Decoder::new(input).unwrap().into_rows().unwrap();
+    assert!(rows.is_streaming());
+    let mut count = 0;
+    for row in rows {
+        let row = row.unwrap();
+        assert_eq!((row.y, row.data.len()), (count, width as usize));
+        count += 1;
+    }
+    let peak = PEAK.load(Ordering::Relaxed).saturating_sub(start);
+
+    assert_eq!(count, height);
+    assert!(peak < 1 << 20, "{peak} bytes allocated while streaming");
+}
+
+#[test]
+fn huge_image_data_length() {
+    let _serial = serial();
+    // Image data chunk claiming to be 2 GiB, in a file of under 100 bytes
+    let signature = vec![137, 80, 78, 71, 13, 10, 26, 10];
+    let mut enc = Encoder::new(signature).into_chunk_enc();
+    enc.encode(&mut Chunk::ImageHeader(ImageHeader {
+        width: 1,
+        height: 1,
+        color_type: ColorType::Grey,
+        bit_depth: 8,
+        interlace: false,
+    }))
+    .unwrap();
+    let mut png = enc.into_inner().unwrap();
+    png.extend(0x7FFF_FFFFu32.to_be_bytes());
+    png.extend(b"IDAT");
+    png.extend([0; 16]);
+
+    let decoders: [fn(&[u8]) -> Result<(), DecoderError>; 5] = [
+        |png| Decoder::new(png)?.into_steps().next().unwrap().map(drop),
+        |png| Decoder::new(png)?.into_chunks().into_vec().map(drop),
+        |png| Decoder::new(png)?.into_raw_idat_bytes().map(drop),
+        |png| decode_single_chunk(png, *b"IDAT").map(drop),
+        |png| read_png(png).map(drop),
+    ];
+    for decode in decoders {
+        let start = ALLOCATED.load(Ordering::Relaxed);
+        PEAK.store(start, Ordering::Relaxed);
+        let result = decode(&png);
+        let peak = PEAK.load(Ordering::Relaxed).saturating_sub(start);
+
+        assert!(matches!(result, Err(DecoderError::Io(_))), "{result:?}");
+        assert!(peak < 1 << 20, "{peak} bytes allocated for a tiny file");
+    }
+}
+
+#[test]
+fn repair_zlib_bomb() {
+    let _serial = serial();
+    // 1 by 1 image, with 16 MiB of zeros compressed into it's image data
+    let signature = vec![137, 80, 78, 71, 13, 10, 26, 10];
+    let mut enc = Encoder::new(signature).into_chunk_enc();
+    enc.encode(&mut Chunk::ImageHeader(ImageHeader {
+        width: 1,
+        height: 1,
+        color_type: ColorType::Grey,
+        bit_depth: 8,
+        interlace: false,
+    }))
+    .unwrap();
+    let mut png = enc.into_inner().unwrap();
+    let idat = Chunk::ImageData(ImageData::with_data(vec![0; 1 << 24]));
+    png.extend(idat.to_bytes().unwrap());
+    png.extend(Chunk::ImageEnd(ImageEnd).to_bytes().unwrap());
+
+    let mut output = Vec::new();
+    let start = ALLOCATED.load(Ordering::Relaxed);
+    PEAK.store(start, Ordering::Relaxed);
+    let report = repair(png.as_slice(), &mut output, 1 << 20).unwrap();
+    let peak = PEAK.load(Ordering::Relaxed).saturating_sub(start);
+
+    assert_eq!(report.image_bytes_replaced, 0);
+    assert!(peak < 1 << 20, "{peak} bytes allocated for a 1 pixel image");
+}
@@ -0,0 +1,1807 @@
+use std::io::Cursor;
+
+use pix::{
+    gray::{Gray8, SGray8},
+    rgb::SRgb8,
+    Palette, Raster,
+};
+use png_pong::{
+    chunk::{
+        Chunk, ColorType, CompressedText, ImageData, ImageEnd, ImageHeader,
+        InternationalText, Physical, Text, Time, Transparency, Unknown,
+    },
+    decode::{
+        all_chunks_valid, decode_planes_into, decode_single_chunk,
+        measure_compression_ratio, png_chunk_names, png_chunk_sizes,
+        png_color_type, png_dimensions, png_header, ChunkWarning, CrcPolicy,
+        Error, Limits, PlaneChannel, PlaneLayout, Row,
+    },
+    encode::generate_test_image,
+    Decoder, Encoder, PngRaster,
+};
+
+/// Encode a 1x1 image with `count` tEXt chunks after the header.
+fn png_with_text_chunks(count: usize) -> Vec<u8> {
+    let raster = Raster::<SGray8>::with_clear(1, 1);
+    let mut file = Vec::new();
+    Encoder::new(&mut file)
+        .into_step_enc()
+        .still(&raster)
+        .unwrap();
+
+    let mut text = Vec::new();
+    let mut encoder = Encoder::new(&mut text).into_chunk_enc();
+    for _ in 0..count {
+        let mut chunk = Chunk::Text(Text::new("k", "").unwrap());
+        encoder.encode(&mut chunk).unwrap();
+    }
+
+    // Insert after the signature (8 bytes) and header chunk (25 bytes)
+    let tail = file.split_off(33);
+    file.extend(text);
+    file.extend(tail);
+    file
+}
+
+#[test]
+fn too_many_chunks() {
+    let file = png_with_text_chunks(100_000);
+    let error = Decoder::new(Cursor::new(file))
+        .unwrap()
+        .into_steps()
+        .next()
+        .unwrap()
+        .unwrap_err();
+
+    assert!(matches!(error, Error::LimitExceeded));
+}
+
+#[test]
+fn excess_text_chunks_skipped() {
+    let limits = Limits {
+        max_text_chunks: 10,
+        ..Limits::default()
+    };
+    let file = png_with_text_chunks(1000);
+    let mut chunks = Decoder::new(Cursor::new(&file))
+        .unwrap()
+        .limits(limits)
+        .into_chunks();
+    let kept = chunks.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+
+    assert_eq!(kept.iter().filter(|c| c.text_key().is_some()).count(), 10);
+    assert_eq!(chunks.warnings(), [ChunkWarning::OverLimit(*b"tEXt"); 990]);
+
+    let step = Decoder::new(Cursor::new(&file))
+        .unwrap()
+        .limits(limits)
+        .into_steps()
+        .next()
+        .unwrap();
+
+    assert!(step.is_ok());
+}
+
+#[test]
+fn excess_text_chunks_strict() {
+    let limits = Limits {
+        max_text_chunks: 10,
+        strict: true,
+        ..Limits::default()
+    };
+    let file = png_with_text_chunks(1000);
+    let result = Decoder::new(Cursor::new(file))
+        .unwrap()
+        .limits(limits)
+        .into_chunks()
+        .collect::<Result<Vec<_>, _>>();
+
+    assert!(matches!(result, Err(Error::LimitExceeded)));
+}
+
+#[test]
+fn frame_data_not_limited() {
+    let limits = Limits {
+        max_ancillary_bytes: 0,
+        ..Limits::default()
+    };
+    let mut encoder = Encoder::new(Vec::new()).animation().into_step_enc();
+    for value in [10, 20, 30] {
+        let raster =
+            Raster::<SGray8>::with_pixels(2, 2, vec![SGray8::new(value); 4]);
+        encoder
+            .encode(&png_pong::Step {
+                raster: PngRaster::Gray8(raster),
+                delay: 100,
+            })
+            .unwrap();
+    }
+    encoder.finish().unwrap();
+    let file = encoder.into_inner();
+
+    let mut chunks = Decoder::new(Cursor::new(&file))
+        .unwrap()
+        .limits(limits)
+        .into_chunks();
+    let frame_data = chunks
+        .by_ref()
+        .map(Result::unwrap)
+        .filter(|chunk| {
+            matches!(chunk, Chunk::Unknown(unknown) if &unknown.name == b"fdAT")
+        })
+        .count();
+    assert_eq!(frame_data, 2);
+    // The other animation chunks are skipped
+    let warnings = chunks.warnings();
+    assert!(warnings.contains(&ChunkWarning::OverLimit(*b"fcTL")));
+    assert!(!warnings.contains(&ChunkWarning::OverLimit(*b"fdAT")));
+}
+
+#[test]
+fn steps_metadata_indexed() {
+    let mut palette = Palette::new(2);
+    palette.set_entry(SRgb8::new(255, 0, 0)).unwrap();
+    palette.set_entry(SRgb8::new(0, 0, 255)).unwrap();
+    let raster = PngRaster::Palette(
+        Raster::<Gray8>::with_u8_buffer(2, 1, [0, 1]),
+        Box::new(palette),
+        vec![128],
+    );
+    let mut file = Vec::new();
+    Encoder::new(&mut file)
+        .into_step_enc()
+        .still(&raster)
+        .unwrap();
+
+    let mut steps = Decoder::new(Cursor::new(file)).unwrap().into_steps();
+    assert!(steps.header().is_none());
+    assert!(steps.palette().is_none());
+    assert!(steps.transparency().is_none());
+
+    steps.next().unwrap().unwrap();
+    let header = steps.header().unwrap();
+    assert_eq!((header.width, header.height), (2, 1));
+    assert_eq!(header.color_type, ColorType::Palette);
+    assert_eq!(
+        steps.palette().unwrap().palette,
+        [SRgb8::new(255, 0, 0), SRgb8::new(0, 0, 255)],
+    );
+    assert_eq!(
+        steps.transparency(),
+        Some(&Transparency::Palette(vec![128])),
+    );
+}
+
+#[test]
+fn steps_metadata_truecolor() {
+    let file = std::fs::read("tests/png/icon.png").unwrap();
+    let mut steps = Decoder::new(Cursor::new(file)).unwrap().into_steps();
+    assert!(steps.header().is_none());
+
+    steps.next().unwrap().unwrap();
+    let header = steps.header().unwrap();
+    assert_eq!((header.width, header.height), (256, 256));
+    assert_eq!(header.color_type, ColorType::Rgba);
+    assert!(steps.palette().is_none());
+    assert!(steps.transparency().is_none());
+}
+
+#[test]
+fn chunk_too_large() {
+    let mut file = png_with_text_chunks(0);
+    file.truncate(33);
+    // Critical, so it can't be skipped as too much ancillary data
+    file.extend(0x7FFF_FFFFu32.to_be_bytes());
+    file.extend(b"RUSt");
+    let result = Decoder::new(Cursor::new(file))
+        .unwrap()
+        .into_chunks()
+        .collect::<Result<Vec<_>, _>>();
+
+    assert!(
+        matches!(result, Err(Error::ChunkTooLarge(name)) if &name == b"RUSt")
+    );
+
+    // Image data isn't limited, all of it is needed to decode the image
+    let limits = Limits {
+        max_chunk_bytes: 16,
+        ..Limits::default()
+    };
+    let pixels: Vec<_> = (0..64 * 64).map(|i| SGray8::new(i as u8)).collect();
+    let raster = Raster::<SGray8>::with_pixels(64, 64, pixels);
+    let mut file = Vec::new();
+    Encoder::new(&mut file)
+        .into_step_enc()
+        .still(&raster)
+        .unwrap();
+    assert!(png_chunk_sizes(Cursor::new(&file))
+        .unwrap()
+        .iter()
+        .any(|(name, size, _)| name == b"IDAT" && *size > 16));
+    let step = Decoder::new(Cursor::new(file))
+        .unwrap()
+        .limits(limits)
+        .into_steps()
+        .next()
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(step.raster, PngRaster::Gray8(raster));
+}
+
+#[test]
+fn peek_chunk_name() {
+    let file = std::fs::read("tests/png/profile.png").unwrap();
+    let mut chunks = Decoder::new(Cursor::new(file)).unwrap().into_chunks();
+
+    assert_eq!(chunks.peek_name().unwrap(), Some((*b"IHDR", 13)));
+    assert_eq!(chunks.peek_name().unwrap(), Some((*b"IHDR", 13)));
+    assert!(matches!(chunks.next(), Some(Ok(Chunk::ImageHeader(_)))));
+
+    while let Some((name, _len)) = chunks.peek_name().unwrap() {
+        let chunk = chunks.next().unwrap().unwrap();
+        let expected = match chunk {
+            Chunk::ImageData(_) => *b"IDAT",
+            Chunk::ImageEnd(_) => *b"IEND",
+            Chunk::InternationalText(_) => *b"iTXt",
+            Chunk::Physical(_) => *b"pHYs",
+            Chunk::Unknown(unknown) => unknown.name,
+            _ => unreachable!(),
+        };
+        assert_eq!(name, expected);
+    }
+    assert!(chunks.next().is_none());
+}
+
+#[test]
+fn single_chunk() {
+    let file = std::fs::read("tests/png/profile.png").unwrap();
+    let phys = decode_single_chunk(Cursor::new(&file), *b"pHYs").unwrap();
+
+    assert_eq!(phys.map(|data| data.len()), Some(9));
+    assert!(decode_single_chunk(Cursor::new(&file), *b"iCCP")
+        .unwrap()
+        .is_some());
+    assert!(decode_single_chunk(Cursor::new(&file), *b"PLTE")
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn crc_mismatch_values() {
+    let file = std::fs::read("tests/png/bad/badcrc.png").unwrap();
+    let result = Decoder::new(Cursor::new(file))
+        .unwrap()
+        .into_steps()
+        .next()
+        .unwrap();
+
+    match result {
+        Err(Error::Crc32 {
+            chunk,
+            expected,
+            found,
+            offset,
+        }) => {
+            assert_eq!(&chunk, b"IDAT");
+            assert_eq!(expected, 0xE221_BC33);
+            assert_eq!(found, 0xABAD_CC32);
+            assert_eq!(offset, 33);
+        }
+        other => panic!("Expected CRC32 error, got {other:?}"),
+    }
+}
+
+#[test]
+fn adler_mismatch_values() {
+    let file = std::fs::read("tests/png/bad/badadler.png").unwrap();
+    let result = Decoder::new(Cursor::new(file))
+        .unwrap()
+        .into_steps()
+        .next()
+        .unwrap();
+
+    match result {
+        Err(error @ Error::AdlerChecksum { expected, found }) => {
+            assert_eq!(expected, 0x0002_0001);
+            assert_eq!(found, 0xABAD_AD32);
+            assert!(error.to_string().contains("0xabadad32"));
+        }
+        other => panic!("Expected Adler-32 error, got {other:?}"),
+    }
+}
+
+#[test]
+fn inflated_size_mismatch() {
+    let mut file = vec![137, 80, 78, 71, 13, 10, 26, 10];
+    let mut encoder = Encoder::new(&mut file).into_chunk_enc();
+    let header = ImageHeader {
+        width: 4,
+        height: 4,
+        color_type: ColorType::Grey,
+        bit_depth: 8,
+        interlace: false,
+    };
+    encoder.encode(&mut Chunk::ImageHeader(header)).unwrap();
+    encoder
+        .encode(&mut Chunk::ImageData(ImageData::with_data(vec![0, 0])))
+        .unwrap();
+    encoder.encode(&mut Chunk::ImageEnd(ImageEnd)).unwrap();
+
+    let result = Decoder::new(Cursor::new(file))
+        .unwrap()
+        .into_steps()
+        .next()
+        .unwrap();
+
+    assert!(matches!(
+        result,
+        Err(Error::InflatedSize {
+            expected: 20,
+            found: 2
+        })
+    ));
+}
+
+#[test]
+fn chunk_names() {
+    let file = std::fs::read("tests/png/profile.png").unwrap();
+    let names = png_chunk_names(Cursor::new(file)).unwrap();
+
+    assert_eq!(
+        names,
+        [*b"IHDR", *b"iCCP", *b"pHYs", *b"iTXt", *b"IDAT", *b"IEND"]
+    );
+
+    let file = std::fs::read("tests/png/bad/badcrc.png").unwrap();
+    let names = png_chunk_names(Cursor::new(file)).unwrap();
+
+    assert_eq!(names, [*b"IHDR", *b"IDAT", *b"IEND"]);
+    assert!(matches!(
+        png_chunk_names(Cursor::new(b"GIF89a")),
+        Err(Error::Io(_))
+    ));
+}
+
+#[test]
+fn chunk_sizes() {
+    let file = std::fs::read("tests/png/bad/badcrc.png").unwrap();
+    let chunks = png_chunk_sizes(Cursor::new(file)).unwrap();
+
+    assert_eq!(
+        chunks,
+        [(*b"IHDR", 13, 8), (*b"IDAT", 10, 33), (*b"IEND", 0, 55)],
+    );
+}
+
+#[test]
+fn step_through_until() {
+    let file = std::fs::read("tests/png/profile.png").unwrap();
+    let mut chunks = Decoder::new(Cursor::new(&file)).unwrap().into_chunks();
+
+    assert!(matches!(
+        chunks.step_through_until(*b"iTXt"),
+        Ok(Some(Chunk::InternationalText(_)))
+    ));
+    assert!(matches!(chunks.next(), Some(Ok(Chunk::ImageData(_)))));
+    assert!(matches!(
+        chunks.step_through_until(*b"IEND"),
+        Ok(Some(Chunk::ImageEnd(_)))
+    ));
+
+    let mut chunks = Decoder::new(Cursor::new(&file)).unwrap().into_chunks();
+
+    assert!(matches!(chunks.step_through_until(*b"tEXt"), Ok(None)));
+    assert!(chunks.next().is_none());
+}
+
+#[test]
+fn skip_to_palette() {
+    let file = std::fs::read("tests/pngsuite-basic/basn3p08.png").unwrap();
+    let mut chunks = Decoder::new(Cursor::new(&file)).unwrap().into_chunks();
+
+    let palette = chunks.skip_to_palette().unwrap().unwrap();
+    assert_eq!(palette.palette.len(), 256);
+    assert!(matches!(chunks.next(), Some(Ok(Chunk::ImageData(_)))));
+
+    let file = std::fs::read("tests/pngsuite-basic/basn2c08.png").unwrap();
+    let mut chunks = Decoder::new(Cursor::new(&file)).unwrap().into_chunks();
+
+    assert!(chunks.skip_to_palette().unwrap().is_none());
+    // The image data is left unread
+    assert!(matches!(chunks.next(), Some(Ok(Chunk::ImageData(_)))));
+}
+
+#[test]
+fn erased_reader() {
+    let path = "tests/pngsuite-basic/basn3p08.png";
+    let file = std::fs::read(path).unwrap();
+    let expected = Decoder::new(Cursor::new(file.clone()))
+        .unwrap()
+        .into_raw_idat_bytes()
+        .unwrap();
+
+    let cursor = Decoder::new_erased(Cursor::new(file)).unwrap();
+    let reader = std::io::BufReader::new(std::fs::File::open(path).unwrap());
+    let buffered = Decoder::new_erased(reader).unwrap();
+    for decoder in [cursor, buffered] {
+        assert_eq!(decoder.into_raw_idat_bytes().unwrap(), expected);
+    }
+
+    let step = Decoder::new_erased(std::fs::File::open(path).unwrap())
+        .unwrap()
+        .into_steps()
+        .next()
+        .unwrap()
+        .unwrap();
+    assert!(matches!(step.raster, PngRaster::Palette(..)));
+}
+
+/// Decode the first chunk of a PNG file with only one chunk.
+fn decode_first_chunk(name: [u8; 4], data: Vec<u8>) -> Result<Chunk, Error> {
+    let mut file = vec![137, 80, 78, 71, 13, 10, 26, 10];
+    let mut chunk = Chunk::Unknown(Unknown { name, data });
+    Encoder::new(&mut file)
+        .into_chunk_enc()
+        .encode(&mut chunk)
+        .unwrap();
+
+    Decoder::new(Cursor::new(file))
+        .unwrap()
+        .into_chunks()
+        .next()
+        .unwrap()
+}
+
+#[test]
+fn text_keyword_bounds() {
+    let error = decode_first_chunk(*b"tEXt", b"Title".to_vec()).unwrap_err();
+    assert!(matches!(error, Error::NulTerm));
+
+    let mut data = b"Keyword that goes on".repeat(5);
+    data.extend(b"\0value");
+    let error = decode_first_chunk(*b"tEXt", data).unwrap_err();
+    assert!(matches!(
+        error,
+        Error::KeySize { size: 80, key } if key == *b"Keyword th"
+    ));
+    assert!(error.to_string().contains("\"Keyword th\""));
+
+    for key in [&b"Title "[..], b"Ti  tle", b"\tTitle", b"Ti\x7Ftle"] {
+        let mut data = key.to_vec();
+        data.extend(b"\0value");
+        let error = decode_first_chunk(*b"tEXt", data).unwrap_err();
+        assert!(matches!(error, Error::InvalidKeyword(_)), "{key:?}");
+        assert!(error.is_recoverable());
+    }
+
+    let chunk = decode_first_chunk(*b"tEXt", b"Title\0value".to_vec()).unwrap();
+    assert!(matches!(chunk, Chunk::Text(text) if text.val() == "value"));
+}
+
+#[test]
+fn international_text_roundtrip() {
+    let text = |langtag: &str, val: &str, compressed| InternationalText {
+        key: "Title".to_string(),
+        langtag: langtag.to_string(),
+        transkey: "Titel".to_string(),
+        val: val.to_string(),
+        compressed,
+    };
+    let texts = [
+        text("de", "Grüße", false),
+        text("de", "Grüße", true),
+        // An empty language tag means the language is unknown
+        text("", "Hello", false),
+        // Empty text, compressed and not
+        text("en", "", true),
+        text("en", "", false),
+    ];
+
+    for text in texts {
+        let chunk = Chunk::InternationalText(text.clone());
+        let mut file = vec![137, 80, 78, 71, 13, 10, 26, 10];
+        file.extend(chunk.to_bytes().unwrap());
+        let decoded = Decoder::new(Cursor::new(file))
+            .unwrap()
+            .into_chunks()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(decoded.as_international_text(), Some(&text));
+    }
+
+    let mut long = text("en", "", false);
+    long.key = "k".repeat(80);
+    let chunk = Chunk::InternationalText(long);
+    assert!(matches!(
+        chunk.to_bytes(),
+        Err(png_pong::encode::Error::KeySize(80))
+    ));
+    let mut data = b"k".repeat(80);
+    data.extend(b"\0\0\0en\0\0");
+    let error = decode_first_chunk(*b"iTXt", data).unwrap_err();
+    assert!(matches!(error, Error::KeySize { size: 80, .. }));
+}
+
+#[test]
+fn raw_idat_bytes() {
+    let file = std::fs::read("tests/png/icon.png").unwrap();
+    let idat = Decoder::new(Cursor::new(&file))
+        .unwrap()
+        .into_raw_idat_bytes()
+        .unwrap();
+    let expected: Vec<u8> = Decoder::new(Cursor::new(&file))
+        .unwrap()
+        .into_chunks()
+        .filter_map(|chunk| match chunk.unwrap() {
+            Chunk::ImageData(image_data) => Some(image_data.data),
+            _ => None,
+        })
+        .flatten()
+        .collect();
+
+    assert!(!idat.is_empty());
+    assert_eq!(idat, expected);
+    // zlib header: deflate with a 32K window
+    assert_eq!(idat[0], 0x78);
+}
+
+#[test]
+fn fixed_chunk_length() {
+    let mut data = vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0];
+    assert!(matches!(
+        decode_first_chunk(*b"IHDR", data.clone()),
+        Ok(Chunk::ImageHeader(_))
+    ));
+
+    // Extra byte at the end of the header
+    data.push(0);
+    let error = decode_first_chunk(*b"IHDR", data).unwrap_err();
+    assert!(matches!(error, Error::ChunkLength(name) if &name == b"IHDR"));
+
+    let error = decode_first_chunk(*b"tIME", vec![0; 8]).unwrap_err();
+    assert!(matches!(error, Error::ChunkLength(name) if &name == b"tIME"));
+
+    // Data in the end chunk is skipped, with a warning
+    assert!(matches!(
+        decode_first_chunk(*b"IEND", vec![0]),
+        Ok(Chunk::ImageEnd(_))
+    ));
+}
+
+#[test]
+fn jpeg_in_idat() {
+    let mut file = vec![137, 80, 78, 71, 13, 10, 26, 10];
+    let mut encoder = Encoder::new(&mut file).into_chunk_enc();
+    let mut chunks = [
+        Chunk::ImageHeader(ImageHeader {
+            width: 1,
+            height: 1,
+            color_type: ColorType::Grey,
+            bit_depth: 8,
+            interlace: false,
+        }),
+        Chunk::Unknown(Unknown {
+            name: *b"IDAT",
+            data: vec![
+                0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, b'J', b'F', b'I', b'F',
+            ],
+        }),
+        Chunk::ImageEnd(ImageEnd),
+    ];
+    for chunk in &mut chunks {
+        encoder.encode(chunk).unwrap();
+    }
+
+    let error = Decoder::new(Cursor::new(file))
+        .unwrap()
+        .into_steps()
+        .next()
+        .unwrap()
+        .unwrap_err();
+
+    assert!(matches!(error, Error::JpegInIdat));
+}
+
+#[test]
+#[cfg(target_pointer_width = "64")]
+#[ignore = "needs over 40 GiB of memory"]
+fn size_over_4_gib() {
+    use pix::rgb::SRgba16;
+
+    // Clear, so the image data compresses to about 12 MiB
+    let raster = Raster::<SRgba16>::with_clear(40_000, 40_000);
+    let mut file = Vec::new();
+    Encoder::new(&mut file)
+        .into_step_enc()
+        .still(&raster)
+        .unwrap();
+
+    let step = Decoder::new(Cursor::new(file))
+        .unwrap()
+        .into_steps()
+        .next()
+        .unwrap()
+        .unwrap();
+
+    match step.raster {
+        PngRaster::Rgba16(decoded) => {
+            assert_eq!((decoded.width(), decoded.height()), (40_000, 40_000));
+            assert_eq!(decoded.as_u8_slice().len(), 12_800_000_000);
+        }
+        _ => panic!("Wrong color format"),
+    }
+}
+
+#[test]
+fn all_chunks_valid_report() {
+    assert!(all_chunks_valid("tests/png/icon.png").is_ok());
+
+    // The image data is missing once its chunk is rejected
+    let errors = all_chunks_valid("tests/png/bad/badcrc.png").unwrap_err();
+    assert!(matches!(
+        errors[..],
+        [Error::Crc32 { chunk, .. }, Error::NoImageData] if &chunk == b"IDAT"
+    ));
+
+    // Bad CRC on a text chunk, and a text chunk after IEND
+    let mut file = png_with_text_chunks(1);
+    let text = png_with_text_chunks(1)[33..47].to_vec();
+    file[46] ^= 0xFF;
+    file.extend(text);
+    let path = std::env::temp_dir().join("png_pong_all_chunks_valid.png");
+    std::fs::write(&path, file).unwrap();
+    let errors = all_chunks_valid(&path).unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(errors.len(), 2, "{errors:?}");
+    assert!(
+        matches!(errors[0], Error::Crc32 { chunk, .. } if &chunk == b"tEXt")
+    );
+    assert!(matches!(errors[1], Error::TrailingChunk));
+}
+
+#[test]
+fn dimensions() {
+    let file = std::fs::read("tests/png/icon.png").unwrap();
+
+    assert_eq!(png_dimensions(&file[..]).unwrap(), (256, 256));
+    // Only the signature and header are read
+    assert_eq!(png_dimensions(&file[..33]).unwrap(), (256, 256));
+    assert!(matches!(png_dimensions(&file[..32]), Err(Error::Io(_))));
+    assert!(matches!(
+        png_dimensions(&b"GIF89a.."[..]),
+        Err(Error::InvalidSignature)
+    ));
+}
+
+#[test]
+fn unparsed_text() {
+    let file = std::fs::read("tests/png/profile.png").unwrap();
+    let decode = |parse_text| {
+        let step = Decoder::new(Cursor::new(&file))
+            .unwrap()
+            .parse_text(parse_text)
+            .into_steps()
+            .next()
+            .unwrap()
+            .unwrap();
+        let raster: Raster<pix::rgb::SRgba8> = step.raster.into();
+
+        raster.as_u8_slice().to_vec()
+    };
+    assert_eq!(decode(false), decode(true));
+
+    // The iCCP chunk is never parsed, but the iTXt chunk is only left
+    // unparsed with parse_text(false)
+    let unknown = |parse_text| {
+        Decoder::new(Cursor::new(&file))
+            .unwrap()
+            .parse_text(parse_text)
+            .into_chunks()
+            .filter_map(|chunk| match chunk.unwrap() {
+                Chunk::Unknown(unknown) => Some(unknown.name),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(unknown(false), [*b"iCCP", *b"iTXt"]);
+    assert_eq!(unknown(true), [*b"iCCP"]);
+
+    let file = png_with_text_chunks(1);
+    let chunk = Decoder::new(Cursor::new(file))
+        .unwrap()
+        .parse_text(false)
+        .into_chunks()
+        .nth(1)
+        .unwrap()
+        .unwrap();
+    let Chunk::Unknown(unknown) = chunk else {
+        panic!("Text chunk was parsed");
+    };
+    assert_eq!(
+        Text::parse_raw(&unknown.data).unwrap(),
+        Text::new("k", "").unwrap()
+    );
+}
+
+#[test]
+fn unparsed_text_after_image_data() {
+    let raster = Raster::<SGray8>::with_clear(2, 2);
+    let mut file = Vec::new();
+    Encoder::new(&mut file)
+        .into_step_enc()
+        .still(&raster)
+        .unwrap();
+
+    let mut text = Vec::new();
+    let mut encoder = Encoder::new(&mut text).into_chunk_enc();
+    encoder
+        .encode(&mut Chunk::Text(Text::new("Comment", "after").unwrap()))
+        .unwrap();
+    encoder
+        .encode(&mut Chunk::CompressedText(CompressedText {
+            key: "Comment".to_string(),
+            val: "compressed".to_string(),
+        }))
+        .unwrap();
+
+    // Insert before IEND (12 bytes)
+    let tail = file.split_off(file.len() - 12);
+    file.extend(text);
+    file.extend(tail);
+
+    for parse_text in [true, false] {
+        let mut steps = Decoder::new(Cursor::new(&file))
+            .unwrap()
+            .parse_text(parse_text)
+            .into_steps()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(steps.len(), 1);
+        let raster: Raster<SGray8> = steps.pop().unwrap().raster.into();
+        assert_eq!(raster.as_u8_slice(), [0; 4]);
+    }
+}
+
+#[test]
+fn color_type_and_header() {
+    let file = std::fs::read("tests/png/icon.png").unwrap();
+
+    assert_eq!(png_color_type(&file[..33]).unwrap(), (ColorType::Rgba, 8));
+
+    let header = png_header(&file[..33]).unwrap();
+    assert_eq!((header.width, header.height), (256, 256));
+    assert_eq!((header.color_type, header.bit_depth), (ColorType::Rgba, 8));
+    assert!(!header.interlace);
+}
+
+#[test]
+fn scan_pngsuite() {
+    for dir in std::fs::read_dir("tests").unwrap() {
+        let dir = dir.unwrap().path();
+        if !dir.to_str().unwrap().contains("pngsuite-") {
+            continue;
+        }
+        for file in std::fs::read_dir(dir).unwrap() {
+            let file = std::fs::read(file.unwrap().path()).unwrap();
+            let Ok(Ok(chunks)) = Decoder::new(file.as_slice()).map(|decoder| {
+                decoder.into_chunks().collect::<Result<Vec<_>, _>>()
+            }) else {
+                // Broken files are covered by other tests
+                continue;
+            };
+            let summary = png_pong::scan(file.as_slice()).unwrap();
+
+            let mut keywords = Vec::new();
+            for chunk in chunks {
+                match chunk {
+                    Chunk::ImageHeader(header) => {
+                        assert_eq!(summary.width, header.width);
+                        assert_eq!(summary.height, header.height);
+                        assert_eq!(summary.color_type, header.color_type);
+                        assert_eq!(summary.bit_depth, header.bit_depth);
+                        assert_eq!(summary.interlace, header.interlace);
+                    }
+                    Chunk::Physical(physical) => {
+                        let found = summary.physical.unwrap();
+                        assert_eq!(found.ppu_x, physical.ppu_x);
+                        assert_eq!(found.ppu_y, physical.ppu_y);
+                        assert_eq!(found.is_meter, physical.is_meter);
+                    }
+                    Chunk::Time(time) => {
+                        assert_eq!(summary.time.unwrap().year, time.year);
+                    }
+                    Chunk::Text(text) => keywords.push(text.key().to_string()),
+                    Chunk::CompressedText(text) => keywords.push(text.key),
+                    Chunk::InternationalText(text) => keywords.push(text.key),
+                    Chunk::Unknown(Unknown { name, data }) => match &name {
+                        b"gAMA" => assert_eq!(
+                            summary.gamma,
+                            Some(u32::from_be_bytes(
+                                data[..].try_into().unwrap()
+                            )),
+                        ),
+                        b"sRGB" => {
+                            assert_eq!(summary.srgb_intent, Some(data[0]))
+                        }
+                        _ => {}
+                    },
+                    _ => {}
+                }
+            }
+            assert_eq!(summary.keywords, keywords);
+        }
+    }
+}
+
+#[test]
+fn scan_metadata() {
+    let mut file = generate_test_image(ColorType::Rgb, 8, 64, 32);
+    let mut chunks = Vec::new();
+    let mut encoder = Encoder::new(&mut chunks).into_chunk_enc();
+    let metadata = [
+        Chunk::Unknown(Unknown {
+            name: *b"acTL",
+            data: vec![0, 0, 0, 3, 0, 0, 0, 0],
+        }),
+        Chunk::Unknown(Unknown {
+            name: *b"gAMA",
+            data: 45_455u32.to_be_bytes().to_vec(),
+        }),
+        Chunk::Unknown(Unknown {
+            name: *b"sRGB",
+            data: vec![0],
+        }),
+        Chunk::Physical(Physical {
+            ppu_x: 3780,
+            ppu_y: 7559,
+            is_meter: true,
+        }),
+        Chunk::Time(Time {
+            year: 2024,
+            month: 2,
+            day: 29,
+            hour: 12,
+            minute: 30,
+            second: 0,
+        }),
+        Chunk::Text(Text::new("Title", "Test").unwrap()),
+        Chunk::CompressedText(CompressedText {
+            key: "Description".to_string(),
+            val: "A test image".repeat(100),
+        }),
+        Chunk::InternationalText(InternationalText {
+            key: "Author".to_string(),
+            langtag: "en".to_string(),
+            transkey: "Author".to_string(),
+            val: "Nobody".to_string(),
+            compressed: true,
+        }),
+    ];
+    for mut chunk in metadata {
+        encoder.encode(&mut chunk).unwrap();
+    }
+    // Insert after the signature and header chunk
+    let tail = file.split_off(33);
+    file.extend(chunks);
+    file.extend(tail);
+
+    let summary = png_pong::scan(file.as_slice()).unwrap();
+
+    assert_eq!((summary.width, summary.height), (64, 32));
+    assert_eq!((summary.color_type, summary.bit_depth), (ColorType::Rgb, 8));
+    assert!(!summary.interlace);
+    assert!(summary.is_animation());
+    assert_eq!(summary.frames, Some(3));
+    let (x, y) = summary.dpi().unwrap();
+    assert_eq!((x.round(), y.round()), (96.0, 192.0));
+    assert_eq!(summary.gamma, Some(45_455));
+    assert_eq!(summary.srgb_intent, Some(0));
+    let time = summary.time.unwrap();
+    assert_eq!((time.year, time.month, time.day), (2024, 2, 29));
+    assert_eq!(summary.keywords, ["Title", "Description", "Author"]);
+
+    let summary = png_pong::scan(
+        generate_test_image(ColorType::Grey, 8, 4, 4).as_slice(),
+    )
+    .unwrap();
+    assert!(!summary.is_animation());
+    assert!(summary.physical.is_none() && summary.dpi().is_none());
+    assert!(summary.time.is_none() && summary.keywords.is_empty());
+}
+
+#[test]
+fn decoder_error_into_io_error() {
+    let io_error: std::io::Error = Error::InvalidSignature.into();
+
+    assert_eq!(io_error.kind(), std::io::ErrorKind::InvalidData);
+    assert!(matches!(
+        io_error.get_ref().unwrap().downcast_ref::<Error>(),
+        Some(Error::InvalidSignature)
+    ));
+}
+
+#[test]
+fn image_end_with_length() {
+    let raster = Raster::<SGray8>::with_clear(2, 2);
+    let mut file = Vec::new();
+    Encoder::new(&mut file)
+        .into_step_enc()
+        .still(&raster)
+        .unwrap();
+    // Replace IEND with one written by a broken encoder
+    file.truncate(file.len() - 12);
+    let end = Chunk::Unknown(Unknown {
+        name: *b"IEND",
+        data: vec![0, 0, 0, 0],
+    });
+    file.extend(end.to_bytes().unwrap());
+
+    let mut chunks = Decoder::new(Cursor::new(&file)).unwrap().into_chunks();
+    let last = chunks.by_ref().last().unwrap().unwrap();
+    assert!(matches!(last, Chunk::ImageEnd(_)));
+    assert_eq!(chunks.warnings(), [ChunkWarning::ImageEndLength(4)]);
+
+    let decoded = Decoder::new(Cursor::new(&file))
+        .unwrap()
+        .into_steps()
+        .last()
+        .unwrap()
+        .unwrap();
+    let PngRaster::Gray8(decoded) = decoded.raster else {
+        panic!("not gray");
+    };
+    assert_eq!(decoded.pixels(), raster.pixels());
+}
+
+#[test]
+fn low_bit_depths() {
+    let decode = |path: &str| {
+        let file = std::fs::read(path).unwrap();
+        Decoder::new(file.as_slice())
+            .unwrap()
+            .into_steps()
+            .next()
+            .unwrap()
+            .unwrap()
+            .raster
+    };
+
+    for depth in ["01", "02", "04"] {
+        let grey = decode(&format!("tests/pngsuite-basic/basn0g{depth}.png"));
+        let interlaced =
+            decode(&format!("tests/pngsuite-interlaced/basi0g{depth}.png"));
+        assert_eq!(grey, interlaced);
+        let PngRaster::Gray8(grey) = grey else {
+            panic!("not greyscale");
+        };
+        // Scaled up to 8 bits by bit replication
+        let factor = 255 / ((1 << depth.parse::<u8>().unwrap()) - 1);
+        assert!(grey.as_u8_slice().iter().all(|v| v % factor == 0));
+
+        let palette =
+            decode(&format!("tests/pngsuite-basic/basn3p{depth}.png"));
+        let interlaced =
+            decode(&format!("tests/pngsuite-interlaced/basi3p{depth}.png"));
+        assert_eq!(palette, interlaced);
+        let PngRaster::Palette(indices, palette, _) = palette else {
+            panic!("not a palette image");
+        };
+        assert!(indices
+            .as_u8_slice()
+            .iter()
+            .all(|&i| usize::from(i) < palette.len()));
+    }
+}
+
+#[test]
+fn chunks_into_vec() {
+    let file = png_with_text_chunks(3);
+    let chunks = Decoder::new(Cursor::new(&file))
+        .unwrap()
+        .into_chunks()
+        .into_vec()
+        .unwrap();
+    let names: Vec<_> = chunks
+        .iter()
+        .map(|chunk| match chunk {
+            Chunk::ImageHeader(_) => *b"IHDR",
+            Chunk::Text(_) => *b"tEXt",
+            Chunk::ImageData(_) => *b"IDAT",
+            Chunk::ImageEnd(_) => *b"IEND",
+            _ => *b"????",
+        })
+        .collect();
+    assert_eq!(names, png_chunk_names(file.as_slice()).unwrap());
+
+    // Stops at the first error
+    let mut file = file;
+    file[41] ^= 0xFF;
+    let result = Decoder::new(Cursor::new(file))
+        .unwrap()
+        .into_chunks()
+        .into_vec();
+    assert!(matches!(result, Err(Error::Crc32 { .. })));
+}
+
+#[test]
+fn chunks_into_count() {
+    let file = png_with_text_chunks(3);
+    let count = Decoder::new(Cursor::new(&file))
+        .unwrap()
+        .into_chunks()
+        .into_count()
+        .unwrap();
+    assert_eq!(count, png_chunk_names(file.as_slice()).unwrap().len());
+
+    // Counts what's left
+    let mut chunks = Decoder::new(Cursor::new(&file)).unwrap().into_chunks();
+    chunks.next().unwrap().unwrap();
+    assert_eq!(chunks.into_count().unwrap(), count - 1);
+
+    let mut file = file;
+    file[41] ^= 0xFF;
+    let result = Decoder::new(Cursor::new(file))
+        .unwrap()
+        .into_chunks()
+        .into_count();
+    assert!(matches!(result, Err(Error::Crc32 { .. })));
+}
+
+#[test]
+fn decode_planes() {
+    use PlaneChannel::*;
+
+    let formats = [
+        (ColorType::Grey, 2, vec![Grey]),
+        (ColorType::Grey, 8, vec![Grey]),
+        (ColorType::Grey, 16, vec![Grey]),
+        (ColorType::GreyAlpha, 8, vec![Grey, Alpha]),
+        (ColorType::Rgb, 8, vec![Red, Green, Blue]),
+        (ColorType::Rgb, 16, vec![Red, Green, Blue]),
+        (ColorType::Rgba, 8, vec![Red, Green, Blue, Alpha]),
+        (ColorType::Rgba, 16, vec![Red, Green, Blue, Alpha]),
+        (ColorType::Palette, 4, vec![Index]),
+    ];
+    for (color_type, bit_depth, channels) in formats {
+        let file = generate_test_image(color_type, bit_depth, 7, 5);
+        let header = png_header(file.as_slice()).unwrap();
+        let layout = PlaneLayout::new(&header);
+        assert_eq!(layout.channels, channels);
+
+        let mut planes = vec![vec![0; layout.plane_size()]; channels.len()];
+        let mut slices: Vec<&mut [u8]> =
+            planes.iter_mut().map(Vec::as_mut_slice).collect();
+        let decoded = decode_planes_into(file.as_slice(), &mut slices).unwrap();
+        assert_eq!(decoded, layout);
+
+        let step = Decoder::new(file.as_slice())
+            .unwrap()
+            .into_steps()
+            .next()
+            .unwrap()
+            .unwrap();
+        let interleaved = match step.raster {
+            PngRaster::Gray8(r) => r.as_u8_slice().to_vec(),
+            PngRaster::Gray16(r) => r.as_u8_slice().to_vec(),
+            PngRaster::Graya8(r) => r.as_u8_slice().to_vec(),
+            PngRaster::Rgb8(r) => r.as_u8_slice().to_vec(),
+            PngRaster::Rgb16(r) => r.as_u8_slice().to_vec(),
+            PngRaster::Rgba8(r) => r.as_u8_slice().to_vec(),
+            PngRaster::Rgba16(r) => r.as_u8_slice().to_vec(),
+            PngRaster::Palette(r, _, _) => r.as_u8_slice().to_vec(),
+            _ => unreachable!(),
+        };
+        let size = layout.sample_bytes;
+        for (i, pixel) in interleaved.chunks(channels.len() * size).enumerate()
+        {
+            for (plane, sample) in planes.iter().zip(pixel.chunks(size)) {
+                assert_eq!(&plane[i * size..][..size], sample);
+            }
+        }
+    }
+}
+
+#[test]
+fn chunk_as_image_header() {
+    let file = png_with_text_chunks(1);
+    let chunks = Decoder::new(Cursor::new(file))
+        .unwrap()
+        .into_chunks()
+        .into_vec()
+        .unwrap();
+
+    let header = chunks[0].as_image_header().unwrap();
+    assert_eq!((header.width, header.height), (1, 1));
+    assert_eq!(header.color_type, ColorType::Grey);
+    assert!(chunks[1..].iter().all(|c| c.as_image_header().is_none()));
+}
+
+#[test]
+fn sniff_signature() {
+    use png_pong::{is_png, sniff, SIGNATURE};
+
+    assert!(!is_png(&[]));
+    assert!(!is_png(&SIGNATURE[..7]));
+    assert!(is_png(&SIGNATURE));
+    assert!(is_png(&png_with_text_chunks(1)));
+    // MNG and JPEG
+    assert!(!is_png(&[138, 77, 78, 71, 13, 10, 26, 10]));
+    assert!(!is_png(&[0xFF, 0xD8, 0xFF, 0xE0, 0, 16, b'J', b'F']));
+
+    let file = png_with_text_chunks(1);
+    let mut reader = Cursor::new(&file);
+    assert!(sniff(&mut reader).unwrap());
+    assert_eq!(reader.position(), 8);
+    let mut reader =
+        Cursor::new(&[0xFF, 0xD8, 0xFF, 0xE0, 0, 16, b'J', b'F', 0]);
+    assert!(!sniff(&mut reader).unwrap());
+    assert_eq!(reader.position(), 8);
+    assert!(!sniff(&mut Cursor::new(&SIGNATURE[..4])).unwrap());
+}
+
+#[test]
+fn chunk_as_text() {
+    let chunks = Decoder::new(Cursor::new(png_with_text_chunks(2)))
+        .unwrap()
+        .into_chunks()
+        .into_vec()
+        .unwrap();
+
+    let texts: Vec<_> = chunks.iter().filter_map(Chunk::as_text).collect();
+    assert_eq!(texts.len(), 2);
+    assert!(chunks[0].as_text().is_none());
+    assert!(chunks.iter().all(|c| c.as_compressed_text().is_none()
+        && c.as_international_text().is_none()));
+}
+
+#[test]
+fn decode_at_offset() {
+    let png = png_with_text_chunks(1);
+    let mut archive = vec![0xAA; 100];
+    archive.extend(&png);
+    archive.extend([0x55; 50]);
+
+    let mut steps = Decoder::new_at_offset(Cursor::new(&archive), 100)
+        .unwrap()
+        .into_steps();
+    let step = steps.next().unwrap().unwrap();
+    let expected = Decoder::new(Cursor::new(&png))
+        .unwrap()
+        .into_steps()
+        .next()
+        .unwrap()
+        .unwrap();
+    assert_eq!(step, expected);
+
+    assert!(matches!(
+        Decoder::new_at_offset(Cursor::new(&archive), 99),
+        Err(Error::InvalidSignature)
+    ));
+    assert!(Decoder::new_at_offset(Cursor::new(&archive), 1000).is_err());
+}
+
+#[test]
+fn crc_policy() {
+    let file = png_with_text_chunks(1);
+    // Signature, IHDR and a tEXt chunk with 2 bytes of data come first
+    assert_eq!(&file[51..55], b"IDAT");
+    let idat_len = u32::from_be_bytes(file[47..51].try_into().unwrap());
+    let mut bad_idat = file.clone();
+    bad_idat[55 + idat_len as usize] ^= 1;
+    let mut bad_text = file.clone();
+    bad_text[43] ^= 1;
+
+    let decode = |file: &[u8], policy| {
+        Decoder::new(file)
+            .unwrap()
+            .verify_crc(policy)
+            .into_steps()
+            .next()
+            .unwrap()
+    };
+    assert!(decode(&file, CrcPolicy::All).is_ok());
+    assert!(matches!(
+        decode(&bad_idat, CrcPolicy::All),
+        Err(Error::Crc32 { chunk, .. }) if chunk == *b"IDAT"
+    ));
+    assert!(decode(&bad_idat, CrcPolicy::AncillaryOnly).is_ok());
+    assert!(decode(&bad_idat, CrcPolicy::None).is_ok());
+    assert!(matches!(
+        decode(&bad_text, CrcPolicy::AncillaryOnly),
+        Err(Error::Crc32 { chunk, .. }) if chunk == *b"tEXt"
+    ));
+    assert!(decode(&bad_text, CrcPolicy::None).is_ok());
+}
+
+#[test]
+fn decode_all_steps() {
+    let file = png_with_text_chunks(1);
+    let steps = png_pong::decode_steps(file.as_slice()).unwrap();
+    assert_eq!(steps.len(), 1);
+    assert_eq!(
+        steps[0],
+        Decoder::new(file.as_slice())
+            .unwrap()
+            .into_steps()
+            .next()
+            .unwrap()
+            .unwrap(),
+    );
+
+    assert!(matches!(
+        png_pong::decode_steps(&file[1..]),
+        Err(Error::InvalidSignature)
+    ));
+    let mut corrupt = file.clone();
+    corrupt[43] ^= 1;
+    assert!(matches!(
+        png_pong::decode_steps(corrupt.as_slice()),
+        Err(Error::Crc32 { .. })
+    ));
+}
+
+#[test]
+fn nth_step() {
+    let mut file = png_pong::SIGNATURE.to_vec();
+    let mut encoder = Encoder::new(&mut file).into_chunk_enc();
+    let header = ImageHeader {
+        width: 2,
+        height: 2,
+        color_type: ColorType::Grey,
+        bit_depth: 8,
+        interlace: false,
+    };
+    encoder.encode(&mut Chunk::ImageHeader(header)).unwrap();
+    for i in 0..400u16 {
+        let [a, b] = i.to_le_bytes();
+        let data = vec![0, a, b, 0, b, a];
+        encoder
+            .encode(&mut Chunk::ImageData(ImageData::with_data(data)))
+            .unwrap();
+        let mut chunk = Chunk::Text(Text::new("Frame", i.to_string()).unwrap());
+        encoder.encode(&mut chunk).unwrap();
+    }
+    encoder.encode(&mut Chunk::ImageEnd(ImageEnd)).unwrap();
+
+    let steps = png_pong::decode_steps(file.as_slice()).unwrap();
+    assert_eq!(steps.len(), 400);
+    let mut seek = Decoder::new(file.as_slice()).unwrap().into_steps();
+    assert_eq!(seek.nth(300).unwrap().unwrap(), steps[300]);
+    assert_eq!(seek.nth(0).unwrap().unwrap(), steps[301]);
+    assert_eq!(seek.nth(97).unwrap().unwrap(), steps[399]);
+    assert!(seek.nth(0).is_none());
+    assert!(Decoder::new(file.as_slice())
+        .unwrap()
+        .into_steps()
+        .nth(400)
+        .is_none());
+}
+
+#[test]
+fn compression_ratio() {
+    let encode = |raster: &Raster<SRgb8>| {
+        let mut file = Vec::new();
+        Encoder::new(&mut file)
+            .into_step_enc()
+            .still(raster)
+            .unwrap();
+        file
+    };
+    let clear = encode(&Raster::with_clear(64, 64));
+    let mut state = 2_463_534_242u32;
+    let pixels: Vec<_> = (0..64 * 64)
+        .map(|_| {
+            // xorshift32
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let [r, g, b, _] = state.to_le_bytes();
+            SRgb8::new(r, g, b)
+        })
+        .collect();
+    let noise = encode(&Raster::with_pixels(64, 64, pixels));
+
+    assert!(measure_compression_ratio(&clear).unwrap() > 10.0);
+    let ratio = measure_compression_ratio(&noise).unwrap();
+    assert!(ratio > 0.5 && ratio < 2.0, "{ratio}");
+    assert!(matches!(
+        measure_compression_ratio(&clear[1..]),
+        Err(Error::InvalidSignature)
+    ));
+}
+
+#[test]
+fn linear_f32_premultiplied() {
+    use pix::{
+        el::Pixel,
+        gray::SGray16,
+        rgb::{SRgb8, SRgba8},
+    };
+    use png_pong::encode::{rewrite, ChunkSelector, Edit};
+
+    let encode = |raster: &PngRaster| {
+        let mut file = Vec::new();
+        Encoder::new(&mut file)
+            .into_step_enc()
+            .still(raster)
+            .unwrap();
+        file
+    };
+    // 16-bit rasters are stored big-endian, as in the file
+    let words = |samples: Vec<u16>| -> Vec<u16> {
+        samples
+            .into_iter()
+            .map(|v| u16::from_ne_bytes(v.to_be_bytes()))
+            .collect()
+    };
+    let ramp16 = |i: u16| i * 256;
+    let rgba16 = |i: u16| {
+        [
+            ramp16(i),
+            u16::MAX - ramp16(i),
+            ramp16(i) / 2,
+            ramp16(i) + 255,
+        ]
+    };
+    let insert = |file: &[u8], chunks: &[(&[u8; 4], &[u8])]| {
+        let edits: Vec<_> = chunks
+            .iter()
+            .map(|(name, data)| {
+                Edit::Insert(
+                    ChunkSelector::Name(*b"IHDR"),
+                    Chunk::Unknown(Unknown {
+                        name: **name,
+                        data: data.to_vec(),
+                    }),
+                )
+            })
+            .collect();
+        let mut output = Vec::new();
+        rewrite(file, &mut output, &edits).unwrap();
+        output
+    };
+    let decode = |file: &[u8]| {
+        let mut rasters = Decoder::new(file)
+            .unwrap()
+            .into_steps()
+            .into_linear_f32_premultiplied();
+        let raster = rasters.next().unwrap().unwrap();
+        assert!(rasters.next().is_none());
+        raster
+    };
+    let srgb = |v: f64| {
+        if v <= 0.04045 {
+            v / 12.92
+        } else {
+            ((v + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    // Check against the (not yet linear) RGBA samples of each pixel, between
+    // 0 and 1
+    let check = |file: &[u8],
+                 linear: &dyn Fn(f64) -> f64,
+                 samples: &dyn Fn(u16) -> [f64; 4]| {
+        let raster = decode(file);
+        assert_eq!((raster.width(), raster.height()), (256, 1));
+        for (i, p) in (0..=255u16).zip(raster.pixels()) {
+            let [r, g, b, a] = samples(i);
+            let expected = [linear(r) * a, linear(g) * a, linear(b) * a, a];
+            let found = [p.one(), p.two(), p.three(), p.four()]
+                .map(|c| f64::from(f32::from(c)));
+            for (e, f) in expected.iter().zip(found) {
+                assert!((e - f).abs() < 1e-3, "{i}: {expected:?} {found:?}");
+            }
+        }
+    };
+    let rgba8 = |i: u16| {
+        let i = f64::from(i);
+        [i, 255.0 - i, (i / 2.0).floor(), i].map(|v| v / 255.0)
+    };
+
+    let pixels: Vec<_> = (0..=255u8)
+        .map(|i| SRgba8::new(i, 255 - i, i / 2, i))
+        .collect();
+    let file = encode(&PngRaster::Rgba8(Raster::with_pixels(256, 1, pixels)));
+    // No color space chunks means sRGB
+    check(&file, &srgb, &rgba8);
+    // Gamma of 1.0
+    let gamma = 100_000u32.to_be_bytes();
+    check(&insert(&file, &[(b"gAMA", &gamma)]), &|v| v, &rgba8);
+    // Gamma of 1 / 2.2
+    let gamma = 45_455u32.to_be_bytes();
+    check(
+        &insert(&file, &[(b"gAMA", &gamma)]),
+        &|v| v.powf(2.2),
+        &rgba8,
+    );
+    // sRGB takes precedence over gAMA
+    check(
+        &insert(&file, &[(b"gAMA", &gamma), (b"sRGB", &[0])]),
+        &srgb,
+        &rgba8,
+    );
+
+    // 16-bit RGBA
+    let samples = (0..=255u16).flat_map(rgba16).collect();
+    let raster = Raster::with_u16_buffer(256, 1, words(samples));
+    check(&encode(&PngRaster::Rgba16(raster)), &srgb, &|i| {
+        rgba16(i).map(|v| f64::from(v) / 65535.0)
+    });
+
+    // Greyscale
+    let ramp8: Vec<u8> = (0..=255).collect();
+    let raster = Raster::with_u8_buffer(256, 1, ramp8.clone());
+    check(&encode(&PngRaster::Gray8(raster)), &srgb, &|i| {
+        let v = f64::from(i) / 255.0;
+        [v, v, v, 1.0]
+    });
+
+    // 16-bit greyscale
+    let raster: Raster<SGray16> =
+        Raster::with_u16_buffer(256, 1, words((0..=255).map(ramp16).collect()));
+    check(&encode(&PngRaster::Gray16(raster)), &srgb, &|i| {
+        let v = f64::from(ramp16(i)) / 65535.0;
+        [v, v, v, 1.0]
+    });
+
+    // Greyscale with alpha
+    let raster = Raster::with_u8_buffer(
+        256,
+        1,
+        (0..=255u8).flat_map(|i| [i, 255 - i]).collect::<Vec<_>>(),
+    );
+    check(&encode(&PngRaster::Graya8(raster)), &srgb, &|i| {
+        let v = f64::from(i) / 255.0;
+        [v, v, v, 1.0 - v]
+    });
+
+    // 16-bit greyscale with alpha
+    let samples = (0..=255u16)
+        .flat_map(|i| [ramp16(i), u16::MAX - ramp16(i)])
+        .collect();
+    let raster = Raster::with_u16_buffer(256, 1, words(samples));
+    check(&encode(&PngRaster::Graya16(raster)), &srgb, &|i| {
+        let v = f64::from(ramp16(i)) / 65535.0;
+        [v, v, v, f64::from(u16::MAX - ramp16(i)) / 65535.0]
+    });
+
+    // Palette with transparency, which is shorter than the palette
+    let mut palette = Palette::new(256);
+    for i in 0..=255u8 {
+        palette.set_entry(SRgb8::new(i, 255 - i, i / 2)).unwrap();
+    }
+    let raster = PngRaster::Palette(
+        Raster::<Gray8>::with_u8_buffer(256, 1, ramp8),
+        Box::new(palette),
+        (0..128u8).map(|i| i * 2).collect(),
+    );
+    check(&encode(&raster), &srgb, &|i| {
+        let [r, g, b, _] = rgba8(i);
+        let a = if i < 128 {
+            f64::from(i * 2) / 255.0
+        } else {
+            1.0
+        };
+        [r, g, b, a]
+    });
+}
+
+#[test]
+fn read_png() {
+    // Bits per pixel of each format
+    let formats = [
+        (ColorType::Grey, 1, 1),
+        (ColorType::Grey, 4, 4),
+        (ColorType::Grey, 16, 16),
+        (ColorType::Rgb, 8, 24),
+        (ColorType::Rgba, 16, 64),
+    ];
+    for (color_type, bit_depth, bpp) in formats {
+        for interlace in [false, true] {
+            let header = ImageHeader {
+                width: 5,
+                height: 3,
+                color_type,
+                bit_depth,
+                interlace,
+            };
+            let scanline = header.scanline_bytes().unwrap();
+            // Padding bits are zero
+            let padding = scanline * 8 - 5 * bpp;
+            let rows: Vec<Vec<u8>> = (0..3u8)
+                .map(|y| {
+                    let mut row: Vec<u8> = (0..scanline as u8)
+                        .map(|x| x.wrapping_mul(97) ^ y.wrapping_mul(31))
+                        .collect();
+                    row[scanline - 1] &= 0xFF << padding;
+                    row
+                })
+                .collect();
+            let slices: Vec<&[u8]> = rows.iter().map(Vec::as_slice).collect();
+            let mut png = Vec::new();
+            png_pong::write_png(&mut png, header, &slices).unwrap();
+
+            let (decoded, decoded_rows) =
+                png_pong::read_png(png.as_slice()).unwrap();
+            assert_eq!(decoded, header);
+            assert_eq!(decoded_rows, rows);
+        }
+    }
+}
+
+#[test]
+fn decode_rows() {
+    let names = ["0g01", "0g04", "0g16", "2c08", "3p02", "4a16", "6a08"];
+    for name in names {
+        for path in [
+            format!("tests/pngsuite-basic/basn{name}.png"),
+            format!("tests/pngsuite-interlaced/basi{name}.png"),
+        ] {
+            let file = std::fs::read(&path).unwrap();
+            let (header, expected) =
+                png_pong::read_png(file.as_slice()).unwrap();
+
+            let rows =
+                Decoder::new(file.as_slice()).unwrap().into_rows().unwrap();
+            assert_eq!(rows.header(), &header, "{path}");
+            assert_eq!(rows.is_streaming(), !header.interlace, "{path}");
+            let rows: Vec<Row> = rows.collect::<Result<_, _>>().unwrap();
+            for (y, row) in rows.iter().enumerate() {
+                assert_eq!((row.header, row.y), (header, y as u32), "{path}");
+            }
+            let rows: Vec<Vec<u8>> =
+                rows.into_iter().map(|row| row.data).collect();
+            assert_eq!(rows, expected, "{path}");
+        }
+    }
+
+    // Image data split into an IDAT chunk per byte
+    let file = std::fs::read("tests/pngsuite-basic/basn2c08.png").unwrap();
+    let idat = Decoder::new(file.as_slice())
+        .unwrap()
+        .into_raw_idat_bytes()
+        .unwrap();
+    let mut split = file[..33].to_vec();
+    let mut encoder = Encoder::new(&mut split).into_chunk_enc();
+    for &byte in &idat {
+        encoder.write_chunk(*b"IDAT", &[byte]).unwrap();
+    }
+    encoder.encode(&mut Chunk::ImageEnd(ImageEnd)).unwrap();
+    let rows: Vec<Vec<u8>> = Decoder::new(split.as_slice())
+        .unwrap()
+        .into_rows()
+        .unwrap()
+        .map(|row| row.unwrap().data)
+        .collect();
+    assert_eq!(rows, png_pong::read_png(file.as_slice()).unwrap().1);
+
+    // A wrong checksum is an error by the end of the rows (once the stream
+    // has been read to the end, which may be before the last row)
+    let file = std::fs::read("tests/png/bad/badadler.png").unwrap();
+    let rows: Vec<_> = Decoder::new(file.as_slice())
+        .unwrap()
+        .into_rows()
+        .unwrap()
+        .collect();
+    let (last, rows) = rows.split_last().unwrap();
+    assert!(rows.iter().all(Result::is_ok));
+    assert!(matches!(last, Err(Error::AdlerChecksum { .. })));
+}
+
+#[test]
+fn validation_report() {
+    use png_pong::decode::Severity;
+
+    let report =
+        png_pong::validate(std::fs::File::open("tests/png/icon.png").unwrap());
+    assert!(report.is_valid());
+    assert!(report.violations.is_empty());
+    assert!(report.decodable);
+
+    // Codes found in each file, whether the file is valid (has only
+    // warnings), and whether the image data decodes
+    let expected: &[(&str, &[&str], bool, bool)] = &[
+        ("crc_mismatch.png", &["crc_mismatch"], false, true),
+        ("duplicate_chunk.png", &["duplicate_chunk"], false, true),
+        ("filter_type.png", &["filter_type"], false, false),
+        ("frame_sequence.png", &["frame_sequence"], false, true),
+        ("gamma_after_palette.png", &["chunk_order"], false, true),
+        ("image_end_length.png", &["image_end_length"], true, true),
+        ("missing_end.png", &["unexpected_eof"], false, true),
+        (
+            "physical_after_image_data.png",
+            &["chunk_order"],
+            false,
+            true,
+        ),
+        (
+            "several.png",
+            &["duplicate_chunk", "crc_mismatch", "unexpected_eof"],
+            false,
+            true,
+        ),
+        ("split_image_data.png", &["chunk_order"], false, true),
+        ("trailing_chunk.png", &["trailing_chunk"], true, true),
+    ];
+    let mut files: Vec<_> = std::fs::read_dir("tests/png/invalid")
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.ends_with(".png"))
+        .collect();
+    files.sort();
+    let names: Vec<_> = expected.iter().map(|(name, ..)| *name).collect();
+    assert_eq!(files, names);
+    for &(name, codes, valid, decodable) in expected {
+        let path = format!("tests/png/invalid/{name}");
+        let report = png_pong::validate(std::fs::File::open(path).unwrap());
+        assert_eq!(report.codes(), codes, "{name}");
+        assert_eq!(report.is_valid(), valid, "{name}");
+        assert_eq!(report.decodable, decodable, "{name}");
+    }
+
+    // Missing image data isn't reported again when its only IDAT chunk is
+    let file = std::fs::File::open("tests/png/bad/badcrc.png").unwrap();
+    let report = png_pong::validate(file);
+    assert_eq!(report.codes(), ["crc_mismatch"]);
+    assert!(!report.decodable);
+
+    // Where each problem is
+    let file = std::fs::File::open("tests/png/invalid/several.png").unwrap();
+    let violations = png_pong::validate(file).violations;
+    // After the signature, IHDR chunk and first pHYs chunk
+    let second_phys = 8 + 25 + 21;
+    assert_eq!(violations[0].chunk, Some(*b"pHYs"));
+    assert_eq!(violations[0].offset, Some(second_phys));
+    assert_eq!(violations[1].chunk, Some(*b"tEXt"));
+    assert_eq!(violations[1].offset, Some(second_phys + 21));
+    assert_eq!(violations[1].severity, Severity::Error);
+    assert_eq!(violations[2].chunk, None);
+    assert_eq!(violations[2].offset, None);
+    let file =
+        std::fs::File::open("tests/png/invalid/trailing_chunk.png").unwrap();
+    let violations = png_pong::validate(file).violations;
+    assert_eq!(violations[0].severity, Severity::Warning);
+    assert_eq!(violations[0].chunk, Some(*b"tEXt"));
+    assert_eq!(violations[0].offset, Some(8 + 25 + 22 + 12));
+}
+
+#[test]
+fn interlaced_same_as_progressive() {
+    let names = [
+        "0g01", "0g02", "0g04", "0g08", "0g16", "2c08", "2c16", "3p01", "3p08",
+        "4a08", "4a16", "6a08", "6a16",
+    ];
+    for name in names {
+        let progressive =
+            std::fs::read(format!("tests/pngsuite-basic/basn{name}.png"))
+                .unwrap();
+        let interlaced =
+            std::fs::read(format!("tests/pngsuite-interlaced/basi{name}.png"))
+                .unwrap();
+
+        let (header, rows) =
+            png_pong::read_png(progressive.as_slice()).unwrap();
+        let (interlaced_header, interlaced_rows) =
+            png_pong::read_png(interlaced.as_slice()).unwrap();
+        assert!(!header.interlace && interlaced_header.interlace, "{name}");
+        assert_eq!(rows, interlaced_rows, "{name}");
+
+        let layout = PlaneLayout::new(&header);
+        let planes = |file: &[u8]| {
+            let mut planes =
+                vec![vec![0; layout.plane_size()]; layout.channels.len()];
+            let mut slices: Vec<&mut [u8]> =
+                planes.iter_mut().map(Vec::as_mut_slice).collect();
+            decode_planes_into(file, &mut slices).unwrap();
+            planes
+        };
+        assert_eq!(planes(&progressive), planes(&interlaced), "{name}");
+    }
+}
+
+#[test]
+fn bounded_histogram_and_suggested_palette() {
+    let chunks = |path: &str, limits: Limits| {
+        let file = std::fs::read(path).unwrap();
+        Decoder::new(Cursor::new(file))
+            .unwrap()
+            .limits(limits)
+            .into_chunks()
+            .take(4)
+            .collect::<Vec<_>>()
+    };
+
+    // Declared lengths of 1 GiB, with the data cut off: over the ancillary
+    // limit, so either an error before reading them or skipped until the end
+    // of the file
+    let strict = Limits {
+        strict: true,
+        ..Limits::default()
+    };
+    for name in ["hIST", "sPLT"] {
+        let path = format!("tests/png/bad/huge_{name}_chunk.png");
+        let result = chunks(&path, strict);
+        assert!(matches!(result[1], Err(Error::LimitExceeded)), "{name}");
+        let result = chunks(&path, Limits::default());
+        assert!(matches!(result[1], Err(Error::Io(_))), "{name}");
+    }
+
+    // Longer than any palette, skipped without reading the rest of the file
+    let result =
+        chunks("tests/png/bad/hIST_257_entries.png", Limits::default());
+    assert!(
+        matches!(result[2], Err(Error::ChunkLength(name)) if &name == b"hIST")
+    );
+    assert!(matches!(result[3], Ok(Chunk::ImageData(_))));
+
+    // Not a whole number of 6 byte entries
+    let result = chunks("tests/png/bad/sPLT_off_by_one.png", Limits::default());
+    assert!(
+        matches!(result[1], Err(Error::ChunkLength(name)) if &name == b"sPLT")
+    );
+    assert!(matches!(result[2], Ok(Chunk::ImageData(_))));
+}
+
+#[test]
+fn histogram_and_suggested_palette_lengths() {
+    use png_pong::encode::{rewrite, ChunkSelector, Edit};
+
+    let image = generate_test_image(ColorType::Palette, 8, 4, 4);
+    let (_, palette, _) = png_chunk_sizes(Cursor::new(&image))
+        .unwrap()
+        .into_iter()
+        .find(|(name, _, _)| name == b"PLTE")
+        .unwrap();
+    let entries = palette as usize / 3;
+    // Insert a chunk after the palette, and decode it
+    let decode = |name: [u8; 4], data: Vec<u8>, limits: Limits| {
+        let chunk = Chunk::Unknown(Unknown { name, data });
+        let mut file = Vec::new();
+        rewrite(
+            image.as_slice(),
+            &mut file,
+            &[Edit::Insert(ChunkSelector::Name(*b"PLTE"), chunk)],
+        )
+        .unwrap();
+        Decoder::new(Cursor::new(file))
+            .unwrap()
+            .limits(limits)
+            .into_chunks()
+            .find(|chunk| {
+                !matches!(chunk, Ok(Chunk::ImageHeader(_) | Chunk::Palette(_)))
+            })
+            .unwrap()
+    };
+    let is_length_error = |result: Result<Chunk, Error>, name: &[u8; 4]| {
+        matches!(result, Err(Error::ChunkLength(n)) if &n == name)
+    };
+
+    // One entry per palette entry
+    let histogram =
+        |entries| decode(*b"hIST", vec![0; 2 * entries], Limits::default());
+    assert!(matches!(histogram(entries), Ok(Chunk::Unknown(_))));
+    assert!(is_length_error(histogram(entries - 1), b"hIST"));
+    assert!(is_length_error(histogram(entries + 1), b"hIST"));
+
+    // The sample depth is checked before reading the entries, which are over
+    // the chunk size limit here (but the palette isn't)
+    let small = Limits {
+        max_chunk_bytes: 3 * entries,
+        ..Limits::default()
+    };
+    let mut suggested = b"name\0\x08".to_vec();
+    suggested.resize(suggested.len() + 6 * entries, 0);
+    assert!(matches!(
+        decode(*b"sPLT", suggested.clone(), small),
+        Err(Error::ChunkTooLarge(name)) if &name == b"sPLT"
+    ));
+    assert!(matches!(
+        decode(*b"sPLT", suggested.clone(), Limits::default()),
+        Ok(Chunk::Unknown(_))
+    ));
+    suggested[5] = 7;
+    assert!(is_length_error(decode(*b"sPLT", suggested, small), b"sPLT"));
+}
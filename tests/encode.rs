@@ -0,0 +1,1632 @@
+use std::{
+    error::Error as _,
+    io::{self, Cursor, ErrorKind},
+};
+
+use png_pong::{
+    chunk::{Chunk, ColorType, Text, Time},
+    decode::{decode_planes_into, png_chunk_names, png_header},
+    diff,
+    encode::{
+        generate_test_image, rewrite, ChunkSelector, Defaults, Edit, Effort,
+        Error, FilterChoice, FilterStrategy, Rung,
+    },
+    encode_steps, same_pixels, ChunkDiff, Decoder, Encoder, PngRaster, Step,
+};
+
+#[test]
+fn encoder_error_into_io_error() {
+    let io_error: io::Error = Error::KeySize(80).into();
+
+    assert_eq!(io_error.kind(), ErrorKind::InvalidData);
+    assert!(matches!(
+        io_error.get_ref().unwrap().downcast_ref::<Error>(),
+        Some(Error::KeySize(80))
+    ));
+}
+
+#[test]
+fn io_error_roundtrip() {
+    let original = io::Error::new(ErrorKind::WriteZero, "disk full");
+    let error = Error::from(original);
+    let source = error.source().unwrap().downcast_ref::<io::Error>().unwrap();
+
+    assert_eq!(source.kind(), ErrorKind::WriteZero);
+    assert_eq!(source.to_string(), "disk full");
+
+    let io_error: io::Error = error.into();
+
+    assert_eq!(io_error.kind(), ErrorKind::WriteZero);
+    assert_eq!(io_error.to_string(), "disk full");
+}
+
+#[test]
+fn text_keyword_rules() {
+    assert!(Text::new("Title", "Ünïcödé in Latin-1").is_ok());
+    assert!(Text::new("Säge", "").is_ok());
+    assert!(matches!(Text::new("", "x"), Err(Error::KeySize(0))));
+    assert!(matches!(
+        Text::new("k".repeat(80), "x"),
+        Err(Error::KeySize(80))
+    ));
+    assert!(Text::new("k".repeat(79), "x").is_ok());
+    for key in [
+        " Title",
+        "Title ",
+        "Ti  tle",
+        "Ti\ntle",
+        "Ti\0tle",
+        "Title\u{A0}",
+        "€",
+    ] {
+        assert!(
+            matches!(Text::new(key, "x"), Err(Error::InvalidKeyword(_))),
+            "{key:?}",
+        );
+    }
+    assert!(matches!(Text::new("Title", "\0"), Err(Error::InvalidText)));
+    assert!(matches!(Text::new("Title", "€"), Err(Error::InvalidText)));
+}
+
+/// Encode a text chunk, and decode it again.
+fn text_roundtrip(text: &Text) -> Text {
+    let mut file = vec![137, 80, 78, 71, 13, 10, 26, 10];
+    Encoder::new(&mut file)
+        .into_chunk_enc()
+        .encode(&mut Chunk::Text(text.clone()))
+        .unwrap();
+
+    match Decoder::new(Cursor::new(file))
+        .unwrap()
+        .into_chunks()
+        .next()
+    {
+        Some(Ok(Chunk::Text(text))) => text,
+        other => panic!("Expected text chunk, got {other:?}"),
+    }
+}
+
+#[test]
+fn text_accepted_roundtrips() {
+    // Deterministic pseudo-random keywords and text
+    let mut state = 0x2545_F491_u32;
+    let mut random = move |max: u32| {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state % max
+    };
+    let mut accepted = 0;
+
+    for _ in 0..2000 {
+        // Mostly printable Latin-1, with spaces and some arbitrary characters
+        let mut string = |max_len, wild| {
+            let len = random(max_len);
+            (0..len)
+                .map(|_| {
+                    if random(wild) == 0 {
+                        return char::from_u32(random(0x200)).unwrap();
+                    }
+                    if random(8) == 0 {
+                        return ' ';
+                    }
+                    let c = random(94 + 95);
+                    char::from_u32(if c < 94 {
+                        0x21 + c
+                    } else {
+                        0xA1 + c - 94
+                    })
+                    .unwrap()
+                })
+                .collect::<String>()
+        };
+        let (key, val) = (string(90, 64), string(200, 256));
+
+        if let Ok(text) = Text::new(key, val) {
+            assert_eq!(text_roundtrip(&text), text);
+            accepted += 1;
+        }
+    }
+    assert!(accepted > 0);
+}
+
+#[test]
+fn text_unchecked() {
+    let text = Text::new_unchecked("Title", "Test");
+    assert_eq!(text, Text::new("Title", "Test").unwrap());
+    assert_eq!((text.key(), text.val()), ("Title", "Test"));
+
+    // Checked when encoding instead
+    let text = Text::new_unchecked("Title", "\0");
+    assert!(matches!(
+        Chunk::Text(text).to_bytes(),
+        Err(Error::InvalidText)
+    ));
+}
+
+#[test]
+fn encode_into_vec() {
+    let raster = pix::Raster::<pix::rgb::SRgba8>::with_pixels(
+        1,
+        1,
+        &[pix::rgb::SRgba8::new(1, 2, 3, 4)][..],
+    );
+    let mut encoder = Encoder::new(Vec::new()).into_step_enc();
+    encoder.still(&raster).unwrap();
+    let png = encoder.into_inner();
+
+    let step = Decoder::new(Cursor::new(png))
+        .unwrap()
+        .into_steps()
+        .next()
+        .unwrap()
+        .unwrap();
+
+    let decoded: pix::Raster<pix::rgb::SRgba8> = step.raster.into();
+
+    assert_eq!(decoded.as_u8_slice(), raster.as_u8_slice());
+}
+
+#[test]
+fn recompress_idat() {
+    let original = std::fs::read("tests/png/icon.png").unwrap();
+    let decode = |png: &[u8]| {
+        let step = Decoder::new(png)
+            .unwrap()
+            .into_steps()
+            .next()
+            .unwrap()
+            .unwrap();
+        let raster: pix::Raster<pix::rgb::SRgba8> = step.raster.into();
+
+        raster.as_u8_slice().to_vec()
+    };
+    let pixels = decode(&original);
+    let names = |png: &[u8]| {
+        let mut names = png_pong::decode::png_chunk_names(png).unwrap();
+        names.dedup();
+        names
+    };
+
+    let stored = png_pong::encode::recompress_idat(&original, 0).unwrap();
+    let best = png_pong::encode::recompress_idat(&original, 10).unwrap();
+
+    assert!(stored.len() > best.len());
+    for png in [stored, best] {
+        assert_eq!(decode(&png), pixels);
+        assert_eq!(names(&png), names(&original));
+    }
+}
+
+#[test]
+fn small_image_fast_path() {
+    let pixels: Vec<_> = (0..16 * 16)
+        .map(|i: u32| {
+            let [r, g, b, a] = i.wrapping_mul(2_654_435_761).to_le_bytes();
+            pix::rgb::SRgba8::new(r, g, b, a)
+        })
+        .collect();
+    let raster = pix::Raster::with_pixels(16, 16, &pixels[..]);
+
+    // Fast path (default), and adaptive filtering
+    for threshold in [1024, 0] {
+        let mut encoder = Encoder::new(Vec::new())
+            .small_image_threshold(threshold)
+            .into_step_enc();
+        encoder.still(&raster).unwrap();
+        let png = encoder.into_inner();
+
+        let step = Decoder::new(Cursor::new(png))
+            .unwrap()
+            .into_steps()
+            .next()
+            .unwrap()
+            .unwrap();
+        let decoded: pix::Raster<pix::rgb::SRgba8> = step.raster.into();
+
+        assert_eq!(decoded.as_u8_slice(), raster.as_u8_slice());
+    }
+
+    // The fast path caps the default level, but not a chosen one
+    let mut encoder = Encoder::new(Vec::new()).into_step_enc();
+    encoder.still(&raster).unwrap();
+    assert!(encoder.stats().unwrap().level <= 1);
+    let mut encoder = Encoder::new(Vec::new())
+        .compression_level(9)
+        .into_step_enc();
+    encoder.still(&raster).unwrap();
+    assert_eq!(encoder.stats().unwrap().level, 9);
+}
+
+#[test]
+fn color_type_defaults() {
+    let gradient: Vec<_> = (0..64 * 64)
+        .map(|i: u32| pix::gray::SGray8::new((i / 64 * 4) as u8))
+        .collect();
+    let gradient = pix::Raster::with_pixels(64, 64, &gradient[..]);
+    let rgb = pix::Raster::<pix::rgb::SRgb8>::with_clear(64, 64);
+    let file = std::fs::read("tests/pngsuite-basic/basn3p08.png").unwrap();
+    let palette = Decoder::new(Cursor::new(file))
+        .unwrap()
+        .into_steps()
+        .next()
+        .unwrap()
+        .unwrap()
+        .raster;
+
+    assert_eq!(
+        Defaults::for_color_type(ColorType::Grey, 8).filter_strategy,
+        FilterStrategy::MinSum,
+    );
+    assert_eq!(
+        Defaults::for_color_type(ColorType::Grey, 4).filter_strategy,
+        FilterStrategy::Zero,
+    );
+
+    let mut encoder = Encoder::new(Vec::new()).into_step_enc();
+    encoder.still(&gradient).unwrap();
+    let stats = encoder.stats().unwrap().clone();
+    assert_eq!(stats.filter_strategy, FilterStrategy::MinSum);
+    assert_eq!(stats.level, 6);
+    assert_eq!(stats.filters.iter().sum::<usize>(), 64);
+    let decoded: pix::Raster<pix::gray::SGray8> =
+        Decoder::new(Cursor::new(encoder.into_inner()))
+            .unwrap()
+            .into_steps()
+            .next()
+            .unwrap()
+            .unwrap()
+            .raster
+            .into();
+    assert_eq!(decoded.as_u8_slice(), gradient.as_u8_slice());
+
+    let mut encoder = Encoder::new(Vec::new()).into_step_enc();
+    encoder.still(&rgb).unwrap();
+    let stats = encoder.stats().unwrap();
+    assert_eq!(stats.filter_strategy, FilterStrategy::MinSum);
+    assert_eq!(stats.filters.iter().sum::<usize>(), 64);
+
+    let mut encoder = Encoder::new(Vec::new()).into_step_enc();
+    encoder.still(&palette).unwrap();
+    let stats = encoder.stats().unwrap();
+    assert_eq!(stats.filter_strategy, FilterStrategy::Zero);
+    assert_eq!(stats.filters, [32, 0, 0, 0, 0]);
+
+    // Explicit settings win over the table
+    let mut encoder = Encoder::new(Vec::new())
+        .filter_strategy(FilterStrategy::Zero)
+        .compression_level(9)
+        .into_step_enc();
+    encoder.still(&gradient).unwrap();
+    let stats = encoder.stats().unwrap();
+    assert_eq!(stats.level, 9);
+    assert_eq!(stats.filters, [64, 0, 0, 0, 0]);
+}
+
+#[test]
+fn test_images() {
+    use ColorType::*;
+
+    let valid: &[(ColorType, &[u8])] = &[
+        (Grey, &[1, 2, 4, 8, 16]),
+        (Rgb, &[8, 16]),
+        (Palette, &[1, 2, 4, 8]),
+        (GreyAlpha, &[8, 16]),
+        (Rgba, &[8, 16]),
+    ];
+    for (color_type, bit_depths) in valid.iter().copied() {
+        for bit_depth in bit_depths.iter().copied() {
+            let png = generate_test_image(color_type, bit_depth, 13, 7);
+            assert_eq!(png, generate_test_image(color_type, bit_depth, 13, 7));
+
+            let header = png_header(png.as_slice()).unwrap();
+            assert_eq!((header.width, header.height), (13, 7));
+            assert_eq!(header.color_type, color_type);
+            assert_eq!(header.bit_depth, bit_depth);
+
+            let idat = Decoder::new(png.as_slice())
+                .unwrap()
+                .into_raw_idat_bytes()
+                .unwrap();
+            assert!(!idat.is_empty());
+        }
+    }
+
+    let png = generate_test_image(Rgba, 8, 16, 16);
+    let step = Decoder::new(png.as_slice())
+        .unwrap()
+        .into_steps()
+        .next()
+        .unwrap()
+        .unwrap();
+    let raster: pix::Raster<pix::rgb::SRgba8> = step.raster.into();
+    assert!(raster.as_u8_slice().iter().any(|&byte| byte != 0));
+}
+
+#[test]
+fn rewrite_unchanged() {
+    use png_pong::decode::Error as DecodeError;
+
+    // Files with a bad signature, the only ones that can't be rewritten
+    const BAD_SIGNATURE: &[&str] = &[
+        "xcrn0g04.png",
+        "xlfn0g04.png",
+        "xs1n0g01.png",
+        "xs2n0g01.png",
+        "xs4n0g01.png",
+        "xs7n0g01.png",
+    ];
+
+    let (mut count, mut bad) = (0, 0);
+    for dir in std::fs::read_dir("tests").unwrap() {
+        let dir = dir.unwrap().path();
+        if !dir.to_str().unwrap().contains("pngsuite-") {
+            continue;
+        }
+        for path in std::fs::read_dir(dir).unwrap() {
+            let path = path.unwrap().path();
+            let name = path.file_name().unwrap().to_str().unwrap();
+            let file = std::fs::read(&path).unwrap();
+            let mut out = Vec::new();
+            let result = rewrite(file.as_slice(), &mut out, &[]);
+            if BAD_SIGNATURE.contains(&name) {
+                let error = result.unwrap_err();
+                assert!(
+                    matches!(
+                        error.get_ref().and_then(|e| e.downcast_ref()),
+                        Some(DecodeError::InvalidSignature)
+                    ),
+                    "{name}: {error}",
+                );
+                bad += 1;
+                continue;
+            }
+            result.unwrap_or_else(|e| panic!("{name}: {e}"));
+            assert_eq!(out, file, "{name}");
+            count += 1;
+        }
+    }
+    assert_eq!(bad, BAD_SIGNATURE.len());
+    assert!(count > 100);
+}
+
+#[test]
+fn rewrite_edits() {
+    let file = generate_test_image(ColorType::Rgb, 8, 8, 8);
+    let names = |png: &[u8]| -> Vec<[u8; 4]> {
+        png_pong::decode::png_chunk_names(png).unwrap()
+    };
+    let text = |key: &str| Chunk::Text(Text::new(key, "value").unwrap());
+
+    let mut out = Vec::new();
+    let edits = [
+        Edit::Insert(ChunkSelector::Name(*b"IHDR"), text("Title")),
+        Edit::Insert(ChunkSelector::Index(0), text("Author")),
+    ];
+    rewrite(file.as_slice(), &mut out, &edits).unwrap();
+    assert_eq!(
+        names(&out),
+        [*b"IHDR", *b"tEXt", *b"tEXt", *b"IDAT", *b"IEND"]
+    );
+    // Unedited chunks are unchanged
+    assert_eq!(out[..33], file[..33]);
+    assert!(out.ends_with(&file[33..]));
+
+    let edited = out;
+    let mut out = Vec::new();
+    let edits = [
+        Edit::Replace(ChunkSelector::Nth(*b"tEXt", 1), text("Comment")),
+        Edit::Remove(ChunkSelector::Nth(*b"tEXt", 0)),
+    ];
+    rewrite(edited.as_slice(), &mut out, &edits).unwrap();
+    assert_eq!(names(&out), [*b"IHDR", *b"tEXt", *b"IDAT", *b"IEND"]);
+    let chunks: Vec<_> = Decoder::new(out.as_slice())
+        .unwrap()
+        .into_chunks()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert!(matches!(&chunks[1], Chunk::Text(text) if text.key() == "Comment"));
+
+    let mut out = Vec::new();
+    let edits = [Edit::Insert(
+        ChunkSelector::Index(0),
+        Chunk::Text(Text::new_unchecked("", "value")),
+    )];
+    let error = rewrite(file.as_slice(), &mut out, &edits).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    assert!(matches!(
+        error.get_ref().unwrap().downcast_ref::<Error>(),
+        Some(Error::KeySize(0))
+    ));
+}
+
+#[test]
+fn normalize_png() {
+    use png_pong::encode::normalize_png;
+
+    let names = |png: &[u8]| png_pong::decode::png_chunk_names(png).unwrap();
+    // Same header and compressed image data means identical pixels
+    let idat =
+        |png: &[u8]| Decoder::new(png).unwrap().into_raw_idat_bytes().unwrap();
+
+    // Split image data, misordered and duplicate chunks
+    let image = generate_test_image(ColorType::Rgb, 8, 16, 16);
+    let idat_bytes = idat(&image);
+    let (first, second) = idat_bytes.split_at(idat_bytes.len() / 2);
+    let unknown = |name: &[u8; 4], data: &[u8]| {
+        Chunk::Unknown(png_pong::chunk::Unknown {
+            name: *name,
+            data: data.to_vec(),
+        })
+    };
+    let build = |chunks: Vec<Chunk>| {
+        let signature = vec![137, 80, 78, 71, 13, 10, 26, 10];
+        let mut encoder = Encoder::new(signature).into_chunk_enc();
+        for mut chunk in chunks {
+            encoder.encode(&mut chunk).unwrap();
+        }
+        encoder.into_inner().unwrap()
+    };
+    let header = Chunk::ImageHeader(png_header(image.as_slice()).unwrap());
+    let title = Chunk::Text(Text::new("Title", "Test").unwrap());
+    // Compressed image data, written as is
+    let image_data = |data: &[u8]| unknown(b"IDAT", data);
+    let end = Chunk::ImageEnd(png_pong::chunk::ImageEnd);
+
+    let input = build(vec![
+        header.clone(),
+        title.clone(),
+        unknown(b"pHYs", &[0, 0, 0, 1, 0, 0, 0, 1, 0]),
+        unknown(b"sRGB", &[0]),
+        unknown(b"sRGB", &[1]),
+        unknown(b"gAMA", &45_455u32.to_be_bytes()),
+        image_data(first),
+        image_data(second),
+        unknown(b"prVt", &[1, 2, 3]),
+        end.clone(),
+    ]);
+    let output = normalize_png(&input).unwrap();
+    assert_eq!(
+        names(&output),
+        [
+            *b"IHDR", *b"gAMA", *b"sRGB", *b"pHYs", *b"tEXt", *b"IDAT",
+            *b"prVt", *b"IEND",
+        ],
+    );
+    assert_eq!(idat(&output), idat_bytes);
+    assert_eq!(png_header(output.as_slice()).unwrap().width, 16);
+    assert_eq!(normalize_png(&output).unwrap(), output);
+
+    // Files that only differ in chunk layout normalize the same
+    let layout = build(vec![
+        header,
+        unknown(b"gAMA", &45_455u32.to_be_bytes()),
+        unknown(b"sRGB", &[0]),
+        image_data(&idat_bytes),
+        title,
+        unknown(b"pHYs", &[0, 0, 0, 1, 0, 0, 0, 1, 0]),
+        unknown(b"prVt", &[1, 2, 3]),
+        end,
+    ]);
+    assert_eq!(normalize_png(&layout).unwrap(), output);
+}
+
+#[test]
+fn normalize_animation() {
+    use png_pong::encode::normalize_png;
+
+    let mut encoder = Encoder::new(Vec::new()).animation().into_step_enc();
+    for value in [10, 20, 30] {
+        let raster = PngRaster::Gray8(pix::Raster::with_pixels(
+            2,
+            2,
+            vec![pix::gray::SGray8::new(value); 4],
+        ));
+        encoder.encode(&Step { raster, delay: 100 }).unwrap();
+    }
+    encoder.finish().unwrap();
+    let png = encoder.into_inner();
+
+    // Every frame is kept, in order
+    let output = normalize_png(&png).unwrap();
+    let names = png_chunk_names(output.as_slice()).unwrap();
+    assert_eq!(names, png_chunk_names(png.as_slice()).unwrap());
+    let chunks = Decoder::new(output.as_slice())
+        .unwrap()
+        .into_chunks()
+        .into_vec()
+        .unwrap();
+    let Chunk::Unknown(actl) = &chunks[1] else {
+        panic!("acTL not kept as an unknown chunk");
+    };
+    let frames = u32::from_be_bytes(actl.data[..4].try_into().unwrap());
+    assert_eq!(frames, 3);
+    assert_eq!(names.iter().filter(|name| *name == b"fcTL").count(), 3);
+    assert_eq!(names.iter().filter(|name| *name == b"fdAT").count(), 2);
+}
+
+#[test]
+fn chunk_diff() {
+    let image = generate_test_image(ColorType::Rgb, 8, 8, 8);
+    let text = |value: &str| Chunk::Text(Text::new("Title", value).unwrap());
+    let time = Chunk::Time(Time {
+        year: 2000,
+        month: 1,
+        day: 1,
+        hour: 0,
+        minute: 0,
+        second: 0,
+    });
+    let mut file = Vec::new();
+    let edits = [
+        Edit::Insert(ChunkSelector::Name(*b"IHDR"), text("value")),
+        Edit::Insert(ChunkSelector::Name(*b"IHDR"), time.clone()),
+    ];
+    rewrite(image.as_slice(), &mut file, &edits).unwrap();
+
+    // Strip the ancillary chunks
+    let mut stripped = Vec::new();
+    let edits = [
+        Edit::Remove(ChunkSelector::Name(*b"tEXt")),
+        Edit::Remove(ChunkSelector::Name(*b"tIME")),
+    ];
+    rewrite(file.as_slice(), &mut stripped, &edits).unwrap();
+
+    assert_eq!(
+        diff(file.as_slice(), stripped.as_slice()).unwrap(),
+        [
+            ChunkDiff::OnlyInFirst {
+                name: *b"tEXt",
+                offset: 33,
+            },
+            ChunkDiff::OnlyInFirst {
+                name: *b"tIME",
+                offset: 33 + 23,
+            },
+        ]
+    );
+    assert!(diff(file.as_slice(), file.as_slice()).unwrap().is_empty());
+    assert!(same_pixels(file.as_slice(), stripped.as_slice()).unwrap());
+
+    // Move tIME in front of tEXt, and change the text
+    let mut edited = Vec::new();
+    let edits = [
+        Edit::Remove(ChunkSelector::Name(*b"tIME")),
+        Edit::Insert(ChunkSelector::Name(*b"IHDR"), time),
+        Edit::Replace(ChunkSelector::Name(*b"tEXt"), text("other")),
+    ];
+    rewrite(file.as_slice(), &mut edited, &edits).unwrap();
+    assert_eq!(
+        diff(file.as_slice(), edited.as_slice()).unwrap(),
+        [
+            ChunkDiff::Payload {
+                name: *b"tEXt",
+                first_len: 11,
+                second_len: 11,
+                differs_at: 6,
+                first_offset: 33,
+                second_offset: 33 + 19,
+            },
+            ChunkDiff::Moved {
+                name: *b"tEXt",
+                first_index: 1,
+                second_index: 2,
+            },
+        ]
+    );
+    // Many chunks with the same name (within the decoder's chunk limit)
+    let mut many = png_pong::SIGNATURE.to_vec();
+    let mut encoder = Encoder::new(&mut many).into_chunk_enc();
+    let header = png_header(image.as_slice()).unwrap();
+    encoder.encode(&mut Chunk::ImageHeader(header)).unwrap();
+    for _ in 0..99_000 {
+        encoder.write_chunk(*b"IDAT", &[0]).unwrap();
+    }
+    encoder.write_chunk(*b"IEND", &[]).unwrap();
+    let mut fewer = Vec::new();
+    let edits = [Edit::Remove(ChunkSelector::Nth(*b"IDAT", 50_000))];
+    rewrite(many.as_slice(), &mut fewer, &edits).unwrap();
+    assert_eq!(
+        diff(many.as_slice(), fewer.as_slice()).unwrap(),
+        [ChunkDiff::OnlyInFirst {
+            name: *b"IDAT",
+            offset: 33 + 98_999 * 13,
+        }]
+    );
+}
+
+#[test]
+fn write_raw_chunk() {
+    let mut file = generate_test_image(ColorType::Grey, 8, 1, 1);
+    let end = file.split_off(file.len() - 12);
+    let mut enc = Encoder::new(file).into_chunk_enc();
+    enc.write_chunk(*b"prVt", b"private data").unwrap();
+    let mut file = enc.into_inner().unwrap();
+    file.extend(end);
+
+    let chunks: Vec<_> = Decoder::new(file.as_slice())
+        .unwrap()
+        .into_chunks()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert!(matches!(
+        &chunks[2],
+        Chunk::Unknown(unknown)
+            if unknown.name == *b"prVt" && unknown.data == b"private data"
+    ));
+    assert!(matches!(chunks[3], Chunk::ImageEnd(_)));
+}
+
+#[test]
+fn optimize_screenshot() {
+    // Opaque RGBA "screenshot" with a few flat colors: title bar, window,
+    // and lines of text
+    let pixels: Vec<_> = (0..96 * 128)
+        .map(|i| {
+            let (x, y) = (i % 128, i / 128);
+            if y < 12 {
+                pix::rgb::SRgba8::new(40, 60, 120, 255)
+            } else if y % 8 < 2 && (x * 7 + y) % 13 < 9 {
+                pix::rgb::SRgba8::new(20, 20, 20, 255)
+            } else {
+                pix::rgb::SRgba8::new(240, 240, 240, 255)
+            }
+        })
+        .collect();
+    let step = png_pong::Step {
+        raster: png_pong::PngRaster::Rgba8(pix::Raster::with_pixels(
+            128,
+            96,
+            &pixels[..],
+        )),
+        delay: 0,
+    };
+    let mut default = Vec::new();
+    Encoder::new(&mut default)
+        .into_step_enc()
+        .encode(&step)
+        .unwrap();
+
+    let optimized = png_pong::optimize(&step, Effort::Fast).unwrap();
+    assert!(optimized.len() < default.len());
+    let decoded = Decoder::new(optimized.as_slice())
+        .unwrap()
+        .into_steps()
+        .next()
+        .unwrap()
+        .unwrap();
+    let raster: pix::Raster<pix::rgb::SRgba8> = decoded.raster.into();
+    assert_eq!(raster.pixels(), &pixels[..]);
+}
+
+/// Encode a `width` pixel wide greyscale raster with `reduce_depth(true)`,
+/// returning the bit depth it was stored at, checking that it decodes to the
+/// same pixels.
+fn encode_reduced(width: u32, samples: &[u8], interlace: bool) -> u8 {
+    let pixels: Vec<_> =
+        samples.iter().map(|&v| pix::gray::SGray8::new(v)).collect();
+    let height = samples.len() as u32 / width;
+    let raster = pix::Raster::with_pixels(width, height, &pixels[..]);
+    let mut png = Vec::new();
+    let mut encoder = Encoder::new(&mut png).reduce_depth(true);
+    if interlace {
+        encoder = encoder.interlace();
+    }
+    encoder.into_step_enc().still(&raster).unwrap();
+
+    let step = Decoder::new(png.as_slice())
+        .unwrap()
+        .into_steps()
+        .next()
+        .unwrap()
+        .unwrap();
+    let png_pong::PngRaster::Gray8(decoded) = step.raster else {
+        panic!("not greyscale");
+    };
+    assert_eq!(decoded.pixels(), raster.pixels());
+    png_header(png.as_slice()).unwrap().bit_depth
+}
+
+#[test]
+fn reduce_depth() {
+    // Binarized document: black text on white
+    let document: Vec<u8> = (0..16 * 16)
+        .map(|i| {
+            if (i % 16) % 3 == 0 && (i / 16) % 4 != 0 {
+                0
+            } else {
+                255
+            }
+        })
+        .collect();
+    assert_eq!(encode_reduced(16, &document, false), 1);
+
+    let levels: Vec<u8> =
+        (0..16 * 16).map(|i| [0, 85, 170, 255][i % 4]).collect();
+    assert_eq!(encode_reduced(16, &levels, false), 2);
+
+    let mut odd = document;
+    odd[100] = 100;
+    assert_eq!(encode_reduced(16, &odd, false), 8);
+}
+
+#[test]
+fn reduce_depth_padded_rows() {
+    // Rows that don't end on a byte boundary at any reduced depth
+    for (levels, bit_depth) in [
+        (&[0, 255][..], 1),
+        (&[0, 85, 170, 255], 2),
+        (&[0, 17, 34, 51, 68, 85, 102, 119, 136, 255], 4),
+    ] {
+        for width in [1, 3, 5, 13] {
+            let samples: Vec<u8> =
+                (0..width * 11).map(|i| levels[i % levels.len()]).collect();
+            for interlace in [false, true] {
+                assert_eq!(
+                    encode_reduced(width as u32, &samples, interlace),
+                    bit_depth,
+                    "{width} wide at {bit_depth} bits",
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn still_encodes_once() {
+    let raster = pix::Raster::<pix::gray::SGray8>::with_clear(2, 2);
+    let mut encoder = Encoder::new(Vec::new()).into_step_enc();
+    encoder.still(&raster).unwrap();
+    assert!(matches!(
+        encoder.still(&raster),
+        Err(Error::AlreadyFinished)
+    ));
+    encoder.finish().unwrap();
+
+    let png = encoder.into_inner();
+    assert_eq!(
+        png_chunk_names(png.as_slice()).unwrap(),
+        [*b"IHDR", *b"IDAT", *b"IEND"],
+    );
+}
+
+#[test]
+fn animation_frames() {
+    let frame = |value: u8| Step {
+        raster: PngRaster::Gray8(pix::Raster::with_pixels(
+            2,
+            2,
+            vec![pix::gray::SGray8::new(value); 4],
+        )),
+        delay: 100,
+    };
+    let mut encoder = Encoder::new(Vec::new()).animation().into_step_enc();
+    for value in [10, 20, 30] {
+        encoder.encode(&frame(value)).unwrap();
+    }
+    let small = pix::Raster::<pix::gray::SGray8>::with_clear(1, 1);
+    assert!(matches!(encoder.still(&small), Err(Error::FrameMismatch)));
+    encoder.finish().unwrap();
+    assert!(matches!(
+        encoder.encode(&frame(40)),
+        Err(Error::AlreadyFinished)
+    ));
+
+    let png = encoder.into_inner();
+    assert_eq!(
+        png_chunk_names(png.as_slice()).unwrap(),
+        [
+            *b"IHDR", *b"acTL", *b"fcTL", *b"IDAT", *b"fcTL", *b"fdAT",
+            *b"fcTL", *b"fdAT", *b"IEND",
+        ],
+    );
+    let chunks = Decoder::new(png.as_slice())
+        .unwrap()
+        .into_chunks()
+        .into_vec()
+        .unwrap();
+    let Chunk::Unknown(actl) = &chunks[1] else {
+        panic!("acTL not kept as an unknown chunk");
+    };
+    assert_eq!(actl.data, [0, 0, 0, 3, 0, 0, 0, 0]);
+
+    // The default image is the first frame
+    let mut plane = [0; 4];
+    decode_planes_into(png.as_slice(), &mut [&mut plane]).unwrap();
+    assert_eq!(plane, [10; 4]);
+}
+
+#[test]
+fn encode_frame_with() {
+    use png_pong::encode::FrameOptions;
+
+    let frame = |value: u8| Step {
+        raster: PngRaster::Gray8(pix::Raster::with_pixels(
+            4,
+            4,
+            (0..16)
+                .map(|i| pix::gray::SGray8::new(value.wrapping_add(i * 7)))
+                .collect::<Vec<_>>(),
+        )),
+        delay: 100,
+    };
+    let mut encoder = Encoder::new(Vec::new())
+        .animation()
+        .compression_level(3)
+        .into_step_enc();
+    encoder
+        .encode_frame_with(
+            &frame(10),
+            FrameOptions {
+                filter: Some(FilterStrategy::Up),
+                level: Some(9),
+            },
+        )
+        .unwrap();
+    let stats = encoder.stats().unwrap();
+    assert_eq!(stats.filter_strategy, FilterStrategy::Up);
+    assert_eq!(stats.filters, [0, 0, 4, 0, 0]);
+    assert_eq!(stats.level, 9);
+    encoder
+        .encode_frame_with(
+            &frame(20),
+            FrameOptions {
+                filter: Some(FilterStrategy::Zero),
+                level: None,
+            },
+        )
+        .unwrap();
+    let stats = encoder.stats().unwrap();
+    assert_eq!(stats.filter_strategy, FilterStrategy::Zero);
+    assert_eq!(stats.filters, [4, 0, 0, 0, 0]);
+    // Falls back to the encoder's level
+    assert_eq!(stats.level, 3);
+
+    // The header can't change between frames
+    let rgb = Step {
+        raster: PngRaster::Rgb8(pix::Raster::with_clear(4, 4)),
+        delay: 100,
+    };
+    assert!(matches!(
+        encoder.encode_frame_with(&rgb, FrameOptions::default()),
+        Err(Error::FrameMismatch),
+    ));
+    encoder.finish().unwrap();
+
+    let png = encoder.into_inner();
+    assert_eq!(
+        png_chunk_names(png.as_slice()).unwrap(),
+        [
+            *b"IHDR", *b"acTL", *b"fcTL", *b"IDAT", *b"fcTL", *b"fdAT",
+            *b"IEND",
+        ],
+    );
+    let mut plane = [0; 16];
+    decode_planes_into(png.as_slice(), &mut [&mut plane]).unwrap();
+    let expected: Vec<u8> = (0..16).map(|i| 10 + i * 7).collect();
+    assert_eq!(plane[..], expected[..]);
+}
+
+/// Get the inflated (filtered) scanlines of a PNG file.
+fn filtered_scanlines(png: &[u8]) -> Vec<u8> {
+    let mut zlib = Vec::new();
+    for chunk in Decoder::new(png).unwrap().into_chunks() {
+        if let Chunk::ImageData(data) = chunk.unwrap() {
+            zlib.extend(data.data);
+        }
+    }
+    miniz_oxide::inflate::decompress_to_vec_zlib(&zlib).unwrap()
+}
+
+#[test]
+fn explicit_filters() {
+    for path in [
+        "tests/pngsuite-filtering/f00n2c08.png",
+        "tests/pngsuite-filtering/f01n0g08.png",
+        "tests/pngsuite-filtering/f02n2c08.png",
+        "tests/pngsuite-filtering/f03n0g08.png",
+        "tests/pngsuite-filtering/f04n2c08.png",
+        "tests/pngsuite-interlaced/basi0g08.png",
+        "tests/pngsuite-interlaced/basi2c16.png",
+        "tests/pngsuite-interlaced/basi3p08.png",
+        "tests/pngsuite-interlaced/basi6a08.png",
+    ] {
+        let original = std::fs::read(path).unwrap();
+        let mut steps = Decoder::new(original.as_slice()).unwrap().into_steps();
+        let step = steps.next().unwrap().unwrap();
+        let filters = steps.stats().unwrap().filters.clone();
+        let interlace = steps.header().unwrap().interlace;
+
+        let mut encoder = Encoder::new(Vec::new())
+            .filter_strategy(FilterStrategy::Explicit(filters.clone()));
+        if interlace {
+            encoder = encoder.interlace();
+        }
+        let mut encoder = encoder.into_step_enc();
+        encoder.encode(&step).unwrap();
+        let stats = encoder.stats().unwrap().clone();
+        assert_eq!(stats.filter_strategy, FilterStrategy::Explicit(filters));
+        let png = encoder.into_inner();
+
+        assert!(
+            filtered_scanlines(&png) == filtered_scanlines(&original),
+            "{path}",
+        );
+    }
+}
+
+/// Encode a still image with the given settings, without the small image fast
+/// path.
+fn encode_with(
+    raster: &PngRaster,
+    strategy: FilterStrategy,
+    level: u8,
+) -> Vec<u8> {
+    let mut encoder = Encoder::new(Vec::new())
+        .filter_strategy(strategy)
+        .compression_level(level)
+        .small_image_threshold(0)
+        .into_step_enc();
+    encoder.still(raster).unwrap();
+    encoder.into_inner()
+}
+
+#[test]
+fn max_output_size() {
+    // Grey gradient, stored as RGB
+    let pixels = (0..64 * 64u32)
+        .map(|i| {
+            let grey = (i % 64 + i / 64) as u8;
+            pix::rgb::SRgb8::new(grey, grey, grey)
+        })
+        .collect::<Vec<_>>();
+    let raster = PngRaster::Rgb8(pix::Raster::with_pixels(64, 64, pixels));
+    let ladder = vec![
+        Rung {
+            filter_strategy: FilterStrategy::Zero,
+            level: 0,
+            reduce_colors: false,
+        },
+        Rung {
+            filter_strategy: FilterStrategy::MinSum,
+            level: 9,
+            reduce_colors: false,
+        },
+        Rung {
+            filter_strategy: FilterStrategy::MinSum,
+            level: 9,
+            reduce_colors: true,
+        },
+    ];
+    let encode = |budget| {
+        let mut encoder = Encoder::new(Vec::new())
+            .filter_strategy(FilterStrategy::Zero)
+            .compression_level(0)
+            .max_output_size(budget)
+            .size_ladder(ladder.clone())
+            .into_step_enc();
+        encoder.still(&raster).map(|()| encoder.into_inner())
+    };
+
+    // Fits without retrying
+    let stored = encode_with(&raster, FilterStrategy::Zero, 0);
+    assert_eq!(encode(usize::MAX).unwrap(), stored);
+
+    // Falls back to the second rung
+    let compressed = encode_with(&raster, FilterStrategy::MinSum, 9);
+    assert!(compressed.len() < stored.len());
+    assert_eq!(encode(compressed.len()).unwrap(), compressed);
+
+    // Falls back to the third rung, which stores it as greyscale
+    let png = encode(compressed.len() - 1).unwrap();
+    assert!(png.len() < compressed.len());
+    assert_eq!(
+        png_header(png.as_slice()).unwrap().color_type,
+        ColorType::Grey
+    );
+    let decoded = Decoder::new(png.as_slice())
+        .unwrap()
+        .into_steps()
+        .next()
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        pix::Raster::<pix::rgb::SRgb8>::from(decoded.raster).as_u8_slice(),
+        pix::Raster::<pix::rgb::SRgb8>::from(raster.clone()).as_u8_slice(),
+    );
+
+    // Nothing fits, so the error has the smallest size
+    match encode(10) {
+        Err(Error::SizeBudget { best, budget }) => {
+            assert_eq!((best, budget), (png.len(), 10));
+        }
+        other => panic!("{other:?}"),
+    }
+}
+
+#[test]
+fn max_output_size_16_bit_raster() {
+    let pixels = (0..8 * 8u16)
+        .map(|i| pix::rgb::SRgb16::new(i * 1000, 65535 - i, i << 8))
+        .collect::<Vec<_>>();
+    let raster = pix::Raster::<pix::rgb::SRgb16>::with_pixels(8, 8, pixels);
+    let mut encoder = Encoder::new(Vec::new())
+        .max_output_size(usize::MAX)
+        .into_step_enc();
+    encoder.still(&raster).unwrap();
+    let png = encoder.into_inner();
+
+    let decoded = Decoder::new(png.as_slice())
+        .unwrap()
+        .into_steps()
+        .next()
+        .unwrap()
+        .unwrap();
+    match decoded.raster {
+        PngRaster::Rgb16(decoded) => {
+            assert_eq!(decoded.pixels(), raster.pixels());
+        }
+        other => panic!("{other:?}"),
+    }
+}
+
+#[test]
+fn encode_steps_still_and_animation() {
+    let step = |value: u8| Step {
+        raster: PngRaster::Gray8(pix::Raster::with_pixels(
+            1,
+            1,
+            vec![pix::gray::SGray8::new(value)],
+        )),
+        delay: 0,
+    };
+
+    let mut png = Vec::new();
+    encode_steps(&mut png, [step(7)]).unwrap();
+    let decoded = Decoder::new(png.as_slice())
+        .unwrap()
+        .into_steps()
+        .next()
+        .unwrap()
+        .unwrap();
+    assert_eq!(decoded, step(7));
+
+    let mut png = Vec::new();
+    encode_steps(&mut png, (1..=3).map(step)).unwrap();
+    let names = png_chunk_names(png.as_slice()).unwrap();
+    assert!(names.contains(b"acTL"));
+    assert_eq!(names.iter().filter(|name| *name == b"fcTL").count(), 3);
+
+    let mut png = Vec::new();
+    encode_steps(&mut png, std::iter::empty()).unwrap();
+    assert!(png.is_empty());
+}
+
+/// Encode with a filter hook, returning the decoded filter types and
+/// whether the pixels decode unchanged.
+fn encode_with_hook(
+    strategy: FilterStrategy,
+    hook: impl Fn(u32, &[u8], &[u8], usize) -> FilterChoice + Send + Sync + 'static,
+) -> (Vec<u8>, bool) {
+    let pixels: Vec<_> = (0..8 * 6u32)
+        .map(|i| pix::rgb::SRgb8::new(i as u8, (i * 3) as u8, (i * 7) as u8))
+        .collect();
+    let raster = pix::Raster::<pix::rgb::SRgb8>::with_pixels(8, 6, pixels);
+    let mut encoder = Encoder::new(Vec::new())
+        .filter_strategy(strategy)
+        .filter_fn(hook)
+        .into_step_enc();
+    encoder.still(&raster).unwrap();
+    let png = encoder.into_inner();
+
+    let mut steps = Decoder::new(png.as_slice()).unwrap().into_steps();
+    let step = steps.next().unwrap().unwrap();
+    let decoded = pix::Raster::<pix::rgb::SRgb8>::from(step.raster);
+    let filters = steps.stats().unwrap().filters.clone();
+    (filters, decoded.as_u8_slice() == raster.as_u8_slice())
+}
+
+#[test]
+fn filter_hook() {
+    let (filters, same) =
+        encode_with_hook(FilterStrategy::Zero, |row, raw, prev, bpp| {
+            assert_eq!((raw.len(), bpp), (24, 3));
+            assert_eq!(prev.len(), if row == 0 { 0 } else { 24 });
+            if row % 2 == 0 {
+                FilterChoice::Sub
+            } else {
+                FilterChoice::Up
+            }
+        });
+    assert_eq!(filters, [1, 2, 1, 2, 1, 2]);
+    assert!(same);
+
+    // Auto rows use the strategy
+    let (filters, same) =
+        encode_with_hook(FilterStrategy::Zero, |row, _, _, _| {
+            if row < 2 {
+                FilterChoice::Paeth
+            } else {
+                FilterChoice::Auto
+            }
+        });
+    assert_eq!(filters, [4, 4, 0, 0, 0, 0]);
+    assert!(same);
+    let (filters, _) =
+        encode_with_hook(FilterStrategy::Explicit(vec![3; 6]), |_, _, _, _| {
+            FilterChoice::Auto
+        });
+    assert_eq!(filters, [3; 6]);
+}
+
+#[test]
+fn index_out_of_palette() {
+    let palette = |size: usize| {
+        let mut palette = pix::Palette::new(size);
+        for i in 0..size {
+            palette
+                .set_entry(pix::rgb::SRgb8::new(i as u8, 0, 0))
+                .unwrap();
+        }
+        Box::new(palette)
+    };
+    let raster = |indices: Vec<u8>, size| {
+        let pixels: Vec<_> =
+            indices.into_iter().map(pix::gray::Gray8::new).collect();
+        PngRaster::Palette(
+            pix::Raster::with_pixels(2, 2, pixels),
+            palette(size),
+            Vec::new(),
+        )
+    };
+    let encode = |raster: &PngRaster| {
+        Encoder::new(Vec::new()).into_step_enc().still(raster)
+    };
+
+    assert!(encode(&raster(vec![0, 1, 2, 3], 4)).is_ok());
+    assert!(matches!(
+        encode(&raster(vec![0, 1, 4, 5], 4)),
+        Err(Error::IndexOutOfPalette {
+            index: 4,
+            palette_size: 4,
+        })
+    ));
+    assert!(encode(&raster(vec![0, 255, 128, 3], 256)).is_ok());
+}
+
+#[test]
+fn interlaced_roundtrip() {
+    // Images under 8 pixels across or down have empty passes
+    let sizes = [(1, 1), (1, 9), (2, 3), (5, 1), (7, 7), (9, 10), (33, 17)];
+    for (width, height) in sizes {
+        let indices: Vec<u8> =
+            (0..width * height).map(|i| (i * 7 % 5) as u8).collect();
+        // Black and white, so reduced to 1 bit per pixel
+        let grey: Vec<_> = indices
+            .iter()
+            .map(|&i| pix::gray::SGray8::new(if i % 2 == 0 { 0 } else { 255 }))
+            .collect();
+        let mut palette = pix::Palette::new(5);
+        for i in 0..5 {
+            palette
+                .set_entry(pix::rgb::SRgb8::new(i * 50, 0, 0))
+                .unwrap();
+        }
+        let rasters = [
+            PngRaster::Gray8(pix::Raster::with_pixels(width, height, grey)),
+            PngRaster::Palette(
+                pix::Raster::with_u8_buffer(width, height, indices),
+                Box::new(palette),
+                Vec::new(),
+            ),
+        ];
+
+        for raster in rasters {
+            let mut encoder = Encoder::new(Vec::new())
+                .interlace()
+                .reduce_depth(true)
+                .small_image_threshold(0)
+                .into_step_enc();
+            encoder.still(&raster).unwrap();
+            let png = encoder.into_inner();
+
+            let mut steps = Decoder::new(png.as_slice()).unwrap().into_steps();
+            let step = steps.next().unwrap().unwrap();
+            let header = *steps.header().unwrap();
+            assert!(header.interlace, "{width}x{height}");
+            let (expected, decoded) = match (&raster, &step.raster) {
+                (PngRaster::Gray8(a), PngRaster::Gray8(b)) => {
+                    assert_eq!(header.bit_depth, 1);
+                    (a.as_u8_slice(), b.as_u8_slice())
+                }
+                (PngRaster::Palette(a, ..), PngRaster::Palette(b, ..)) => {
+                    (a.as_u8_slice(), b.as_u8_slice())
+                }
+                _ => panic!("{width}x{height}: decoded {:?}", step.raster),
+            };
+            assert_eq!(expected, decoded, "{width}x{height}");
+        }
+    }
+}
+
+#[test]
+fn chunk_positions() {
+    use png_pong::chunk::{ImageData, ImageEnd, ImageHeader, Position};
+
+    let header = ImageHeader {
+        width: 1,
+        height: 1,
+        color_type: ColorType::Grey,
+        bit_depth: 8,
+        interlace: false,
+    };
+    let mut input = png_pong::SIGNATURE.to_vec();
+    let mut encoder = Encoder::new(&mut input).into_chunk_enc();
+    for mut chunk in [
+        Chunk::ImageHeader(header),
+        Chunk::ImageData(ImageData::with_data(vec![0, 0])),
+        Chunk::Text(Text::new("Comment", "Appended").unwrap()),
+        Chunk::ImageEnd(ImageEnd),
+    ] {
+        encoder.encode(&mut chunk).unwrap();
+    }
+
+    // Re-encode the decoded chunks, copying the compressed image data
+    let reencode = |png: &[u8], encoder: Encoder<Vec<u8>>| {
+        let mut encoder = encoder.into_chunk_enc();
+        let mut chunks = Decoder::new(png).unwrap().into_chunks();
+        let mut positions = Vec::new();
+        while let Some(chunk) = chunks.next() {
+            let mut chunk = chunk.unwrap();
+            positions.push(chunks.position());
+            match &chunk {
+                Chunk::ImageData(data) => {
+                    encoder.write_chunk(*b"IDAT", data.data()).unwrap()
+                }
+                _ => encoder.encode(&mut chunk).unwrap(),
+            }
+        }
+        (encoder.into_inner().unwrap(), positions)
+    };
+    let encoder = || Encoder::new(png_pong::SIGNATURE.to_vec());
+
+    let (output, positions) = reencode(&input, encoder());
+    assert_eq!(output, input);
+    assert_eq!(
+        positions,
+        [
+            Position::BeforeImage,
+            Position::BeforeImage,
+            Position::AfterImage,
+            Position::AfterImage,
+        ],
+    );
+
+    let (before, _) =
+        reencode(&input, encoder().normalize_positions(Position::BeforeImage));
+    assert_eq!(
+        png_chunk_names(before.as_slice()).unwrap(),
+        [*b"IHDR", *b"tEXt", *b"IDAT", *b"IEND"],
+    );
+    assert!(same_pixels(input.as_slice(), before.as_slice()).unwrap());
+
+    let (after, _) =
+        reencode(&before, encoder().normalize_positions(Position::AfterImage));
+    assert_eq!(after, input);
+
+    // Held chunks are written when unwrapping the writer
+    let header = Chunk::ImageHeader(header);
+    let text = Chunk::Text(Text::new("Comment", "Held").unwrap());
+    let idat = Chunk::ImageData(ImageData::with_data(vec![0, 0]));
+    for (position, mut chunk) in
+        [(Position::BeforeImage, idat), (Position::AfterImage, text)]
+    {
+        let mut encoder =
+            encoder().normalize_positions(position).into_chunk_enc();
+        encoder.encode(&mut header.clone()).unwrap();
+        encoder.encode(&mut chunk).unwrap();
+        let output = encoder.into_inner().unwrap();
+        let expected = [
+            png_pong::SIGNATURE.to_vec(),
+            header.to_bytes().unwrap(),
+            chunk.to_bytes().unwrap(),
+        ];
+        assert_eq!(output, expected.concat());
+    }
+}
+
+#[test]
+fn encode_region() {
+    use pix::{
+        el::Pixel,
+        gray::SGray8,
+        rgb::{SRgb16, SRgba8},
+        Raster,
+    };
+
+    // Same file as encoding a copy of the region, whatever the settings
+    fn same_as_copy<P: Pixel>(
+        raster: &Raster<P>,
+        (x, y, width, height): (u32, u32, u32, u32),
+    ) {
+        let settings: [fn(Encoder<Vec<u8>>) -> Encoder<Vec<u8>>; 5] = [
+            |enc| enc,
+            |enc| enc.interlace(),
+            |enc| enc.filter_strategy(FilterStrategy::MinSum),
+            |enc| enc.reduce_depth(true),
+            |enc| enc.max_output_size(1 << 20),
+        ];
+        let crop: Vec<P> = (y..y + height)
+            .flat_map(|row| {
+                let start = (row * raster.width() + x) as usize;
+                raster.pixels()[start..][..width as usize].to_vec()
+            })
+            .collect();
+        let crop = Raster::with_pixels(width, height, crop);
+        for settings in settings {
+            let mut region = settings(Encoder::new(Vec::new())).into_step_enc();
+            region.encode_region(raster, x, y, width, height).unwrap();
+            let mut copy = settings(Encoder::new(Vec::new())).into_step_enc();
+            copy.still(&crop).unwrap();
+            assert_eq!(region.into_inner(), copy.into_inner());
+        }
+    }
+
+    let decode = |png: &[u8]| {
+        let step = Decoder::new(png).unwrap().into_steps().next().unwrap();
+        step.unwrap().raster
+    };
+    let pixels: Vec<_> = (0..16u8)
+        .map(|i| SRgba8::new(i, i * 2, i * 3, 255 - i))
+        .collect();
+    let atlas = Raster::with_pixels(4, 4, pixels);
+    for (x, y) in [(0, 0), (2, 0), (0, 2), (2, 2)] {
+        let mut png = Vec::new();
+        Encoder::new(&mut png)
+            .into_step_enc()
+            .encode_region(&atlas, x, y, 2, 2)
+            .unwrap();
+
+        let crop: Vec<_> = (y..y + 2)
+            .flat_map(|row| (x..x + 2).map(move |col| (row * 4 + col) as u8))
+            .map(|i| SRgba8::new(i, i * 2, i * 3, 255 - i))
+            .collect();
+        assert_eq!(
+            decode(&png),
+            PngRaster::Rgba8(Raster::with_pixels(2, 2, crop)),
+        );
+    }
+
+    // 16-bit samples, one row
+    let pixels: Vec<_> = (0..6u16)
+        .map(|i| SRgb16::new(i * 1000, i, u16::MAX - i))
+        .collect();
+    let strip = Raster::with_pixels(3, 2, pixels);
+    let mut png = Vec::new();
+    Encoder::new(&mut png)
+        .into_step_enc()
+        .encode_region(&strip, 1, 1, 2, 1)
+        .unwrap();
+    let crop = vec![
+        SRgb16::new(4000, 4, u16::MAX - 4),
+        SRgb16::new(5000, 5, u16::MAX - 5),
+    ];
+    assert_eq!(
+        decode(&png),
+        PngRaster::Rgb16(Raster::with_pixels(2, 1, crop))
+    );
+
+    same_as_copy(&atlas, (1, 1, 3, 3));
+    same_as_copy(&strip, (0, 1, 3, 1));
+    // Black and white, so reducing the bit depth packs the samples
+    let pixels: Vec<_> = (0..64)
+        .map(|i| SGray8::new(if i % 3 == 0 { 255 } else { 0 }))
+        .collect();
+    same_as_copy(&Raster::with_pixels(8, 8, pixels), (1, 2, 5, 4));
+
+    for (x, y, width, height) in
+        [(3, 0, 2, 2), (0, 3, 1, 2), (u32::MAX, 0, 2, 1)]
+    {
+        assert!(matches!(
+            Encoder::new(Vec::new())
+                .into_step_enc()
+                .encode_region(&atlas, x, y, width, height),
+            Err(Error::RegionBounds)
+        ));
+    }
+}
+
+#[test]
+fn repair() {
+    use png_pong::{
+        chunk::Unknown,
+        decode::{png_chunk_sizes, Error as DecodeError},
+        encode::RepairReport,
+    };
+
+    let repair = |png: &[u8]| {
+        let mut output = Vec::new();
+        let report = png_pong::repair(png, &mut output, 1 << 20).unwrap();
+        // The output decodes with CRCs checked
+        Decoder::new(output.as_slice())
+            .unwrap()
+            .into_steps()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        (output, report)
+    };
+    let chunk = |png: &[u8], name: &[u8; 4]| {
+        png_chunk_sizes(Cursor::new(png))
+            .unwrap()
+            .into_iter()
+            .find(|(n, _, _)| n == name)
+            .map(|(_, length, offset)| (offset as usize, length as usize))
+            .unwrap()
+    };
+
+    let image = generate_test_image(ColorType::Rgb, 8, 32, 32);
+    let (output, report) = repair(&image);
+    assert_eq!(report, RepairReport::default());
+    assert!(same_pixels(image.as_slice(), output.as_slice()).unwrap());
+
+    // Bad CRCs
+    let mut damaged = Vec::new();
+    let text = Chunk::Text(Text::new("Title", "Test").unwrap());
+    rewrite(
+        image.as_slice(),
+        &mut damaged,
+        &[Edit::Insert(ChunkSelector::Name(*b"IHDR"), text)],
+    )
+    .unwrap();
+    for name in [b"tEXt", b"IDAT"] {
+        let (offset, length) = chunk(&damaged, name);
+        damaged[offset + 8 + length] ^= 1;
+    }
+    let (output, report) = repair(&damaged);
+    assert_eq!(report.dropped, [*b"tEXt"]);
+    assert_eq!(report.fixed, [*b"IDAT"]);
+    assert!(same_pixels(image.as_slice(), output.as_slice()).unwrap());
+
+    // Truncated image data
+    let (offset, length) = chunk(&image, b"IDAT");
+    let (output, report) = repair(&image[..offset + 8 + length / 2]);
+    assert_eq!(report.truncated, [*b"IDAT"]);
+    assert!(report.missing_end);
+    assert!(report.image_bytes_replaced > 0);
+    assert_eq!(png_header(output.as_slice()).unwrap().width, 32);
+
+    // Palette after the image data
+    let image = generate_test_image(ColorType::Palette, 8, 16, 16);
+    let (offset, length) = chunk(&image, b"PLTE");
+    let palette = Chunk::Unknown(Unknown {
+        name: *b"PLTE",
+        data: image[offset + 8..][..length].to_vec(),
+    });
+    let mut misordered = Vec::new();
+    rewrite(
+        image.as_slice(),
+        &mut misordered,
+        &[
+            Edit::Remove(ChunkSelector::Name(*b"PLTE")),
+            Edit::Insert(ChunkSelector::Name(*b"IDAT"), palette),
+        ],
+    )
+    .unwrap();
+    let (output, report) = repair(&misordered);
+    assert!(report.reordered);
+    assert_eq!(
+        png_chunk_names(output.as_slice()).unwrap(),
+        png_chunk_names(image.as_slice()).unwrap(),
+    );
+    assert!(same_pixels(image.as_slice(), output.as_slice()).unwrap());
+
+    // Nothing to recover
+    assert!(matches!(
+        png_pong::repair(&image[1..], Vec::new(), 1 << 20),
+        Err(DecodeError::InvalidSignature)
+    ));
+    let (offset, _) = chunk(&image, b"IHDR");
+    assert!(matches!(
+        png_pong::repair(&image[..offset], Vec::new(), 1 << 20),
+        Err(DecodeError::Empty)
+    ));
+
+    // Too large, with the image data missing
+    let mut header = Chunk::ImageHeader(png_pong::chunk::ImageHeader {
+        width: 65_535,
+        height: 65_535,
+        color_type: ColorType::Rgba,
+        bit_depth: 16,
+        interlace: false,
+    });
+    let mut huge = vec![137, 80, 78, 71, 13, 10, 26, 10];
+    Encoder::new(&mut huge)
+        .into_chunk_enc()
+        .encode(&mut header)
+        .unwrap();
+    assert!(matches!(
+        png_pong::repair(huge.as_slice(), Vec::new(), 1 << 20),
+        Err(DecodeError::LimitExceeded)
+    ));
+}
+
+#[test]
+fn write_png() {
+    use pix::{rgb::SRgb8, Raster};
+    use png_pong::chunk::ImageHeader;
+
+    let header = |color_type, bit_depth, interlace| ImageHeader {
+        width: 3,
+        height: 2,
+        color_type,
+        bit_depth,
+        interlace,
+    };
+    let rows: [&[u8]; 2] = [
+        &[1, 2, 3, 4, 5, 6, 7, 8, 9],
+        &[10, 11, 12, 13, 14, 15, 16, 17, 18],
+    ];
+    let mut png = Vec::new();
+    png_pong::write_png(&mut png, header(ColorType::Rgb, 8, false), &rows)
+        .unwrap();
+    let step = Decoder::new(&png[..]).unwrap().into_steps().next().unwrap();
+    let pixels: Vec<_> = (0..6u8)
+        .map(|i| SRgb8::new(i * 3 + 1, i * 3 + 2, i * 3 + 3))
+        .collect();
+    assert_eq!(
+        step.unwrap().raster,
+        PngRaster::Rgb8(Raster::with_pixels(3, 2, pixels)),
+    );
+
+    // Padded rows of pixels smaller than a byte, interlaced or not
+    let rows: [&[u8]; 2] = [&[0b1010_0000], &[0b0110_0000]];
+    let mut png = Vec::new();
+    png_pong::write_png(&mut png, header(ColorType::Grey, 1, false), &rows)
+        .unwrap();
+    let mut interlaced = Vec::new();
+    png_pong::write_png(
+        &mut interlaced,
+        header(ColorType::Grey, 1, true),
+        &rows,
+    )
+    .unwrap();
+    assert!(png_header(&png[..]).unwrap().bit_depth == 1);
+    assert!(png_header(&interlaced[..]).unwrap().interlace);
+    assert!(same_pixels(&png[..], &interlaced[..]).unwrap());
+
+    assert!(matches!(
+        png_pong::write_png(Vec::new(), header(ColorType::Grey, 1, false), &[]),
+        Err(Error::RowCount {
+            expected: 2,
+            found: 0
+        }),
+    ));
+    assert!(matches!(
+        png_pong::write_png(
+            Vec::new(),
+            header(ColorType::Grey, 1, false),
+            &[&[0], &[0, 0]],
+        ),
+        Err(Error::RowSize {
+            row: 1,
+            expected: 1,
+            found: 2
+        }),
+    ));
+    assert!(matches!(
+        png_pong::write_png(
+            Vec::new(),
+            header(ColorType::Rgb, 4, false),
+            &rows
+        ),
+        Err(Error::ColorMode(ColorType::Rgb, 4)),
+    ));
+    assert!(matches!(
+        png_pong::write_png(
+            Vec::new(),
+            header(ColorType::Palette, 1, false),
+            &rows,
+        ),
+        Err(Error::BadPalette),
+    ));
+}
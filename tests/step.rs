@@ -0,0 +1,93 @@
+use pix::{
+    gray::{Gray8, SGray8},
+    rgb::{SRgb8, SRgba8},
+    Palette, Raster,
+};
+use png_pong::{PngRaster, Step};
+
+fn rgba_step() -> Step {
+    let raster = Raster::<SRgba8>::with_pixels(
+        2,
+        1,
+        &[SRgba8::new(1, 2, 3, 4), SRgba8::new(5, 6, 7, 8)][..],
+    );
+
+    Step {
+        raster: PngRaster::Rgba8(raster),
+        delay: 0,
+    }
+}
+
+#[test]
+fn clone_is_independent() {
+    let step = rgba_step();
+    let mut clone = step.clone();
+
+    assert_eq!(step, clone);
+    if let PngRaster::Rgba8(raster) = &mut clone.raster {
+        raster.as_u8_slice_mut()[0] = 9;
+    }
+    assert_ne!(step, clone);
+    assert_eq!(step, rgba_step());
+}
+
+#[test]
+fn equality() {
+    let step = rgba_step();
+
+    // Different delay
+    let mut other = rgba_step();
+    other.delay = 1;
+    assert_ne!(step, other);
+
+    // Different dimensions, same data
+    let mut other = rgba_step();
+    other.raster = PngRaster::Rgba8(Raster::with_u8_buffer(
+        1,
+        2,
+        [1, 2, 3, 4, 5, 6, 7, 8],
+    ));
+    assert_ne!(step, other);
+
+    // Different format, same bytes
+    let other = Step {
+        raster: PngRaster::Gray8(Raster::<SGray8>::with_u8_buffer(
+            8,
+            1,
+            [1, 2, 3, 4, 5, 6, 7, 8],
+        )),
+        delay: 0,
+    };
+    assert_ne!(step, other);
+}
+
+#[test]
+fn palette_equality() {
+    let palette_raster = |alpha: u8| {
+        let mut palette = Palette::new(2);
+        palette.set_entry(SRgb8::new(255, 0, 0)).unwrap();
+        palette.set_entry(SRgb8::new(0, 255, 0)).unwrap();
+
+        PngRaster::Palette(
+            Raster::<Gray8>::with_u8_buffer(2, 1, [0, 1]),
+            Box::new(palette),
+            vec![alpha, 255],
+        )
+    };
+    let raster = palette_raster(0);
+
+    assert_eq!(raster, raster.clone());
+    assert_eq!(raster, palette_raster(0));
+    assert_ne!(raster, palette_raster(128));
+    assert_eq!(format!("{raster:?}"), format!("{:?}", palette_raster(128)));
+}
+
+#[test]
+fn debug_omits_pixels() {
+    let debug = format!("{:?}", rgba_step());
+
+    assert_eq!(
+        debug,
+        "Step { raster: Rgba8 { width: 2, height: 1 }, delay: 0 }"
+    );
+}